@@ -15,6 +15,16 @@ fn main() {
 
     #[cfg(feature = "s3")]
     println!("cargo:rustc-cfg=s3");
+    #[cfg(feature = "azblob")]
+    println!("cargo:rustc-cfg=azblob");
+    #[cfg(feature = "gcs")]
+    println!("cargo:rustc-cfg=gcs");
+    #[cfg(feature = "webdav")]
+    println!("cargo:rustc-cfg=webdav");
+    #[cfg(feature = "redis")]
+    println!("cargo:rustc-cfg=redis");
+    #[cfg(any(feature = "s3", feature = "azblob", feature = "gcs", feature = "webdav"))]
+    println!("cargo:rustc-cfg=remote_storage");
 
     // Use check-cfg to let cargo know which cfg's we define,
     // and avoid warnings when they are used in the code.
@@ -22,6 +32,11 @@ fn main() {
     println!("cargo::rustc-check-cfg=cfg(mysql)");
     println!("cargo::rustc-check-cfg=cfg(postgresql)");
     println!("cargo::rustc-check-cfg=cfg(s3)");
+    println!("cargo::rustc-check-cfg=cfg(azblob)");
+    println!("cargo::rustc-check-cfg=cfg(gcs)");
+    println!("cargo::rustc-check-cfg=cfg(webdav)");
+    println!("cargo::rustc-check-cfg=cfg(remote_storage)");
+    println!("cargo::rustc-check-cfg=cfg(redis)");
 
     // Rerun when these paths are changed.
     // Someone could have checked-out a tag or specific commit, but no other files changed.
@@ -0,0 +1,4 @@
+CREATE TABLE ratelimits (
+  limit_key VARCHAR(512) NOT NULL PRIMARY KEY,
+  tat       BIGINT       NOT NULL
+);
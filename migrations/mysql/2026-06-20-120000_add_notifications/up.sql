@@ -0,0 +1,24 @@
+CREATE TABLE notifications (
+    uuid              CHAR(36)  NOT NULL PRIMARY KEY,
+    priority          INTEGER   NOT NULL,
+    global            BOOLEAN   NOT NULL,
+    client_type       INTEGER   NOT NULL,
+    user_uuid         CHAR(36),
+    organization_uuid CHAR(36),
+    title             TEXT,
+    body              TEXT,
+    creation_date     DATETIME  NOT NULL,
+    revision_date     DATETIME  NOT NULL,
+    FOREIGN KEY (user_uuid) REFERENCES users (uuid) ON DELETE CASCADE,
+    FOREIGN KEY (organization_uuid) REFERENCES organizations (uuid) ON DELETE CASCADE
+);
+
+CREATE TABLE notification_statuses (
+    notification_uuid CHAR(36) NOT NULL,
+    user_uuid         CHAR(36) NOT NULL,
+    read_date         DATETIME,
+    deleted_date      DATETIME,
+    PRIMARY KEY (notification_uuid, user_uuid),
+    FOREIGN KEY (notification_uuid) REFERENCES notifications (uuid) ON DELETE CASCADE,
+    FOREIGN KEY (user_uuid) REFERENCES users (uuid) ON DELETE CASCADE
+);
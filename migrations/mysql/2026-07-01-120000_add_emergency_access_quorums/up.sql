@@ -0,0 +1,5 @@
+CREATE TABLE emergency_access_quorums (
+    grantor_uuid CHAR(36) NOT NULL PRIMARY KEY,
+    quorum       INTEGER  NOT NULL,
+    FOREIGN KEY (grantor_uuid) REFERENCES users (uuid) ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+CREATE TABLE mail_queue (
+  uuid            CHAR(36)     NOT NULL PRIMARY KEY,
+  recipient       VARCHAR(255) NOT NULL,
+  subject         TEXT         NOT NULL,
+  body_html       MEDIUMTEXT   NOT NULL,
+  body_text       MEDIUMTEXT   NOT NULL,
+  attempts        INTEGER      NOT NULL,
+  last_error      TEXT,
+  created_at      DATETIME     NOT NULL,
+  next_attempt_at DATETIME     NOT NULL
+);
+
+CREATE INDEX mail_queue_next_attempt_at_idx ON mail_queue (next_attempt_at);
@@ -0,0 +1,9 @@
+CREATE TABLE tombstones (
+    uuid        CHAR(36) NOT NULL PRIMARY KEY,
+    user_uuid   CHAR(36) NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    object_type INTEGER  NOT NULL,
+    object_uuid CHAR(36) NOT NULL,
+    deleted_at  DATETIME NOT NULL
+);
+
+CREATE INDEX tombstones_user_uuid_deleted_at_idx ON tombstones (user_uuid, deleted_at);
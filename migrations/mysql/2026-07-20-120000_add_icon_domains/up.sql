@@ -0,0 +1,6 @@
+CREATE TABLE icon_domains (
+  domain       VARCHAR(255) NOT NULL PRIMARY KEY,
+  created_at   DATETIME     NOT NULL,
+  refreshed_at DATETIME,
+  last_error   TEXT
+);
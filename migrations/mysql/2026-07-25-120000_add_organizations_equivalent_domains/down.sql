@@ -0,0 +1 @@
+ALTER TABLE organizations DROP COLUMN equivalent_domains;
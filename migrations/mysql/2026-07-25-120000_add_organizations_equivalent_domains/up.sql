@@ -0,0 +1,2 @@
+ALTER TABLE organizations ADD COLUMN equivalent_domains TEXT NOT NULL;
+UPDATE organizations SET equivalent_domains = '[]';
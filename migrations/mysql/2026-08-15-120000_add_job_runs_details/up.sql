@@ -0,0 +1 @@
+ALTER TABLE job_runs ADD COLUMN details TEXT;
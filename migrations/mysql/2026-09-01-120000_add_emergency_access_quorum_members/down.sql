@@ -0,0 +1 @@
+DROP TABLE emergency_access_quorum_members;
@@ -0,0 +1,5 @@
+ALTER TABLE users ADD COLUMN language VARCHAR(35);
+
+UPDATE users SET language = (SELECT language FROM user_languages WHERE user_languages.user_uuid = users.uuid);
+
+DROP TABLE user_languages;
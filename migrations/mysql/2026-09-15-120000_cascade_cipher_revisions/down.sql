@@ -0,0 +1,14 @@
+-- Dynamically create DROP FOREIGN KEY
+-- MySQL ignores an inline REFERENCES, so the key only exists on MariaDB
+SET @drop_cipher_revisions_fk = IF((SELECT true FROM information_schema.TABLE_CONSTRAINTS WHERE
+    CONSTRAINT_SCHEMA = DATABASE() AND
+    TABLE_NAME = 'cipher_revisions' AND
+    CONSTRAINT_NAME = 'cipher_revisions_ibfk_1' AND
+    CONSTRAINT_TYPE = 'FOREIGN KEY') = true,
+    'ALTER TABLE cipher_revisions DROP FOREIGN KEY cipher_revisions_ibfk_1',
+    'SELECT 1');
+PREPARE stmt FROM @drop_cipher_revisions_fk;
+EXECUTE stmt;
+DEALLOCATE PREPARE stmt;
+
+ALTER TABLE cipher_revisions ADD CONSTRAINT cipher_revisions_ibfk_1 FOREIGN KEY (cipher_uuid) REFERENCES ciphers (uuid);
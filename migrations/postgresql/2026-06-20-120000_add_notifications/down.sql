@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS notification_statuses;
+DROP TABLE IF EXISTS notifications;
@@ -0,0 +1,20 @@
+CREATE TABLE notifications (
+    uuid              CHAR(36)  NOT NULL PRIMARY KEY,
+    priority          INTEGER   NOT NULL,
+    global            BOOLEAN   NOT NULL,
+    client_type       INTEGER   NOT NULL,
+    user_uuid         CHAR(36)  REFERENCES users (uuid) ON DELETE CASCADE,
+    organization_uuid CHAR(36)  REFERENCES organizations (uuid) ON DELETE CASCADE,
+    title             TEXT,
+    body              TEXT,
+    creation_date     TIMESTAMP NOT NULL,
+    revision_date     TIMESTAMP NOT NULL
+);
+
+CREATE TABLE notification_statuses (
+    notification_uuid CHAR(36)  NOT NULL REFERENCES notifications (uuid) ON DELETE CASCADE,
+    user_uuid         CHAR(36)  NOT NULL REFERENCES users (uuid) ON DELETE CASCADE,
+    read_date         TIMESTAMP,
+    deleted_date      TIMESTAMP,
+    PRIMARY KEY (notification_uuid, user_uuid)
+);
@@ -0,0 +1,6 @@
+CREATE TABLE icon_domains (
+  domain       VARCHAR(255) NOT NULL PRIMARY KEY,
+  created_at   TIMESTAMP    NOT NULL,
+  refreshed_at TIMESTAMP,
+  last_error   TEXT
+);
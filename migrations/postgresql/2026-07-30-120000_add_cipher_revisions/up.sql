@@ -0,0 +1,17 @@
+CREATE TABLE cipher_revisions (
+    uuid             CHAR(36)  NOT NULL PRIMARY KEY,
+    cipher_uuid      CHAR(36)  NOT NULL REFERENCES ciphers (uuid),
+    revision_date    TIMESTAMP NOT NULL,
+    created_at       TIMESTAMP NOT NULL,
+    atype            INTEGER   NOT NULL,
+    name             TEXT      NOT NULL,
+    notes            TEXT,
+    fields           TEXT,
+    data             TEXT      NOT NULL,
+    key              TEXT,
+    password_history TEXT,
+    reprompt         INTEGER,
+    attachments      TEXT
+);
+
+CREATE INDEX cipher_revisions_cipher_uuid_created_at_idx ON cipher_revisions (cipher_uuid, created_at);
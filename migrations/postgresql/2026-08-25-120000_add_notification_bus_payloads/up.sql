@@ -0,0 +1,9 @@
+-- Updates too large for pg_notify(), published through the PostgreSQL WebSocket backplane.
+-- The backplane is only available with PostgreSQL, so this table only exists there.
+CREATE TABLE notification_bus_payloads (
+  uuid       CHAR(36)  NOT NULL PRIMARY KEY,
+  payload    TEXT      NOT NULL,
+  created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX idx_notification_bus_payloads_created_at ON notification_bus_payloads (created_at);
@@ -0,0 +1,11 @@
+CREATE TABLE emergency_access_quorum_members (
+    emergency_access_uuid CHAR(36) NOT NULL PRIMARY KEY REFERENCES emergency_access (uuid) ON DELETE CASCADE,
+    grantor_uuid          CHAR(36) NOT NULL REFERENCES emergency_access_quorums (grantor_uuid) ON DELETE CASCADE
+);
+
+-- The existing quorums were counted over all the confirmed emergency contacts
+INSERT INTO emergency_access_quorum_members (emergency_access_uuid, grantor_uuid)
+SELECT ea.uuid, ea.grantor_uuid
+FROM emergency_access ea
+INNER JOIN emergency_access_quorums q ON q.grantor_uuid = ea.grantor_uuid
+WHERE ea.status >= 2;
@@ -0,0 +1 @@
+ALTER TABLE mail_queue ADD COLUMN expires_at TIMESTAMP;
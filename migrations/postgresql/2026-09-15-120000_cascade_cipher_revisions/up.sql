@@ -0,0 +1,3 @@
+ALTER TABLE cipher_revisions
+  DROP CONSTRAINT "cipher_revisions_cipher_uuid_fkey",
+  ADD CONSTRAINT "cipher_revisions_cipher_uuid_fkey" FOREIGN KEY (cipher_uuid) REFERENCES ciphers (uuid) ON UPDATE CASCADE ON DELETE CASCADE;
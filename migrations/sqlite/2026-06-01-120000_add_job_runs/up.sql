@@ -0,0 +1,11 @@
+CREATE TABLE job_runs (
+  uuid          TEXT     NOT NULL PRIMARY KEY,
+  job_name      TEXT     NOT NULL,
+  scheduled_at  DATETIME NOT NULL,
+  instance      TEXT     NOT NULL,
+  started_at    DATETIME NOT NULL,
+  finished_at   DATETIME,
+  result        TEXT,
+  rows_affected BIGINT,
+  UNIQUE (job_name, scheduled_at)
+);
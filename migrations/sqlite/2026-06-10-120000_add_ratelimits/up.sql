@@ -0,0 +1,4 @@
+CREATE TABLE ratelimits (
+  limit_key TEXT   NOT NULL PRIMARY KEY,
+  tat       BIGINT NOT NULL
+);
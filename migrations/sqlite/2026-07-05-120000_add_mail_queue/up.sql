@@ -0,0 +1,13 @@
+CREATE TABLE mail_queue (
+  uuid            TEXT     NOT NULL PRIMARY KEY,
+  recipient       TEXT     NOT NULL,
+  subject         TEXT     NOT NULL,
+  body_html       TEXT     NOT NULL,
+  body_text       TEXT     NOT NULL,
+  attempts        INTEGER  NOT NULL,
+  last_error      TEXT,
+  created_at      DATETIME NOT NULL,
+  next_attempt_at DATETIME NOT NULL
+);
+
+CREATE INDEX mail_queue_next_attempt_at_idx ON mail_queue (next_attempt_at);
@@ -0,0 +1,10 @@
+CREATE TABLE tombstones (
+    uuid        CHAR(36) NOT NULL PRIMARY KEY,
+    user_uuid   CHAR(36) NOT NULL,
+    object_type INTEGER  NOT NULL,
+    object_uuid CHAR(36) NOT NULL,
+    deleted_at  DATETIME NOT NULL,
+    FOREIGN KEY (user_uuid) REFERENCES users (uuid) ON DELETE CASCADE
+);
+
+CREATE INDEX tombstones_user_uuid_deleted_at_idx ON tombstones (user_uuid, deleted_at);
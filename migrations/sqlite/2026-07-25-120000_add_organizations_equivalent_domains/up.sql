@@ -0,0 +1 @@
+ALTER TABLE organizations ADD COLUMN equivalent_domains TEXT NOT NULL DEFAULT '[]';
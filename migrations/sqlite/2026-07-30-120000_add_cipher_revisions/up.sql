@@ -0,0 +1,18 @@
+CREATE TABLE cipher_revisions (
+    uuid             CHAR(36) NOT NULL PRIMARY KEY,
+    cipher_uuid      CHAR(36) NOT NULL,
+    revision_date    DATETIME NOT NULL,
+    created_at       DATETIME NOT NULL,
+    atype            INTEGER  NOT NULL,
+    name             TEXT     NOT NULL,
+    notes            TEXT,
+    fields           TEXT,
+    data             TEXT     NOT NULL,
+    key              TEXT,
+    password_history TEXT,
+    reprompt         INTEGER,
+    attachments      TEXT,
+    FOREIGN KEY (cipher_uuid) REFERENCES ciphers (uuid)
+);
+
+CREATE INDEX cipher_revisions_cipher_uuid_created_at_idx ON cipher_revisions (cipher_uuid, created_at);
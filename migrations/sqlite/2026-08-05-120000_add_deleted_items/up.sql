@@ -0,0 +1,13 @@
+CREATE TABLE deleted_items (
+    uuid              CHAR(36) NOT NULL PRIMARY KEY,
+    object_type       INTEGER  NOT NULL,
+    object_uuid       CHAR(36) NOT NULL,
+    user_uuid         CHAR(36),
+    organization_uuid CHAR(36),
+    created_at        DATETIME NOT NULL,
+    deleted_at        DATETIME NOT NULL,
+    data              TEXT     NOT NULL
+);
+
+CREATE INDEX deleted_items_user_uuid_idx ON deleted_items (user_uuid);
+CREATE INDEX deleted_items_organization_uuid_idx ON deleted_items (organization_uuid);
@@ -0,0 +1 @@
+ALTER TABLE organizations ADD COLUMN trash_retention_days INTEGER;
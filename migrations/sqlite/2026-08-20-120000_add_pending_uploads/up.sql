@@ -0,0 +1,10 @@
+CREATE TABLE pending_uploads (
+  uuid        TEXT     NOT NULL PRIMARY KEY,
+  atype       INTEGER  NOT NULL,
+  user_uuid   TEXT     NOT NULL,
+  device_uuid TEXT     NOT NULL,
+  device_type INTEGER  NOT NULL,
+  ip_address  TEXT     NOT NULL,
+  presigned   BOOLEAN  NOT NULL,
+  expires_at  DATETIME NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE user_languages (
+    user_uuid TEXT NOT NULL PRIMARY KEY,
+    language  TEXT NOT NULL,
+    FOREIGN KEY (user_uuid) REFERENCES users (uuid) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+INSERT INTO user_languages (user_uuid, language)
+SELECT uuid, language FROM users WHERE language IS NOT NULL;
+
+ALTER TABLE users DROP COLUMN language;
@@ -0,0 +1,24 @@
+CREATE TABLE cipher_revisions_new (
+    uuid             CHAR(36) NOT NULL PRIMARY KEY,
+    cipher_uuid      CHAR(36) NOT NULL,
+    revision_date    DATETIME NOT NULL,
+    created_at       DATETIME NOT NULL,
+    atype            INTEGER  NOT NULL,
+    name             TEXT     NOT NULL,
+    notes            TEXT,
+    fields           TEXT,
+    data             TEXT     NOT NULL,
+    key              TEXT,
+    password_history TEXT,
+    reprompt         INTEGER,
+    attachments      TEXT,
+    FOREIGN KEY (cipher_uuid) REFERENCES ciphers (uuid) ON UPDATE CASCADE ON DELETE CASCADE
+);
+
+INSERT INTO cipher_revisions_new SELECT * FROM cipher_revisions;
+
+DROP TABLE cipher_revisions;
+
+ALTER TABLE cipher_revisions_new RENAME TO cipher_revisions;
+
+CREATE INDEX cipher_revisions_cipher_uuid_created_at_idx ON cipher_revisions (cipher_uuid, created_at);
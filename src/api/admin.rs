@@ -1,5 +1,6 @@
 use std::{env, sync::LazyLock};
 
+use chrono::{DateTime, NaiveDateTime};
 use reqwest::Method;
 use rocket::{
     Catcher, Route,
@@ -16,20 +17,21 @@ use crate::{
     CONFIG, VERSION,
     api::{
         ApiResult, EmptyResult, JsonResult, Notify,
-        core::{log_event, two_factor},
-        unregister_push_device,
+        core::{NotificationData, VaultOwner, log_event, restore_vault, send_notification, two_factor},
+        icons, unregister_push_device,
     },
     auth::{ClientIp, Secure, decode_admin, encode_jwt, generate_admin_claims},
     config::ConfigBuilder,
     db::{
         ACTIVE_DB_TYPE, DbConn, DbConnType, backup_sqlite, get_sql_server_version,
         models::{
-            Attachment, Cipher, Collection, Device, Event, EventType, Group, Invitation, Membership, MembershipId,
-            MembershipType, OrgPolicy, Organization, OrganizationId, SsoUser, TwoFactor, User, UserId,
+            Attachment, Cipher, Collection, Device, Event, EventType, Group, IconDomain, Invitation, JobRun, MailId,
+            Membership, MembershipId, MembershipType, OrgPolicy, Organization, OrganizationId, QueuedMail, SsoUser,
+            TwoFactor, User, UserId,
         },
     },
     error::{Error, MapResult},
-    http_client::make_http_request,
+    http_client::{get_valid_host, make_http_request},
     mail,
     sso::FAKE_SSO_IDENTIFIER,
     util::{
@@ -67,10 +69,26 @@ pub fn routes() -> Vec<Route> {
         users_overview,
         organizations_overview,
         delete_organization,
+        mail_queue_overview,
+        retry_queued_mail,
+        delete_queued_mail,
+        icons_overview,
+        add_icon_domains,
+        delete_icon_domain,
+        refresh_icons,
+        retry_icon,
+        purge_negcached_icon,
+        save_custom_icon,
+        delete_custom_icon,
         diagnostics,
         get_diagnostics_config,
         resend_user_invite,
+        preview_bulk_users,
+        bulk_user_action,
+        restore_user_vault,
+        restore_organization_vault,
         get_diagnostics_http,
+        post_notification,
     ]
 }
 
@@ -182,7 +200,7 @@ struct LoginForm {
 }
 
 #[post("/", format = "application/x-www-form-urlencoded", data = "<data>")]
-fn post_admin_login(
+async fn post_admin_login(
     data: Form<LoginForm>,
     cookies: &CookieJar<'_>,
     ip: ClientIp,
@@ -191,7 +209,7 @@ fn post_admin_login(
     let data = data.into_inner();
     let redirect = data.redirect;
 
-    if crate::ratelimit::check_limit_admin(&ip.ip).is_err() {
+    if crate::ratelimit::check_limit_admin(&ip.ip).await.is_err() {
         return Err(AdminResponse::TooManyRequests(render_admin_login(
             Some("Too many requests, try again later."),
             redirect.as_deref(),
@@ -314,7 +332,7 @@ async fn invite_user(data: Json<InviteData>, _token: AdminToken, conn: DbConn) -
                 FAKE_ADMIN_UUID.into()
             };
             let member_id: MembershipId = FAKE_ADMIN_UUID.to_owned().into();
-            mail::send_invite(user, org_id, member_id, &CONFIG.invitation_org_name(), None).await
+            mail::send_invite(user, org_id, member_id, &CONFIG.invitation_org_name(), None, conn).await
         } else {
             let invitation = Invitation::new(&user.email);
             invitation.save(conn).await
@@ -335,11 +353,11 @@ async fn invite_user(data: Json<InviteData>, _token: AdminToken, conn: DbConn) -
 }
 
 #[post("/test/smtp", format = "application/json", data = "<data>")]
-async fn test_smtp(data: Json<InviteData>, _token: AdminToken) -> EmptyResult {
+async fn test_smtp(data: Json<InviteData>, _token: AdminToken, conn: DbConn) -> EmptyResult {
     let data: InviteData = data.into_inner();
 
     if CONFIG.mail_enabled() {
-        mail::send_test(&data.email).await
+        mail::send_test(&data.email, &conn).await
     } else {
         err!("Mail is not enabled")
     }
@@ -418,10 +436,13 @@ async fn get_user_json(user_id: UserId, _token: AdminToken, conn: DbConn) -> Jso
 #[post("/users/<user_id>/delete", format = "application/json")]
 async fn delete_user(user_id: UserId, token: AdminToken, conn: DbConn) -> EmptyResult {
     let user = get_user_or_404(&user_id, &conn).await?;
+    delete_user_account(user, &token.ip, &conn).await
+}
 
+async fn delete_user_account(user: User, ip: &ClientIp, conn: &DbConn) -> EmptyResult {
     // Get the membership records before deleting the actual user
-    let memberships = Membership::find_any_state_by_user(&user_id, &conn).await;
-    let res = user.delete(&conn).await;
+    let memberships = Membership::find_any_state_by_user(&user.uuid, conn).await;
+    let res = user.delete(conn).await;
 
     for membership in memberships {
         log_event(
@@ -430,8 +451,8 @@ async fn delete_user(user_id: UserId, token: AdminToken, conn: DbConn) -> EmptyR
             &membership.org_uuid,
             &ACTING_ADMIN_USER.into(),
             14, // Use UnknownBrowser type
-            &token.ip.ip,
-            &conn,
+            &ip.ip,
+            conn,
         )
         .await;
     }
@@ -462,12 +483,15 @@ async fn delete_sso_user(user_id: UserId, token: AdminToken, conn: DbConn) -> Em
 
 #[post("/users/<user_id>/deauth", format = "application/json")]
 async fn deauth_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Notify<'_>) -> EmptyResult {
-    let mut user = get_user_or_404(&user_id, &conn).await?;
+    let user = get_user_or_404(&user_id, &conn).await?;
+    deauth_user_sessions(user, &conn, &nt).await
+}
 
-    nt.send_logout(&user, None, &conn).await;
+async fn deauth_user_sessions(mut user: User, conn: &DbConn, nt: &Notify<'_>) -> EmptyResult {
+    nt.send_logout(&user, None, conn).await;
 
     if CONFIG.push_enabled() {
-        for device in Device::find_push_devices_by_user(&user.uuid, &conn).await {
+        for device in Device::find_push_devices_by_user(&user.uuid, conn).await {
             match unregister_push_device(device.push_uuid.as_ref()).await {
                 Ok(r) => r,
                 Err(e) => error!("Unable to unregister devices from Bitwarden server: {e}"),
@@ -475,23 +499,27 @@ async fn deauth_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Noti
         }
     }
 
-    Device::delete_all_by_user(&user.uuid, &conn).await?;
-    user.reset_security_stamp(&conn).await?;
+    Device::delete_all_by_user(&user.uuid, conn).await?;
+    user.reset_security_stamp(conn).await?;
 
-    user.save(&conn).await
+    user.save(conn).await
 }
 
 #[post("/users/<user_id>/disable", format = "application/json")]
 async fn disable_user(user_id: UserId, _token: AdminToken, conn: DbConn, nt: Notify<'_>) -> EmptyResult {
-    let mut user = get_user_or_404(&user_id, &conn).await?;
-    user.reset_security_stamp(&conn).await?;
+    let user = get_user_or_404(&user_id, &conn).await?;
+    disable_user_account(user, &conn, &nt).await
+}
+
+async fn disable_user_account(mut user: User, conn: &DbConn, nt: &Notify<'_>) -> EmptyResult {
+    user.reset_security_stamp(conn).await?;
     user.enabled = false;
 
-    let save_result = user.save(&conn).await;
+    let save_result = user.save(conn).await;
 
-    nt.send_logout(&user, None, &conn).await;
+    nt.send_logout(&user, None, conn).await;
 
-    Device::delete_all_by_user(&user.uuid, &conn).await?;
+    Device::delete_all_by_user(&user.uuid, conn).await?;
 
     save_result
 }
@@ -506,35 +534,272 @@ async fn enable_user(user_id: UserId, _token: AdminToken, conn: DbConn) -> Empty
 
 #[post("/users/<user_id>/remove-2fa", format = "application/json")]
 async fn remove_2fa(user_id: UserId, token: AdminToken, conn: DbConn) -> EmptyResult {
-    let mut user = get_user_or_404(&user_id, &conn).await?;
-    TwoFactor::delete_all_by_user(&user.uuid, &conn).await?;
-    two_factor::enforce_2fa_policy(&user, &ACTING_ADMIN_USER.into(), 14, &token.ip.ip, &conn).await?;
+    let user = get_user_or_404(&user_id, &conn).await?;
+    remove_user_2fa(user, &token.ip, &conn).await
+}
+
+async fn remove_user_2fa(mut user: User, ip: &ClientIp, conn: &DbConn) -> EmptyResult {
+    TwoFactor::delete_all_by_user(&user.uuid, conn).await?;
+    two_factor::enforce_2fa_policy(&user, &ACTING_ADMIN_USER.into(), 14, &ip.ip, conn).await?;
     user.totp_recover = None;
-    user.save(&conn).await
+    user.save(conn).await
 }
 
 #[post("/users/<user_id>/invite/resend", format = "application/json")]
 async fn resend_user_invite(user_id: UserId, _token: AdminToken, conn: DbConn) -> EmptyResult {
     if let Some(user) = User::find_by_uuid(&user_id, &conn).await {
-        //TODO: replace this with user.status check when it will be available (PR#3397)
-        if !user.password_hash.is_empty() {
-            err_code!("User already accepted invitation", Status::BadRequest.code);
+        resend_invite_mail(&user, &conn).await
+    } else {
+        err_code!("User doesn't exist", Status::NotFound.code);
+    }
+}
+
+async fn resend_invite_mail(user: &User, conn: &DbConn) -> EmptyResult {
+    //TODO: replace this with user.status check when it will be available (PR#3397)
+    if !user.password_hash.is_empty() {
+        err_code!("User already accepted invitation", Status::BadRequest.code);
+    }
+
+    if CONFIG.mail_enabled() {
+        let org_id: OrganizationId = if CONFIG.sso_enabled() {
+            FAKE_SSO_IDENTIFIER.into()
+        } else {
+            FAKE_ADMIN_UUID.into()
+        };
+        let member_id: MembershipId = FAKE_ADMIN_UUID.to_owned().into();
+        mail::send_invite(user, org_id, member_id, &CONFIG.invitation_org_name(), None, conn).await
+    } else {
+        Ok(())
+    }
+}
+
+/// Selects users for a bulk action, all the set filters have to match.
+#[expect(clippy::struct_excessive_bools, reason = "Each filter is a separate checkbox in the admin panel")]
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkUserFilter {
+    /// RFC 3339 timestamp, users which never logged in count as active when they were created
+    last_active_before: Option<String>,
+    #[serde(default)]
+    no_two_factor: bool,
+    #[serde(default)]
+    unverified_email: bool,
+    #[serde(default)]
+    never_logged_in: bool,
+    #[serde(default)]
+    sso_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BulkUserAction {
+    Deauth,
+    Disable,
+    Enable,
+    Delete,
+    Remove2fa,
+    ResendInvite,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BulkUserData {
+    #[serde(flatten)]
+    filter: BulkUserFilter,
+    action: BulkUserAction,
+    /// The users shown in the preview, users which match the filter by now but were not previewed are left alone.
+    /// Required, so an action like deleting users is never applied to whoever happens to match the filter.
+    user_ids: Vec<UserId>,
+}
+
+struct BulkUserCandidate {
+    user: User,
+    last_active: Option<NaiveDateTime>,
+    two_factor: bool,
+    sso: bool,
+}
+
+impl BulkUserFilter {
+    fn is_empty(&self) -> bool {
+        self.last_active_before.is_none()
+            && !self.no_two_factor
+            && !self.unverified_email
+            && !self.never_logged_in
+            && !self.sso_only
+    }
+
+    fn matches(&self, candidate: &BulkUserCandidate, last_active_before: Option<&NaiveDateTime>) -> bool {
+        last_active_before.is_none_or(|dt| candidate.last_active.unwrap_or(candidate.user.created_at) < *dt)
+            && (!self.no_two_factor || !candidate.two_factor)
+            && (!self.unverified_email || candidate.user.verified_at.is_none())
+            && (!self.never_logged_in || candidate.last_active.is_none())
+            && (!self.sso_only || candidate.sso)
+    }
+
+    async fn find_users(&self, conn: &DbConn) -> ApiResult<Vec<(User, Option<NaiveDateTime>)>> {
+        if self.is_empty() {
+            err!("Select at least one filter")
         }
+        let last_active_before = match &self.last_active_before {
+            Some(timestamp) => Some(parse_timestamp(timestamp)?),
+            None => None,
+        };
 
-        if CONFIG.mail_enabled() {
-            let org_id: OrganizationId = if CONFIG.sso_enabled() {
-                FAKE_SSO_IDENTIFIER.into()
-            } else {
-                FAKE_ADMIN_UUID.into()
+        let mut users = Vec::new();
+        for (user, sso_user, last_active, two_factor) in User::get_all_with_activity(conn).await {
+            let candidate = BulkUserCandidate {
+                user,
+                last_active,
+                two_factor,
+                sso: sso_user.is_some(),
             };
-            let member_id: MembershipId = FAKE_ADMIN_UUID.to_owned().into();
-            mail::send_invite(&user, org_id, member_id, &CONFIG.invitation_org_name(), None).await
-        } else {
-            Ok(())
+            if self.matches(&candidate, last_active_before.as_ref()) {
+                users.push((candidate.user, candidate.last_active));
+            }
         }
-    } else {
-        err_code!("User doesn't exist", Status::NotFound.code);
+        Ok(users)
+    }
+}
+
+#[post("/users/bulk/preview", format = "application/json", data = "<data>")]
+async fn preview_bulk_users(data: Json<BulkUserFilter>, _token: AdminToken, conn: DbConn) -> JsonResult {
+    let users = data.find_users(&conn).await?;
+    let users_json: Vec<Value> = users
+        .iter()
+        .map(|(u, last_active)| {
+            json!({
+                "id": u.uuid,
+                "email": u.email,
+                "name": u.name,
+                "lastActive": last_active.map(|dt| format_naive_datetime_local(&dt, DT_FMT)),
+            })
+        })
+        .collect();
+
+    Ok(Json(json!({
+        "total": users_json.len(),
+        "users": users_json,
+    })))
+}
+
+#[post("/users/bulk", format = "application/json", data = "<data>")]
+async fn bulk_user_action(data: Json<BulkUserData>, token: AdminToken, conn: DbConn, nt: Notify<'_>) -> JsonResult {
+    let data = data.into_inner();
+    if data.user_ids.is_empty() {
+        err!("Select at least one user")
+    }
+    let mut users = data.filter.find_users(&conn).await?;
+    users.retain(|(u, _)| data.user_ids.contains(&u.uuid));
+
+    let total = users.len();
+    let mut failed = Vec::new();
+    for (mut user, _) in users {
+        let email = user.email.clone();
+        let res = match data.action {
+            BulkUserAction::Deauth => deauth_user_sessions(user, &conn, &nt).await,
+            BulkUserAction::Disable => disable_user_account(user, &conn, &nt).await,
+            BulkUserAction::Enable => {
+                user.enabled = true;
+                user.save(&conn).await
+            }
+            BulkUserAction::Delete => delete_user_account(user, &token.ip, &conn).await,
+            BulkUserAction::Remove2fa => remove_user_2fa(user, &token.ip, &conn).await,
+            BulkUserAction::ResendInvite => resend_invite_mail(&user, &conn).await,
+        };
+        if let Err(e) = res {
+            failed.push(json!({
+                "email": email,
+                "error": e.message(),
+            }));
+        }
+    }
+
+    Ok(Json(json!({
+        "action": data.action,
+        "total": total,
+        "succeeded": total - failed.len(),
+        "failed": failed,
+    })))
+}
+
+fn parse_timestamp(timestamp: &str) -> ApiResult<NaiveDateTime> {
+    match DateTime::parse_from_rfc3339(timestamp) {
+        Ok(dt) => Ok(dt.naive_utc()),
+        Err(e) => err!(format!("Invalid timestamp '{timestamp}': {e}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct RestoreVaultData {
+    timestamp: String,
+}
+
+impl RestoreVaultData {
+    fn restore_point(&self) -> ApiResult<NaiveDateTime> {
+        parse_timestamp(&self.timestamp)
+    }
+}
+
+#[post("/users/<user_id>/restore-vault", format = "application/json", data = "<data>")]
+async fn restore_user_vault(
+    user_id: UserId,
+    data: Json<RestoreVaultData>,
+    _token: AdminToken,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let user = get_user_or_404(&user_id, &conn).await?;
+    let dt = data.restore_point()?;
+    restore_vault(VaultOwner::User(&user.uuid), dt, &conn, &nt).await
+}
+
+#[post("/organizations/<org_id>/restore-vault", format = "application/json", data = "<data>")]
+async fn restore_organization_vault(
+    org_id: OrganizationId,
+    data: Json<RestoreVaultData>,
+    _token: AdminToken,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let org = Organization::find_by_uuid(&org_id, &conn).await.map_res("Organization doesn't exist")?;
+    let dt = data.restore_point()?;
+    restore_vault(VaultOwner::Organization(&org.uuid), dt, &conn, &nt).await
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdminNotificationData {
+    user_id: Option<UserId>,
+    organization_id: Option<OrganizationId>,
+    #[serde(flatten)]
+    notification: NotificationData,
+}
+
+/// Shows a notification to a single user, all confirmed members of an organization, or all users if neither is set.
+#[post("/notifications", format = "application/json", data = "<data>")]
+async fn post_notification(
+    data: Json<AdminNotificationData>,
+    _token: AdminToken,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let data = data.into_inner();
+    if data.user_id.is_some() && data.organization_id.is_some() {
+        err!("A notification can't be sent to both a user and an organization")
     }
+    if let Some(user_id) = &data.user_id {
+        get_user_or_404(user_id, &conn).await?;
+    }
+    if let Some(org_id) = &data.organization_id
+        && Organization::find_by_uuid(org_id, &conn).await.is_none()
+    {
+        err_code!("Organization doesn't exist", Status::NotFound.code)
+    }
+
+    let notification = data.notification.into_notification(data.user_id, data.organization_id)?;
+    send_notification(&notification, &conn, &nt).await?;
+
+    Ok(Json(notification.to_json(None)))
 }
 
 #[derive(Debug, Deserialize)]
@@ -600,6 +865,11 @@ async fn organizations_overview(_token: AdminToken, conn: DbConn) -> ApiResult<H
         org["collection_count"] = json!(Collection::count_by_org(&o.uuid, &conn).await);
         org["group_count"] = json!(Group::count_by_org(&o.uuid, &conn).await);
         org["event_count"] = json!(Event::count_by_org(&o.uuid, &conn).await);
+        org["trash_retention"] = json!(match o.trash_retention_days {
+            None => "Default".to_owned(),
+            Some(0) => "Deleted at the next purge".to_owned(),
+            Some(days) => format!("{days} days"),
+        });
         org["attachment_count"] = json!(Attachment::count_by_org(&o.uuid, &conn).await);
         org["attachment_size"] = json!(get_display_size(Attachment::size_by_org(&o.uuid, &conn).await));
         organizations_json.push(org);
@@ -615,6 +885,136 @@ async fn delete_organization(org_id: OrganizationId, _token: AdminToken, conn: D
     org.delete(&conn).await
 }
 
+#[get("/mail-queue/overview")]
+async fn mail_queue_overview(_token: AdminToken, conn: DbConn) -> ApiResult<Html<String>> {
+    let max_attempts = i32::try_from(CONFIG.mail_queue_max_attempts()).unwrap_or(i32::MAX);
+    let mails_json: Vec<Value> = QueuedMail::get_all(&conn).await.iter().map(|m| m.to_json(max_attempts)).collect();
+
+    let page_data = json!({
+        "enabled": CONFIG.mail_queue_enabled(),
+        "mails": mails_json,
+    });
+    let text = AdminTemplateData::new("admin/mail_queue", page_data).render()?;
+    Ok(Html(text))
+}
+
+#[post("/mail-queue/<mail_id>/retry", format = "application/json")]
+async fn retry_queued_mail(mail_id: MailId, _token: AdminToken, conn: DbConn) -> EmptyResult {
+    mail::retry_queued_email(&mail_id, &conn).await
+}
+
+#[post("/mail-queue/<mail_id>/delete", format = "application/json")]
+async fn delete_queued_mail(mail_id: MailId, _token: AdminToken, conn: DbConn) -> EmptyResult {
+    let mail = QueuedMail::find_by_uuid(&mail_id, &conn).await.map_res("Queued mail doesn't exist")?;
+    mail.delete(&conn).await
+}
+
+#[get("/icons/overview")]
+async fn icons_overview(_token: AdminToken, conn: DbConn) -> ApiResult<Html<String>> {
+    let domains_json: Vec<Value> = IconDomain::get_all(&conn).await.iter().map(IconDomain::to_json).collect();
+    let negcached_json: Vec<Value> = icons::list_negcached_icons()
+        .await?
+        .into_iter()
+        .map(|(domain, failed_at)| {
+            json!({
+                "domain": domain,
+                "failed_at": failed_at.map(|dt| format_naive_datetime_local(&dt, "%Y-%m-%d %H:%M:%S %Z")),
+            })
+        })
+        .collect();
+
+    let custom_json: Vec<Value> = icons::list_custom_icons()
+        .await?
+        .into_iter()
+        .map(|(domain, icon, icon_type)| {
+            json!({
+                "domain": domain,
+                "icon": format!("data:image/{icon_type};base64,{}", data_encoding::BASE64.encode(&icon)),
+            })
+        })
+        .collect();
+
+    let page_data = json!({
+        "internal": CONFIG.icon_service() == "internal",
+        "download_disabled": CONFIG.disable_icon_download(),
+        "domains": domains_json,
+        "negcached": negcached_json,
+        "custom": custom_json,
+    });
+    let text = AdminTemplateData::new("admin/icons", page_data).render()?;
+    Ok(Html(text))
+}
+
+#[derive(Deserialize)]
+struct IconDomainsData {
+    domains: String,
+}
+
+#[post("/icons/domains", format = "application/json", data = "<data>")]
+async fn add_icon_domains(data: Json<IconDomainsData>, _token: AdminToken, conn: DbConn) -> EmptyResult {
+    let mut domains = Vec::new();
+    for entry in data.into_inner().domains.split(|c: char| c.is_whitespace() || c == ',') {
+        if entry.is_empty() {
+            continue;
+        }
+        // Also accept URLs, and only keep their host
+        let host = url::Url::parse(entry).ok().and_then(|url| url.host_str().map(str::to_owned));
+        let Ok(host) = get_valid_host(host.as_deref().unwrap_or(entry)) else {
+            err!(format!("Invalid domain: {entry}"))
+        };
+        domains.push(IconDomain::new(host.to_string()));
+    }
+    IconDomain::add_all(domains, &conn).await
+}
+
+#[post("/icons/domains/<domain>/delete", format = "application/json")]
+async fn delete_icon_domain(domain: &str, _token: AdminToken, conn: DbConn) -> EmptyResult {
+    IconDomain::delete(domain, &conn).await
+}
+
+#[post("/icons/refresh", format = "application/json")]
+async fn refresh_icons(_token: AdminToken, conn: DbConn) -> EmptyResult {
+    if CONFIG.icon_service() != "internal" {
+        err!("Icons are only cached by the internal icon service")
+    }
+    icons::refresh_icons(true, &conn).await.map(|_| ())
+}
+
+#[post("/icons/<domain>/retry", format = "application/json")]
+async fn retry_icon(domain: &str, _token: AdminToken, conn: DbConn) -> EmptyResult {
+    icons::retry_icon(domain, &conn).await
+}
+
+#[post("/icons/<domain>/purge", format = "application/json")]
+async fn purge_negcached_icon(domain: &str, _token: AdminToken) -> EmptyResult {
+    icons::purge_negcached_icon(domain).await
+}
+
+#[derive(Deserialize)]
+struct CustomIconData {
+    domain: String,
+    /// Base64 encoded image
+    icon: String,
+}
+
+#[post("/icons/custom", format = "application/json", data = "<data>")]
+async fn save_custom_icon(data: Json<CustomIconData>, _token: AdminToken) -> EmptyResult {
+    let data = data.into_inner();
+    let Ok(icon) = data_encoding::BASE64.decode(data.icon.as_bytes()) else {
+        err!("Invalid icon data")
+    };
+    // Same limit as for downloaded icons
+    if icon.len() > 5120 * 1024 {
+        err!("The icon can't be larger than 5MB")
+    }
+    icons::save_custom_icon(&data.domain, icon).await
+}
+
+#[post("/icons/custom/<domain>/delete", format = "application/json")]
+async fn delete_custom_icon(domain: &str, _token: AdminToken) -> EmptyResult {
+    icons::delete_custom_icon(domain).await
+}
+
 #[derive(Deserialize)]
 struct GitRelease {
     tag_name: String,
@@ -686,7 +1086,7 @@ async fn get_ntp_time(has_http_access: bool) -> String {
                 && key == "ts"
             {
                 let ts = value.split_once('.').map_or(value, |(s, _)| s);
-                if let Ok(dt) = chrono::DateTime::parse_from_str(ts, "%s") {
+                if let Ok(dt) = DateTime::parse_from_str(ts, "%s") {
                     return dt.format("%Y-%m-%d %H:%M:%S UTC").to_string();
                 }
                 break;
@@ -802,6 +1202,9 @@ async fn diagnostics(_token: AdminToken, ip_header: IpHeader, conn: DbConn) -> A
         "overrides": &CONFIG.get_overrides().join(", "),
         "template_overrides": check_template_overrides().join(", "),
         "invalid_feature_flags": invalid_feature_flags,
+        "attachment_integrity": crate::storage::integrity::last_report_json(&conn).await,
+        "sync_cache": crate::api::sync_cache_stats(),
+        "job_runs": JobRun::find_recent(20, &conn).await.iter().map(JobRun::to_json).collect::<Vec<Value>>(),
         "host_arch": env::consts::ARCH,
         "host_os":  env::consts::OS,
         "tz_env": env::var("TZ").unwrap_or_default(),
@@ -924,4 +1327,79 @@ mod tests {
         assert!(web_vault_compare("2025.12.2+build.1", "2025.12.1+build.1") == 1);
         assert!(web_vault_compare("2025.12.1+build.3", "2025.12.1+build.2") == 1);
     }
+
+    #[test]
+    fn bulk_user_filter_matches_all_set_filters() {
+        let now = chrono::Utc::now().naive_utc();
+        let candidate = |last_active: Option<NaiveDateTime>, two_factor: bool| BulkUserCandidate {
+            user: User::new("user@example.com", None),
+            last_active,
+            two_factor,
+            sso: false,
+        };
+        let filter = BulkUserFilter {
+            no_two_factor: true,
+            ..Default::default()
+        };
+        let before = now + chrono::TimeDelta::days(1);
+
+        assert!(filter.matches(&candidate(None, false), None));
+        assert!(!filter.matches(&candidate(None, true), None));
+        // Users which never logged in count as active since their creation
+        assert!(filter.matches(&candidate(None, false), Some(&before)));
+        assert!(!filter.matches(&candidate(Some(before), false), Some(&before)));
+
+        let filter = BulkUserFilter {
+            never_logged_in: true,
+            sso_only: true,
+            ..Default::default()
+        };
+        assert!(!filter.matches(&candidate(None, false), None));
+        assert!(!filter.matches(&candidate(Some(now), false), None));
+    }
+
+    #[test]
+    fn bulk_user_filter_matches_each_filter() {
+        let now = chrono::Utc::now().naive_utc();
+        let candidate = |last_active: Option<NaiveDateTime>, verified: bool, sso: bool| {
+            let mut user = User::new("user@example.com", None);
+            user.verified_at = verified.then_some(now);
+            BulkUserCandidate {
+                user,
+                last_active,
+                two_factor: false,
+                sso,
+            }
+        };
+
+        let filter = BulkUserFilter {
+            never_logged_in: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&candidate(None, true, false), None));
+        assert!(!filter.matches(&candidate(Some(now), true, false), None));
+
+        let filter = BulkUserFilter {
+            sso_only: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&candidate(Some(now), true, true), None));
+        assert!(!filter.matches(&candidate(Some(now), true, false), None));
+
+        let filter = BulkUserFilter {
+            unverified_email: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&candidate(Some(now), false, false), None));
+        assert!(!filter.matches(&candidate(Some(now), true, false), None));
+
+        let filter = BulkUserFilter {
+            unverified_email: true,
+            never_logged_in: true,
+            sso_only: true,
+            ..Default::default()
+        };
+        assert!(filter.matches(&candidate(None, false, true), None));
+        assert!(!filter.matches(&candidate(None, true, true), None));
+    }
 }
@@ -13,16 +13,17 @@ use crate::{
     api::{
         AnonymousNotify, ApiResult, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType,
         core::{accept_org_invite, log_user_event, two_factor::email},
-        master_password_policy, register_push_device, unregister_push_device,
+        master_password_policy, register_push_device, unregister_push_device, validate_push_token,
     },
     auth::{ClientHeaders, ClientIp, Headers, decode_delete, decode_invite, decode_verify_email},
     crypto,
     db::{
         DbConn, DbPool,
         models::{
-            AuthRequest, AuthRequestId, Cipher, CipherId, Device, DeviceId, DeviceType, DeviceWithAuthRequest,
-            EmergencyAccess, EmergencyAccessId, EventType, Folder, FolderId, Invitation, Membership, MembershipId,
-            OrgPolicy, OrgPolicyType, Organization, OrganizationId, Send, SendId, User, UserId, UserKdfType,
+            AuthRequest, AuthRequestId, Cipher, CipherId, CipherRevision, DeletedItem, Device, DeviceId, DeviceType,
+            DeviceWithAuthRequest, EmergencyAccess, EmergencyAccessId, EventType, Folder, FolderId, Invitation,
+            Membership, MembershipId, OrgPolicy, OrgPolicyType, Organization, OrganizationId, Send, SendId, User,
+            UserId, UserKdfType, UserLanguage,
         },
     },
     mail,
@@ -407,11 +408,11 @@ pub async fn register(data: Json<RegisterData>, email_verification: bool, conn:
 
     if CONFIG.mail_enabled() {
         if CONFIG.signups_verify() && !email_verified {
-            if let Err(e) = mail::send_welcome_must_verify(&user.email, &user.uuid).await {
+            if let Err(e) = mail::send_welcome_must_verify(&user.email, &user.uuid, &conn).await {
                 error!("Error sending welcome email: {e:#?}");
             }
             user.last_verifying_at = Some(user.created_at);
-        } else if let Err(e) = mail::send_welcome(&user.email).await {
+        } else if let Err(e) = mail::send_welcome(&user.email, &conn).await {
             error!("Error sending welcome email: {e:#?}");
         }
 
@@ -482,7 +483,7 @@ async fn post_set_password(data: Json<SetPasswordData>, headers: Headers, conn:
     }
 
     if CONFIG.mail_enabled() {
-        mail::send_welcome(&user.email.to_lowercase()).await?;
+        mail::send_welcome(&user.email.to_lowercase(), &conn).await?;
     } else {
         Membership::accept_user_invitations(&user.uuid, &conn).await?;
     }
@@ -506,7 +507,7 @@ async fn profile(headers: Headers, conn: DbConn) -> Json<Value> {
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ProfileData {
-    // culture: String, // Ignored, always use en-US
+    culture: Option<String>,
     name: String,
 }
 
@@ -528,6 +529,14 @@ async fn post_profile(data: Json<ProfileData>, headers: Headers, conn: DbConn) -
     let mut user = headers.user;
     user.name = data.name;
 
+    // Clients which don't let the user choose still send the deprecated default `en-US`
+    if let Some(culture) = data.culture
+        && culture != "en-US"
+        && crate::util::is_valid_language_tag(&culture)
+    {
+        UserLanguage::new(user.uuid.clone(), culture.replace('_', "-")).save(&conn).await?;
+    }
+
     user.save(&conn).await?;
     Ok(Json(user.to_json(&conn).await))
 }
@@ -976,8 +985,12 @@ async fn post_rotatekey(data: Json<KeyData>, headers: Headers, conn: DbConn, nt:
             // The user sessions are invalidated because all the ciphers were re-encrypted and thus triggering an update could cause issues.
             // We force the users to logout after the user has been saved to try and prevent these issues.
             update_cipher_from_data(saved_cipher, cipher_data, &headers, None, &conn, &nt, UpdateType::None).await?;
+            // Previous versions are still encrypted with the old key and can't be decrypted anymore
+            CipherRevision::delete_all_by_cipher(&saved_cipher.uuid, &conn).await?;
         }
     }
+    // The same goes for the kept deleted items
+    DeletedItem::delete_all_by_user(user_id, &conn).await?;
 
     // Update user data
     let mut user = headers.user;
@@ -1045,12 +1058,12 @@ async fn post_email_token(data: Json<EmailTokenData>, headers: Headers, conn: Db
             if existing_user.password_hash.is_empty() {
                 // inform an invited user about how to delete their temporary account if the
                 // request was done intentionally and they want to update their mail address
-                if let Err(e) = mail::send_change_email_invited(&data.new_email, &user.email).await {
+                if let Err(e) = mail::send_change_email_invited(&data.new_email, &user.email, &conn).await {
                     error!("Error sending change-email-invited email: {e:#?}");
                 }
             } else {
                 // inform existing user about the failed attempt to change their mail address
-                if let Err(e) = mail::send_change_email_existing(&data.new_email, &user.email).await {
+                if let Err(e) = mail::send_change_email_existing(&data.new_email, &user.email, &conn).await {
                     error!("Error sending change-email-existing email: {e:#?}");
                 }
             }
@@ -1065,7 +1078,7 @@ async fn post_email_token(data: Json<EmailTokenData>, headers: Headers, conn: Db
     let token = crypto::generate_email_token(6);
 
     if CONFIG.mail_enabled() {
-        if let Err(e) = mail::send_change_email(&data.new_email, &token).await {
+        if let Err(e) = mail::send_change_email(&data.new_email, &token, &conn).await {
             error!("Error sending change-email email: {e:#?}");
         }
     } else {
@@ -1141,14 +1154,14 @@ async fn post_email(data: Json<ChangeEmailData>, headers: Headers, conn: DbConn,
 }
 
 #[post("/accounts/verify-email")]
-async fn post_verify_email(headers: Headers) -> EmptyResult {
+async fn post_verify_email(headers: Headers, conn: DbConn) -> EmptyResult {
     let user = headers.user;
 
     if !CONFIG.mail_enabled() {
         err!("Cannot verify email address");
     }
 
-    if let Err(e) = mail::send_verify_email(&user.email, &user.uuid).await {
+    if let Err(e) = mail::send_verify_email(&user.email, &user.uuid, &conn).await {
         error!("Error sending verify_email email: {e:#?}");
     }
 
@@ -1194,13 +1207,13 @@ struct DeleteRecoverData {
 
 #[post("/accounts/delete-recover", data = "<data>")]
 async fn post_delete_recover(data: Json<DeleteRecoverData>, ip: ClientIp, conn: DbConn) -> EmptyResult {
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     let data: DeleteRecoverData = data.into_inner();
 
     if CONFIG.mail_enabled() {
         if let Some(user) = User::find_by_mail(&data.email, &conn).await
-            && let Err(e) = mail::send_delete_account(&user.email, &user.uuid).await
+            && let Err(e) = mail::send_delete_account(&user.email, &user.uuid, &conn).await
         {
             error!("Error sending delete account email: {e:#?}");
         }
@@ -1271,7 +1284,7 @@ struct PasswordHintData {
 async fn password_hint(data: Json<PasswordHintData>, ip: ClientIp, conn: DbConn) -> EmptyResult {
     const NO_HINT: &str = "Sorry, you have no password hint...";
 
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     if !CONFIG.password_hints_allowed() || (!CONFIG.mail_enabled() && !CONFIG.show_password_hint()) {
         err!("This server is not configured to provide password hints.");
@@ -1299,7 +1312,7 @@ async fn password_hint(data: Json<PasswordHintData>, ip: ClientIp, conn: DbConn)
         Some(user) => {
             let hint: Option<String> = user.password_hint;
             if CONFIG.mail_enabled() {
-                mail::send_password_hint(email, hint).await?;
+                mail::send_password_hint(email, hint, &conn).await?;
                 Ok(())
             } else if let Some(hint) = hint {
                 err!(format!("Your password hint is: {hint}"));
@@ -1506,6 +1519,7 @@ async fn put_device_token(device_id: DeviceId, data: Json<PushToken>, headers: H
         return Ok(());
     }
 
+    validate_push_token(&token)?;
     device.push_token = Some(token);
     if let Err(e) = device.save(true, &conn).await {
         err!(format!("An error occurred while trying to save the device push token: {e}"));
@@ -1518,7 +1532,7 @@ async fn put_device_token(device_id: DeviceId, data: Json<PushToken>, headers: H
 
 #[put("/devices/identifier/<device_id>/clear-token")]
 async fn put_clear_device_token(device_id: DeviceId, ip: ClientIp, conn: DbConn) -> EmptyResult {
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     // This only clears push token
     // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Controllers/DevicesController.cs#L215
@@ -1798,11 +1812,12 @@ async fn get_auth_requests_pending(headers: Headers, conn: DbConn) -> JsonResult
     })))
 }
 
-pub async fn purge_auth_requests(pool: DbPool) {
+pub async fn purge_auth_requests(pool: DbPool) -> usize {
     debug!("Purging auth requests");
     if let Ok(conn) = pool.get().await {
-        AuthRequest::purge_expired_auth_requests(&conn).await;
+        AuthRequest::purge_expired_auth_requests(&conn).await
     } else {
         error!("Failed to get DB connection while purging auth requests");
+        0
     }
 }
@@ -1,18 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
 
-use chrono::{NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 use num_traits::ToPrimitive;
 use rocket::{
-    Route,
+    Data, Route,
     form::{Form, FromForm},
     fs::TempFile,
+    response::content::RawJson,
     serde::json::Json,
 };
 use serde_json::Value;
 
 use crate::{
     CONFIG,
-    api::{self, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType, core::log_event},
+    api::{
+        self, ApiResult, EmptyResult, JsonResult, Notify, PasswordOrOtpData, UpdateType, WS_USERS,
+        core::{
+            log_event, log_personal_cipher_event,
+            uploads::{ChunkedUpload, FileUploadType, UploadOffset, UploadStatus},
+        },
+        notifications::WebSocketUsers,
+    },
     auth::ClientVersion,
     auth::{Headers, OrgIdGuard, OwnerHeaders},
     config::PathType,
@@ -20,12 +36,14 @@ use crate::{
     db::{
         DbConn, DbPool,
         models::{
-            Archive, Attachment, AttachmentId, Cipher, CipherId, Collection, CollectionCipher, CollectionGroup,
-            CollectionId, CollectionUser, EventType, Favorite, Folder, FolderCipher, FolderId, Group, Membership,
-            MembershipType, OrgPolicy, OrgPolicyType, OrganizationId, RepromptType, Send, UserId,
+            Archive, Attachment, AttachmentId, Cipher, CipherId, CipherRevision, CipherRevisionId, Collection,
+            CollectionCipher, CollectionGroup, CollectionId, CollectionUser, DeletedItem, Device, EventType, Favorite,
+            Folder, FolderCipher, FolderId, Group, Membership, MembershipType, OrgPolicy, OrgPolicyType, Organization,
+            OrganizationId, PendingUpload, PendingUploadType, RepromptType, Send, Tombstone, TombstoneType, User,
+            UserId, VersionAt,
         },
     },
-    util::{NumberOrString, deser_opt_nonempty_str, save_temp_file},
+    util::{NumberOrString, deser_opt_nonempty_str, format_date, save_temp_file},
 };
 
 use super::folders::FolderData;
@@ -47,6 +65,8 @@ pub fn routes() -> Vec<Route> {
         get_cipher,
         get_cipher_admin,
         get_cipher_details,
+        get_cipher_revisions,
+        post_cipher_revision_restore,
         post_ciphers,
         put_cipher_admin,
         post_ciphers_admin,
@@ -55,6 +75,10 @@ pub fn routes() -> Vec<Route> {
         get_attachment,
         post_attachment_v2,
         post_attachment_v2_data,
+        head_attachment_upload,
+        patch_attachment_upload,
+        renew_attachment_upload_url,
+        post_attachment_validate,
         post_attachment,       // legacy
         post_attachment_admin, // legacy
         post_attachment_share,
@@ -103,12 +127,18 @@ pub fn routes() -> Vec<Route> {
     ]
 }
 
-pub async fn purge_trashed_ciphers(pool: DbPool) {
+pub async fn purge_trashed_ciphers(pool: DbPool) -> usize {
     debug!("Purging trashed ciphers");
     if let Ok(conn) = pool.get().await {
-        Cipher::purge_trash(&conn).await;
+        // Also forget old deletions, clients which didn't sync since then need a full sync anyway
+        Tombstone::purge(&conn).await;
+        // Also remove the item history which is past its retention
+        CipherRevision::purge(&conn).await;
+        DeletedItem::purge(&conn).await;
+        Cipher::purge_trash(&conn).await
     } else {
         error!("Failed to get DB connection while purging trashed ciphers");
+        0
     }
 }
 
@@ -116,14 +146,93 @@ pub async fn purge_trashed_ciphers(pool: DbPool) {
 struct SyncData {
     #[field(name = "excludeDomains")]
     exclude_domains: bool, // Default: 'false'
+    /// Only return what changed since this `revisionDate` of a previous sync
+    since: Option<String>,
 }
 
-#[get("/sync?<data..>")]
-async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVersion>, conn: DbConn) -> JsonResult {
-    let user_json = headers.user.to_json(&conn).await;
+/// A full sync response, which is reused as long as nothing changed for the user, see `SYNC_CACHE_TTL`.
+struct CachedSync {
+    key: SyncCacheKey,
+    body: String,
+}
+
+#[derive(PartialEq, Eq)]
+struct SyncCacheKey {
+    revision_date: NaiveDateTime,
+    host: String,
+    exclude_domains: bool,
+    show_ssh_keys: bool,
+}
+
+static SYNC_CACHE: LazyLock<moka::sync::Cache<UserId, Arc<CachedSync>>> = LazyLock::new(|| {
+    moka::sync::Cache::builder()
+        .max_capacity(CONFIG.sync_cache_size_mb() * 1024 * 1024)
+        .weigher(|_, cached: &Arc<CachedSync>| u32::try_from(cached.body.len()).unwrap_or(u32::MAX))
+        .time_to_live(Duration::from_secs(CONFIG.sync_cache_ttl()))
+        .build()
+});
+static SYNC_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static SYNC_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+
+/// Called whenever the revision of the user changes.
+pub fn invalidate_sync_cache(user_id: &UserId) {
+    if CONFIG.sync_cache_ttl() > 0 {
+        SYNC_CACHE.invalidate(user_id);
+    }
+}
+
+/// Called when the config changes, as the sync also contains global settings like the equivalent domains.
+pub fn clear_sync_cache() {
+    if CONFIG.sync_cache_ttl() > 0 {
+        SYNC_CACHE.invalidate_all();
+    }
+}
+
+pub fn sync_cache_stats() -> Value {
+    let hits = SYNC_CACHE_HITS.load(Ordering::Relaxed);
+    let misses = SYNC_CACHE_MISSES.load(Ordering::Relaxed);
+    // The counts are only updated by the pending maintenance
+    SYNC_CACHE.run_pending_tasks();
+    json!({
+        "enabled": CONFIG.sync_cache_ttl() > 0,
+        "hits": hits,
+        "misses": misses,
+        "hit_ratio": hits.saturating_mul(100).checked_div(hits + misses).map_or_else(|| "-".to_owned(), |r| format!("{r}%")),
+        "entries": SYNC_CACHE.entry_count(),
+        "size": crate::util::get_display_size(i64::try_from(SYNC_CACHE.weighted_size()).unwrap_or(i64::MAX)),
+    })
+}
 
-    // Get all ciphers which are visible by the user
-    let mut ciphers = Cipher::find_by_user_visible(&headers.user.uuid, &conn).await;
+/// With `since`, only the ciphers, folders and sends which changed since then are returned, and the uuids
+/// of the deleted objects in `deleted`. Unless nothing changed at all, all collections are returned, so clients
+/// can also remove the ones they lost access to. Without `since`, or when it is too old, this is a full sync.
+#[get("/sync?<data..>")]
+async fn sync(
+    data: SyncData,
+    headers: Headers,
+    client_version: Option<ClientVersion>,
+    conn: DbConn,
+) -> ApiResult<RawJson<String>> {
+    // Taken before loading anything, so changes made during this sync are part of the next one
+    let revision_date = Utc::now().naive_utc();
+
+    // A delta sync is only possible as long as the tombstones of the deletions are kept.
+    // It overlaps a bit with the previous sync, to include changes which were being saved while that one ran.
+    let since = match data.since.as_deref().map(|since| NaiveDateTime::parse_from_str(since, "%+")) {
+        None => None,
+        Some(Ok(since)) => {
+            Some(since - TimeDelta::seconds(30)).filter(|since| *since > revision_date - Tombstone::RETENTION)
+        }
+        Some(Err(_)) => err!("Invalid since date"),
+    };
+    // When the access of the user changed, the ciphers they lost or got access to can only be told by a full sync
+    let since = match since {
+        Some(since) if Tombstone::access_changed_since(&headers.user.uuid, &since, &conn).await => None,
+        since => since,
+    };
+    // Every change to the vault of the user also updates the revision of the user
+    let unchanged = since.is_some_and(|since| headers.user.updated_at < since);
+    let changed_since = |date: &NaiveDateTime| since.is_none_or(|since| *date >= since);
 
     // Filter out SSH keys if the client version is less than 2024.12.0
     let show_ssh_keys = if let Some(client_version) = client_version {
@@ -132,31 +241,92 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
     } else {
         false
     };
-    if !show_ssh_keys {
-        ciphers.retain(|c| c.atype != 5);
+
+    // Only full syncs are cached, delta syncs are cheap already
+    let cache_key = (since.is_none() && CONFIG.sync_cache_ttl() > 0).then(|| SyncCacheKey {
+        revision_date: headers.user.updated_at,
+        host: headers.host.clone(),
+        exclude_domains: data.exclude_domains,
+        show_ssh_keys,
+    });
+    if let Some(key) = &cache_key {
+        if let Some(cached) = SYNC_CACHE.get(&headers.user.uuid).filter(|cached| cached.key == *key) {
+            SYNC_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(RawJson(cached.body.clone()));
+        }
+        SYNC_CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
     }
 
-    let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::User, &conn).await;
+    let user_json = headers.user.to_json(&conn).await;
 
-    // Lets generate the ciphers_json using all the gathered info
-    let mut ciphers_json = Vec::with_capacity(ciphers.len());
-    for c in ciphers {
-        ciphers_json.push(
-            c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::User, &conn).await?,
-        );
-    }
+    let mut visible_cipher_uuids = HashSet::new();
+    let (ciphers_json, collections_json, folders_json, sends_json) = if unchanged {
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new())
+    } else {
+        // Get all ciphers which are visible by the user
+        let mut ciphers = Cipher::find_by_user_visible(&headers.user.uuid, &conn).await;
+        visible_cipher_uuids = ciphers.iter().map(|c| c.uuid.to_string()).collect();
+        ciphers.retain(|c| (show_ssh_keys || c.atype != 5) && changed_since(&c.updated_at));
+
+        let cipher_sync_data = CipherSyncData::new(&headers.user.uuid, CipherSyncType::User, &conn).await;
+
+        // Lets generate the ciphers_json using all the gathered info
+        let mut ciphers_json = Vec::with_capacity(ciphers.len());
+        for c in ciphers {
+            ciphers_json.push(
+                c.to_json(&headers.host, &headers.user.uuid, Some(&cipher_sync_data), CipherSyncType::User, &conn)
+                    .await?,
+            );
+        }
 
-    let collections = Collection::find_by_user_uuid(headers.user.uuid.clone(), &conn).await;
-    let mut collections_json = Vec::with_capacity(collections.len());
-    for c in collections {
-        collections_json.push(c.to_json_details(&headers.user.uuid, Some(&cipher_sync_data), &conn).await);
-    }
+        let collections = Collection::find_by_user_uuid(headers.user.uuid.clone(), &conn).await;
+        let mut collections_json = Vec::with_capacity(collections.len());
+        for c in collections {
+            collections_json.push(c.to_json_details(&headers.user.uuid, Some(&cipher_sync_data), &conn).await);
+        }
+
+        let folders_json: Vec<Value> = Folder::find_by_user(&headers.user.uuid, &conn)
+            .await
+            .iter()
+            .filter(|f| changed_since(&f.updated_at))
+            .map(Folder::to_json)
+            .collect();
+
+        let sends_json: Vec<Value> = Send::find_by_user(&headers.user.uuid, &conn)
+            .await
+            .iter()
+            .filter(|s| changed_since(&s.revision_date))
+            .map(Send::to_json)
+            .collect();
 
-    let folders_json: Vec<Value> =
-        Folder::find_by_user(&headers.user.uuid, &conn).await.iter().map(Folder::to_json).collect();
+        (ciphers_json, collections_json, folders_json, sends_json)
+    };
 
-    let sends_json: Vec<Value> =
-        Send::find_by_user(&headers.user.uuid, &conn).await.iter().map(Send::to_json).collect();
+    let deleted_json = if let Some(since) = since {
+        let mut deleted_json = json!({});
+        for (key, object_type) in [
+            ("ciphers", TombstoneType::Cipher),
+            ("folders", TombstoneType::Folder),
+            ("collections", TombstoneType::Collection),
+            ("sends", TombstoneType::Send),
+            ("organizations", TombstoneType::Organization),
+        ] {
+            deleted_json[key] = if unchanged {
+                json!([])
+            } else {
+                let mut object_uuids =
+                    Tombstone::find_object_uuids_since(&headers.user.uuid, object_type, &since, &conn).await;
+                // A cipher the user lost access to could be visible again, like when it was added back to a collection
+                if object_type == TombstoneType::Cipher {
+                    object_uuids.retain(|uuid| !visible_cipher_uuids.contains(uuid));
+                }
+                json!(object_uuids)
+            };
+        }
+        deleted_json
+    } else {
+        Value::Null
+    };
 
     let policies_json: Vec<Value> =
         OrgPolicy::find_confirmed_by_user(&headers.user.uuid, &conn).await.iter().map(OrgPolicy::to_json).collect();
@@ -164,7 +334,8 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
     let domains_json = if data.exclude_domains {
         Value::Null
     } else {
-        api::core::get_eq_domains(&headers, true).into_inner()
+        let org_equivalent_domains = Organization::find_equivalent_domains_by_user(&headers.user.uuid, &conn).await;
+        api::core::get_eq_domains(&headers, org_equivalent_domains, true).into_inner()
     };
 
     // This is very similar to the the userDecryptionOptions sent in connect/token,
@@ -188,7 +359,7 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
         Value::Null
     };
 
-    Ok(Json(json!({
+    let body = json!({
         "profile": user_json,
         "folders": folders_json,
         "collections": collections_json,
@@ -199,8 +370,28 @@ async fn sync(data: SyncData, headers: Headers, client_version: Option<ClientVer
         "userDecryption": {
             "masterPasswordUnlock": master_password_unlock,
         },
+        "revisionDate": format_date(&revision_date),
+        "delta": since.is_some(),
+        "deleted": deleted_json,
         "object": "sync"
-    })))
+    })
+    .to_string();
+
+    // Some databases only store the revision in seconds, so a change right after this sync started
+    // could have the same revision. Those responses aren't cached, to never serve a stale one.
+    if let Some(key) = cache_key
+        && revision_date - key.revision_date > TimeDelta::seconds(2)
+    {
+        SYNC_CACHE.insert(
+            headers.user.uuid.clone(),
+            Arc::new(CachedSync {
+                key,
+                body: body.clone(),
+            }),
+        );
+    }
+
+    Ok(RawJson(body))
 }
 
 #[get("/ciphers")]
@@ -246,6 +437,97 @@ async fn get_cipher_details(cipher_id: CipherId, headers: Headers, conn: DbConn)
     get_cipher(cipher_id, headers, conn).await
 }
 
+#[get("/ciphers/<cipher_id>/revisions")]
+async fn get_cipher_revisions(cipher_id: CipherId, headers: Headers, conn: DbConn) -> JsonResult {
+    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+
+    // Previous versions can contain passwords which are hidden from read-only users
+    if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
+        err!("Cipher is not write accessible")
+    }
+
+    let revisions_json: Vec<Value> =
+        CipherRevision::find_by_cipher(&cipher.uuid, &conn).await.iter().map(CipherRevision::to_json).collect();
+
+    Ok(Json(json!({
+        "data": revisions_json,
+        "object": "list",
+        "continuationToken": null
+    })))
+}
+
+#[post("/ciphers/<cipher_id>/revisions/<revision_id>/restore")]
+async fn post_cipher_revision_restore(
+    cipher_id: CipherId,
+    revision_id: CipherRevisionId,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+
+    if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
+        err!("Cipher is not write accessible")
+    }
+
+    let Some(revision) = CipherRevision::find_by_uuid_and_cipher(&revision_id, &cipher.uuid, &conn).await else {
+        err!("Cipher revision doesn't exist")
+    };
+
+    // Keep the current version as well, so restoring can be undone
+    let mut attachments = Attachment::find_by_cipher(&cipher.uuid, &conn).await;
+    let current = CipherRevision::from_cipher(&cipher, &attachments);
+
+    revision.apply_to(&mut cipher, &mut attachments);
+    for attachment in &attachments {
+        attachment.save(&conn).await?;
+    }
+    cipher.save(&conn).await?;
+
+    if current.differs_from(&cipher, &attachments) {
+        current.save(&conn).await?;
+    }
+
+    if let Some(org_id) = &cipher.organization_uuid {
+        log_event(
+            EventType::CipherRevisionRestored as i32,
+            &cipher.uuid,
+            org_id,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+    } else {
+        log_personal_cipher_event(
+            EventType::CipherRevisionRestored as i32,
+            &cipher.uuid,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+    }
+
+    nt.send_cipher_update(
+        UpdateType::SyncCipherUpdate,
+        &cipher,
+        &cipher.update_users_revision(&conn).await,
+        &headers.device,
+        None,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?))
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CipherData {
@@ -478,6 +760,19 @@ pub async fn update_cipher_from_data(
         err!("Invalid folder", "Folder does not exist or belongs to another user");
     }
 
+    // Keep the current version of an updated cipher, so it can be restored later.
+    // Moving a cipher into an organization re-encrypts it, which makes the previous versions useless.
+    // The attachments are kept along, to compare the new version with the previous one without loading them again.
+    let mut revision = if transfer_cipher {
+        CipherRevision::delete_all_by_cipher(&cipher.uuid, conn).await?;
+        None
+    } else if ut == UpdateType::SyncCipherUpdate && CONFIG.cipher_revisions_limit() > 0 {
+        let attachments = Attachment::find_by_cipher(&cipher.uuid, conn).await;
+        Some((CipherRevision::from_cipher(cipher, &attachments), attachments))
+    } else {
+        None
+    };
+
     // Modify attachments name and keys when rotating
     if let Some(attachments) = data.attachments2 {
         for (id, attachment) in attachments {
@@ -499,6 +794,12 @@ pub async fn update_cipher_from_data(
 
             saved_att.akey = Some(attachment.key);
             saved_att.file_name = attachment.file_name;
+            if let Some((_, attachments)) = &mut revision
+                && let Some(current) = attachments.iter_mut().find(|a| a.id == saved_att.id)
+            {
+                current.akey.clone_from(&saved_att.akey);
+                current.file_name.clone_from(&saved_att.file_name);
+            }
 
             saved_att.save(conn).await?;
         }
@@ -534,6 +835,11 @@ pub async fn update_cipher_from_data(
     cipher.reprompt = data.reprompt.filter(|r| *r == RepromptType::None as i32 || *r == RepromptType::Password as i32);
 
     cipher.save(conn).await?;
+    if let Some((revision, attachments)) = revision
+        && revision.differs_from(cipher, &attachments)
+    {
+        revision.save(conn).await?;
+    }
     cipher.move_to_folder(data.folder_id, &headers.user.uuid, conn).await?;
     cipher.set_favorite(data.favorite, &headers.user.uuid, conn).await?;
 
@@ -727,7 +1033,7 @@ async fn put_cipher_partial(
 ) -> JsonResult {
     let data: PartialCipherData = data.into_inner();
 
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
         err!("Cipher does not exist")
     };
 
@@ -804,7 +1110,7 @@ async fn post_collections_update(
 ) -> JsonResult {
     let data: CollectionsAdminData = data.into_inner();
 
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
         err!("Cipher doesn't exist")
     };
 
@@ -812,7 +1118,7 @@ async fn post_collections_update(
         err!("Collection cannot be changed")
     }
 
-    let Some(ref org_uuid) = cipher.organization_uuid else {
+    let Some(org_uuid) = cipher.organization_uuid.clone() else {
         err!("Cipher is not owned by an organization")
     };
 
@@ -821,7 +1127,7 @@ async fn post_collections_update(
         HashSet::<CollectionId>::from_iter(cipher.get_collections(headers.user.uuid.clone(), &conn).await);
 
     for collection in posted_collections.symmetric_difference(&current_collections) {
-        match Collection::find_by_uuid_and_org(collection, org_uuid, &conn).await {
+        match Collection::find_by_uuid_and_org(collection, &org_uuid, &conn).await {
             None => err!("Invalid collection ID provided"),
             Some(collection) => {
                 if collection.is_writable_by_user(&headers.user.uuid, &conn).await {
@@ -838,6 +1144,7 @@ async fn post_collections_update(
             }
         }
     }
+    cipher.touch(&conn).await?;
 
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
@@ -852,7 +1159,7 @@ async fn post_collections_update(
     log_event(
         EventType::CipherUpdatedCollections as i32,
         &cipher.uuid,
-        org_uuid,
+        &org_uuid,
         &headers.user.uuid,
         headers.device.atype,
         &headers.ip.ip,
@@ -884,7 +1191,7 @@ async fn post_collections_admin(
 ) -> JsonResult {
     let data: CollectionsAdminData = data.into_inner();
 
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
         err!("Cipher doesn't exist")
     };
 
@@ -892,7 +1199,7 @@ async fn post_collections_admin(
         err!("Collection cannot be changed")
     }
 
-    let Some(ref org_uuid) = cipher.organization_uuid else {
+    let Some(org_uuid) = cipher.organization_uuid.clone() else {
         err!("Cipher is not owned by an organization")
     };
 
@@ -901,7 +1208,7 @@ async fn post_collections_admin(
         HashSet::<CollectionId>::from_iter(cipher.get_admin_collections(headers.user.uuid.clone(), &conn).await);
 
     for collection in posted_collections.symmetric_difference(&current_collections) {
-        match Collection::find_by_uuid_and_org(collection, org_uuid, &conn).await {
+        match Collection::find_by_uuid_and_org(collection, &org_uuid, &conn).await {
             None => err!("Invalid collection ID provided"),
             Some(collection) => {
                 if collection.is_writable_by_user(&headers.user.uuid, &conn).await {
@@ -918,6 +1225,7 @@ async fn post_collections_admin(
             }
         }
     }
+    cipher.touch(&conn).await?;
 
     nt.send_cipher_update(
         UpdateType::SyncCipherUpdate,
@@ -932,7 +1240,7 @@ async fn post_collections_admin(
     log_event(
         EventType::CipherUpdatedCollections as i32,
         &cipher.uuid,
-        org_uuid,
+        &org_uuid,
         &headers.user.uuid,
         headers.device.atype,
         &headers.ip.ip,
@@ -1107,7 +1415,7 @@ async fn get_attachment(
     }
 
     match Attachment::find_by_id(&attachment_id, &conn).await {
-        Some(attachment) if cipher_id == attachment.cipher_uuid => Ok(Json(attachment.to_json(&headers.host).await?)),
+        Some(attachment) if cipher_id == attachment.cipher_uuid => Ok(Json(attachment.to_json(&headers.host))),
         Some(_) => err!("Attachment doesn't belong to cipher"),
         None => err!("Attachment doesn't exist"),
     }
@@ -1122,15 +1430,23 @@ struct AttachmentRequestData {
     admin_request: Option<bool>, // true when attaching from an org vault view
 }
 
-enum FileUploadType {
-    Direct = 0,
-    // Azure = 1, // only used upstream
+/// Returns the URL the client should upload the content of `attachment` to.
+async fn attachment_upload_url(attachment: &Attachment) -> Result<(String, FileUploadType), crate::error::Error> {
+    let direct_url =
+        crate::storage::direct_upload_url(&PathType::Attachments, &attachment.get_file_path(), attachment.file_size)
+            .await?;
+
+    Ok(match direct_url {
+        Some(url) => (url, FileUploadType::Azure),
+        None => (format!("/ciphers/{}/attachment/{}", attachment.cipher_uuid, attachment.id), FileUploadType::Direct),
+    })
 }
 
 /// v2 API for creating an attachment associated with a cipher.
 /// This redirects the client to the API it should use to upload the attachment.
 /// For upstream's cloud-hosted service, it's an Azure object storage API.
-/// For self-hosted instances, it's another API on the local instance.
+/// For self-hosted instances, it's another API on the local instance,
+/// or a presigned URL of the object storage when `STORAGE_DIRECT_UPLOAD` is enabled.
 #[post("/ciphers/<cipher_id>/attachment/v2", data = "<data>")]
 async fn post_attachment_v2(
     cipher_id: CipherId,
@@ -1155,9 +1471,24 @@ async fn post_attachment_v2(
     let attachment_id = crypto::generate_attachment_id();
     let attachment =
         Attachment::new(attachment_id.clone(), cipher.uuid.clone(), data.file_name, file_size, Some(data.key));
+
+    let (url, upload_type) = attachment_upload_url(&attachment).await?;
+    if matches!(upload_type, FileUploadType::Azure) {
+        // The content doesn't pass through save_attachment(), so the limits have to be checked up front.
+        check_attachment_size_limit(&cipher, file_size, 0, &conn).await?;
+    }
+
     attachment.save(&conn).await.expect("Error saving attachment");
+    PendingUpload::new(
+        attachment_id.to_string(),
+        PendingUploadType::Attachment,
+        &headers.device,
+        headers.ip.ip.to_string(),
+        matches!(upload_type, FileUploadType::Azure),
+    )
+    .save(&conn)
+    .await?;
 
-    let url = format!("/ciphers/{}/attachment/{attachment_id}", cipher.uuid);
     let response_key = match data.admin_request {
         Some(b) if b => "cipherMiniResponse",
         _ => "cipherResponse",
@@ -1167,7 +1498,7 @@ async fn post_attachment_v2(
         "object": "attachment-fileUpload",
         "attachmentId": attachment_id,
         "url": url,
-        "fileUploadType": FileUploadType::Direct as i32,
+        "fileUploadType": upload_type as i32,
         response_key: cipher.to_json(&headers.host, &headers.user.uuid, None, CipherSyncType::User, &conn).await?,
     })))
 }
@@ -1178,51 +1509,14 @@ struct UploadData<'f> {
     data: TempFile<'f>,
 }
 
-/// Saves the data content of an attachment to a file. This is common code
-/// shared between the v2 and legacy attachment APIs.
-///
-/// When used with the legacy API, this function is responsible for creating
-/// the attachment database record, so `attachment` is None.
-///
-/// When used with the v2 API, post_attachment_v2() has already created the
-/// database record, which is passed in as `attachment`.
-async fn save_attachment(
-    mut attachment: Option<Attachment>,
-    cipher_id: CipherId,
-    data: Form<UploadData<'_>>,
-    headers: &Headers,
-    conn: DbConn,
-    nt: Notify<'_>,
-) -> Result<(Cipher, DbConn), crate::error::Error> {
-    let data = data.into_inner();
-
-    let Some(size) = data.data.len().to_i64() else {
-        err!("Attachment data size overflow");
-    };
-    if size < 0 {
-        err!("Attachment size can't be negative")
-    }
-
-    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
-        err!("Cipher doesn't exist")
-    };
-
-    if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
-        err!("Cipher is not write accessible")
-    }
-
-    // In the v2 API, the attachment record has already been created,
-    // so the size limit needs to be adjusted to account for that.
-    let size_adjust = match &attachment {
-        None => 0,              // Legacy API
-        Some(a) => a.file_size, // v2 API
-    };
-
+/// Checks whether storing an attachment of `size` bytes for `cipher` stays within the configured attachment limits.
+/// `size_adjust` is the size of an attachment record which is already accounted for but not stored yet.
+async fn check_attachment_size_limit(cipher: &Cipher, size: i64, size_adjust: i64, conn: &DbConn) -> EmptyResult {
     let size_limit = if let Some(ref user_id) = cipher.user_uuid {
         match CONFIG.user_attachment_limit() {
             Some(0) => err!("Attachments are disabled"),
             Some(limit_kb) => {
-                let already_used = Attachment::size_by_user(user_id, &conn).await;
+                let already_used = Attachment::size_by_user(user_id, conn).await;
                 let left = limit_kb
                     .checked_mul(1024)
                     .and_then(|l| l.checked_sub(already_used))
@@ -1244,7 +1538,7 @@ async fn save_attachment(
         match CONFIG.org_attachment_limit() {
             Some(0) => err!("Attachments are disabled"),
             Some(limit_kb) => {
-                let already_used = Attachment::size_by_org(org_id, &conn).await;
+                let already_used = Attachment::size_by_org(org_id, conn).await;
                 let left = limit_kb
                     .checked_mul(1024)
                     .and_then(|l| l.checked_sub(already_used))
@@ -1272,6 +1566,189 @@ async fn save_attachment(
         err!("Attachment storage limit exceeded with this file");
     }
 
+    Ok(())
+}
+
+/// Checks the actual size of an uploaded attachment against the size provided when creating it through the v2 API.
+/// The attachment is deleted when the sizes don't match.
+async fn check_attachment_upload_size(attachment: &mut Attachment, size: i64, conn: &DbConn) -> EmptyResult {
+    // Check the actual size against the size initially provided by
+    // the client. Upstream allows +/- 1 MiB deviation from this
+    // size, but it's not clear when or why this is needed.
+    const LEEWAY: i64 = 1024 * 1024; // 1 MiB
+    let Some(max_size) = attachment.file_size.checked_add(LEEWAY) else {
+        err!("Invalid attachment size max")
+    };
+    let Some(min_size) = attachment.file_size.checked_sub(LEEWAY) else {
+        err!("Invalid attachment size min")
+    };
+
+    if min_size <= size && size <= max_size {
+        if size != attachment.file_size {
+            // Update the attachment with the actual file size.
+            attachment.file_size = size;
+            attachment.save(conn).await.expect("Error updating attachment");
+        }
+    } else {
+        attachment.delete(conn).await.ok();
+
+        err!(format!("Attachment size mismatch (expected within [{min_size}, {max_size}], got {size})"));
+    }
+
+    Ok(())
+}
+
+/// Notifies the user's devices about a newly stored attachment and logs the event for organization ciphers.
+/// `device` and `ip` are the ones which created the attachment.
+async fn attachment_created(cipher: &Cipher, device: &Device, ip: &IpAddr, conn: &DbConn, nt: &WebSocketUsers) {
+    nt.send_cipher_update(
+        UpdateType::SyncCipherUpdate,
+        cipher,
+        &cipher.update_users_revision(conn).await,
+        device,
+        None,
+        conn,
+    )
+    .await;
+
+    if let Some(org_id) = &cipher.organization_uuid {
+        log_event(
+            EventType::CipherAttachmentCreated as i32,
+            &cipher.uuid,
+            org_id,
+            &device.user_uuid,
+            device.atype,
+            ip,
+            conn,
+        )
+        .await;
+    }
+}
+
+/// Returns the size of the stored content of an attachment, or None when it hasn't been uploaded yet.
+async fn stored_attachment_size(attachment: &Attachment) -> Result<Option<i64>, crate::error::Error> {
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments)?;
+    let size = match operator.stat(&attachment.get_file_path()).await {
+        Ok(metadata) => metadata.content_length(),
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let Some(size) = size.to_i64() else {
+        err!("Attachment data size overflow");
+    };
+    Ok(Some(size))
+}
+
+/// Checks the stored content of an attachment created through the v2 API against the size provided when creating the
+/// attachment and the attachment limits, and stores its checksum. The attachment is deleted if either check fails.
+async fn verify_stored_attachment(
+    cipher: &Cipher,
+    attachment: &mut Attachment,
+    size: i64,
+    conn: &DbConn,
+) -> EmptyResult {
+    PendingUpload::delete(&attachment.id, conn).await?;
+
+    let declared_size = attachment.file_size;
+    check_attachment_upload_size(attachment, size, conn).await?;
+    if let Err(e) = check_attachment_size_limit(cipher, size, declared_size, conn).await {
+        attachment.delete(conn).await.ok();
+        return Err(e);
+    }
+
+    attachment.checksum = Some(attachment.compute_checksum().await?);
+    attachment.save(conn).await
+}
+
+/// Completes an attachment created through the v2 API whose content was uploaded through a presigned URL,
+/// or deletes it when its content wasn't received before the upload expired.
+/// Returns whether the pending upload was resolved.
+pub async fn check_pending_attachment(pending: &PendingUpload, conn: &DbConn) -> Result<bool, crate::error::Error> {
+    let attachment = Attachment::find_by_id(&AttachmentId(pending.uuid.clone()), conn).await;
+    let cipher = match &attachment {
+        Some(attachment) => Cipher::find_by_uuid(&attachment.cipher_uuid, conn).await,
+        None => None,
+    };
+    let (Some(mut attachment), Some(cipher)) = (attachment, cipher) else {
+        PendingUpload::delete(&pending.uuid, conn).await?;
+        return Ok(true);
+    };
+
+    // The server completes the uploads it receives itself, unless it stopped while doing so
+    if !pending.presigned && !pending.is_expired() {
+        return Ok(false);
+    }
+
+    match stored_attachment_size(&attachment).await? {
+        Some(size) => {
+            if let Err(e) = verify_stored_attachment(&cipher, &mut attachment, size, conn).await {
+                info!("Deleted attachment {} of cipher {}: {e}", attachment.id, cipher.uuid);
+            } else if let Some(device) =
+                Device::find_by_uuid_and_user(&pending.device_uuid, &pending.user_uuid, conn).await
+            {
+                let ip = pending.ip_address.parse().unwrap_or(IpAddr::from([0, 0, 0, 0]));
+                attachment_created(&cipher, &device, &ip, conn, &WS_USERS).await;
+                return Ok(true);
+            }
+        }
+        None if pending.is_expired() && !chunked_attachment_upload(&attachment)?.in_progress().await => {
+            info!("Deleted attachment {} of cipher {}, its content was never uploaded", attachment.id, cipher.uuid);
+            attachment.delete(conn).await?;
+            PendingUpload::delete(&pending.uuid, conn).await?;
+        }
+        None => return Ok(false),
+    }
+
+    // Let the clients pick up the change, also when the device which created the attachment is gone
+    let user_ids = cipher.update_users_revision(conn).await;
+    if let Some(device) = Device::find_by_uuid_and_user(&pending.device_uuid, &pending.user_uuid, conn).await {
+        WS_USERS.send_cipher_update(UpdateType::SyncCipherUpdate, &cipher, &user_ids, &device, None, conn).await;
+    }
+    Ok(true)
+}
+
+/// Saves the data content of an attachment to a file. This is common code
+/// shared between the v2 and legacy attachment APIs.
+///
+/// When used with the legacy API, this function is responsible for creating
+/// the attachment database record, so `attachment` is None.
+///
+/// When used with the v2 API, post_attachment_v2() has already created the
+/// database record, which is passed in as `attachment`.
+async fn save_attachment(
+    mut attachment: Option<Attachment>,
+    cipher_id: CipherId,
+    data: Form<UploadData<'_>>,
+    headers: &Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> Result<(Cipher, DbConn), crate::error::Error> {
+    let data = data.into_inner();
+
+    let Some(size) = data.data.len().to_i64() else {
+        err!("Attachment data size overflow");
+    };
+    if size < 0 {
+        err!("Attachment size can't be negative")
+    }
+
+    let Some(cipher) = Cipher::find_by_uuid(&cipher_id, &conn).await else {
+        err!("Cipher doesn't exist")
+    };
+
+    if !cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await {
+        err!("Cipher is not write accessible")
+    }
+
+    // In the v2 API, the attachment record has already been created,
+    // so the size limit needs to be adjusted to account for that.
+    let size_adjust = match &attachment {
+        None => 0,              // Legacy API
+        Some(a) => a.file_size, // v2 API
+    };
+
+    check_attachment_size_limit(&cipher, size, size_adjust, &conn).await?;
+
     let file_id = match &attachment {
         Some(attachment) => attachment.id.clone(), // v2 API
         None => crypto::generate_attachment_id(),  // Legacy API
@@ -1279,29 +1756,7 @@ async fn save_attachment(
 
     if let Some(attachment) = &mut attachment {
         // v2 API
-
-        // Check the actual size against the size initially provided by
-        // the client. Upstream allows +/- 1 MiB deviation from this
-        // size, but it's not clear when or why this is needed.
-        const LEEWAY: i64 = 1024 * 1024; // 1 MiB
-        let Some(max_size) = attachment.file_size.checked_add(LEEWAY) else {
-            err!("Invalid attachment size max")
-        };
-        let Some(min_size) = attachment.file_size.checked_sub(LEEWAY) else {
-            err!("Invalid attachment size min")
-        };
-
-        if min_size <= size && size <= max_size {
-            if size != attachment.file_size {
-                // Update the attachment with the actual file size.
-                attachment.file_size = size;
-                attachment.save(&conn).await.expect("Error updating attachment");
-            }
-        } else {
-            attachment.delete(&conn).await.ok();
-
-            err!(format!("Attachment size mismatch (expected within [{min_size}, {max_size}], got {size})"));
-        }
+        check_attachment_upload_size(attachment, size, &conn).await?;
     } else {
         // Legacy API
 
@@ -1316,36 +1771,21 @@ async fn save_attachment(
         if data.key.is_none() {
             err!("No attachment key provided")
         }
-        let attachment =
+        let new_attachment =
             Attachment::new(file_id.clone(), cipher_id.clone(), encrypted_filename.unwrap(), size, data.key);
-        attachment.save(&conn).await.expect("Error saving attachment");
+        new_attachment.save(&conn).await.expect("Error saving attachment");
+        attachment = Some(new_attachment);
     }
 
-    save_temp_file(&PathType::Attachments, &format!("{cipher_id}/{file_id}"), data.data, true).await?;
-
-    nt.send_cipher_update(
-        UpdateType::SyncCipherUpdate,
-        &cipher,
-        &cipher.update_users_revision(&conn).await,
-        &headers.device,
-        None,
-        &conn,
-    )
-    .await;
-
-    if let Some(org_id) = &cipher.organization_uuid {
-        log_event(
-            EventType::CipherAttachmentCreated as i32,
-            &cipher.uuid,
-            org_id,
-            &headers.user.uuid,
-            headers.device.atype,
-            &headers.ip.ip,
-            &conn,
-        )
-        .await;
+    let checksum = save_temp_file(&PathType::Attachments, &format!("{cipher_id}/{file_id}"), data.data, true).await?;
+    if let Some(mut attachment) = attachment {
+        attachment.checksum = Some(checksum);
+        attachment.save(&conn).await?;
+        PendingUpload::delete(&attachment.id, &conn).await?;
     }
 
+    attachment_created(&cipher, &headers.device, &headers.ip.ip, &conn, nt).await;
+
     Ok((cipher, conn))
 }
 
@@ -1373,6 +1813,138 @@ async fn post_attachment_v2_data(
     Ok(())
 }
 
+/// Looks up an attachment record created through the v2 API, whose content the user is allowed to upload.
+async fn find_uploadable_attachment(
+    cipher_id: &CipherId,
+    attachment_id: &AttachmentId,
+    headers: &Headers,
+    conn: &DbConn,
+) -> Result<(Cipher, Attachment), crate::error::Error> {
+    let attachment = match Attachment::find_by_id(attachment_id, conn).await {
+        Some(attachment) if *cipher_id == attachment.cipher_uuid => attachment,
+        Some(_) => err!("Attachment doesn't belong to cipher"),
+        None => err!("Attachment doesn't exist"),
+    };
+
+    let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
+        err!("Cipher doesn't exist")
+    };
+
+    if !cipher.is_write_accessible_to_user(&headers.user.uuid, conn).await {
+        err!("Cipher is not write accessible")
+    }
+
+    Ok((cipher, attachment))
+}
+
+fn chunked_attachment_upload(attachment: &Attachment) -> Result<ChunkedUpload, crate::error::Error> {
+    let Some(size) = attachment.file_size.to_u64() else {
+        err!("Invalid attachment size")
+    };
+    ChunkedUpload::new(
+        &format!("attachment-{}", attachment.id),
+        size,
+        &PathType::Attachments,
+        attachment.get_file_path(),
+        true,
+    )
+}
+
+/// Chunked upload API, returns how much of the attachment content has been received so far.
+#[head("/ciphers/<cipher_id>/attachment/<attachment_id>/upload")]
+async fn head_attachment_upload(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    headers: Headers,
+    conn: DbConn,
+) -> ApiResult<UploadStatus> {
+    let (_, attachment) = find_uploadable_attachment(&cipher_id, &attachment_id, &headers, &conn).await?;
+
+    Ok(chunked_attachment_upload(&attachment)?.status().await)
+}
+
+/// Chunked upload API, appends a chunk to the content of an attachment created through the v2 API.
+/// Once all chunks have been received, the attachment is stored the same way as with post_attachment_v2_data().
+/// Unlike that API, the total size has to match the size provided when creating the attachment.
+#[patch("/ciphers/<cipher_id>/attachment/<attachment_id>/upload", data = "<data>")]
+async fn patch_attachment_upload(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    offset: UploadOffset,
+    data: Data<'_>,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> ApiResult<UploadStatus> {
+    let (cipher, mut attachment) = find_uploadable_attachment(&cipher_id, &attachment_id, &headers, &conn).await?;
+
+    // The size can't change anymore, so check the limits before accepting any data.
+    check_attachment_size_limit(&cipher, attachment.file_size, attachment.file_size, &conn).await?;
+
+    let (status, checksum) = chunked_attachment_upload(&attachment)?.append(offset.0, data).await?;
+    if let Some(checksum) = checksum {
+        attachment.checksum = Some(checksum);
+        attachment.save(&conn).await?;
+        PendingUpload::delete(&attachment.id, &conn).await?;
+
+        attachment_created(&cipher, &headers.device, &headers.ip.ip, &conn, nt).await;
+    }
+
+    Ok(status)
+}
+
+/// Returns a new upload URL for an attachment created through the v2 API, for when the previous one expired.
+#[get("/ciphers/<cipher_id>/attachment/<attachment_id>/renew")]
+async fn renew_attachment_upload_url(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let (_, attachment) = find_uploadable_attachment(&cipher_id, &attachment_id, &headers, &conn).await?;
+    let (url, upload_type) = attachment_upload_url(&attachment).await?;
+    PendingUpload::new(
+        attachment.id.to_string(),
+        PendingUploadType::Attachment,
+        &headers.device,
+        headers.ip.ip.to_string(),
+        matches!(upload_type, FileUploadType::Azure),
+    )
+    .save(&conn)
+    .await?;
+
+    Ok(Json(json!({ // AttachmentUploadDataResponseModel
+        "object": "attachment-fileUpload",
+        "attachmentId": attachment.id,
+        "url": url,
+        "fileUploadType": upload_type as i32,
+    })))
+}
+
+/// Completion callback for attachments uploaded directly to the object storage through a presigned URL.
+/// Checks the uploaded file against the size provided when creating the attachment and the attachment limits,
+/// and deletes the attachment if either check fails.
+/// Most clients don't call this, in which case the pending uploads job completes the attachment.
+#[post("/ciphers/<cipher_id>/attachment/<attachment_id>/validate")]
+async fn post_attachment_validate(
+    cipher_id: CipherId,
+    attachment_id: AttachmentId,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    let (cipher, mut attachment) = find_uploadable_attachment(&cipher_id, &attachment_id, &headers, &conn).await?;
+
+    let Some(size) = stored_attachment_size(&attachment).await? else {
+        err!("Attachment has not been uploaded")
+    };
+    verify_stored_attachment(&cipher, &mut attachment, size, &conn).await?;
+
+    attachment_created(&cipher, &headers.device, &headers.ip.ip, &conn, nt).await;
+
+    Ok(())
+}
+
 /// Legacy API for creating an attachment associated with a cipher.
 #[post("/ciphers/<cipher_id>/attachment", format = "multipart/form-data", data = "<data>")]
 async fn post_attachment(
@@ -1622,7 +2194,7 @@ async fn move_cipher_selected(
     // Find all ciphers a user has access to, all others will be ignored
     let accessible_ciphers = Cipher::find_by_user_and_ciphers(user_id, &data.ids, &conn).await;
     let accessible_ciphers_count = accessible_ciphers.len();
-    for cipher in accessible_ciphers {
+    for mut cipher in accessible_ciphers {
         cipher.move_to_folder(data.folder_id.clone(), user_id, &conn).await?;
         if cipher_count == 1 {
             single_cipher = Some(cipher);
@@ -1768,6 +2340,194 @@ async fn unarchive_cipher_selected(
     unarchive_multiple_ciphers(data, &headers, &conn, &nt).await
 }
 
+/// The vault which `restore_vault` rolls back.
+pub enum VaultOwner<'a> {
+    User(&'a UserId),
+    Organization(&'a OrganizationId),
+}
+
+/// Restores the personal vault of a user, or the vault of an organization, to its state at the given time.
+/// This uses the kept item history, so it can only bring back what is still within the configured retention.
+/// Ciphers which were created afterwards are moved to the trash instead of being deleted, and ciphers whose
+/// history doesn't go back far enough are left as they are and reported as incomplete.
+pub async fn restore_vault(owner: VaultOwner<'_>, dt: NaiveDateTime, conn: &DbConn, nt: &Notify<'_>) -> JsonResult {
+    if CONFIG.cipher_revisions_limit() == 0 {
+        err!("The item history is disabled, set CIPHER_REVISIONS_LIMIT to keep it")
+    }
+    let now = Utc::now().naive_utc();
+    if dt >= now {
+        err!("The restore point has to be in the past")
+    }
+    // Older versions and deleted items were already removed by the purge job
+    let retention_days = CONFIG.cipher_revisions_retention_days();
+    if TimeDelta::try_days(retention_days).and_then(|d| now.checked_sub_signed(d)).is_none_or(|oldest| dt < oldest) {
+        err!(format!("The item history only goes back {retention_days} days"))
+    }
+
+    let result = conn.transaction(async || restore_vault_items(&owner, dt, now, conn).await).await?;
+
+    let user_ids = match owner {
+        VaultOwner::User(user_id) => vec![user_id.clone()],
+        VaultOwner::Organization(org_id) => {
+            Membership::find_confirmed_by_org(org_id, conn).await.into_iter().map(|m| m.user_uuid).collect()
+        }
+    };
+    for user_id in user_ids {
+        if let Some(mut user) = User::find_by_uuid(&user_id, conn).await {
+            user.update_revision(conn).await?;
+            nt.send_user_update(UpdateType::SyncVault, &user, None, conn).await;
+        }
+    }
+
+    Ok(Json(result))
+}
+
+async fn restore_vault_items(
+    owner: &VaultOwner<'_>,
+    dt: NaiveDateTime,
+    now: NaiveDateTime,
+    conn: &DbConn,
+) -> ApiResult<Value> {
+    let (ciphers, deleted_items) = match owner {
+        VaultOwner::User(user_id) => (
+            Cipher::find_owned_by_user(user_id, conn).await,
+            DeletedItem::find_by_user_deleted_after(user_id, &dt, conn).await,
+        ),
+        VaultOwner::Organization(org_id) => {
+            (Cipher::find_by_org(org_id, conn).await, DeletedItem::find_by_org_deleted_after(org_id, &dt, conn).await)
+        }
+    };
+    // Items which were created after the restore point didn't exist back then
+    let deleted_items: Vec<DeletedItem> = deleted_items.into_iter().filter(|i| i.created_at <= dt).collect();
+
+    let (mut restored, mut recreated, mut trashed, mut folders) = (0, 0, 0, 0);
+    let mut incomplete = Vec::new();
+
+    // Bring back the folders first, so the ciphers can be put back into them
+    let mut folder_ciphers = Vec::new();
+    for item in &deleted_items {
+        let (Some(user_id), Some(deleted)) = (&item.user_uuid, item.get_folder()) else {
+            continue;
+        };
+
+        let mut folder = Folder::new(user_id.clone(), deleted.name);
+        folder.uuid = FolderId::from(item.object_uuid.clone());
+        folder.created_at = item.created_at;
+        folder.save(conn).await?;
+        Tombstone::delete_by_object(&item.object_uuid, conn).await?;
+        item.delete(conn).await?;
+
+        folder_ciphers.extend(deleted.ciphers.into_iter().map(|cipher_id| (folder.uuid.clone(), cipher_id)));
+        folders += 1;
+    }
+
+    for mut cipher in ciphers {
+        if cipher.created_at > dt {
+            if cipher.deleted_at.is_none() {
+                cipher.deleted_at = Some(now);
+                cipher.save(conn).await?;
+                trashed += 1;
+            }
+            continue;
+        }
+
+        let mut attachments = Attachment::find_by_cipher(&cipher.uuid, conn).await;
+        let current = CipherRevision::from_cipher(&cipher, &attachments);
+        let revisions = CipherRevision::find_by_cipher(&cipher.uuid, conn).await;
+        match CipherRevision::version_at(&revisions, &dt, CipherRevision::history_is_complete(revisions.len())) {
+            VersionAt::Current => {}
+            VersionAt::Version(version) => version.apply_to(&mut cipher, &mut attachments),
+            VersionAt::Unknown => {
+                incomplete.push(cipher.uuid.clone());
+                continue;
+            }
+        }
+
+        let changed = current.differs_from(&cipher, &attachments);
+        let untrashed = cipher.deleted_at.is_some_and(|deleted_at| deleted_at > dt);
+        if untrashed {
+            cipher.deleted_at = None;
+        }
+        if !changed && !untrashed {
+            continue;
+        }
+
+        for attachment in &attachments {
+            attachment.save(conn).await?;
+        }
+        cipher.save(conn).await?;
+        if changed {
+            // Keep the current version, so the restore can be undone per cipher
+            current.save(conn).await?;
+        }
+        restored += 1;
+    }
+
+    for item in &deleted_items {
+        let Some(deleted) = item.get_cipher() else {
+            continue;
+        };
+        // The versions always start with the one at the time of deletion, which happened after the restore point
+        let complete = CipherRevision::history_is_complete(deleted.versions.len().saturating_sub(1));
+        let version = match CipherRevision::version_at(&deleted.versions, &dt, complete) {
+            VersionAt::Version(version) => version,
+            VersionAt::Current => continue,
+            VersionAt::Unknown => {
+                incomplete.push(CipherId::from(item.object_uuid.clone()));
+                continue;
+            }
+        };
+
+        let mut cipher = Cipher::new(version.atype, version.name.clone());
+        cipher.uuid = CipherId::from(item.object_uuid.clone());
+        cipher.created_at = item.created_at;
+        cipher.user_uuid.clone_from(&item.user_uuid);
+        cipher.organization_uuid.clone_from(&item.organization_uuid);
+        // The attachment files were removed together with the cipher, so those can't be restored
+        version.apply_to(&mut cipher, &mut []);
+        cipher.deleted_at = deleted.deleted_at.filter(|deleted_at| *deleted_at <= dt);
+        cipher.save(conn).await?;
+
+        for collection_id in &deleted.collections {
+            if Collection::find_by_uuid(collection_id, conn).await.is_some() {
+                CollectionCipher::save(&cipher.uuid, collection_id, conn).await?;
+            }
+        }
+        for folder_id in deleted.folders {
+            folder_ciphers.push((folder_id, cipher.uuid.clone()));
+        }
+        for user_id in &deleted.favorites {
+            if User::find_by_uuid(user_id, conn).await.is_some() {
+                Favorite::set_favorite(true, &cipher.uuid, user_id, conn).await?;
+            }
+        }
+        // Keep the history, oldest first so the newest versions are kept within the limit
+        for revision in deleted.versions.iter().rev() {
+            revision.save(conn).await?;
+        }
+
+        Tombstone::delete_by_object(&item.object_uuid, conn).await?;
+        item.delete(conn).await?;
+        recreated += 1;
+    }
+
+    for (folder_id, cipher_id) in folder_ciphers {
+        if Folder::find_by_uuid(&folder_id, conn).await.is_some()
+            && Cipher::find_by_uuid(&cipher_id, conn).await.is_some()
+        {
+            FolderCipher::new(folder_id, cipher_id).save(conn).await?;
+        }
+    }
+
+    Ok(json!({
+        "restored": restored,
+        "recreated": recreated,
+        "trashed": trashed,
+        "folders": folders,
+        "incomplete": incomplete,
+    }))
+}
+
 #[derive(PartialEq)]
 pub enum CipherDeleteOptions {
     SoftSingle,
@@ -1993,7 +2753,7 @@ async fn archive_cipher(
     conn: &DbConn,
     nt: &Notify<'_>,
 ) -> JsonResult {
-    let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
         err!("Cipher doesn't exist")
     };
 
@@ -2025,7 +2785,7 @@ async fn unarchive_cipher(
     conn: &DbConn,
     nt: &Notify<'_>,
 ) -> JsonResult {
-    let Some(cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
+    let Some(mut cipher) = Cipher::find_by_uuid(cipher_id, conn).await else {
         err!("Cipher doesn't exist")
     };
 
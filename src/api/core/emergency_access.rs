@@ -1,3 +1,5 @@
+use std::{collections::HashSet, net::IpAddr};
+
 use chrono::{TimeDelta, Utc};
 use rocket::{Route, serde::json::Json};
 use serde_json::Value;
@@ -6,14 +8,14 @@ use crate::{
     CONFIG,
     api::{
         EmptyResult, JsonResult,
-        core::{CipherSyncData, CipherSyncType},
+        core::{CipherSyncData, CipherSyncType, log_user_event_by},
     },
     auth::{Headers, decode_emergency_access_invite},
     db::{
         DbConn, DbPool,
         models::{
-            Cipher, EmergencyAccess, EmergencyAccessId, EmergencyAccessStatus, EmergencyAccessType, Invitation,
-            Membership, MembershipType, OrgPolicy, TwoFactor, User, UserId,
+            Cipher, EmergencyAccess, EmergencyAccessId, EmergencyAccessQuorum, EmergencyAccessStatus,
+            EmergencyAccessType, EventType, Invitation, Membership, MembershipType, OrgPolicy, TwoFactor, User, UserId,
         },
     },
     mail,
@@ -40,6 +42,8 @@ pub fn routes() -> Vec<Route> {
         password_emergency_access,
         view_emergency_access,
         policies_emergency_access,
+        get_emergency_access_quorum,
+        put_emergency_access_quorum,
     ]
 }
 
@@ -85,6 +89,18 @@ async fn get_grantees(headers: Headers, conn: DbConn) -> Json<Value> {
     }))
 }
 
+#[get("/emergency-access/quorum")]
+async fn get_emergency_access_quorum(headers: Headers, conn: DbConn) -> JsonResult {
+    check_emergency_access_enabled()?;
+
+    let members = EmergencyAccessQuorum::find_members(&headers.user.uuid, &conn).await;
+    Ok(Json(json!({
+        "quorum": EmergencyAccessQuorum::find_by_grantor(&headers.user.uuid, &conn).await,
+        "emergencyAccessIds": members.iter().map(|emer| &emer.uuid).collect::<Vec<_>>(),
+        "object": "emergencyAccessQuorum",
+    })))
+}
+
 #[get("/emergency-access/<emer_id>")]
 async fn get_emergency_access(emer_id: EmergencyAccessId, headers: Headers, conn: DbConn) -> JsonResult {
     check_emergency_access_enabled()?;
@@ -155,6 +171,56 @@ async fn post_emergency_access(
     Ok(Json(emergency_access.to_json()))
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EmergencyAccessQuorumData {
+    quorum: i32,
+    /// The emergency accesses whose recoveries count towards the quorum, all the confirmed ones when not given
+    emergency_access_ids: Option<Vec<EmergencyAccessId>>,
+}
+
+/// Sets how many trusted contacts have to initiate a recovery together before it is approved without waiting.
+#[put("/emergency-access/quorum", data = "<data>")]
+async fn put_emergency_access_quorum(
+    data: Json<EmergencyAccessQuorumData>,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    check_emergency_access_enabled()?;
+
+    let data = data.into_inner();
+    let quorum = data.quorum;
+    if quorum < 1 {
+        err!("The quorum needs to be at least one.")
+    }
+
+    let confirmed = EmergencyAccess::find_all_confirmed_by_grantor_uuid(&headers.user.uuid, &conn).await;
+    let members: HashSet<EmergencyAccessId> = match data.emergency_access_ids {
+        Some(ids) => {
+            if ids.iter().any(|id| !confirmed.iter().any(|emer| emer.uuid == *id)) {
+                err!("Emergency access not valid.")
+            }
+            ids.into_iter().collect()
+        }
+        None => confirmed.into_iter().map(|emer| emer.uuid).collect(),
+    };
+    if quorum > 1 && usize::try_from(quorum).unwrap_or(usize::MAX) > members.len() {
+        err!("The quorum can't be higher than the number of confirmed emergency contacts which are part of it.")
+    }
+
+    EmergencyAccessQuorum::set(&headers.user.uuid, quorum, members.iter().cloned().collect(), &conn).await?;
+
+    // Recoveries which were already initiated might reach the new quorum
+    approve_recoveries_by_quorum(&headers.user, &headers.user.uuid, headers.device.atype, &headers.ip.ip, &conn)
+        .await?;
+
+    Ok(Json(json!({
+        "quorum": quorum,
+        "emergencyAccessIds": members,
+        "object": "emergencyAccessQuorum",
+    })))
+}
+
 // endregion
 
 // region delete
@@ -268,6 +334,7 @@ async fn send_invite(data: Json<EmergencyAccessInviteData>, headers: Headers, co
             new_emergency_access.uuid,
             &grantor_user.name,
             &grantor_user.email,
+            &conn,
         )
         .await?;
     } else if !new_user {
@@ -309,6 +376,7 @@ async fn resend_invite(emer_id: EmergencyAccessId, headers: Headers, conn: DbCon
             emergency_access.uuid,
             &grantor_user.name,
             &grantor_user.email,
+            &conn,
         )
         .await?;
     } else if !grantee_user.password_hash.is_empty() {
@@ -374,7 +442,7 @@ async fn accept_invite(
         emergency_access.accept_invite(&grantee_user.uuid, &grantee_user.email, &conn).await?;
 
         if CONFIG.mail_enabled() {
-            mail::send_emergency_access_invite_accepted(&grantor_user.email, &grantee_user.email).await?;
+            mail::send_emergency_access_invite_accepted(&grantor_user.email, &grantee_user.email, &conn).await?;
         }
 
         Ok(())
@@ -430,7 +498,7 @@ async fn confirm_emergency_access(
         emergency_access.save(&conn).await?;
 
         if CONFIG.mail_enabled() {
-            mail::send_emergency_access_invite_confirmed(&grantee_user.email, &grantor_user.name).await?;
+            mail::send_emergency_access_invite_confirmed(&grantee_user.email, &grantor_user.name, &conn).await?;
         }
         Ok(Json(emergency_access.to_json()))
     } else {
@@ -468,15 +536,33 @@ async fn initiate_emergency_access(emer_id: EmergencyAccessId, headers: Headers,
     emergency_access.last_notification_at = Some(now);
     emergency_access.save(&conn).await?;
 
+    log_user_event_by(
+        EventType::UserEmergencyAccessInitiated as i32,
+        &grantor_user.uuid,
+        &initiating_user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
     if CONFIG.mail_enabled() {
         mail::send_emergency_access_recovery_initiated(
             &grantor_user.email,
             &initiating_user.name,
             emergency_access.get_type_as_str(),
             &emergency_access.wait_time_days,
+            &conn,
         )
         .await?;
     }
+
+    let approved =
+        approve_recoveries_by_quorum(&grantor_user, &initiating_user.uuid, headers.device.atype, &headers.ip.ip, &conn)
+            .await?;
+    if approved.contains(&emergency_access.uuid) {
+        emergency_access.status = EmergencyAccessStatus::RecoveryApproved as i32;
+    }
     Ok(Json(emergency_access.to_json()))
 }
 
@@ -506,8 +592,18 @@ async fn approve_emergency_access(emer_id: EmergencyAccessId, headers: Headers,
         emergency_access.status = EmergencyAccessStatus::RecoveryApproved as i32;
         emergency_access.save(&conn).await?;
 
+        log_user_event_by(
+            EventType::UserEmergencyAccessApproved as i32,
+            &grantor_user.uuid,
+            &grantor_user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+
         if CONFIG.mail_enabled() {
-            mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name).await?;
+            mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name, &conn).await?;
         }
         Ok(Json(emergency_access.to_json()))
     } else {
@@ -539,8 +635,18 @@ async fn reject_emergency_access(emer_id: EmergencyAccessId, headers: Headers, c
         emergency_access.status = EmergencyAccessStatus::Confirmed as i32;
         emergency_access.save(&conn).await?;
 
+        log_user_event_by(
+            EventType::UserEmergencyAccessRejected as i32,
+            &headers.user.uuid,
+            &headers.user.uuid,
+            headers.device.atype,
+            &headers.ip.ip,
+            &conn,
+        )
+        .await;
+
         if CONFIG.mail_enabled() {
-            mail::send_emergency_access_recovery_rejected(&grantee_user.email, &headers.user.name).await?;
+            mail::send_emergency_access_recovery_rejected(&grantee_user.email, &headers.user.name, &conn).await?;
         }
         Ok(Json(emergency_access.to_json()))
     } else {
@@ -566,8 +672,14 @@ async fn view_emergency_access(emer_id: EmergencyAccessId, headers: Headers, con
         err!("Emergency access not valid.")
     }
 
-    let ciphers = Cipher::find_owned_by_user(&emergency_access.grantor_uuid, &conn).await;
-    let cipher_sync_data = CipherSyncData::new(&emergency_access.grantor_uuid, CipherSyncType::User, &conn).await;
+    let Some(grantor_user) = User::find_by_uuid(&emergency_access.grantor_uuid, &conn).await else {
+        err!("Grantor user not found.")
+    };
+
+    // Only the personal items are shared. The organization items would need the organization keys,
+    // which are only encrypted for the grantor and can't be decrypted by the grantee.
+    let ciphers = Cipher::find_owned_by_user(&grantor_user.uuid, &conn).await;
+    let cipher_sync_data = CipherSyncData::new(&grantor_user.uuid, CipherSyncType::User, &conn).await;
 
     let mut ciphers_json = Vec::with_capacity(ciphers.len());
     for c in ciphers {
@@ -583,6 +695,16 @@ async fn view_emergency_access(emer_id: EmergencyAccessId, headers: Headers, con
         );
     }
 
+    log_user_event_by(
+        EventType::UserEmergencyAccessViewed as i32,
+        &grantor_user.uuid,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
     Ok(Json(json!({
       "ciphers": ciphers_json,
       "keyEncrypted": &emergency_access.key_encrypted,
@@ -609,6 +731,16 @@ async fn takeover_emergency_access(emer_id: EmergencyAccessId, headers: Headers,
         err!("Grantor user not found.")
     };
 
+    log_user_event_by(
+        EventType::UserEmergencyAccessTakeover as i32,
+        &grantor_user.uuid,
+        &requesting_user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
     let result = json!({
         "kdf": grantor_user.client_kdf_type,
         "kdfIterations": grantor_user.client_kdf_iter,
@@ -663,6 +795,16 @@ async fn password_emergency_access(
     // Disable TwoFactor providers since they will otherwise block logins
     TwoFactor::delete_all_by_user(&grantor_user.uuid, &conn).await?;
 
+    log_user_event_by(
+        EventType::UserEmergencyAccessPasswordReset as i32,
+        &grantor_user.uuid,
+        &requesting_user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
     // Remove grantor from all organisations unless Owner
     for member in Membership::find_any_state_by_user(&grantor_user.uuid, &conn).await {
         if member.atype != MembershipType::Owner as i32 {
@@ -719,6 +861,67 @@ fn check_emergency_access_enabled() -> EmptyResult {
     Ok(())
 }
 
+/// Approves the initiated recoveries of the grantor once enough members of the quorum initiated one.
+/// The quorum is counted per type of access, so a takeover is only approved when enough members asked for a takeover,
+/// not when some of them only asked to view the vault. Returns the approved emergency accesses.
+async fn approve_recoveries_by_quorum(
+    grantor_user: &User,
+    act_user_id: &UserId,
+    device_type: i32,
+    ip: &IpAddr,
+    conn: &DbConn,
+) -> Result<Vec<EmergencyAccessId>, crate::Error> {
+    let quorum = EmergencyAccessQuorum::find_by_grantor(&grantor_user.uuid, conn).await;
+    if quorum <= 1 {
+        return Ok(Vec::new());
+    }
+    let quorum = usize::try_from(quorum).unwrap_or(usize::MAX);
+
+    let members = EmergencyAccessQuorum::find_members(&grantor_user.uuid, conn).await;
+    let reached: Vec<i32> = [EmergencyAccessType::View as i32, EmergencyAccessType::Takeover as i32]
+        .into_iter()
+        .filter(|atype| {
+            let initiated = members
+                .iter()
+                .filter(|emer| {
+                    emer.atype == *atype
+                        && (emer.status == EmergencyAccessStatus::RecoveryInitiated as i32
+                            || emer.status == EmergencyAccessStatus::RecoveryApproved as i32)
+                })
+                .count();
+            initiated >= quorum
+        })
+        .collect();
+
+    let now = Utc::now().naive_utc();
+    let mut approved = Vec::new();
+    for mut emer in members {
+        if emer.status != EmergencyAccessStatus::RecoveryInitiated as i32 || !reached.contains(&emer.atype) {
+            continue;
+        }
+        emer.update_access_status_and_save(EmergencyAccessStatus::RecoveryApproved as i32, &now, conn).await?;
+
+        log_user_event_by(
+            EventType::UserEmergencyAccessApproved as i32,
+            &grantor_user.uuid,
+            act_user_id,
+            device_type,
+            ip,
+            conn,
+        )
+        .await;
+
+        if CONFIG.mail_enabled()
+            && let Some(grantee_uuid) = &emer.grantee_uuid
+            && let Some(grantee_user) = User::find_by_uuid(grantee_uuid, conn).await
+        {
+            mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name, conn).await?;
+        }
+        approved.push(emer.uuid);
+    }
+    Ok(approved)
+}
+
 pub async fn emergency_request_timeout_job(pool: DbPool) {
     debug!("Start emergency_request_timeout_job");
     if !CONFIG.emergency_access_allowed() {
@@ -759,11 +962,12 @@ pub async fn emergency_request_timeout_job(pool: DbPool) {
                         &grantor_user.email,
                         &grantee_user.name,
                         emer.get_type_as_str(),
+                        &conn,
                     )
                     .await
                     .expect("Error on sending email");
 
-                    mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name)
+                    mail::send_emergency_access_recovery_approved(&grantee_user.email, &grantor_user.name, &conn)
                         .await
                         .expect("Error on sending email");
                 }
@@ -822,6 +1026,7 @@ pub async fn emergency_notification_reminder_job(pool: DbPool) {
                         &grantee_user.name,
                         emer.get_type_as_str(),
                         "1", // This notification is only triggered one day before the activation
+                        &conn,
                     )
                     .await
                     .expect("Error on sending email");
@@ -832,3 +1037,125 @@ pub async fn emergency_notification_reminder_job(pool: DbPool) {
         error!("Failed to get DB connection while searching emergency notification reminder");
     }
 }
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    async fn user(email: &str, conn: &DbConn) -> User {
+        let mut user = User::new(email, None);
+        user.save(conn).await.unwrap();
+        user
+    }
+
+    async fn grantee(grantor: &User, email: &str, atype: EmergencyAccessType, conn: &DbConn) -> EmergencyAccess {
+        let grantee = user(email, conn).await;
+        let mut emer = EmergencyAccess::new(
+            grantor.uuid.clone(),
+            String::from(email),
+            EmergencyAccessStatus::Confirmed as i32,
+            atype as i32,
+            7,
+        );
+        emer.grantee_uuid = Some(grantee.uuid);
+        emer.email = None;
+        emer.save(conn).await.unwrap();
+        emer
+    }
+
+    async fn initiate(emer: &mut EmergencyAccess, grantor: &User, conn: &DbConn) -> Vec<EmergencyAccessId> {
+        emer.status = EmergencyAccessStatus::RecoveryInitiated as i32;
+        emer.recovery_initiated_at = Some(Utc::now().naive_utc());
+        emer.save(conn).await.unwrap();
+        approve_recoveries_by_quorum(grantor, emer.grantee_uuid.as_ref().unwrap(), 0, &IP, conn).await.unwrap()
+    }
+
+    async fn status(emer: &EmergencyAccess, conn: &DbConn) -> i32 {
+        EmergencyAccess::find_by_uuid_and_grantor_uuid(&emer.uuid, &emer.grantor_uuid, conn).await.unwrap().status
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn approves_the_recoveries_once_the_quorum_is_reached() {
+        let pool = DbPool::for_tests("emergency-access-quorum");
+        let conn = &pool.get().await.unwrap();
+
+        let grantor = user("grantor@example.com", conn).await;
+        let mut a = grantee(&grantor, "a@example.com", EmergencyAccessType::View, conn).await;
+        let mut b = grantee(&grantor, "b@example.com", EmergencyAccessType::View, conn).await;
+        let mut outsider = grantee(&grantor, "c@example.com", EmergencyAccessType::View, conn).await;
+        EmergencyAccessQuorum::set(&grantor.uuid, 2, vec![a.uuid.clone(), b.uuid.clone()], conn).await.unwrap();
+
+        // Grantees which aren't part of the quorum don't count towards it
+        assert!(initiate(&mut outsider, &grantor, conn).await.is_empty());
+        assert!(initiate(&mut a, &grantor, conn).await.is_empty());
+        assert_eq!(status(&a, conn).await, EmergencyAccessStatus::RecoveryInitiated as i32);
+
+        let approved: HashSet<EmergencyAccessId> = initiate(&mut b, &grantor, conn).await.into_iter().collect();
+        assert_eq!(approved, HashSet::from([a.uuid.clone(), b.uuid.clone()]));
+        assert_eq!(status(&a, conn).await, EmergencyAccessStatus::RecoveryApproved as i32);
+        assert_eq!(status(&b, conn).await, EmergencyAccessStatus::RecoveryApproved as i32);
+        // The recovery of the grantee outside of the quorum still has to wait
+        assert_eq!(status(&outsider, conn).await, EmergencyAccessStatus::RecoveryInitiated as i32);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn takeovers_need_a_quorum_of_takeovers() {
+        let pool = DbPool::for_tests("emergency-access-quorum-takeover");
+        let conn = &pool.get().await.unwrap();
+
+        let grantor = user("grantor@example.com", conn).await;
+        let mut view = grantee(&grantor, "a@example.com", EmergencyAccessType::View, conn).await;
+        let mut takeover = grantee(&grantor, "b@example.com", EmergencyAccessType::Takeover, conn).await;
+        EmergencyAccessQuorum::set(&grantor.uuid, 2, vec![view.uuid.clone(), takeover.uuid.clone()], conn)
+            .await
+            .unwrap();
+
+        assert!(initiate(&mut view, &grantor, conn).await.is_empty());
+        assert!(initiate(&mut takeover, &grantor, conn).await.is_empty());
+        assert_eq!(status(&takeover, conn).await, EmergencyAccessStatus::RecoveryInitiated as i32);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_members_are_replaced_and_removed_with_their_emergency_access() {
+        let pool = DbPool::for_tests("emergency-access-quorum-members");
+        let conn = &pool.get().await.unwrap();
+
+        let grantor = user("grantor@example.com", conn).await;
+        let a = grantee(&grantor, "a@example.com", EmergencyAccessType::View, conn).await;
+        let b = grantee(&grantor, "b@example.com", EmergencyAccessType::View, conn).await;
+        let c = grantee(&grantor, "c@example.com", EmergencyAccessType::View, conn).await;
+
+        let members = async |conn| -> Vec<EmergencyAccessId> {
+            let mut members: Vec<EmergencyAccessId> =
+                EmergencyAccessQuorum::find_members(&grantor.uuid, conn).await.into_iter().map(|e| e.uuid).collect();
+            members.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+            members
+        };
+        let sorted = |mut ids: Vec<EmergencyAccessId>| {
+            ids.sort_by(|x, y| x.as_ref().cmp(y.as_ref()));
+            ids
+        };
+
+        EmergencyAccessQuorum::set(&grantor.uuid, 2, vec![a.uuid.clone(), b.uuid.clone()], conn).await.unwrap();
+        EmergencyAccessQuorum::set(&grantor.uuid, 2, vec![b.uuid.clone(), c.uuid.clone()], conn).await.unwrap();
+        assert_eq!(members(conn).await, sorted(vec![b.uuid.clone(), c.uuid.clone()]));
+
+        // A failing insert leaves the previous quorum untouched
+        assert!(
+            EmergencyAccessQuorum::set(&grantor.uuid, 3, vec![a.uuid.clone(), String::from("missing").into()], conn)
+                .await
+                .is_err()
+        );
+        assert_eq!(EmergencyAccessQuorum::find_by_grantor(&grantor.uuid, conn).await, 2);
+        assert_eq!(members(conn).await, sorted(vec![b.uuid.clone(), c.uuid.clone()]));
+
+        c.delete(conn).await.unwrap();
+        assert_eq!(members(conn).await, vec![b.uuid.clone()]);
+
+        EmergencyAccessQuorum::set(&grantor.uuid, 1, Vec::new(), conn).await.unwrap();
+        assert_eq!(EmergencyAccessQuorum::find_by_grantor(&grantor.uuid, conn).await, 1);
+        assert!(members(conn).await.is_empty());
+    }
+}
@@ -12,13 +12,14 @@ use crate::{
         DbConn, DbPool,
         models::{Cipher, CipherId, Event, Membership, MembershipId, OrganizationId, UserId},
     },
+    error::Error,
     util::parse_date,
 };
 
 /// ###############################################################################################################
 /// /api routes
 pub fn routes() -> Vec<Route> {
-    routes![get_org_events, get_cipher_events, get_user_events,]
+    routes![get_org_events, get_cipher_events, get_user_events, get_account_events]
 }
 
 #[derive(FromForm)]
@@ -125,6 +126,35 @@ async fn get_user_events(
     })))
 }
 
+/// The personal event log of the user, like the emergency access actions on their vault.
+#[get("/accounts/events?<data..>")]
+async fn get_account_events(data: EventRange, headers: Headers, conn: DbConn) -> JsonResult {
+    // Return an empty vec when events are disabled.
+    // This prevents client errors
+    let events_json: Vec<Value> = if CONFIG.org_events_enabled() {
+        let start_date = parse_date(&data.start);
+        let end_date = if let Some(before_date) = &data.continuation_token {
+            parse_date(before_date)
+        } else {
+            parse_date(&data.end)
+        };
+
+        Event::find_by_user_uuid(&headers.user.uuid, &start_date, &end_date, &conn)
+            .await
+            .iter()
+            .map(Event::to_json)
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(Json(json!({
+        "data": events_json,
+        "object": "list",
+        "continuationToken": get_continuation_token(&events_json),
+    })))
+}
+
 fn get_continuation_token(events_json: &[Value]) -> Option<&str> {
     // When the length of the vec equals the max page_size there probably is more data
     // When it is less, then all events are loaded.
@@ -170,10 +200,14 @@ async fn post_events_collect(data: Json<Vec<EventCollection>>, headers: Headers,
     for event in data.iter() {
         let event_date = parse_date(&event.date);
         match event.r#type {
+            // The emergency access events are only logged by the server
+            1090..=1095 => {}
             1000..=1099 => {
                 log_user_event_impl(
                     event.r#type,
                     &headers.user.uuid,
+                    &headers.user.uuid,
+                    None,
                     headers.device.atype,
                     Some(event_date),
                     &headers.ip.ip,
@@ -229,24 +263,68 @@ pub async fn log_user_event(event_type: i32, user_id: &UserId, device_type: i32,
     if !CONFIG.org_events_enabled() {
         return;
     }
-    log_user_event_impl(event_type, user_id, device_type, None, ip, conn).await;
+    log_user_event_impl(event_type, user_id, user_id, None, device_type, None, ip, conn).await;
 }
 
+/// Logs an event on the vault of `user_id` which was caused by another user, like a grantee using emergency access.
+/// The event is only logged for the user, the organizations of the user don't see events on their personal vault.
+pub async fn log_user_event_by(
+    event_type: i32,
+    user_id: &UserId,
+    act_user_id: &UserId,
+    device_type: i32,
+    ip: &IpAddr,
+    conn: &DbConn,
+) {
+    if !CONFIG.org_events_enabled() {
+        return;
+    }
+    log_user_event_impl(event_type, user_id, act_user_id, Some(&[]), device_type, None, ip, conn).await;
+}
+
+/// Logs an event on a cipher of the personal vault of `user_id`, which shows up in the personal event log of the user.
+pub async fn log_personal_cipher_event(
+    event_type: i32,
+    cipher_id: &CipherId,
+    user_id: &UserId,
+    device_type: i32,
+    ip: &IpAddr,
+    conn: &DbConn,
+) {
+    if !CONFIG.org_events_enabled() {
+        return;
+    }
+    let mut event = Event::new(event_type, None);
+    event.cipher_uuid = Some(cipher_id.clone());
+    event.user_uuid = Some(user_id.clone());
+    event.act_user_uuid = Some(user_id.clone());
+    event.device_type = Some(device_type);
+    event.ip_address = Some(ip.to_string());
+    event.save(conn).await.unwrap_or(());
+}
+
+/// Logs the event for the user and for the organizations the user is a member of, or only those in `org_ids`.
+#[expect(clippy::too_many_arguments)]
 async fn log_user_event_impl(
     event_type: i32,
     user_id: &UserId,
+    act_user_id: &UserId,
+    org_ids: Option<&[OrganizationId]>,
     device_type: i32,
     event_date: Option<NaiveDateTime>,
     ip: &IpAddr,
     conn: &DbConn,
 ) {
-    let memberships = Membership::find_confirmed_by_user(user_id, conn).await;
+    let mut memberships = Membership::find_confirmed_by_user(user_id, conn).await;
+    if let Some(org_ids) = org_ids {
+        memberships.retain(|membership| org_ids.contains(&membership.org_uuid));
+    }
     let mut events: Vec<Event> = Vec::with_capacity(memberships.len() + 1); // We need an event per org and one without an org
 
     // Upstream saves the event also without any org_id.
     let mut event = Event::new(event_type, event_date);
     event.user_uuid = Some(user_id.clone());
-    event.act_user_uuid = Some(user_id.clone());
+    event.act_user_uuid = Some(act_user_id.clone());
     event.device_type = Some(device_type);
     event.ip_address = Some(ip.to_string());
     events.push(event);
@@ -257,7 +335,7 @@ async fn log_user_event_impl(
         event.user_uuid = Some(user_id.clone());
         event.org_uuid = Some(membership.org_uuid);
         event.org_user_uuid = Some(membership.uuid);
-        event.act_user_uuid = Some(user_id.clone());
+        event.act_user_uuid = Some(act_user_id.clone());
         event.device_type = Some(device_type);
         event.ip_address = Some(ip.to_string());
         events.push(event);
@@ -328,16 +406,16 @@ async fn log_event_impl(
     event.save(conn).await.unwrap_or(());
 }
 
-pub async fn event_cleanup_job(pool: DbPool) {
+pub async fn event_cleanup_job(pool: DbPool) -> Result<usize, Error> {
     debug!("Start events cleanup job");
     if CONFIG.events_days_retain().is_none() {
         debug!("events_days_retain is not configured, abort");
-        return;
+        return Ok(0);
     }
 
     if let Ok(conn) = pool.get().await {
-        Event::clean_events(&conn).await.ok();
+        Event::clean_events(&conn).await
     } else {
-        error!("Failed to get DB connection while trying to cleanup the events table");
+        err!("Failed to get DB connection while trying to cleanup the events table")
     }
 }
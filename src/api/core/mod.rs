@@ -5,15 +5,22 @@ mod ciphers;
 mod emergency_access;
 mod events;
 mod folders;
+mod notification_center;
 mod organizations;
 mod public;
 mod sends;
+mod uploads;
 
 pub use accounts::purge_auth_requests;
-pub use ciphers::{CipherData, CipherSyncData, CipherSyncType, purge_trashed_ciphers};
+pub use ciphers::{
+    CipherData, CipherSyncData, CipherSyncType, VaultOwner, clear_sync_cache, invalidate_sync_cache,
+    purge_trashed_ciphers, restore_vault, sync_cache_stats,
+};
 pub use emergency_access::{emergency_notification_reminder_job, emergency_request_timeout_job};
-pub use events::{event_cleanup_job, log_event, log_user_event};
+pub use events::{event_cleanup_job, log_event, log_personal_cipher_event, log_user_event, log_user_event_by};
+pub use notification_center::{NotificationData, send_notification};
 pub use sends::purge_sends;
+pub use uploads::{pending_uploads_job, purge_stale_uploads};
 
 use reqwest::Method;
 use rocket::{Catcher, Route, serde::json::Json, serde::json::Value};
@@ -43,6 +50,7 @@ pub fn routes() -> Vec<Route> {
     routes.append(&mut emergency_access::routes());
     routes.append(&mut events::routes());
     routes.append(&mut folders::routes());
+    routes.append(&mut notification_center::routes());
     routes.append(&mut organizations::routes());
     routes.append(&mut two_factor::routes());
     routes.append(&mut sends::routes());
@@ -74,15 +82,17 @@ const GLOBAL_DOMAINS: &str = include_str!("../../static/global_domains.json");
 #[expect(clippy::needless_pass_by_value, reason = "Not beneficial for Headers")]
 #[get("/settings/domains")]
 fn get_settings_domains(headers: Headers) -> Json<Value> {
-    get_eq_domains(&headers, false)
+    get_eq_domains(&headers, Vec::new(), false)
 }
 
-fn get_eq_domains(headers: &Headers, no_excluded: bool) -> Json<Value> {
+/// The equivalent domains of the user, followed by `org_equivalent_domains`, the groups of the user's organizations.
+fn get_eq_domains(headers: &Headers, org_equivalent_domains: Vec<Vec<String>>, no_excluded: bool) -> Json<Value> {
     use serde_json::from_str;
 
     let user = &headers.user;
 
-    let equivalent_domains: Vec<Vec<String>> = from_str(&user.equivalent_domains).unwrap();
+    let mut equivalent_domains: Vec<Vec<String>> = from_str(&user.equivalent_domains).unwrap();
+    equivalent_domains.extend(org_equivalent_domains);
     let excluded_globals: Vec<i32> = from_str(&user.excluded_globals).unwrap();
 
     let mut globals: Vec<GlobalDomain> = from_str(GLOBAL_DOMAINS).unwrap();
@@ -300,7 +310,7 @@ async fn accept_org_invite(
             err!("Organization not found.")
         };
         // User was invited to an organization, so they must be confirmed manually after acceptance
-        mail::send_invite_accepted(&user.email, &member.invited_by_email.unwrap_or(org.billing_email), &org.name)
+        mail::send_invite_accepted(&user.email, &member.invited_by_email.unwrap_or(org.billing_email), &org.name, conn)
             .await?;
     }
 
@@ -0,0 +1,188 @@
+use num_traits::FromPrimitive;
+use rocket::serde::json::Json;
+use serde_json::Value;
+
+use crate::{
+    api::{EmptyResult, JsonResult, Notify, UpdateType},
+    auth::{Headers, OwnerHeaders},
+    db::{
+        DbConn,
+        models::{
+            Membership, Notification, NotificationId, NotificationPriority, NotificationStatus, OrganizationId, User,
+            UserId,
+        },
+    },
+};
+
+pub fn routes() -> Vec<rocket::Route> {
+    routes![get_notifications, mark_notification_read, mark_notification_deleted, post_organization_notification]
+}
+
+const DEFAULT_PAGE_SIZE: usize = 10;
+
+#[derive(FromForm)]
+struct NotificationFilter {
+    #[field(name = "readStatusFilter")]
+    read_status_filter: Option<bool>,
+    #[field(name = "deletedStatusFilter")]
+    deleted_status_filter: Option<bool>,
+    #[field(name = "continuationToken")]
+    continuation_token: Option<usize>,
+    #[field(name = "pageSize")]
+    page_size: Option<usize>,
+}
+
+#[get("/notifications?<filter..>")]
+async fn get_notifications(filter: NotificationFilter, headers: Headers, conn: DbConn) -> Json<Value> {
+    let notifications = Notification::find_by_user(&headers.user.uuid, headers.device.client_type(), &conn).await;
+
+    // Without a filter, notifications are returned regardless of their status
+    let matches =
+        |date: Option<chrono::NaiveDateTime>, filter: Option<bool>| filter.is_none_or(|f| f == date.is_some());
+    let notifications: Vec<_> = notifications
+        .iter()
+        .filter(|(_, status)| {
+            matches(status.as_ref().and_then(|s| s.read_date), filter.read_status_filter)
+                && matches(status.as_ref().and_then(|s| s.deleted_date), filter.deleted_status_filter)
+        })
+        .collect();
+
+    // The continuation token is the number of the next page, starting at 1
+    let page_size = filter.page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+    let page = filter.continuation_token.unwrap_or(1).max(1);
+    let start = (page - 1).saturating_mul(page_size);
+    let notifications_json: Vec<Value> = notifications
+        .iter()
+        .skip(start)
+        .take(page_size)
+        .map(|(notification, status)| notification.to_json(status.as_ref()))
+        .collect();
+    let continuation_token = (notifications.len() > start.saturating_add(page_size)).then(|| (page + 1).to_string());
+
+    Json(json!({
+        "data": notifications_json,
+        "object": "list",
+        "continuationToken": continuation_token,
+    }))
+}
+
+#[patch("/notifications/<notification_id>/read")]
+async fn mark_notification_read(
+    notification_id: NotificationId,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    update_notification_status(&notification_id, &headers, &conn, &nt, |status| {
+        status.read_date.get_or_insert_with(|| chrono::Utc::now().naive_utc());
+    })
+    .await
+}
+
+#[patch("/notifications/<notification_id>/delete")]
+async fn mark_notification_deleted(
+    notification_id: NotificationId,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    update_notification_status(&notification_id, &headers, &conn, &nt, |status| {
+        status.deleted_date.get_or_insert_with(|| chrono::Utc::now().naive_utc());
+    })
+    .await
+}
+
+async fn update_notification_status(
+    notification_id: &NotificationId,
+    headers: &Headers,
+    conn: &DbConn,
+    nt: &Notify<'_>,
+    update: impl FnOnce(&mut NotificationStatus),
+) -> EmptyResult {
+    let Some(notification) = Notification::find_by_uuid_and_user(notification_id, &headers.user.uuid, conn).await
+    else {
+        err_code!("Notification not found", rocket::http::Status::NotFound.code)
+    };
+
+    let mut status = NotificationStatus::find_or_new(notification_id, &headers.user.uuid, conn).await;
+    update(&mut status);
+    status.save(conn).await?;
+
+    nt.send_notification(
+        UpdateType::NotificationStatus,
+        &notification,
+        Some(&status),
+        std::slice::from_ref(&headers.user.uuid),
+        Some(&headers.device),
+        conn,
+    )
+    .await;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationData {
+    title: Option<String>,
+    body: Option<String>,
+    priority: Option<i32>,
+    /// Only show the notification on this kind of client, all clients if not set
+    client_type: Option<i32>,
+}
+
+impl NotificationData {
+    /// Creates the notification for the given recipients, none meaning all users.
+    pub fn into_notification(
+        self,
+        user_uuid: Option<UserId>,
+        organization_uuid: Option<OrganizationId>,
+    ) -> Result<Notification, crate::Error> {
+        if self.title.as_deref().is_none_or(str::is_empty) && self.body.as_deref().is_none_or(str::is_empty) {
+            err!("A notification needs a title or a body")
+        }
+        let Some(priority) = NotificationPriority::from_i32(self.priority.unwrap_or(0)) else {
+            err!("Invalid notification priority")
+        };
+        let client_type = self.client_type.unwrap_or(0);
+        if !(0..=5).contains(&client_type) {
+            err!("Invalid notification client type")
+        }
+
+        Ok(Notification::new(priority, client_type, user_uuid, organization_uuid, self.title, self.body))
+    }
+}
+
+/// Saves the notification and sends it to everyone it is shown to.
+pub async fn send_notification(notification: &Notification, conn: &DbConn, nt: &Notify<'_>) -> EmptyResult {
+    notification.save(conn).await?;
+
+    let user_ids: Vec<UserId> = if let Some(user_id) = &notification.user_uuid {
+        vec![user_id.clone()]
+    } else if let Some(org_id) = &notification.organization_uuid {
+        Membership::find_confirmed_by_org(org_id, conn).await.into_iter().map(|m| m.user_uuid).collect()
+    } else {
+        User::get_all(conn).await.into_iter().map(|(user, _)| user.uuid).collect()
+    };
+
+    nt.send_notification(UpdateType::Notification, notification, None, &user_ids, None, conn).await;
+    Ok(())
+}
+
+/// Lets the owners of an organization show a notification to all confirmed members.
+#[post("/organizations/<org_id>/notifications", data = "<data>")]
+async fn post_organization_notification(
+    org_id: OrganizationId,
+    data: Json<NotificationData>,
+    headers: OwnerHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+
+    let notification = data.into_inner().into_notification(None, Some(org_id))?;
+    send_notification(&notification, &conn, &nt).await?;
+
+    Ok(Json(notification.to_json(None)))
+}
@@ -15,9 +15,9 @@ use crate::{
     db::{
         DbConn,
         models::{
-            Cipher, CipherId, Collection, CollectionCipher, CollectionGroup, CollectionId, CollectionUser, EventType,
-            Group, GroupId, GroupUser, Invitation, Membership, MembershipId, MembershipStatus, MembershipType,
-            OrgPolicy, OrgPolicyType, Organization, OrganizationApiKey, OrganizationId, User, UserId,
+            Cipher, CipherId, Collection, CollectionCipher, CollectionGroup, CollectionId, CollectionUser, Device,
+            EventType, Group, GroupId, GroupUser, Invitation, Membership, MembershipId, MembershipStatus,
+            MembershipType, OrgPolicy, OrgPolicyType, Organization, OrganizationApiKey, OrganizationId, User, UserId,
         },
     },
     mail,
@@ -70,6 +70,12 @@ pub fn routes() -> Vec<Route> {
         get_policy,
         put_policy,
         put_policy_vnext,
+        get_org_domains,
+        post_org_domains,
+        put_org_domains,
+        get_org_trash_retention,
+        post_org_trash_retention,
+        put_org_trash_retention,
         get_plans,
         post_org_keys,
         get_organization_keys,
@@ -340,6 +346,146 @@ async fn post_organization(
     Ok(Json(org.to_json()))
 }
 
+#[get("/organizations/<org_id>/domains")]
+async fn get_org_domains(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(json!({
+        "equivalentDomains": org.get_equivalent_domains(),
+        "object": "organizationDomains",
+    })))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgDomainsData {
+    equivalent_domains: Vec<Vec<String>>,
+}
+
+// The equivalent domains of the organization are added to those of every member in their sync
+#[post("/organizations/<org_id>/domains", data = "<data>")]
+async fn post_org_domains(
+    org_id: OrganizationId,
+    data: Json<OrgDomainsData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    org.set_equivalent_domains(data.into_inner().equivalent_domains);
+    // Also updates the revision of all members, so their clients pick up the domains
+    org.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(json!({
+        "equivalentDomains": org.get_equivalent_domains(),
+        "object": "organizationDomains",
+    })))
+}
+
+#[put("/organizations/<org_id>/domains", data = "<data>")]
+async fn put_org_domains(
+    org_id: OrganizationId,
+    data: Json<OrgDomainsData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    post_org_domains(org_id, data, headers, conn).await
+}
+
+fn org_trash_retention_json(org: &Organization) -> Value {
+    json!({
+        "trashRetentionDays": org.trash_retention_days,
+        "defaultTrashRetentionDays": CONFIG.trash_auto_delete_days(),
+        "object": "organizationTrashRetention",
+    })
+}
+
+#[get("/organizations/<org_id>/trash-retention")]
+async fn get_org_trash_retention(org_id: OrganizationId, headers: AdminHeaders, conn: DbConn) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    Ok(Json(org_trash_retention_json(&org)))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrgTrashRetentionData {
+    trash_retention_days: Option<i32>,
+}
+
+// Overrides `TRASH_AUTO_DELETE_DAYS` for the items of the organization, `null` falls back to it.
+// Like the global setting, 0 deletes trashed items at the next purge.
+#[post("/organizations/<org_id>/trash-retention", data = "<data>")]
+async fn post_org_trash_retention(
+    org_id: OrganizationId,
+    data: Json<OrgTrashRetentionData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    if org_id != headers.org_id {
+        err!("Organization not found", "Organization id's do not match");
+    }
+    let Some(mut org) = Organization::find_by_uuid(&org_id, &conn).await else {
+        err!("Organization not found")
+    };
+
+    let trash_retention_days = data.into_inner().trash_retention_days;
+    if trash_retention_days.is_some_and(|days| !(0..=36500).contains(&days)) {
+        err!("The trash retention has to be between 0 and 36500 days")
+    }
+    org.trash_retention_days = trash_retention_days;
+    org.save(&conn).await?;
+
+    log_event(
+        EventType::OrganizationUpdated as i32,
+        org_id.as_ref(),
+        &org_id,
+        &headers.user.uuid,
+        headers.device.atype,
+        &headers.ip.ip,
+        &conn,
+    )
+    .await;
+
+    Ok(Json(org_trash_retention_json(&org)))
+}
+
+#[put("/organizations/<org_id>/trash-retention", data = "<data>")]
+async fn put_org_trash_retention(
+    org_id: OrganizationId,
+    data: Json<OrgTrashRetentionData>,
+    headers: AdminHeaders,
+    conn: DbConn,
+) -> JsonResult {
+    post_org_trash_retention(org_id, data, headers, conn).await
+}
+
 // GET /api/collections?writeOnly=false
 #[get("/collections")]
 async fn get_user_collections(headers: Headers, conn: DbConn) -> Json<Value> {
@@ -499,6 +645,7 @@ async fn post_organization_collections(
     headers: ManagerHeadersLoose,
     data: Json<FullCollectionData>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.membership.org_uuid {
         err!("Organization not found", "Organization id's do not match");
@@ -550,6 +697,9 @@ async fn post_organization_collections(
         .await?;
     }
 
+    send_sync_organizations(collection_user_ids(&collection.uuid, &org_id, &conn).await, &headers.device, &conn, &nt)
+        .await;
+
     Ok(Json(collection.to_json_details(&headers.membership.user_uuid, None, &conn).await))
 }
 
@@ -567,6 +717,7 @@ async fn post_bulk_access_collections(
     headers: ManagerHeadersLoose,
     data: Json<BulkCollectionAccessData>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.membership.org_uuid {
         err!("Organization not found", "Organization id's do not match");
@@ -584,6 +735,7 @@ async fn post_bulk_access_collections(
         err!("Invalid group", format!("Group {} does not belong to organization {}!", g.id, org_id))
     }
 
+    let mut affected_user_ids = HashSet::new();
     for col_id in data.collection_ids {
         let Some(collection) = Collection::find_by_uuid_and_org(&col_id, &org_id, &conn).await else {
             err!("Collection not found")
@@ -595,6 +747,7 @@ async fn post_bulk_access_collections(
 
         // update collection modification date
         collection.save(&conn).await?;
+        affected_user_ids.extend(collection_user_ids(&col_id, &org_id, &conn).await);
 
         log_event(
             EventType::CollectionUpdated as i32,
@@ -627,8 +780,10 @@ async fn post_bulk_access_collections(
             CollectionUser::save(&member.user_uuid, &col_id, user.read_only, user.hide_passwords, user.manage, &conn)
                 .await?;
         }
+        affected_user_ids.extend(collection_user_ids(&col_id, &org_id, &conn).await);
     }
 
+    send_sync_organizations(affected_user_ids, &headers.device, &conn, &nt).await;
     Ok(())
 }
 
@@ -639,8 +794,9 @@ async fn put_organization_collection_update(
     headers: ManagerHeaders,
     data: Json<FullCollectionData>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
-    post_organization_collection_update(org_id, col_id, headers, data, conn).await
+    post_organization_collection_update(org_id, col_id, headers, data, conn, nt).await
 }
 
 #[post("/organizations/<org_id>/collections/<col_id>", data = "<data>", rank = 2)]
@@ -650,6 +806,7 @@ async fn post_organization_collection_update(
     headers: ManagerHeaders,
     data: Json<FullCollectionData>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -684,6 +841,7 @@ async fn post_organization_collection_update(
     )
     .await;
 
+    let mut affected_user_ids = collection_user_ids(&col_id, &org_id, &conn).await;
     CollectionGroup::delete_all_by_collection(&col_id, &org_id, &conn).await?;
 
     for group in data.groups {
@@ -707,6 +865,9 @@ async fn post_organization_collection_update(
             .await?;
     }
 
+    affected_user_ids.extend(collection_user_ids(&col_id, &org_id, &conn).await);
+    send_sync_organizations(affected_user_ids, &headers.device, &conn, &nt).await;
+
     Ok(Json(collection.to_json_details(&headers.user.uuid, None, &conn).await))
 }
 
@@ -715,6 +876,7 @@ async fn delete_organization_collection_impl(
     col_id: &CollectionId,
     headers: &ManagerHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -732,7 +894,11 @@ async fn delete_organization_collection_impl(
         conn,
     )
     .await;
-    collection.delete(conn).await
+    // Gather the members before the access rows are removed together with the collection
+    let affected_user_ids = collection_user_ids(col_id, org_id, conn).await;
+    collection.delete(conn).await?;
+    send_sync_organizations(affected_user_ids, &headers.device, conn, nt).await;
+    Ok(())
 }
 
 #[delete("/organizations/<org_id>/collections/<col_id>")]
@@ -741,8 +907,9 @@ async fn delete_organization_collection(
     col_id: CollectionId,
     headers: ManagerHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    delete_organization_collection_impl(&org_id, &col_id, &headers, &conn).await
+    delete_organization_collection_impl(&org_id, &col_id, &headers, &conn, &nt).await
 }
 
 #[post("/organizations/<org_id>/collections/<col_id>/delete")]
@@ -751,8 +918,9 @@ async fn post_organization_collection_delete(
     col_id: CollectionId,
     headers: ManagerHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    delete_organization_collection_impl(&org_id, &col_id, &headers, &conn).await
+    delete_organization_collection_impl(&org_id, &col_id, &headers, &conn, &nt).await
 }
 
 #[derive(Deserialize, Debug)]
@@ -767,6 +935,7 @@ async fn bulk_delete_organization_collections(
     headers: ManagerHeadersLoose,
     data: Json<BulkCollectionIds>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.membership.org_uuid {
         err!("Organization not found", "Organization id's do not match");
@@ -778,7 +947,7 @@ async fn bulk_delete_organization_collections(
     let headers = ManagerHeaders::from_loose(headers, &collections, &conn).await?;
 
     for col_id in collections {
-        delete_organization_collection_impl(&org_id, &col_id, &headers, &conn).await?;
+        delete_organization_collection_impl(&org_id, &col_id, &headers, &conn, &nt).await?;
     }
     Ok(())
 }
@@ -1133,6 +1302,7 @@ async fn send_invite(
                 new_member.uuid.clone(),
                 &org_name,
                 Some(headers.user.email.clone()),
+                &conn,
             )
             .await
             {
@@ -1267,7 +1437,8 @@ async fn reinvite_member_impl(
     };
 
     if CONFIG.mail_enabled() {
-        mail::send_invite(&user, org_id.clone(), member.uuid, &org_name, Some(invited_by_email.to_owned())).await?;
+        mail::send_invite(&user, org_id.clone(), member.uuid, &org_name, Some(invited_by_email.to_owned()), conn)
+            .await?;
     } else if user.password_hash.is_empty() {
         let invitation = Invitation::new(&user.email);
         invitation.save(conn).await?;
@@ -1337,7 +1508,7 @@ async fn accept_invite(
     } else if CONFIG.mail_enabled() {
         // User was invited from /admin, so they are automatically confirmed
         let org_name = CONFIG.invitation_org_name();
-        mail::send_invite_confirmed(&claims.email, &org_name).await?;
+        mail::send_invite_confirmed(&claims.email, &org_name, &conn).await?;
     }
 
     Ok(())
@@ -1468,7 +1639,7 @@ async fn confirm_invite_impl(
         } else {
             err!("Error looking up user.")
         };
-        mail::send_invite_confirmed(&address, &org_name).await?;
+        mail::send_invite_confirmed(&address, &org_name, conn).await?;
     }
 
     let save_result = member_to_confirm.save(conn).await;
@@ -1535,8 +1706,9 @@ async fn put_member(
     data: Json<EditUserData>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    edit_member(org_id, member_id, data, headers, conn).await
+    edit_member(org_id, member_id, data, headers, conn, nt).await
 }
 
 #[post("/organizations/<org_id>/users/<member_id>", data = "<data>", rank = 1)]
@@ -1546,6 +1718,7 @@ async fn edit_member(
     data: Json<EditUserData>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -1594,6 +1767,7 @@ async fn edit_member(
         }
     }
 
+    let limit_collection_creation = member_to_edit.limit_collection_creation();
     member_to_edit.access_all = access_all;
     member_to_edit.atype = new_type as i32;
 
@@ -1647,7 +1821,13 @@ async fn edit_member(
     )
     .await;
 
-    member_to_edit.save(&conn).await
+    member_to_edit.save(&conn).await?;
+
+    if member_to_edit.limit_collection_creation() != limit_collection_creation {
+        nt.send_org_collection_settings_update(&member_to_edit, &headers.device, &conn).await;
+    }
+    send_sync_organizations(HashSet::from([member_to_edit.user_uuid]), &headers.device, &conn, &nt).await;
+    Ok(())
 }
 
 #[delete("/organizations/<org_id>/users", data = "<data>")]
@@ -1945,7 +2125,7 @@ async fn post_bulk_collections(data: Json<BulkCollectionsData>, headers: Headers
     for cipher_id in &data.cipher_ids {
         // Only act on existing cipher uuid's
         // Do not abort the operation just ignore it, it could be a cipher was just deleted for example
-        if let Some(cipher) = Cipher::find_by_uuid_and_org(cipher_id, &data.organization_id, &conn).await
+        if let Some(mut cipher) = Cipher::find_by_uuid_and_org(cipher_id, &data.organization_id, &conn).await
             && cipher.is_write_accessible_to_user(&headers.user.uuid, &conn).await
         {
             // When selecting a specific collection from the left filter list, and use the bulk option, you can remove an item from that collection
@@ -1959,6 +2139,7 @@ async fn post_bulk_collections(data: Json<BulkCollectionsData>, headers: Headers
                     CollectionCipher::save(&cipher.uuid, collection, &conn).await?;
                 }
             }
+            cipher.touch(&conn).await?;
         }
     }
 
@@ -2140,7 +2321,7 @@ async fn put_policy(
                     let org = Organization::find_by_uuid(&member.org_uuid, &conn).await.unwrap();
                     let user = User::find_by_uuid(&member.user_uuid, &conn).await.unwrap();
 
-                    mail::send_single_org_removed_from_org(&user.email, &org.name).await?;
+                    mail::send_single_org_removed_from_org(&user.email, &org.name, &conn).await?;
                 }
 
                 log_event(
@@ -2268,8 +2449,9 @@ async fn revoke_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    revoke_member_impl(&org_id, &member_id, &headers, &conn).await
+    revoke_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/revoke", data = "<data>")]
@@ -2278,6 +2460,7 @@ async fn bulk_revoke_members(
     data: Json<BulkRevokeMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2288,7 +2471,7 @@ async fn bulk_revoke_members(
     match data.ids {
         Some(members) => {
             for member_id in members {
-                let err_msg = match revoke_member_impl(&org_id, &member_id, &headers, &conn).await {
+                let err_msg = match revoke_member_impl(&org_id, &member_id, &headers, &conn, &nt).await {
                     Ok(()) => String::new(),
                     Err(e) => format!("{e:?}"),
                 };
@@ -2317,6 +2500,7 @@ async fn revoke_member_impl(
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2337,6 +2521,7 @@ async fn revoke_member_impl(
 
             member.revoke();
             member.save(conn).await?;
+            nt.send_org_status_update(&member, &headers.device, conn).await;
 
             log_event(
                 EventType::OrganizationUserRevoked as i32,
@@ -2361,10 +2546,11 @@ async fn restore_member_vnext(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     // Vaultwarden does not (yet) support the per User Collection linked to the `Enforce organization data ownership` policy.
     // Therefor we ignore the `defaultUserCollectionName` data sent and just call restore_member
-    restore_member_impl(&org_id, &member_id, &headers, &conn).await
+    restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/<member_id>/restore")]
@@ -2373,8 +2559,9 @@ async fn restore_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    restore_member_impl(&org_id, &member_id, &headers, &conn).await
+    restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await
 }
 
 #[put("/organizations/<org_id>/users/restore", data = "<data>")]
@@ -2383,6 +2570,7 @@ async fn bulk_restore_members(
     data: Json<BulkMembershipIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2391,7 +2579,7 @@ async fn bulk_restore_members(
 
     let mut bulk_response = Vec::new();
     for member_id in data.ids {
-        let err_msg = match restore_member_impl(&org_id, &member_id, &headers, &conn).await {
+        let err_msg = match restore_member_impl(&org_id, &member_id, &headers, &conn, &nt).await {
             Ok(()) => String::new(),
             Err(e) => format!("{e:?}"),
         };
@@ -2417,6 +2605,7 @@ async fn restore_member_impl(
     member_id: &MembershipId,
     headers: &AdminHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2435,6 +2624,7 @@ async fn restore_member_impl(
             // This check need to be done after restoring to work with the correct status
             OrgPolicy::check_user_allowed(&member, "restore", conn).await?;
             member.save(conn).await?;
+            nt.send_org_status_update(&member, &headers.device, conn).await;
 
             log_event(
                 EventType::OrganizationUserRestored as i32,
@@ -2581,8 +2771,9 @@ async fn post_group(
     data: Json<GroupRequest>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
-    put_group(org_id, group_id, data, headers, conn).await
+    put_group(org_id, group_id, data, headers, conn, nt).await
 }
 
 #[post("/organizations/<org_id>/groups", data = "<data>")]
@@ -2591,6 +2782,7 @@ async fn post_groups(
     headers: AdminHeaders,
     data: Json<GroupRequest>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2615,7 +2807,12 @@ async fn post_groups(
     )
     .await;
 
-    add_update_group(group, group_request.collections, group_request.users, org_id, &headers, &conn).await
+    let group_id = group.uuid.clone();
+    let result =
+        add_update_group(group, group_request.collections, group_request.users, org_id.clone(), &headers, &conn)
+            .await?;
+    send_sync_organizations(group_user_ids(&group_id, &org_id, &conn).await, &headers.device, &conn, &nt).await;
+    Ok(result)
 }
 
 #[put("/organizations/<org_id>/groups/<group_id>", data = "<data>")]
@@ -2625,6 +2822,7 @@ async fn put_group(
     data: Json<GroupRequest>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> JsonResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2642,6 +2840,8 @@ async fn put_group(
 
     let updated_group = group_request.update_group(group);
 
+    // The members removed from the group lose its access as well
+    let previous_user_ids = group_user_ids(&group_id, &org_id, &conn).await;
     CollectionGroup::delete_all_by_group(&group_id, &org_id, &conn).await?;
     GroupUser::delete_all_by_group(&group_id, &org_id, &conn).await?;
 
@@ -2656,7 +2856,19 @@ async fn put_group(
     )
     .await;
 
-    add_update_group(updated_group, group_request.collections, group_request.users, org_id, &headers, &conn).await
+    let result = add_update_group(
+        updated_group,
+        group_request.collections,
+        group_request.users,
+        org_id.clone(),
+        &headers,
+        &conn,
+    )
+    .await?;
+    let mut affected_user_ids = previous_user_ids;
+    affected_user_ids.extend(group_user_ids(&group_id, &org_id, &conn).await);
+    send_sync_organizations(affected_user_ids, &headers.device, &conn, &nt).await;
+    Ok(result)
 }
 
 async fn add_update_group(
@@ -2727,13 +2939,20 @@ async fn post_delete_group(
     group_id: GroupId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
-    delete_group_impl(&org_id, &group_id, &headers, &conn).await
+    delete_group_impl(&org_id, &group_id, &headers, &conn, &nt).await
 }
 
 #[delete("/organizations/<org_id>/groups/<group_id>")]
-async fn delete_group(org_id: OrganizationId, group_id: GroupId, headers: AdminHeaders, conn: DbConn) -> EmptyResult {
-    delete_group_impl(&org_id, &group_id, &headers, &conn).await
+async fn delete_group(
+    org_id: OrganizationId,
+    group_id: GroupId,
+    headers: AdminHeaders,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    delete_group_impl(&org_id, &group_id, &headers, &conn, &nt).await
 }
 
 async fn delete_group_impl(
@@ -2741,6 +2960,7 @@ async fn delete_group_impl(
     group_id: &GroupId,
     headers: &AdminHeaders,
     conn: &DbConn,
+    nt: &Notify<'_>,
 ) -> EmptyResult {
     if org_id != &headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2764,7 +2984,10 @@ async fn delete_group_impl(
     )
     .await;
 
-    group.delete(org_id, conn).await
+    let affected_user_ids = group_user_ids(group_id, org_id, conn).await;
+    group.delete(org_id, conn).await?;
+    send_sync_organizations(affected_user_ids, &headers.device, conn, nt).await;
+    Ok(())
 }
 
 #[delete("/organizations/<org_id>/groups", data = "<data>")]
@@ -2773,6 +2996,7 @@ async fn bulk_delete_groups(
     data: Json<BulkGroupIds>,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2784,7 +3008,7 @@ async fn bulk_delete_groups(
     let data: BulkGroupIds = data.into_inner();
 
     for group_id in data.ids {
-        delete_group_impl(&org_id, &group_id, &headers, &conn).await?;
+        delete_group_impl(&org_id, &group_id, &headers, &conn, &nt).await?;
     }
     Ok(())
 }
@@ -2839,6 +3063,7 @@ async fn put_group_members(
     headers: AdminHeaders,
     data: Json<Vec<MembershipId>>,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2859,6 +3084,7 @@ async fn put_group_members(
         err!("Invalid member", format!("Member {} does not belong to organization {}!", e, org_id))
     }
 
+    let mut affected_user_ids = group_user_ids(&group_id, &org_id, &conn).await;
     GroupUser::delete_all_by_group(&group_id, &org_id, &conn).await?;
     for assigned_member in assigned_members {
         let mut user_entry = GroupUser::new(group_id.clone(), assigned_member.clone());
@@ -2876,6 +3102,8 @@ async fn put_group_members(
         .await;
     }
 
+    affected_user_ids.extend(group_user_ids(&group_id, &org_id, &conn).await);
+    send_sync_organizations(affected_user_ids, &headers.device, &conn, &nt).await;
     Ok(())
 }
 
@@ -2886,6 +3114,7 @@ async fn post_delete_group_member(
     member_id: MembershipId,
     headers: AdminHeaders,
     conn: DbConn,
+    nt: Notify<'_>,
 ) -> EmptyResult {
     if org_id != headers.org_id {
         err!("Organization not found", "Organization id's do not match");
@@ -2894,9 +3123,9 @@ async fn post_delete_group_member(
         err!("Group support is disabled");
     }
 
-    if Membership::find_by_uuid_and_org(&member_id, &org_id, &conn).await.is_none() {
+    let Some(member) = Membership::find_by_uuid_and_org(&member_id, &org_id, &conn).await else {
         err!("User could not be found or does not belong to the organization.");
-    }
+    };
 
     if Group::find_by_uuid_and_org(&group_id, &org_id, &conn).await.is_none() {
         err!("Group could not be found or does not belong to the organization.");
@@ -2913,7 +3142,9 @@ async fn post_delete_group_member(
     )
     .await;
 
-    GroupUser::delete_by_group_and_member(&group_id, &member_id, &conn).await
+    GroupUser::delete_by_group_and_member(&group_id, &member_id, &conn).await?;
+    send_sync_organizations(HashSet::from([member.user_uuid]), &headers.device, &conn, &nt).await;
+    Ok(())
 }
 
 #[derive(Deserialize)]
@@ -3027,7 +3258,7 @@ async fn recover_account(
 
     // Sending email before resetting password to ensure working email configuration and the resulting
     // user notification. Also this might add some protection against security flaws and misuse
-    if let Err(e) = mail::send_admin_reset_password(&user.email, user.display_name(), &org.name).await {
+    if let Err(e) = mail::send_admin_reset_password(&user.email, user.display_name(), &org.name, &conn).await {
         err!(format!("Error sending user reset password email: {e:#?}"));
     }
 
@@ -3251,3 +3482,24 @@ async fn rotate_api_key(
 ) -> JsonResult {
     api_key(&org_id, data, true, headers, conn).await
 }
+
+/// The users with access to the collection, either directly or through a group.
+async fn collection_user_ids(col_id: &CollectionId, org_id: &OrganizationId, conn: &DbConn) -> HashSet<UserId> {
+    let mut user_ids: HashSet<UserId> =
+        CollectionUser::find_by_collection(col_id, conn).await.into_iter().map(|c| c.user_uuid).collect();
+    user_ids.extend(CollectionGroup::find_user_uuids_by_collection(col_id, org_id, conn).await);
+    user_ids
+}
+
+async fn group_user_ids(group_id: &GroupId, org_id: &OrganizationId, conn: &DbConn) -> HashSet<UserId> {
+    GroupUser::find_user_uuids_by_group(group_id, org_id, conn).await.into_iter().collect()
+}
+
+/// Lets the clients of the users know their access within the organization changed, so they sync right away.
+async fn send_sync_organizations(user_ids: HashSet<UserId>, device: &Device, conn: &DbConn, nt: &Notify<'_>) {
+    for user_id in user_ids {
+        if let Some(user) = User::find_by_uuid(&user_id, conn).await {
+            nt.send_user_update(UpdateType::SyncOrganizations, &user, device.push_uuid.as_ref(), conn).await;
+        }
+    }
+}
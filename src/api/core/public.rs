@@ -133,7 +133,8 @@ async fn ldap_import(data: Json<OrgImportData>, token: PublicToken, conn: DbConn
 
             if CONFIG.mail_enabled()
                 && let Err(e) =
-                    mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org_name, Some(org_email)).await
+                    mail::send_invite(&user, org_id.clone(), new_member.uuid.clone(), &org_name, Some(org_email), &conn)
+                        .await
             {
                 // Upon error delete the user, invite and org member records when needed
                 if user_created {
@@ -1,22 +1,30 @@
-use std::{path::Path, sync::LazyLock, time::Duration};
+use std::{path::Path, sync::LazyLock};
 
 use chrono::{DateTime, TimeDelta, Utc};
 use num_traits::ToPrimitive;
 use rocket::{
+    Data, Either,
     form::Form,
     fs::{NamedFile, TempFile},
+    response::Redirect,
     serde::json::Json,
 };
 use serde_json::Value;
 
 use crate::{
     CONFIG,
-    api::{ApiResult, EmptyResult, JsonResult, Notify, UpdateType},
+    api::{
+        ApiResult, EmptyResult, JsonResult, Notify, UpdateType, WS_USERS,
+        core::uploads::{ChunkedUpload, FileUploadType, UploadOffset, UploadStatus},
+    },
     auth::{ClientIp, Headers, Host, SendHeaders},
     config::PathType,
     db::{
         DbConn, DbPool,
-        models::{Device, OrgPolicy, OrgPolicyType, Send, SendFileId, SendId, SendType, UserId},
+        models::{
+            Device, OrgPolicy, OrgPolicyType, PendingUpload, PendingUploadType, Send, SendFileId, SendId, SendType,
+            UserId,
+        },
     },
     util::{NumberOrString, save_temp_file},
 };
@@ -56,16 +64,21 @@ pub fn routes() -> Vec<rocket::Route> {
         put_remove_password,
         download_send,
         post_send_file_v2,
-        post_send_file_v2_data
+        post_send_file_v2_data,
+        head_send_file_upload,
+        patch_send_file_upload,
+        renew_send_file_upload_url,
+        post_send_file_validate
     ]
 }
 
-pub async fn purge_sends(pool: DbPool) {
+pub async fn purge_sends(pool: DbPool) -> usize {
     debug!("Purging sends");
     if let Ok(conn) = pool.get().await {
-        Send::purge(&conn).await;
+        Send::purge(&conn).await
     } else {
         error!("Failed to get DB connection while purging sends");
+        0
     }
 }
 
@@ -361,14 +374,35 @@ async fn post_send_file_v2(data: Json<SendData>, headers: Headers, conn: DbConn)
     send.data = serde_json::to_string(&data_value)?;
     send.save(&conn).await?;
 
+    let (url, upload_type) = send_file_upload_url(&send.uuid, &file_id, file_length).await?;
+    PendingUpload::new(
+        send.uuid.to_string(),
+        PendingUploadType::Send,
+        &headers.device,
+        headers.ip.ip.to_string(),
+        matches!(upload_type, FileUploadType::Azure),
+    )
+    .save(&conn)
+    .await?;
+
     Ok(Json(json!({
-        "fileUploadType": 0, // 0 == Direct | 1 == Azure
+        "fileUploadType": upload_type as i32,
         "object": "send-fileUpload",
-        "url": format!("/sends/{}/file/{file_id}", send.uuid),
+        "url": url,
         "sendResponse": send.to_json()
     })))
 }
 
+/// Returns the URL the client should upload the Send file to, and how it has to be uploaded.
+async fn send_file_upload_url(send_id: &SendId, file_id: &str, size: i64) -> ApiResult<(String, FileUploadType)> {
+    let direct_url = crate::storage::direct_upload_url(&PathType::Sends, &format!("{send_id}/{file_id}"), size).await?;
+
+    Ok(match direct_url {
+        Some(url) => (url, FileUploadType::Azure),
+        None => (format!("/sends/{send_id}/file/{file_id}"), FileUploadType::Direct),
+    })
+}
+
 #[derive(Deserialize)]
 #[allow(non_snake_case)]
 pub struct SendFileData {
@@ -377,6 +411,34 @@ pub struct SendFileData {
     fileName: String,
 }
 
+/// Looks up a file Send created through the v2 API, whose file the user is allowed to upload.
+async fn find_uploadable_send_file(
+    send_id: &SendId,
+    file_id: &SendFileId,
+    headers: &Headers,
+    conn: &DbConn,
+) -> ApiResult<(Send, SendFileData)> {
+    enforce_disable_send_policy(headers, conn).await?;
+
+    let Some(send) = Send::find_by_uuid_and_user(send_id, &headers.user.uuid, conn).await else {
+        err!("Send not found. Unable to save the file.", "Invalid send uuid or does not belong to user.")
+    };
+
+    if send.atype != SendType::File as i32 {
+        err!("Send is not a file type send.");
+    }
+
+    let Ok(send_data) = serde_json::from_str::<SendFileData>(&send.data) else {
+        err!("Unable to decode send data as json.")
+    };
+
+    if *file_id != send_data.id {
+        err!("Send file does not match send data.", format!("Expected id {} got {file_id}", send_data.id));
+    }
+
+    Ok((send, send_data))
+}
+
 // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/Tools/Controllers/SendsController.cs#L195
 #[post("/sends/<send_id>/file/<file_id>", format = "multipart/form-data", data = "<data>", rank = 2)]
 async fn post_send_file_v2_data(
@@ -387,22 +449,10 @@ async fn post_send_file_v2_data(
     conn: DbConn,
     nt: Notify<'_>,
 ) -> EmptyResult {
-    enforce_disable_send_policy(&headers, &conn).await?;
+    let (send, send_data) = find_uploadable_send_file(&send_id, &file_id, &headers, &conn).await?;
 
     let data = data.into_inner();
 
-    let Some(send) = Send::find_by_uuid_and_user(&send_id, &headers.user.uuid, &conn).await else {
-        err!("Send not found. Unable to save the file.", "Invalid send uuid or does not belong to user.")
-    };
-
-    if send.atype != SendType::File as i32 {
-        err!("Send is not a file type send.");
-    }
-
-    let Ok(send_data) = serde_json::from_str::<SendFileData>(&send.data) else {
-        err!("Unable to decode send data as json.")
-    };
-
     match data.data.raw_name() {
         Some(raw_file_name)
             if raw_file_name.dangerous_unsafe_unsanitized_raw() == send_data.fileName
@@ -420,10 +470,6 @@ async fn post_send_file_v2_data(
         _ => err!("Send file name does not match or is not provided."),
     }
 
-    if file_id != send_data.id {
-        err!("Send file does not match send data.", format!("Expected id {} got {file_id}", send_data.id));
-    }
-
     let Some(size) = data.data.len().to_u64() else {
         err!("Send file size overflow.");
     };
@@ -435,6 +481,185 @@ async fn post_send_file_v2_data(
     let file_path = format!("{send_id}/{file_id}");
 
     save_temp_file(&PathType::Sends, &file_path, data.data, false).await?;
+    PendingUpload::delete(&send.uuid, &conn).await?;
+
+    nt.send_send_update(
+        UpdateType::SyncSendCreate,
+        &send,
+        &send.update_users_revision(&conn).await,
+        &headers.device,
+        &conn,
+    )
+    .await;
+
+    Ok(())
+}
+
+fn chunked_send_upload(send_id: &SendId, file_id: &SendFileId, send_data: &SendFileData) -> ApiResult<ChunkedUpload> {
+    ChunkedUpload::new(
+        &format!("send-{send_id}-{file_id}"),
+        send_data.size,
+        &PathType::Sends,
+        format!("{send_id}/{file_id}"),
+        false,
+    )
+}
+
+/// Chunked upload API, returns how much of the Send file has been received so far.
+#[head("/sends/<send_id>/file/<file_id>/upload")]
+async fn head_send_file_upload(
+    send_id: SendId,
+    file_id: SendFileId,
+    headers: Headers,
+    conn: DbConn,
+) -> ApiResult<UploadStatus> {
+    let (_, send_data) = find_uploadable_send_file(&send_id, &file_id, &headers, &conn).await?;
+
+    Ok(chunked_send_upload(&send_id, &file_id, &send_data)?.status().await)
+}
+
+/// Chunked upload API, appends a chunk to the file of a Send created through the v2 API.
+/// Once all chunks have been received, the file is stored the same way as with post_send_file_v2_data().
+#[patch("/sends/<send_id>/file/<file_id>/upload", data = "<data>")]
+async fn patch_send_file_upload(
+    send_id: SendId,
+    file_id: SendFileId,
+    offset: UploadOffset,
+    data: Data<'_>,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> ApiResult<UploadStatus> {
+    let (send, send_data) = find_uploadable_send_file(&send_id, &file_id, &headers, &conn).await?;
+
+    let (status, checksum) = chunked_send_upload(&send_id, &file_id, &send_data)?.append(offset.0, data).await?;
+    if checksum.is_some() {
+        PendingUpload::delete(&send.uuid, &conn).await?;
+        nt.send_send_update(
+            UpdateType::SyncSendCreate,
+            &send,
+            &send.update_users_revision(&conn).await,
+            &headers.device,
+            &conn,
+        )
+        .await;
+    }
+
+    Ok(status)
+}
+
+/// Returns a new upload URL for a file Send created through the v2 API, for when the previous one expired.
+#[get("/sends/<send_id>/file/<file_id>")]
+async fn renew_send_file_upload_url(
+    send_id: SendId,
+    file_id: SendFileId,
+    headers: Headers,
+    conn: DbConn,
+) -> JsonResult {
+    let (send, send_data) = find_uploadable_send_file(&send_id, &file_id, &headers, &conn).await?;
+    let Some(size) = send_data.size.to_i64() else {
+        err!("Send file size overflow.");
+    };
+    let (url, upload_type) = send_file_upload_url(&send_id, &file_id, size).await?;
+    PendingUpload::new(
+        send.uuid.to_string(),
+        PendingUploadType::Send,
+        &headers.device,
+        headers.ip.ip.to_string(),
+        matches!(upload_type, FileUploadType::Azure),
+    )
+    .save(&conn)
+    .await?;
+
+    Ok(Json(json!({
+        "fileUploadType": upload_type as i32,
+        "object": "send-fileUpload",
+        "url": url,
+        "sendResponse": send.to_json()
+    })))
+}
+
+/// Returns the size of the stored Send file, or None when it hasn't been uploaded yet.
+async fn stored_send_file_size(send: &Send, send_data: &SendFileData) -> ApiResult<Option<u64>> {
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
+    match operator.stat(&format!("{}/{}", send.uuid, send_data.id)).await {
+        Ok(metadata) => Ok(Some(metadata.content_length())),
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Checks the stored Send file against the size provided when creating the Send.
+/// The file is deleted if they don't match, so it can be uploaded again before the upload expires.
+async fn verify_stored_send_file(send: &Send, send_data: &SendFileData, size: u64, conn: &DbConn) -> EmptyResult {
+    if size != send_data.size {
+        let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
+        operator.delete(&format!("{}/{}", send.uuid, send_data.id)).await.ok();
+        err!("Send file size does not match.", format!("Expected a file size of {} got {size}", send_data.size));
+    }
+
+    PendingUpload::delete(&send.uuid, conn).await
+}
+
+/// Completes a file Send created through the v2 API whose file was uploaded through a presigned URL,
+/// or deletes it when its file wasn't received before the upload expired.
+/// Returns whether the pending upload was resolved.
+pub async fn check_pending_send_file(pending: &PendingUpload, conn: &DbConn) -> ApiResult<bool> {
+    let send = Send::find_by_uuid(&SendId::from(pending.uuid.clone()), conn).await;
+    let send_data = send.as_ref().and_then(|send| serde_json::from_str::<SendFileData>(&send.data).ok());
+    let (Some(send), Some(send_data)) = (send, send_data) else {
+        PendingUpload::delete(&pending.uuid, conn).await?;
+        return Ok(true);
+    };
+
+    // The server completes the uploads it receives itself, unless it stopped while doing so
+    if !pending.presigned && !pending.is_expired() {
+        return Ok(false);
+    }
+
+    let update_type = match stored_send_file_size(&send, &send_data).await? {
+        Some(size) => {
+            if let Err(e) = verify_stored_send_file(&send, &send_data, size, conn).await {
+                info!("Deleted the file of Send {}: {e}", send.uuid);
+                return Ok(false);
+            }
+            UpdateType::SyncSendCreate
+        }
+        None if pending.is_expired()
+            && !chunked_send_upload(&send.uuid, &send_data.id, &send_data)?.in_progress().await =>
+        {
+            info!("Deleted Send {}, its file was never uploaded", send.uuid);
+            send.delete(conn).await?;
+            PendingUpload::delete(&pending.uuid, conn).await?;
+            UpdateType::SyncSendDelete
+        }
+        None => return Ok(false),
+    };
+
+    let user_ids = send.update_users_revision(conn).await;
+    if let Some(device) = Device::find_by_uuid_and_user(&pending.device_uuid, &pending.user_uuid, conn).await {
+        WS_USERS.send_send_update(update_type, &send, &user_ids, &device, conn).await;
+    }
+    Ok(true)
+}
+
+/// Completion callback for Send files uploaded directly to the object storage through a presigned URL.
+/// Deletes the uploaded file if its size doesn't match the size provided when creating the Send.
+/// Most clients don't call this, in which case the pending uploads job completes the Send.
+#[post("/sends/<send_id>/file/<file_id>/validate")]
+async fn post_send_file_validate(
+    send_id: SendId,
+    file_id: SendFileId,
+    headers: Headers,
+    conn: DbConn,
+    nt: Notify<'_>,
+) -> EmptyResult {
+    let (send, send_data) = find_uploadable_send_file(&send_id, &file_id, &headers, &conn).await?;
+
+    let Some(size) = stored_send_file_size(&send, &send_data).await? else {
+        err!("Send file has not been uploaded.")
+    };
+    verify_stored_send_file(&send, &send_data, size, &conn).await?;
 
     nt.send_send_update(
         UpdateType::SyncSendCreate,
@@ -474,7 +699,7 @@ async fn post_access_legacy(
     ip: ClientIp,
     nt: Notify<'_>,
 ) -> JsonResult {
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     let Some(mut send) = Send::find_by_access_id(access_id, &conn).await else {
         err_code!(SEND_INACCESSIBLE_MSG, 404)
@@ -551,7 +776,7 @@ async fn post_access_file_legacy(
     ip: ClientIp,
     nt: Notify<'_>,
 ) -> JsonResult {
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     let Some(mut send) = Send::find_by_uuid(&send_id, &conn).await else {
         err_code!(SEND_INACCESSIBLE_MSG, 404)
@@ -595,31 +820,34 @@ async fn process_access_file(send: Send, file_id: SendFileId, host: Host, conn:
     Ok(Json(json!({
         "object": "send-fileDownload",
         "id": file_id,
-        "url": download_url(&host, &send.uuid, &file_id).await?,
+        "url": download_url(&host, &send.uuid, &file_id),
     })))
 }
 
-async fn download_url(host: &Host, send_id: &SendId, file_id: &SendFileId) -> Result<String, crate::Error> {
-    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
-
-    if crate::storage::is_fs_operator(&operator) {
-        let token_claims = crate::auth::generate_send_claims(send_id, file_id);
-        let token = crate::auth::encode_jwt(&token_claims);
+fn download_url(host: &Host, send_id: &SendId, file_id: &SendFileId) -> String {
+    let token_claims = crate::auth::generate_send_claims(send_id, file_id);
+    let token = crate::auth::encode_jwt(&token_claims);
 
-        Ok(format!("{}/api/sends/{send_id}/{file_id}?t={token}", host.host))
-    } else {
-        Ok(operator.presign_read(&format!("{send_id}/{file_id}"), Duration::from_mins(5)).await?.uri().to_string())
-    }
+    format!("{}/api/sends/{send_id}/{file_id}?t={token}", host.host)
 }
 
+/// Serves Send files from the local filesystem, or redirects to a short-lived presigned URL for object storage.
 #[get("/sends/<send_id>/<file_id>?<t>")]
-async fn download_send(send_id: SendId, file_id: SendFileId, t: &str) -> Option<NamedFile> {
-    if let Ok(claims) = crate::auth::decode_send(t)
-        && claims.sub == format!("{send_id}/{file_id}")
-    {
-        return NamedFile::open(Path::new(&CONFIG.sends_folder()).join(send_id).join(file_id)).await.ok();
+async fn download_send(send_id: SendId, file_id: SendFileId, t: &str) -> Option<Either<NamedFile, Redirect>> {
+    let Ok(claims) = crate::auth::decode_send(t) else {
+        return None;
+    };
+    if claims.sub != format!("{send_id}/{file_id}") {
+        return None;
+    }
+
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends).ok()?;
+    if !crate::storage::is_fs_operator(&operator) {
+        let url = crate::storage::presign_read(&operator, &claims.sub).await.ok()?;
+        return Some(Either::Right(Redirect::temporary(url)));
     }
-    None
+
+    NamedFile::open(Path::new(&CONFIG.sends_folder()).join(send_id).join(file_id)).await.ok().map(Either::Left)
 }
 
 #[put("/sends/<send_id>", data = "<data>")]
@@ -343,12 +343,13 @@ async fn extract_context(state: &str, conn: &DbConn) -> Option<DuoAuthContext> {
 }
 
 // Task to clean up expired Duo authentication contexts that may have accumulated in the database.
-pub async fn purge_duo_contexts(pool: DbPool) {
+pub async fn purge_duo_contexts(pool: DbPool) -> usize {
     debug!("Purging Duo authentication contexts");
     if let Ok(conn) = pool.get().await {
-        TwoFactorDuoContext::purge_expired_duo_contexts(&conn).await;
+        TwoFactorDuoContext::purge_expired_duo_contexts(&conn).await
     } else {
         error!("Failed to get DB connection while purging expired Duo authentications");
+        0
     }
 }
 
@@ -45,7 +45,7 @@ async fn send_email_login(data: Json<SendEmailLoginData>, client_headers: Client
     }
 
     // Ratelimit the login
-    crate::ratelimit::check_limit_login(&client_headers.ip.ip)?;
+    crate::ratelimit::check_limit_login(&client_headers.ip.ip).await?;
 
     // Get the user
     let email = match &data.email {
@@ -62,6 +62,7 @@ async fn send_email_login(data: Json<SendEmailLoginData>, client_headers: Client
     };
 
     let user = if let Some(email) = email {
+        crate::ratelimit::check_limit_login_account(email).await?;
         let Some(user) = User::find_by_mail(email, &conn).await else {
             err!("Username or password is incorrect. Try again.")
         };
@@ -117,7 +118,7 @@ pub async fn send_token(user_id: &UserId, conn: &DbConn) -> EmptyResult {
     twofactor.data = twofactor_data.to_json();
     twofactor.save(conn).await?;
 
-    mail::send_token(&twofactor_data.email, &twofactor_data.last_token.map_res("Token is empty")?).await?;
+    mail::send_token(&twofactor_data.email, &twofactor_data.last_token.map_res("Token is empty")?, conn).await?;
 
     Ok(())
 }
@@ -185,7 +186,7 @@ async fn send_email(data: Json<SendEmailData>, headers: Headers, conn: DbConn) -
     let twofactor = TwoFactor::new(user.uuid, TwoFactorType::EmailVerificationChallenge, twofactor_data.to_json());
     twofactor.save(&conn).await?;
 
-    mail::send_token(&twofactor_data.email, &twofactor_data.last_token.map_res("Token is empty")?).await?;
+    mail::send_token(&twofactor_data.email, &twofactor_data.last_token.map_res("Token is empty")?, &conn).await?;
 
     Ok(())
 }
@@ -183,7 +183,7 @@ pub async fn enforce_2fa_policy(
         if member.atype < MembershipType::Admin {
             if CONFIG.mail_enabled() {
                 let org = Organization::find_by_uuid(&member.org_uuid, conn).await.unwrap();
-                mail::send_2fa_removed_from_org(&user.email, &org.name).await?;
+                mail::send_2fa_removed_from_org(&user.email, &org.name, conn).await?;
             }
             let mut member = member;
             member.revoke();
@@ -218,7 +218,7 @@ pub async fn enforce_2fa_policy_for_org(
         if member.atype < MembershipType::Admin && TwoFactor::find_by_user(&member.user_uuid, conn).await.is_empty() {
             if CONFIG.mail_enabled() {
                 let user = User::find_by_uuid(&member.user_uuid, conn).await.unwrap();
-                mail::send_2fa_removed_from_org(&user.email, &org.name).await?;
+                mail::send_2fa_removed_from_org(&user.email, &org.name, conn).await?;
             }
             let mut member = member;
             member.revoke();
@@ -268,6 +268,7 @@ pub async fn send_incomplete_2fa_notifications(pool: DbPool) {
             &login.login_time,
             &login.device_name,
             &DeviceType::from_i32(login.device_type).to_string(),
+            &conn,
         )
         .await
         {
@@ -91,7 +91,7 @@ async fn request_otp(headers: Headers, conn: DbConn) -> EmptyResult {
     let twofactor = TwoFactor::new(user.uuid, TwoFactorType::ProtectedActions, pa_data.to_json());
     twofactor.save(&conn).await?;
 
-    mail::send_protected_action_token(&user.email, &pa_data.token).await?;
+    mail::send_protected_action_token(&user.email, &pa_data.token, &conn).await?;
 
     Ok(())
 }
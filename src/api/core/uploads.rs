@@ -0,0 +1,488 @@
+// Chunked uploads of attachments and Send files, loosely following the tus resumable upload protocol.
+// See https://tus.io/protocols/resumable-upload for details.
+//
+// After the file record has been created through the regular v2 API, the client sends the file in consecutive
+// `PATCH` requests, each carrying the offset the chunk starts at in the `Upload-Offset` header.
+// When a request fails, a `HEAD` request returns the offset from which the upload has to be resumed.
+//
+// With the local filesystem, the chunks are appended to a file in the `tmp_folder`, which is moved into the regular
+// storage once complete. With object storage, the chunks are written straight to their final location as a multipart
+// upload. That upload only lives in the memory of the instance receiving it, so after a restart, or when the chunks
+// are spread over instances, the upload has to start over.
+
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use dashmap::DashMap;
+use rocket::{
+    Data,
+    data::ToByteUnit,
+    http::{Header, Status},
+    request::{FromRequest, Outcome, Request},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use super::{ciphers::check_pending_attachment, sends::check_pending_send_file};
+use crate::{
+    CONFIG,
+    config::PathType,
+    db::{
+        DbPool,
+        models::{PendingUpload, PendingUploadType},
+    },
+    error::Error,
+    storage::is_fs_operator,
+    util::write_file,
+};
+
+/// How the client has to upload the content of an attachment or Send file created through the v2 API.
+#[derive(Clone, Copy)]
+pub enum FileUploadType {
+    Direct = 0,
+    Azure = 1, // Also used for presigned S3 URLs, which accept the same single `PUT` request
+}
+
+/// The offset of the chunk sent in a `PATCH` request, taken from the `Upload-Offset` header.
+pub struct UploadOffset(pub u64);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for UploadOffset {
+    type Error = &'static str;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one("Upload-Offset").map(str::parse::<u64>) {
+            Some(Ok(offset)) => Outcome::Success(UploadOffset(offset)),
+            Some(Err(_)) => Outcome::Error((Status::BadRequest, "Invalid Upload-Offset header provided")),
+            None => Outcome::Error((Status::BadRequest, "No Upload-Offset header provided")),
+        }
+    }
+}
+
+/// Response to both `HEAD` and `PATCH` requests, telling the client how much of the file has been received.
+#[derive(Responder)]
+#[response(status = 204)]
+pub struct UploadStatus {
+    inner: (),
+    offset: Header<'static>,
+    length: Header<'static>,
+    cache_control: Header<'static>,
+}
+
+impl UploadStatus {
+    fn new(offset: u64, length: u64) -> Self {
+        Self {
+            inner: (),
+            offset: Header::new("Upload-Offset", offset.to_string()),
+            length: Header::new("Upload-Length", length.to_string()),
+            cache_control: Header::new("Cache-Control", "no-store"),
+        }
+    }
+}
+
+/// Partial uploads which didn't receive a chunk for this long are considered abandoned.
+pub const STALE_UPLOAD_AGE: Duration = Duration::from_hours(24);
+
+/// Size of the parts of a multipart upload to object storage.
+const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Largest chunk accepted by a multipart upload, since the chunk is held in memory before it is written.
+const MAX_MULTIPART_CHUNK_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A multipart upload to object storage which is receiving chunks on this instance.
+struct MultipartUpload {
+    writer: opendal::Writer,
+    context: ring::digest::Context,
+    last_write: SystemTime,
+}
+
+/// The uploads which are currently receiving a chunk, or which have an open multipart upload.
+/// The lock makes sure the chunks of an upload are appended one at a time.
+#[derive(Default)]
+struct ActiveUpload {
+    multipart: Mutex<Option<MultipartUpload>>,
+    /// Number of bytes written to the multipart upload
+    received: AtomicU64,
+}
+
+static ACTIVE_UPLOADS: LazyLock<DashMap<String, Arc<ActiveUpload>>> = LazyLock::new(DashMap::new);
+
+pub struct ChunkedUpload {
+    name: String,
+    path: PathBuf,
+    length: u64,
+    operator: opendal::Operator,
+    target: String,
+    overwrite: bool,
+    multipart: bool,
+}
+
+impl ChunkedUpload {
+    /// `name` has to uniquely identify the file being uploaded, `length` is the total size of the file.
+    /// The completed file is stored at `target` in the storage of `path_type`.
+    pub fn new(name: &str, length: u64, path_type: &PathType, target: String, overwrite: bool) -> Result<Self, Error> {
+        let operator = CONFIG.opendal_operator_for_path_type(path_type)?;
+        Ok(Self {
+            name: name.to_owned(),
+            path: PathBuf::from(CONFIG.tmp_folder()).join("uploads").join(name),
+            length,
+            multipart: !is_fs_operator(&operator),
+            operator,
+            target,
+            overwrite,
+        })
+    }
+
+    /// Number of bytes received so far.
+    /// Once the upload has been completed, this is the total size as long as the stored file exists.
+    pub async fn offset(&self) -> u64 {
+        if self.multipart {
+            if let Some(upload) = ACTIVE_UPLOADS.get(&self.name) {
+                return upload.received.load(Ordering::Acquire);
+            }
+        } else if let Ok(meta) = tokio::fs::metadata(&self.path).await {
+            return meta.len();
+        }
+
+        self.stored_length().await
+    }
+
+    /// The total size if the upload has already been completed, otherwise 0.
+    async fn stored_length(&self) -> u64 {
+        if self.operator.exists(&self.target).await.unwrap_or(false) {
+            self.length
+        } else {
+            0
+        }
+    }
+
+    /// Whether chunks of this upload have been received and it wasn't abandoned yet.
+    pub async fn in_progress(&self) -> bool {
+        ACTIVE_UPLOADS.contains_key(&self.name) || tokio::fs::try_exists(&self.path).await.unwrap_or(false)
+    }
+
+    pub async fn status(&self) -> UploadStatus {
+        UploadStatus::new(self.offset().await, self.length)
+    }
+
+    /// Appends a chunk starting at `offset`. Chunks have to be sent in order, one at a time,
+    /// and may not extend past the total size of the file.
+    /// Once the last chunk has been received, the file is stored at its target and its SHA-256 is returned.
+    pub async fn append(&self, offset: u64, data: Data<'_>) -> Result<(UploadStatus, Option<String>), Error> {
+        let limit = self.length.saturating_sub(offset).saturating_add(1);
+        self.append_from(offset, data.open(limit.bytes())).await
+    }
+
+    async fn append_from(
+        &self,
+        offset: u64,
+        reader: impl AsyncRead + Unpin,
+    ) -> Result<(UploadStatus, Option<String>), Error> {
+        let upload = ACTIVE_UPLOADS.entry(self.name.clone()).or_default().clone();
+        let result = match upload.multipart.try_lock() {
+            Ok(mut multipart) if self.multipart => self.append_multipart(&upload, &mut multipart, offset, reader).await,
+            Ok(_) => self.append_file(offset, reader).await,
+            Err(_) => {
+                Err(Error::new("Upload conflict", "Another chunk of this upload is being received").with_code(409))
+            }
+        };
+
+        // Forget the upload unless a multipart upload is still open, or another request is waiting for it
+        drop(upload);
+        ACTIVE_UPLOADS.remove_if(&self.name, |_, upload| {
+            Arc::strong_count(upload) == 1 && upload.multipart.try_lock().is_ok_and(|multipart| multipart.is_none())
+        });
+
+        result
+    }
+
+    async fn append_file(
+        &self,
+        offset: u64,
+        reader: impl AsyncRead + Unpin,
+    ) -> Result<(UploadStatus, Option<String>), Error> {
+        let current = match tokio::fs::metadata(&self.path).await {
+            Ok(meta) => meta.len(),
+            Err(_) => self.stored_length().await,
+        };
+        check_offset(offset, current, self.length)?;
+
+        tokio::fs::create_dir_all(self.path.parent().unwrap()).await?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path).await?;
+
+        let remaining = self.length - offset;
+        let written = tokio::io::copy(&mut reader.take(remaining + 1), &mut file).await?;
+        file.flush().await?;
+
+        if written > remaining {
+            // Drop the chunk so the upload can be retried from `offset`
+            file.set_len(offset).await?;
+            err_code!("Upload chunk exceeds the file size", 413);
+        }
+
+        let offset = offset + written;
+        if offset < self.length {
+            return Ok((UploadStatus::new(offset, self.length), None));
+        }
+
+        drop(file);
+        let file = tokio::fs::File::open(&self.path).await?;
+        let checksum = write_file(&self.operator, &self.target, file, self.overwrite).await?;
+        self.discard().await;
+        Ok((UploadStatus::new(offset, self.length), Some(checksum)))
+    }
+
+    async fn append_multipart(
+        &self,
+        upload: &ActiveUpload,
+        multipart: &mut Option<MultipartUpload>,
+        offset: u64,
+        reader: impl AsyncRead + Unpin,
+    ) -> Result<(UploadStatus, Option<String>), Error> {
+        let current = match multipart {
+            Some(_) => upload.received.load(Ordering::Acquire),
+            None => self.stored_length().await,
+        };
+        check_offset(offset, current, self.length)?;
+
+        let remaining = self.length - offset;
+        let limit = remaining.min(MAX_MULTIPART_CHUNK_SIZE);
+        let mut chunk = Vec::new();
+        reader.take(limit + 1).read_to_end(&mut chunk).await?;
+        let written = chunk.len() as u64;
+        if written > limit {
+            if limit < remaining {
+                err_code!(
+                    "Upload chunk too large",
+                    format!("Chunks may not exceed {MAX_MULTIPART_CHUNK_SIZE} bytes"),
+                    413
+                );
+            }
+            err_code!("Upload chunk exceeds the file size", 413);
+        }
+
+        let state = match multipart {
+            Some(state) => state,
+            None => multipart.insert(MultipartUpload {
+                writer: self
+                    .operator
+                    .writer_with(&self.target)
+                    .chunk(MULTIPART_PART_SIZE)
+                    .if_not_exists(!self.overwrite)
+                    .await?,
+                context: ring::digest::Context::new(&ring::digest::SHA256),
+                last_write: SystemTime::now(),
+            }),
+        };
+
+        state.context.update(&chunk);
+        if let Err(e) = state.writer.write(chunk).await {
+            // The part may have been partially written, so the whole upload has to start over
+            abort_multipart(multipart.take()).await;
+            upload.received.store(0, Ordering::Release);
+            return Err(e.into());
+        }
+        state.last_write = SystemTime::now();
+
+        let offset = offset + written;
+        upload.received.store(offset, Ordering::Release);
+        if offset < self.length {
+            return Ok((UploadStatus::new(offset, self.length), None));
+        }
+
+        let mut state = multipart.take().unwrap();
+        upload.received.store(0, Ordering::Release);
+        if let Err(e) = state.writer.close().await {
+            state.writer.abort().await.ok();
+            return Err(e.into());
+        }
+        let checksum = data_encoding::HEXLOWER.encode(state.context.finish().as_ref());
+        Ok((UploadStatus::new(offset, self.length), Some(checksum)))
+    }
+
+    /// Removes the received chunks.
+    pub async fn discard(&self) {
+        if let Err(e) = tokio::fs::remove_file(&self.path).await
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            error!("Failed to remove partial upload '{}': {e:?}", self.path.display());
+        }
+    }
+}
+
+fn check_offset(offset: u64, current: u64, length: u64) -> Result<(), Error> {
+    if offset != current {
+        err_code!("Upload offset mismatch", format!("Expected offset {current}, got {offset}"), 409);
+    }
+    if offset >= length {
+        err_code!("Upload is already complete", 409);
+    }
+    Ok(())
+}
+
+async fn abort_multipart(multipart: Option<MultipartUpload>) {
+    if let Some(mut multipart) = multipart
+        && let Err(e) = multipart.writer.abort().await
+    {
+        error!("Failed to abort multipart upload: {e:?}");
+    }
+}
+
+/// Removes partial uploads which were abandoned by the client.
+pub async fn purge_stale_uploads() {
+    let is_stale =
+        |modified: SystemTime| SystemTime::now().duration_since(modified).is_ok_and(|age| age >= STALE_UPLOAD_AGE);
+
+    let stale: Vec<String> = ACTIVE_UPLOADS
+        .iter()
+        .filter(|upload| {
+            upload
+                .multipart
+                .try_lock()
+                .is_ok_and(|multipart| multipart.as_ref().is_some_and(|m| is_stale(m.last_write)))
+        })
+        .map(|upload| upload.key().clone())
+        .collect();
+    for name in stale {
+        if let Some((_, upload)) = ACTIVE_UPLOADS.remove(&name) {
+            debug!("Aborting abandoned multipart upload '{name}'");
+            abort_multipart(upload.multipart.lock().await.take()).await;
+        }
+    }
+
+    let Ok(mut entries) = tokio::fs::read_dir(PathBuf::from(CONFIG.tmp_folder()).join("uploads")).await else {
+        return;
+    };
+
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.metadata().await.and_then(|meta| meta.modified()).is_ok_and(is_stale) {
+            debug!("Removing abandoned partial upload '{}'", entry.path().display());
+            if let Err(e) = tokio::fs::remove_file(entry.path()).await {
+                error!("Failed to remove partial upload '{}': {e:?}", entry.path().display());
+            }
+        }
+    }
+}
+
+/// Completes the uploads through presigned URLs which the client didn't report, and removes the attachments and Sends
+/// whose content wasn't received before their upload expired. Returns how many pending uploads were resolved.
+pub async fn pending_uploads_job(pool: DbPool) -> Result<usize, Error> {
+    let conn = pool.get().await?;
+
+    let mut resolved = 0;
+    for pending in PendingUpload::find_all(&conn).await {
+        let result = if pending.atype == PendingUploadType::Attachment as i32 {
+            check_pending_attachment(&pending, &conn).await
+        } else {
+            check_pending_send_file(&pending, &conn).await
+        };
+
+        match result {
+            Ok(true) => resolved += 1,
+            Ok(false) => {}
+            Err(e) => warn!("Failed to check the pending upload of '{}': {e:?}", pending.uuid),
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_upload(name: &str, length: u64, multipart: bool) -> (ChunkedUpload, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("vw-upload-test-{name}"));
+        std::fs::remove_dir_all(&dir).ok();
+        let operator = opendal::Operator::new(opendal::services::Fs::default().root(dir.to_str().unwrap())).unwrap();
+        let upload = ChunkedUpload {
+            name: name.to_owned(),
+            path: dir.join("partial"),
+            length,
+            operator,
+            target: String::from("stored"),
+            overwrite: false,
+            multipart,
+        };
+        (upload, dir)
+    }
+
+    fn sha256_hex(data: &[u8]) -> String {
+        data_encoding::HEXLOWER.encode(ring::digest::digest(&ring::digest::SHA256, data).as_ref())
+    }
+
+    async fn chunks_are_appended_in_order(multipart: bool) {
+        let name = if multipart {
+            "multipart"
+        } else {
+            "file"
+        };
+        let (upload, dir) = test_upload(name, 10, multipart);
+
+        upload.append_from(0, &b"0123"[..]).await.unwrap();
+        assert_eq!(upload.offset().await, 4);
+
+        // A chunk which doesn't start at the received offset is refused
+        let err = upload.append_from(2, &b"23456"[..]).await.err().unwrap();
+        assert!(err.message().contains("offset mismatch"));
+        // A chunk which goes past the file size is refused as well
+        let err = upload.append_from(4, &b"456789abc"[..]).await.err().unwrap();
+        assert!(err.message().contains("exceeds the file size"));
+
+        // Resume from the offset reported by `HEAD`
+        let offset = upload.offset().await;
+        assert_eq!(offset, 4);
+        let (_, checksum) = upload.append_from(offset, &b"456"[..]).await.unwrap();
+        assert!(checksum.is_none());
+        let (_, checksum) = upload.append_from(7, &b"789"[..]).await.unwrap();
+
+        assert_eq!(checksum.unwrap(), sha256_hex(b"0123456789"));
+        assert_eq!(std::fs::read(dir.join("stored")).unwrap(), b"0123456789");
+        assert_eq!(upload.offset().await, 10);
+        assert!(upload.append_from(10, &b""[..]).await.err().unwrap().message().contains("already complete"));
+        assert!(!ACTIVE_UPLOADS.contains_key(name));
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn file_upload_appends_chunks_in_order() {
+        chunks_are_appended_in_order(false).await;
+    }
+
+    #[tokio::test]
+    async fn multipart_upload_appends_chunks_in_order() {
+        chunks_are_appended_in_order(true).await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_chunks_are_refused() {
+        let (upload, dir) = test_upload("concurrent", 8, false);
+        let (mut client, server) = tokio::io::duplex(16);
+
+        // The first chunk keeps the upload busy until the client finishes sending it
+        let first = upload.append_from(0, server);
+        let second = async {
+            tokio::task::yield_now().await;
+            let result = upload.append_from(0, &b"01234567"[..]).await;
+            client.write_all(b"0123").await.unwrap();
+            drop(client);
+            result
+        };
+        let (first, second) = tokio::join!(first, second);
+
+        assert!(first.is_ok());
+        assert_eq!(second.err().unwrap().message(), "Upload conflict");
+        assert_eq!(upload.offset().await, 4);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
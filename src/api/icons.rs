@@ -1,24 +1,34 @@
 use std::{
     collections::HashMap,
+    error::Error as _,
+    io::Cursor,
     net::IpAddr,
-    sync::{Arc, LazyLock},
-    time::{Duration, SystemTime},
+    sync::{Arc, LazyLock, RwLock},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use futures::{TryFutureExt, stream::StreamExt};
 use html5gum::{Emitter, HtmlString, Readable, StringReader, Tokenizer};
+use image::{DynamicImage, ImageFormat, ImageReader, Limits, RgbaImage, imageops, imageops::FilterType};
 use regex::Regex;
 use reqwest::{
     Client, Response,
     header::{self, HeaderMap, HeaderValue},
 };
-use rocket::{Route, http::ContentType, response::Redirect};
+use rocket::{
+    Route,
+    http::{Accept, ContentType, Header, MediaType},
+    response::Redirect,
+};
 use svg_hush::{Filter, data_url_filter};
+use url::Host;
 
 use crate::{
     CONFIG,
     config::PathType,
+    db::{DbConn, DbPool, models::IconDomain},
     error::Error,
     http_client::{CustomHttpClientError, get_reqwest_client_builder, get_valid_host, should_block_host},
     util::Cached,
@@ -108,37 +118,52 @@ fn icon_external(host: &str) -> Cached<Option<Redirect>> {
     Cached::ttl(redir, CONFIG.icon_cache_ttl(), true)
 }
 
+/// The normalized icons are served as WebP or PNG depending on the `Accept` header, so caches need to vary on it.
+#[derive(Responder)]
+struct IconResponse {
+    inner: (ContentType, Vec<u8>),
+    vary: Header<'static>,
+}
+
+impl IconResponse {
+    fn new(icon_type: &str, icon: Vec<u8>) -> Self {
+        Self {
+            inner: (ContentType::new("image", icon_type.to_owned()), icon),
+            vary: Header::new("Vary", "Accept"),
+        }
+    }
+}
+
 #[get("/<host>/icon.png")]
-async fn icon_internal(host: &str) -> Cached<(ContentType, Vec<u8>)> {
+async fn icon_internal(host: &str, accept: Option<&Accept>) -> Cached<IconResponse> {
     const FALLBACK_ICON: &[u8] = include_bytes!("../static/images/fallback-icon.png");
 
     let Ok(host) = get_valid_host(host) else {
         warn!("Invalid host: {host}");
-        return Cached::ttl(
-            (ContentType::new("image", "png"), FALLBACK_ICON.to_vec()),
-            CONFIG.icon_cache_negttl(),
-            true,
-        );
+        return Cached::ttl(IconResponse::new("png", FALLBACK_ICON.to_vec()), CONFIG.icon_cache_negttl(), true);
     };
 
+    let accepts_webp = accept
+        .is_some_and(|accept| accept.iter().any(|m| *m.media_type() == MediaType::WEBP && m.weight_or(1.0) > 0.0));
+
+    // Custom icons are also served for the hosts which icons can't be downloaded from.
+    // They are only cached for a short time, so changes made by the admin show up soon.
+    if let Some((icon, icon_type)) = get_custom_icon(&host, accepts_webp).await {
+        return Cached::short(IconResponse::new(&icon_type, icon), false);
+    }
+
     if should_block_host(&host).is_err() {
         warn!("Blocked address: {host}");
-        return Cached::ttl(
-            (ContentType::new("image", "png"), FALLBACK_ICON.to_vec()),
-            CONFIG.icon_cache_negttl(),
-            true,
-        );
+        return Cached::ttl(IconResponse::new("png", FALLBACK_ICON.to_vec()), CONFIG.icon_cache_negttl(), true);
     }
 
-    match get_icon(&host.to_string()).await {
-        Some((icon, icon_type)) => {
-            Cached::ttl((ContentType::new("image", icon_type), icon), CONFIG.icon_cache_ttl(), true)
-        }
-        _ => Cached::ttl((ContentType::new("image", "png"), FALLBACK_ICON.to_vec()), CONFIG.icon_cache_negttl(), true),
+    match get_icon(&host.to_string(), accepts_webp).await {
+        Some((icon, icon_type)) => Cached::ttl(IconResponse::new(&icon_type, icon), CONFIG.icon_cache_ttl(), true),
+        _ => Cached::ttl(IconResponse::new("png", FALLBACK_ICON.to_vec()), CONFIG.icon_cache_negttl(), true),
     }
 }
 
-async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
+async fn get_icon(domain: &str, accepts_webp: bool) -> Option<(Vec<u8>, String)> {
     let path = format!("{domain}.png");
 
     // Check for expiration of negatively cached copy
@@ -146,6 +171,11 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
         return None;
     }
 
+    // The WebP copy only exists for normalized icons, and is saved together with the PNG
+    if accepts_webp && let Some(icon) = get_cached_icon(&format!("{domain}.webp")).await {
+        return Some((icon, String::from("webp")));
+    }
+
     if let Some(icon) = get_cached_icon(&path).await {
         let icon_type = get_icon_type(&icon).unwrap_or("x-icon");
         return Some((icon, icon_type.to_owned()));
@@ -156,25 +186,320 @@ async fn get_icon(domain: &str) -> Option<(Vec<u8>, String)> {
     }
 
     // Get the icon, or None in case of error
+    let icon = fetch_icon(domain, &path).await.ok()?;
+    match icon.webp {
+        Some(webp) if accepts_webp => Some((webp, String::from("webp"))),
+        _ => Some((icon.icon, icon.icon_type)),
+    }
+}
+
+/// Downloads the icon and saves it in the cache, or saves the negative cache marker when no icon could be found.
+async fn fetch_icon(domain: &str, path: &str) -> Result<StoredIcon, Error> {
     match download_icon(domain).await {
-        Ok((icon, icon_type)) => {
-            save_icon(&path, icon.to_vec()).await;
-            Some((icon.to_vec(), icon_type.unwrap_or("x-icon").to_owned()))
-        }
+        Ok((icon, icon_type)) => Ok(save_icon(domain, &icon, icon_type.unwrap_or("x-icon")).await),
         Err(e) => {
             // If this error comes from the custom resolver, this means this is a blocked domain
             // or non global IP, don't save the miss file in this case to avoid leaking it
             if let Some(error) = CustomHttpClientError::downcast_ref(&e) {
                 warn!("{error}");
-                return None;
+                return Err(e);
             }
 
             warn!("Unable to download icon: {e:?}");
-            let miss_indicator = path + ".miss";
-            save_icon(&miss_indicator, vec![]).await;
-            None
+            write_icon_cache(&format!("{path}.miss"), vec![]).await;
+            Err(e)
+        }
+    }
+}
+
+/// Fetches the icon of the domain ahead of time, unless the cached icon is still fresh or negatively cached.
+/// With `force` the icon is always fetched again. Returns whether the icon was fetched.
+pub async fn refresh_icon(domain: &str, force: bool) -> Result<bool, Error> {
+    let Ok(host) = get_valid_host(domain) else {
+        err_silent!(format!("Invalid domain: {domain}"))
+    };
+    if let Err(e) = should_block_host(&host) {
+        err_silent!(e.to_string())
+    }
+    if CONFIG.disable_icon_download() {
+        err_silent!("Icon downloads are disabled")
+    }
+
+    let domain = host.to_string();
+    let path = format!("{domain}.png");
+    if !force {
+        if icon_is_negcached(&path).await {
+            return Ok(false);
+        }
+        // Refresh the icons halfway through their lifetime, so they don't expire between two runs
+        if !file_is_expired(&path, CONFIG.icon_cache_ttl() / 2).await.unwrap_or(true) {
+            return Ok(false);
         }
     }
+
+    fetch_icon(&domain, &path).await?;
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)?;
+    operator.delete(&format!("{path}.miss")).await?;
+    Ok(true)
+}
+
+/// Refreshes the icons of all domains listed on the admin page and records the outcome per domain.
+/// Returns the number of icons which were fetched and the number of failures.
+pub async fn refresh_icons(force: bool, conn: &DbConn) -> Result<(usize, usize), Error> {
+    let results: Vec<(String, Result<bool, Error>)> = futures::stream::iter(IconDomain::get_all(conn).await)
+        .map(async |icon_domain| {
+            let res = refresh_icon(&icon_domain.domain, force).await;
+            (icon_domain.domain, res)
+        })
+        .buffer_unordered(CONFIG.icon_refresh_concurrency())
+        .collect()
+        .await;
+
+    let (mut fetched, mut failed) = (0, 0);
+    for (domain, res) in results {
+        let error = match res {
+            Ok(false) => continue,
+            Ok(true) => {
+                fetched += 1;
+                None
+            }
+            Err(e) => {
+                failed += 1;
+                let reason = error_reason(&e);
+                warn!("Unable to refresh icon of {domain}: {reason}");
+                Some(reason)
+            }
+        };
+        IconDomain::record_refresh(&domain, error, conn).await?;
+    }
+    Ok((fetched, failed))
+}
+
+/// Fetches the icon of the domain again on request of the admin, also when it's negatively cached.
+pub async fn retry_icon(domain: &str, conn: &DbConn) -> Result<(), Error> {
+    let res = refresh_icon(domain, true).await;
+    let error = res.as_ref().err().map(error_reason);
+    IconDomain::record_refresh(domain, error.clone(), conn).await?;
+    if let Some(reason) = error {
+        err!(reason)
+    }
+    Ok(())
+}
+
+pub async fn icon_refresh_job(pool: DbPool) -> Result<usize, Error> {
+    debug!("Start icon_refresh_job");
+    if CONFIG.icon_service() != "internal" || CONFIG.disable_icon_download() {
+        return Ok(0);
+    }
+
+    let conn = pool.get().await?;
+    let (fetched, failed) = refresh_icons(false, &conn).await?;
+    if failed > 0 {
+        warn!("Failed to refresh {failed} icons");
+    }
+    Ok(fetched)
+}
+
+/// Lists the domains whose icon is negatively cached, with the time the last download failed.
+pub async fn list_negcached_icons() -> Result<Vec<(String, Option<NaiveDateTime>)>, Error> {
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)?;
+    let entries = match operator.list("/").await {
+        Ok(entries) => entries,
+        // No icon was ever cached
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut negcached = Vec::new();
+    for entry in entries {
+        let Some(domain) = entry.name().strip_suffix(".png.miss") else {
+            continue;
+        };
+        // Expired markers are removed the next time the icon is requested
+        if !icon_is_negcached(&format!("{domain}.png")).await {
+            continue;
+        }
+        let failed_at = match entry.metadata().last_modified() {
+            Some(last_modified) => Some(last_modified),
+            None => operator.stat(entry.path()).await.ok().and_then(|meta| meta.last_modified()),
+        };
+        let failed_at = failed_at.map(|ts| DateTime::<Utc>::from(SystemTime::from(ts)).naive_utc());
+        negcached.push((domain.to_owned(), failed_at));
+    }
+    negcached.sort();
+    Ok(negcached)
+}
+
+/// Removes the negative cache marker of the domain, so the icon is downloaded again the next time it's requested.
+pub async fn purge_negcached_icon(domain: &str) -> Result<(), Error> {
+    let Ok(host) = get_valid_host(domain) else {
+        err!(format!("Invalid domain: {domain}"))
+    };
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)?;
+    operator.delete(&format!("{host}.png.miss")).await?;
+    Ok(())
+}
+
+/// Custom icons uploaded by the admin are stored in `CUSTOM_ICONS_FOLDER`, apart from the icon cache.
+/// The icon of `example.com` is stored in `exact/`, the icon of `*.example.com`, for all its subdomains, in `wildcard/`.
+fn custom_icon_path(domain: &str, extension: &str) -> String {
+    match domain.strip_prefix("*.") {
+        Some(parent) => format!("wildcard/{parent}.{extension}"),
+        None => format!("exact/{domain}.{extension}"),
+    }
+}
+
+/// How long a host without a custom icon is remembered, so not every icon request has to check the storage.
+/// Instances sharing the storage serve an icon uploaded through another instance after this time at the latest.
+const CUSTOM_ICON_MISS_TTL: Duration = Duration::from_mins(1);
+/// Above this many remembered hosts the expired ones are dropped, and when none are expired all of them.
+const CUSTOM_ICON_MISSES_MAX: usize = 10_000;
+
+/// The hosts which had no custom icon, with the time they were checked.
+static CUSTOM_ICON_MISSES: LazyLock<RwLock<HashMap<String, Instant>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Validates the domain of a custom icon, which may start with a `*.` wildcard.
+fn parse_custom_icon_domain(domain: &str) -> Result<String, Error> {
+    let (wildcard, host) = match domain.strip_prefix("*.") {
+        Some(host) => ("*.", host),
+        None => ("", domain),
+    };
+    match get_valid_host(host) {
+        Ok(Host::Domain(host)) => Ok(format!("{wildcard}{host}")),
+        Ok(host) if wildcard.is_empty() => Ok(host.to_string()),
+        _ => err!(format!("Invalid domain: {domain}")),
+    }
+}
+
+/// The domains whose custom icon is used for the host, the host itself first and then the wildcards of its parents.
+fn custom_icon_domains(host: &str) -> Vec<String> {
+    let mut domains = vec![host.to_owned()];
+    let mut parent = host;
+    while let Some((_, rest)) = parent.split_once('.') {
+        domains.push(format!("*.{rest}"));
+        parent = rest;
+    }
+    domains
+}
+
+async fn get_custom_icon(host: &Host, accepts_webp: bool) -> Option<(Vec<u8>, String)> {
+    let host = host.to_string();
+    if CUSTOM_ICON_MISSES.read().unwrap().get(&host).is_some_and(|checked| checked.elapsed() < CUSTOM_ICON_MISS_TTL) {
+        return None;
+    }
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::CustomIcons).ok()?;
+
+    // A failing storage isn't remembered as a miss, the icon is looked up again on the next request
+    let mut missing = true;
+    for domain in custom_icon_domains(&host) {
+        if accepts_webp {
+            match operator.read(&custom_icon_path(&domain, "webp")).await {
+                Ok(icon) => return Some((icon.to_vec(), String::from("webp"))),
+                Err(e) => missing &= e.kind() == opendal::ErrorKind::NotFound,
+            }
+        }
+        match operator.read(&custom_icon_path(&domain, "png")).await {
+            Ok(icon) => {
+                let icon = icon.to_vec();
+                let icon_type = get_icon_type(&icon).unwrap_or("x-icon");
+                return Some((icon, icon_type.to_owned()));
+            }
+            Err(e) => missing &= e.kind() == opendal::ErrorKind::NotFound,
+        }
+    }
+
+    if missing {
+        let mut misses = CUSTOM_ICON_MISSES.write().unwrap();
+        if misses.len() >= CUSTOM_ICON_MISSES_MAX {
+            misses.retain(|_, checked| checked.elapsed() < CUSTOM_ICON_MISS_TTL);
+            if misses.len() >= CUSTOM_ICON_MISSES_MAX {
+                misses.clear();
+            }
+        }
+        misses.insert(host, Instant::now());
+    }
+    None
+}
+
+/// Saves the icon uploaded by the admin for the domain, normalized the same way as the downloaded icons.
+pub async fn save_custom_icon(domain: &str, icon: Vec<u8>) -> Result<(), Error> {
+    let domain = parse_custom_icon_domain(domain)?;
+    let Some(icon_type) = get_icon_type(&icon) else {
+        err!("The icon is not a supported image type")
+    };
+
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::CustomIcons)?;
+    let size = CONFIG.icon_size();
+    if size == 0 {
+        let icon = if icon_type == "svg+xml" {
+            let Some(sanitized_svg) = sanitize_svg(&icon) else {
+                err!("The SVG icon is invalid")
+            };
+            sanitized_svg
+        } else {
+            icon
+        };
+        operator.write(&custom_icon_path(&domain, "png"), icon).await?;
+        operator.delete(&custom_icon_path(&domain, "webp")).await?;
+    } else {
+        let normalized = tokio::task::spawn_blocking(move || normalize_icon(&icon, icon_type, size)).await;
+        let (png, webp) = match normalized {
+            Ok(Ok(normalized)) => normalized,
+            Ok(Err(e)) => err!("Unable to decode the icon", e),
+            Err(e) => err!("Unable to decode the icon", e.to_string()),
+        };
+        operator.write(&custom_icon_path(&domain, "png"), png).await?;
+        operator.write(&custom_icon_path(&domain, "webp"), webp).await?;
+    }
+
+    // The icon shows up right away on this instance, the other instances notice it once their misses expire
+    CUSTOM_ICON_MISSES.write().unwrap().clear();
+    Ok(())
+}
+
+pub async fn delete_custom_icon(domain: &str) -> Result<(), Error> {
+    let domain = parse_custom_icon_domain(domain)?;
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::CustomIcons)?;
+    operator.delete(&custom_icon_path(&domain, "png")).await?;
+    operator.delete(&custom_icon_path(&domain, "webp")).await?;
+    Ok(())
+}
+
+/// Lists the domains which have a custom icon, together with the icon and its type.
+pub async fn list_custom_icons() -> Result<Vec<(String, Vec<u8>, &'static str)>, Error> {
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::CustomIcons)?;
+
+    let mut domains = Vec::new();
+    for (folder, prefix) in [("exact", ""), ("wildcard", "*.")] {
+        let entries = match operator.list(&format!("{folder}/")).await {
+            Ok(entries) => entries,
+            // No custom icon was ever saved
+            Err(e) if e.kind() == opendal::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            if let Some(domain) = entry.name().strip_suffix(".png") {
+                domains.push(format!("{prefix}{domain}"));
+            }
+        }
+    }
+    domains.sort();
+
+    let mut custom_icons = Vec::with_capacity(domains.len());
+    for domain in domains {
+        let icon = operator.read(&custom_icon_path(&domain, "png")).await?.to_vec();
+        let icon_type = get_icon_type(&icon).unwrap_or("x-icon");
+        custom_icons.push((domain, icon, icon_type));
+    }
+    Ok(custom_icons)
+}
+
+/// Returns the reason of a failed download, without the JSON wrapping of the API error responses.
+fn error_reason(e: &Error) -> String {
+    match e.source() {
+        Some(source) => source.to_string(),
+        None => e.message().to_owned(),
+    }
 }
 
 async fn get_cached_icon(path: &str) -> Option<Vec<u8>> {
@@ -571,21 +896,240 @@ async fn download_icon(domain: &str) -> Result<(Bytes, Option<&str>), Error> {
     if buffer.is_empty() {
         err_silent!("Empty response or unable find a valid icon", domain);
     } else if icon_type == Some("svg+xml") {
-        let mut svg_filter = Filter::new();
-        svg_filter.set_data_url_filter(data_url_filter::allow_standard_images);
-        let mut sanitized_svg = Vec::new();
-        if svg_filter.filter(&*buffer, &mut sanitized_svg).is_err() {
+        if let Some(sanitized_svg) = sanitize_svg(&buffer) {
+            buffer = sanitized_svg.into();
+        } else {
             icon_type = None;
             buffer.clear();
-        } else {
-            buffer = sanitized_svg.into();
         }
     }
 
     Ok((buffer, icon_type))
 }
 
-async fn save_icon(path: &str, icon: Vec<u8>) {
+/// Removes scripts and external references from an SVG icon.
+fn sanitize_svg(svg: &[u8]) -> Option<Vec<u8>> {
+    let mut svg_filter = Filter::new();
+    svg_filter.set_data_url_filter(data_url_filter::allow_standard_images);
+    let mut sanitized_svg = Vec::new();
+    svg_filter.filter(svg, &mut sanitized_svg).ok()?;
+    Some(sanitized_svg)
+}
+
+/// An icon as it was saved in the cache.
+struct StoredIcon {
+    icon: Vec<u8>,
+    icon_type: String,
+    /// Copy of the normalized icon for the clients which accept WebP
+    webp: Option<Vec<u8>>,
+}
+
+/// Normalizes the downloaded icon, and saves it as `<domain>.png` together with a `<domain>.webp` copy.
+/// Icons which can't be normalized are saved as they were downloaded.
+async fn save_icon(domain: &str, icon: &[u8], icon_type: &str) -> StoredIcon {
+    let size = CONFIG.icon_size();
+    let normalized = if size == 0 {
+        None
+    } else {
+        let (icon, icon_type) = (icon.to_vec(), icon_type.to_owned());
+        match tokio::task::spawn_blocking(move || normalize_icon(&icon, &icon_type, size)).await {
+            Ok(Ok(normalized)) => Some(normalized),
+            Ok(Err(e)) => {
+                warn!("Unable to normalize icon of {domain}, saving it as downloaded: {e}");
+                None
+            }
+            Err(e) => {
+                error!("Icon normalization of {domain} panicked: {e}");
+                None
+            }
+        }
+    };
+
+    let webp_path = format!("{domain}.webp");
+    let stored = if let Some((png, webp)) = normalized {
+        write_icon_cache(&webp_path, webp.clone()).await;
+        StoredIcon {
+            icon: png,
+            icon_type: String::from("png"),
+            webp: Some(webp),
+        }
+    } else {
+        // Don't keep serving the WebP copy of an older version of the icon
+        if let Ok(operator) = CONFIG.opendal_operator_for_path_type(&PathType::IconCache)
+            && let Err(e) = operator.delete(&webp_path).await
+        {
+            warn!("Unable to remove {webp_path}: {e:?}");
+        }
+        StoredIcon {
+            icon: icon.to_vec(),
+            icon_type: icon_type.to_owned(),
+            webp: None,
+        }
+    };
+    write_icon_cache(&format!("{domain}.png"), stored.icon.clone()).await;
+    stored
+}
+
+/// Decodes the icon and resizes it to a `size` by `size` square, keeping the aspect ratio.
+/// Returns the icon encoded as PNG and as lossless WebP.
+fn normalize_icon(icon: &[u8], icon_type: &str, size: u32) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let image = match icon_type {
+        "svg+xml" => rasterize_svg(icon, size)?,
+        "x-icon" => decode_ico(icon, size)?,
+        _ => decode_image(icon, None)?,
+    };
+
+    let image = if image.width() == size && image.height() == size {
+        image.into_rgba8()
+    } else {
+        let resized = image.resize(size, size, FilterType::Lanczos3).into_rgba8();
+        // Center icons which aren't square on a transparent background
+        let mut square = RgbaImage::new(size, size);
+        let x = i64::from((size - resized.width()) / 2);
+        let y = i64::from((size - resized.height()) / 2);
+        imageops::overlay(&mut square, &resized, x, y);
+        square
+    };
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|e| e.to_string())?;
+    let mut webp = Vec::new();
+    image.write_to(&mut Cursor::new(&mut webp), ImageFormat::WebP).map_err(|e| e.to_string())?;
+    Ok((png, webp))
+}
+
+// Icons are fetched from any site, so their decoding is limited to keep an icon which claims to be huge from
+// using up the memory of the server. Icons are served at a much smaller size anyway.
+const MAX_DECODED_ICON_DIMENSION: u32 = 2048;
+const MAX_DECODED_ICON_ALLOC: u64 = 32 * 1024 * 1024;
+
+/// Decodes the image within the limits above, guessing the format when it isn't given.
+fn decode_image(icon: &[u8], format: Option<ImageFormat>) -> Result<DynamicImage, String> {
+    let mut reader = ImageReader::new(Cursor::new(icon));
+    match format {
+        Some(format) => reader.set_format(format),
+        None => reader = reader.with_guessed_format().map_err(|e| e.to_string())?,
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DECODED_ICON_DIMENSION);
+    limits.max_image_height = Some(MAX_DECODED_ICON_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODED_ICON_ALLOC);
+    reader.limits(limits);
+    reader.decode().map_err(|e| e.to_string())
+}
+
+/// Decodes the image of a (multi-resolution) ICO file which fits `size` best.
+/// That's the smallest image which is at least `size` pixels, or the largest image when they are all smaller.
+fn decode_ico(icon: &[u8], size: u32) -> Result<DynamicImage, String> {
+    const HEADER_LEN: usize = 6;
+    const ENTRY_LEN: usize = 16;
+    // The image directly follows the directory of the single image file
+    const SINGLE_IMAGE_OFFSET: u32 = 22;
+
+    let count = icon.get(4..HEADER_LEN).map_or(0, |n| usize::from(u16::from_le_bytes([n[0], n[1]])));
+    let Some(entries) = icon.get(HEADER_LEN..HEADER_LEN + count * ENTRY_LEN) else {
+        return Err(String::from("Truncated ICO directory"));
+    };
+
+    // A width or height of 0 means 256 pixels
+    let dimension = |b: u8| {
+        if b == 0 {
+            256
+        } else {
+            u32::from(b)
+        }
+    };
+    let best = entries.chunks_exact(ENTRY_LEN).max_by_key(|entry| {
+        let (width, height) = (dimension(entry[0]), dimension(entry[1]));
+        let bits_per_pixel = u16::from_le_bytes([entry[6], entry[7]]);
+        let fits = width.min(height) >= size;
+        let area = width * height;
+        (
+            fits,
+            if fits {
+                u32::MAX - area
+            } else {
+                area
+            },
+            bits_per_pixel,
+        )
+    });
+    let Some(best) = best else {
+        return Err(String::from("ICO file without images"));
+    };
+
+    let len = u32::from_le_bytes([best[8], best[9], best[10], best[11]]) as usize;
+    let offset = u32::from_le_bytes([best[12], best[13], best[14], best[15]]) as usize;
+    let Some(data) = icon.get(offset..offset.saturating_add(len)) else {
+        return Err(String::from("Truncated ICO image"));
+    };
+
+    // The directory can't store sizes above 256 pixels, so check the size in the header of the image itself
+    let (width, height) = if data.starts_with(&[137, 80, 78, 71, 13, 10, 26, 10]) {
+        let Some(ihdr) = data.get(16..24) else {
+            return Err(String::from("Truncated ICO image"));
+        };
+        (
+            u32::from_be_bytes([ihdr[0], ihdr[1], ihdr[2], ihdr[3]]),
+            u32::from_be_bytes([ihdr[4], ihdr[5], ihdr[6], ihdr[7]]),
+        )
+    } else {
+        let Some(dib) = data.get(4..12) else {
+            return Err(String::from("Truncated ICO image"));
+        };
+        // The height of a bitmap includes its AND mask, which has the same height as the image
+        let width = i32::from_le_bytes([dib[0], dib[1], dib[2], dib[3]]).unsigned_abs();
+        let height = i32::from_le_bytes([dib[4], dib[5], dib[6], dib[7]]).unsigned_abs() / 2;
+        (width, height)
+    };
+    if width > MAX_DECODED_ICON_DIMENSION || height > MAX_DECODED_ICON_DIMENSION {
+        return Err(format!("ICO image of {width}x{height} pixels is too large"));
+    }
+
+    // Let the ICO decoder handle the PNG and BMP encoded images, by giving it a file with only the chosen image
+    let mut single = Vec::with_capacity(HEADER_LEN + ENTRY_LEN + data.len());
+    single.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+    single.extend_from_slice(&best[..12]);
+    single.extend_from_slice(&SINGLE_IMAGE_OFFSET.to_le_bytes());
+    single.extend_from_slice(data);
+    decode_image(&single, Some(ImageFormat::Ico))
+}
+
+/// Rasterizes an SVG icon, so no scripts or external references are ever served.
+fn rasterize_svg(icon: &[u8], size: u32) -> Result<DynamicImage, String> {
+    let options = resvg::usvg::Options {
+        // Only embedded images are allowed, never read files from the local filesystem
+        image_href_resolver: resvg::usvg::ImageHrefResolver {
+            resolve_data: resvg::usvg::ImageHrefResolver::default_data_resolver(),
+            resolve_string: Box::new(|_, _| None),
+        },
+        ..Default::default()
+    };
+    let tree = resvg::usvg::Tree::from_data(icon, &options).map_err(|e| e.to_string())?;
+
+    let Some(mut pixmap) = resvg::tiny_skia::Pixmap::new(size, size) else {
+        return Err(String::from("Invalid icon size"));
+    };
+    let svg_size = tree.size();
+    let target = f32::from(u16::try_from(size).map_err(|_| String::from("Invalid icon size"))?);
+    let scale = (target / svg_size.width()).min(target / svg_size.height());
+    let x = (target - svg_size.width() * scale) / 2.0;
+    let y = (target - svg_size.height() * scale) / 2.0;
+    let transform = resvg::tiny_skia::Transform::from_scale(scale, scale).post_translate(x, y);
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    // The pixmap has premultiplied alpha
+    let pixels = pixmap.pixels().iter().flat_map(|p| {
+        let c = p.demultiply();
+        [c.red(), c.green(), c.blue(), c.alpha()]
+    });
+    RgbaImage::from_vec(size, size, pixels.collect())
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| String::from("Unable to convert the rasterized icon"))
+}
+
+async fn write_icon_cache(path: &str, icon: Vec<u8>) {
     let operator = match CONFIG.opendal_operator_for_path_type(&PathType::IconCache) {
         Ok(operator) => operator,
         Err(e) => {
@@ -655,7 +1199,7 @@ async fn stream_to_bytes_limit(res: Response, max_size: usize) -> Result<Bytes,
 /// A Cookie Jar is needed because some sites force a redirect with cookies to verify if a request uses cookies or not.
 use cookie_store::CookieStore;
 #[derive(Default)]
-pub struct Jar(std::sync::RwLock<CookieStore>);
+pub struct Jar(RwLock<CookieStore>);
 
 impl reqwest::cookie::CookieStore for Jar {
     fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &url::Url) {
@@ -856,3 +1400,73 @@ impl Emitter for FaviconEmitter {
     fn set_force_quirks(&mut self) {}
     fn set_self_closing(&mut self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{
+        ExtendedColorType, GenericImageView,
+        codecs::ico::{IcoEncoder, IcoFrame},
+    };
+
+    #[test]
+    fn ico_picks_smallest_fitting_frame() {
+        let frames: Vec<IcoFrame<'_>> = [16, 48, 128]
+            .into_iter()
+            .map(|size| {
+                let pixels = RgbaImage::from_pixel(size, size, image::Rgba([0, 0, 0, 255]));
+                IcoFrame::as_png(pixels.as_raw(), size, size, ExtendedColorType::Rgba8).unwrap()
+            })
+            .collect();
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico).encode_images(&frames).unwrap();
+
+        assert_eq!(decode_ico(&ico, 32).unwrap().dimensions(), (48, 48));
+        assert_eq!(decode_ico(&ico, 16).unwrap().dimensions(), (16, 16));
+        assert_eq!(decode_ico(&ico, 256).unwrap().dimensions(), (128, 128));
+    }
+
+    #[test]
+    fn oversized_icons_are_rejected() {
+        // The PNG header claims 100000 by 100000 pixels, the directory entry can only say "256 or more"
+        let mut png = vec![137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, b'I', b'H', b'D', b'R'];
+        png.extend_from_slice(&100_000u32.to_be_bytes());
+        png.extend_from_slice(&100_000u32.to_be_bytes());
+        png.extend_from_slice(&[8, 6, 0, 0, 0, 0, 0, 0, 0]);
+        let mut ico = vec![0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 1, 0, 32, 0];
+        ico.extend_from_slice(&u32::try_from(png.len()).unwrap().to_le_bytes());
+        ico.extend_from_slice(&22u32.to_le_bytes());
+        ico.extend_from_slice(&png);
+        assert!(decode_ico(&ico, 32).unwrap_err().contains("too large"));
+
+        // The limits apply to the other formats as well
+        let wide = RgbaImage::new(MAX_DECODED_ICON_DIMENSION + 1, 1);
+        let mut png = Vec::new();
+        wide.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        assert!(normalize_icon(&png, "png", 32).is_err());
+    }
+
+    #[test]
+    fn svg_is_rasterized_and_centered() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10"><rect width="20" height="10" fill="red"/></svg>"#;
+        let (png, webp) = normalize_icon(svg, "svg+xml", 64).unwrap();
+        assert_eq!(get_icon_type(&png), Some("png"));
+        assert_eq!(get_icon_type(&webp), Some("webp"));
+
+        let image = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(image.dimensions(), (64, 64));
+        // The wide icon is centered vertically on a transparent background
+        assert_eq!(image.get_pixel(32, 0)[3], 0);
+        assert_eq!(image.get_pixel(32, 32), &image::Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn custom_icons_match_the_host_or_a_wildcard_of_its_parents() {
+        assert_eq!(custom_icon_domains("example.com"), ["example.com", "*.com"]);
+        assert_eq!(
+            custom_icon_domains("a.b.corp.example.org"),
+            ["a.b.corp.example.org", "*.b.corp.example.org", "*.corp.example.org", "*.example.org", "*.org"]
+        );
+        assert_eq!(custom_icon_domains("localhost"), ["localhost"]);
+    }
+}
@@ -32,7 +32,7 @@ use crate::{
         models::{
             AuthRequest, AuthRequestId, Device, DeviceId, EventType, Invitation, OIDCCodeResponseError,
             OrganizationApiKey, OrganizationId, SendId, SsoAuth, SsoUser, TwoFactor, TwoFactorIncomplete,
-            TwoFactorType, User, UserId,
+            TwoFactorType, User, UserId, UserLanguage,
         },
     },
     error::MapResult,
@@ -109,7 +109,7 @@ async fn login(
         }
         "authorization_code" => err!("SSO sign-in is not available"),
         "send_access" => {
-            crate::ratelimit::check_limit_unauthenticated(&client_header.ip.ip)?;
+            crate::ratelimit::check_limit_unauthenticated(&client_header.ip.ip).await?;
             check_is_some(data.client_id.as_ref(), "client_id cannot be blank")?;
             check_is_some(data.send_id.as_ref(), "send_id cannot be blank")?;
 
@@ -128,6 +128,11 @@ async fn login(
     if let Some(user_id) = user_id {
         match &login_result {
             Ok(_) => {
+                if let Some(language) = client_header.language
+                    && let Err(e) = UserLanguage::new(user_id.clone(), language).save(&conn).await
+                {
+                    warn!("Failed to update the language of user {user_id}: {e:?}");
+                }
                 log_user_event(
                     EventType::UserLoggedIn as i32,
                     &user_id,
@@ -200,7 +205,7 @@ async fn sso_login(
     AuthMethod::Sso.check_scope(data.scope.as_ref())?;
 
     // Ratelimit the login
-    crate::ratelimit::check_limit_login(&ip.ip)?;
+    crate::ratelimit::check_limit_login(&ip.ip).await?;
 
     let (code, code_verifier) = match (data.code.as_ref(), data.code_verifier.as_ref()) {
         (None, _) => err!(
@@ -341,7 +346,7 @@ async fn sso_login(
 
             if user.email != user_infos.email {
                 if CONFIG.mail_enabled() {
-                    mail::send_sso_change_email(&user_infos.email).await?;
+                    mail::send_sso_change_email(&user_infos.email, conn).await?;
                 }
                 info!("User {} email changed in SSO provider from {} to {}", user.uuid, user.email, user_infos.email);
             }
@@ -370,10 +375,11 @@ async fn password_login(
     AuthMethod::Password.check_scope(data.scope.as_ref())?;
 
     // Ratelimit the login
-    crate::ratelimit::check_limit_login(&ip.ip)?;
+    crate::ratelimit::check_limit_login(&ip.ip).await?;
 
     // Get the user
     let username = data.username.as_ref().unwrap().trim();
+    crate::ratelimit::check_limit_login_account(username).await?;
     let Some(mut user) = User::find_by_mail(username, conn).await else {
         err!("Username or password is incorrect. Try again", format!("IP: {}. Username: {username}.", ip.ip))
     };
@@ -456,7 +462,7 @@ async fn password_login(
                     error!("Error updating user: {e:#?}");
                 }
 
-                if let Err(e) = mail::send_verify_email(&user.email, &user.uuid).await {
+                if let Err(e) = mail::send_verify_email(&user.email, &user.uuid, conn).await {
                     error!("Error auto-sending email verification email: {e:#?}");
                 }
             }
@@ -491,7 +497,7 @@ async fn authenticated_response(
 ) -> JsonResult {
     if CONFIG.mail_enabled() && device.is_new() {
         let now = Utc::now().naive_utc();
-        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, device).await {
+        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, device, conn).await {
             error!("Error sending new device email: {e:#?}");
 
             if CONFIG.require_device_email() {
@@ -583,7 +589,7 @@ async fn authenticated_response(
 
 async fn api_key_login(data: ConnectData, user_id: &mut Option<UserId>, conn: &DbConn, ip: &ClientIp) -> JsonResult {
     // Ratelimit the login
-    crate::ratelimit::check_limit_login(&ip.ip)?;
+    crate::ratelimit::check_limit_login(&ip.ip).await?;
 
     // Validate scope
     match data.scope.as_ref() {
@@ -639,7 +645,7 @@ async fn user_api_key_login(
 
     if CONFIG.mail_enabled() && device.is_new() {
         let now = Utc::now().naive_utc();
-        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, &device).await {
+        if let Err(e) = mail::send_new_device_logged_in(&user.email, &ip.ip.to_string(), &now, &device, conn).await {
             error!("Error sending new device email: {e:#?}");
 
             if CONFIG.require_device_email() {
@@ -1059,7 +1065,7 @@ async fn register_verification_email(
     ip: ClientIp,
     conn: DbConn,
 ) -> ApiResult<RegisterVerificationResponse> {
-    crate::ratelimit::check_limit_unauthenticated(&ip.ip)?;
+    crate::ratelimit::check_limit_unauthenticated(&ip.ip).await?;
 
     let data = data.into_inner();
 
@@ -1086,7 +1092,7 @@ async fn register_verification_email(
             let sleep_ms: u64 = rng.random_range(900..=1100);
             tokio::time::sleep(tokio::time::Duration::from_millis(sleep_ms)).await;
         } else {
-            mail::send_register_verify_email(&data.email, &token).await?;
+            mail::send_register_verify_email(&data.email, &token, &conn).await?;
         }
 
         Ok(RegisterVerificationResponse::NoContent(()))
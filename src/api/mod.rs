@@ -2,6 +2,7 @@ mod admin;
 pub mod core;
 mod icons;
 mod identity;
+mod notification_bus;
 mod notifications;
 mod push;
 mod web;
@@ -13,20 +14,26 @@ pub use crate::api::{
     admin::catchers as admin_catchers,
     admin::routes as admin_routes,
     core::catchers as core_catchers,
+    core::pending_uploads_job,
     core::purge_auth_requests,
     core::purge_sends,
+    core::purge_stale_uploads,
     core::purge_trashed_ciphers,
     core::routes as core_routes,
     core::two_factor::send_incomplete_2fa_notifications,
+    core::{clear_sync_cache, invalidate_sync_cache, sync_cache_stats},
     core::{emergency_notification_reminder_job, emergency_request_timeout_job},
     core::{event_cleanup_job, events_routes as core_events_routes},
+    icons::icon_refresh_job,
     icons::routes as icons_routes,
     identity::routes as identity_routes,
+    notification_bus::start as start_notification_bus,
     notifications::routes as notifications_routes,
     notifications::{AnonymousNotify, Notify, UpdateType, WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS},
     push::{
-        push_cipher_update, push_folder_update, push_logout, push_send_update, push_user_update, register_push_device,
-        unregister_push_device,
+        push_cipher_update, push_folder_update, push_logout, push_notification, push_org_collection_settings_update,
+        push_org_status_update, push_send_update, push_user_update, register_push_device, unregister_push_device,
+        validate_push_token,
     },
     web::catchers as web_catchers,
     web::routes as web_routes,
@@ -0,0 +1,360 @@
+// Shares WebSocket notifications between multiple Vaultwarden instances running behind a load balancer.
+//
+// Every update sent to the locally connected clients is also published on a shared channel,
+// either through PostgreSQL LISTEN/NOTIFY or Redis Pub/Sub, depending on `WEBSOCKET_BACKPLANE`.
+// The other instances deliver the received updates to their own clients.
+// Push notifications are not shared, those are only sent by the instance which handled the request.
+//
+// PostgreSQL limits NOTIFY payloads to 8000 bytes, so larger updates are stored in the `notification_bus_payloads`
+// table, and only a reference to them is published. The receiving instances fetch the update from the table.
+
+use std::sync::OnceLock;
+
+use data_encoding::BASE64;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+
+use crate::{
+    CONFIG,
+    api::{WS_ANONYMOUS_SUBSCRIPTIONS, WS_USERS},
+    util::INSTANCE_ID,
+};
+
+/// Name of the PostgreSQL and Redis channel the updates are published on.
+#[cfg_attr(not(any(postgresql, redis)), allow(dead_code))]
+const CHANNEL: &str = "vaultwarden_notifications";
+
+/// Time to wait before reconnecting after the connection to the backplane was lost.
+#[cfg(any(postgresql, redis))]
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// `pg_notify()` rejects payloads of 8000 bytes or more.
+#[cfg_attr(not(postgresql), allow(dead_code))]
+const MAX_NOTIFY_PAYLOAD: usize = 7999;
+
+/// Queue of updates waiting to be published, only set when a backplane is configured.
+static OUTGOING: OnceLock<UnboundedSender<String>> = OnceLock::new();
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Target {
+    /// `id` is the uuid of the user
+    User,
+    /// `id` is the token of the anonymous subscription
+    Anonymous,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BusMessage {
+    /// The instance which published the update, so it isn't delivered twice to its own clients
+    origin: String,
+    target: Target,
+    id: String,
+    /// Base64 of the MessagePack encoded update
+    data: String,
+}
+
+/// Reference to an update which was too large for `pg_notify()`, and was stored in the database instead.
+#[derive(Serialize, Deserialize)]
+struct StoredMessage {
+    /// The uuid of the row in `notification_bus_payloads`
+    stored: String,
+}
+
+/// Publishes an update for the clients connected to the other instances.
+/// Does nothing when no backplane is configured.
+pub fn publish(target: Target, id: &str, data: &[u8]) {
+    let Some(sender) = OUTGOING.get() else {
+        return;
+    };
+
+    match encode(&INSTANCE_ID, target, id, data) {
+        Ok(payload) => {
+            if sender.send(payload).is_err() {
+                error!("WebSocket backplane is not running, update not published");
+            }
+        }
+        Err(e) => error!("Failed to serialize WebSocket update: {e:?}"),
+    }
+}
+
+fn encode(origin: &str, target: Target, id: &str, data: &[u8]) -> serde_json::Result<String> {
+    serde_json::to_string(&BusMessage {
+        origin: origin.to_owned(),
+        target,
+        id: id.to_owned(),
+        data: BASE64.encode(data),
+    })
+}
+
+/// Returns the target, id and update of a message, or None when it was published by `origin` itself.
+#[cfg_attr(not(any(postgresql, redis)), allow(dead_code))]
+fn decode(payload: &str, origin: &str) -> Result<Option<(Target, String, Vec<u8>)>, String> {
+    let message: BusMessage = serde_json::from_str(payload).map_err(|e| format!("Invalid message: {e}"))?;
+    if message.origin == origin {
+        return Ok(None);
+    }
+
+    let data = BASE64.decode(message.data.as_bytes()).map_err(|e| format!("Invalid data: {e}"))?;
+    Ok(Some((message.target, message.id, data)))
+}
+
+/// Returns the text to publish through `pg_notify()` in place of a stored update.
+#[cfg_attr(not(postgresql), allow(dead_code))]
+fn stored_reference(uuid: &str) -> String {
+    json!({ "stored": uuid }).to_string()
+}
+
+/// Returns the uuid of the stored update when a notification refers to one.
+#[cfg_attr(not(postgresql), allow(dead_code))]
+fn stored_message_uuid(notification: &str) -> Option<String> {
+    serde_json::from_str::<StoredMessage>(notification).ok().map(|message| message.stored)
+}
+
+/// Delivers an update received from the backplane to the clients connected to this instance.
+#[cfg_attr(not(any(postgresql, redis)), allow(dead_code))]
+async fn receive(payload: &str) {
+    let (target, id, data) = match decode(payload, &INSTANCE_ID) {
+        Ok(Some(update)) => update,
+        Ok(None) => return,
+        Err(e) => {
+            warn!("Ignoring WebSocket backplane message: {e}");
+            return;
+        }
+    };
+
+    match target {
+        Target::User => WS_USERS.send_local_update(&id.into(), &data).await,
+        Target::Anonymous => WS_ANONYMOUS_SUBSCRIPTIONS.send_local_update(&id, &data).await,
+    }
+}
+
+/// Connects to the backplane configured in `WEBSOCKET_BACKPLANE`, if any.
+pub fn start() {
+    let backplane = CONFIG.websocket_backplane();
+    if backplane.is_empty() || !CONFIG.enable_websocket() {
+        return;
+    }
+
+    let (sender, receiver) = unbounded_channel();
+    if OUTGOING.set(sender).is_err() {
+        return;
+    }
+
+    match backplane.as_str() {
+        #[cfg(postgresql)]
+        "postgresql" => {
+            let handle = tokio::runtime::Handle::current();
+            std::thread::Builder::new()
+                .name("ws-backplane".into())
+                .spawn(move || postgresql::run(receiver, &handle))
+                .expect("Failed to start WebSocket backplane thread");
+        }
+        #[cfg(redis)]
+        url if url.starts_with("redis://") || url.starts_with("rediss://") => {
+            tokio::spawn(redis::run(url.to_owned(), receiver));
+        }
+        // Other values are rejected while validating the config
+        _ => drop(receiver),
+    }
+}
+
+#[cfg(postgresql)]
+mod postgresql {
+    use std::time::Duration;
+
+    use diesel::{Connection, PgConnection, QueryableByName, RunQueryDsl, sql_types::Text};
+    use tokio::{runtime::Handle, sync::mpsc::UnboundedReceiver};
+
+    use super::{CHANNEL, MAX_NOTIFY_PAYLOAD, RECONNECT_DELAY, receive, stored_message_uuid, stored_reference};
+    use crate::{CONFIG, util::get_uuid};
+
+    /// libpq only hands out notifications when asked, so the connection is checked at this interval.
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    /// Stored updates are kept this long, which leaves the other instances plenty of time to fetch them.
+    const STORED_PAYLOAD_RETENTION: &str = "5 minutes";
+
+    #[derive(QueryableByName)]
+    struct StoredPayload {
+        #[diesel(sql_type = Text)]
+        payload: String,
+    }
+
+    // Runs on a dedicated thread, since diesel connections are blocking.
+    pub fn run(mut receiver: UnboundedReceiver<String>, handle: &Handle) {
+        loop {
+            match PgConnection::establish(&CONFIG.database_url()) {
+                Ok(mut conn) => {
+                    if let Err(e) = listen(&mut conn, &mut receiver, handle) {
+                        error!("WebSocket backplane connection to PostgreSQL failed: {e:?}");
+                    }
+                }
+                Err(e) => error!("Failed to connect the WebSocket backplane to PostgreSQL: {e:?}"),
+            }
+            std::thread::sleep(RECONNECT_DELAY);
+        }
+    }
+
+    fn listen(
+        conn: &mut PgConnection,
+        receiver: &mut UnboundedReceiver<String>,
+        handle: &Handle,
+    ) -> Result<(), diesel::result::Error> {
+        diesel::sql_query(format!("LISTEN {CHANNEL}")).execute(conn)?;
+        info!("WebSocket backplane listening on PostgreSQL channel '{CHANNEL}'");
+
+        loop {
+            while let Ok(payload) = receiver.try_recv() {
+                let payload = if payload.len() > MAX_NOTIFY_PAYLOAD {
+                    store(conn, payload)?
+                } else {
+                    payload
+                };
+                diesel::sql_query("SELECT pg_notify($1, $2)")
+                    .bind::<Text, _>(CHANNEL)
+                    .bind::<Text, _>(payload)
+                    .execute(conn)?;
+            }
+
+            let notifications: Vec<String> =
+                conn.notifications_iter().map(|n| n.map(|n| n.payload)).collect::<Result<_, _>>()?;
+            for notification in notifications {
+                let payload = match stored_message_uuid(&notification) {
+                    Some(uuid) => {
+                        let Some(payload) = fetch(conn, uuid)? else {
+                            warn!("Ignoring WebSocket backplane message which is no longer stored");
+                            continue;
+                        };
+                        payload
+                    }
+                    None => notification,
+                };
+                handle.spawn(async move { receive(&payload).await });
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Stores an update which is too large for `pg_notify()`, and returns the reference to publish instead.
+    fn store(conn: &mut PgConnection, payload: String) -> Result<String, diesel::result::Error> {
+        diesel::sql_query(format!(
+            "DELETE FROM notification_bus_payloads WHERE created_at < NOW() - INTERVAL '{STORED_PAYLOAD_RETENTION}'"
+        ))
+        .execute(conn)?;
+
+        let uuid = get_uuid();
+        diesel::sql_query("INSERT INTO notification_bus_payloads (uuid, payload, created_at) VALUES ($1, $2, NOW())")
+            .bind::<Text, _>(&uuid)
+            .bind::<Text, _>(payload)
+            .execute(conn)?;
+        Ok(stored_reference(&uuid))
+    }
+
+    fn fetch(conn: &mut PgConnection, uuid: String) -> Result<Option<String>, diesel::result::Error> {
+        let stored = diesel::sql_query("SELECT payload FROM notification_bus_payloads WHERE uuid = $1")
+            .bind::<Text, _>(uuid)
+            .load::<StoredPayload>(conn)?;
+        Ok(stored.into_iter().next().map(|stored| stored.payload))
+    }
+}
+
+#[cfg(redis)]
+mod redis {
+    use ::redis::{AsyncCommands, Client, RedisResult, aio::MultiplexedConnection};
+    use rocket::futures::StreamExt;
+    use tokio::sync::mpsc::UnboundedReceiver;
+
+    use super::{CHANNEL, RECONNECT_DELAY, receive};
+
+    pub async fn run(url: String, receiver: UnboundedReceiver<String>) {
+        let client = match Client::open(url) {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Invalid WebSocket backplane Redis URL: {e:?}");
+                return;
+            }
+        };
+
+        tokio::spawn(publish(client.clone(), receiver));
+        loop {
+            if let Err(e) = subscribe(&client).await {
+                error!("WebSocket backplane connection to Redis failed: {e:?}");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn publish(client: Client, mut receiver: UnboundedReceiver<String>) {
+        let mut conn: Option<MultiplexedConnection> = None;
+        while let Some(payload) = receiver.recv().await {
+            if conn.is_none() {
+                conn = client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .inspect_err(|e| error!("Failed to connect the WebSocket backplane to Redis: {e:?}"))
+                    .ok();
+            }
+            if let Some(c) = conn.as_mut()
+                && let Err(e) = c.publish::<_, _, ()>(CHANNEL, payload).await
+            {
+                error!("Failed to publish WebSocket update to Redis: {e:?}");
+                conn = None;
+            }
+        }
+    }
+
+    async fn subscribe(client: &Client) -> RedisResult<()> {
+        let mut pubsub = client.get_async_pubsub().await?;
+        pubsub.subscribe(CHANNEL).await?;
+        info!("WebSocket backplane subscribed to Redis channel '{CHANNEL}'");
+
+        let mut messages = pubsub.on_message();
+        while let Some(message) = messages.next().await {
+            let payload: String = message.get_payload()?;
+            receive(&payload).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_encoded_messages() {
+        let payload = encode("instance-a", Target::User, "user-id", &[1, 2, 3]).unwrap();
+
+        let (target, id, data) = decode(&payload, "instance-b").unwrap().unwrap();
+        assert_eq!(target, Target::User);
+        assert_eq!(id, "user-id");
+        assert_eq!(data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn skips_own_messages() {
+        let payload = encode("instance-a", Target::Anonymous, "token", &[1]).unwrap();
+        assert!(decode(&payload, "instance-a").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_messages() {
+        assert!(decode("not json", "instance-b").is_err());
+
+        let payload = r#"{"origin":"instance-a","target":"user","id":"user-id","data":"not base64!"}"#;
+        assert!(decode(payload, "instance-b").is_err());
+    }
+
+    #[test]
+    fn refers_to_stored_messages() {
+        let reference = stored_reference("stored-id");
+        assert!(reference.len() <= MAX_NOTIFY_PAYLOAD);
+        assert_eq!(stored_message_uuid(&reference).as_deref(), Some("stored-id"));
+
+        // Regular messages are never mistaken for a reference, whatever their size
+        let payload = encode("instance-a", Target::User, "user-id", &[0; MAX_NOTIFY_PAYLOAD]).unwrap();
+        assert!(payload.len() > MAX_NOTIFY_PAYLOAD);
+        assert!(stored_message_uuid(&payload).is_none());
+    }
+}
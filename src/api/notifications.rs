@@ -15,13 +15,17 @@ use crate::{
     auth::{ClientIp, WsAccessTokenHeader},
     db::{
         DbConn,
-        models::{AuthRequestId, Cipher, CollectionId, Device, DeviceId, Folder, PushId, Send as DbSend, User, UserId},
+        models::{
+            AuthRequestId, Cipher, CollectionId, Device, DeviceId, Folder, Membership, MembershipStatus, Notification,
+            NotificationStatus, PushId, Send as DbSend, User, UserId,
+        },
     },
 };
 
 use super::{
-    push::push_auth_request, push::push_auth_response, push_cipher_update, push_folder_update, push_logout,
-    push_send_update, push_user_update,
+    notification_bus, push::push_auth_request, push::push_auth_response, push_cipher_update, push_folder_update,
+    push_logout, push_notification, push_org_collection_settings_update, push_org_status_update, push_send_update,
+    push_user_update,
 };
 
 pub static WS_USERS: LazyLock<Arc<WebSocketUsers>> = LazyLock::new(|| {
@@ -352,6 +356,12 @@ pub struct WebSocketUsers {
 
 impl WebSocketUsers {
     async fn send_update(&self, user_id: &UserId, data: &[u8]) {
+        self.send_local_update(user_id, data).await;
+        notification_bus::publish(notification_bus::Target::User, user_id, data);
+    }
+
+    /// Sends the update only to the clients connected to this instance.
+    pub(super) async fn send_local_update(&self, user_id: &UserId, data: &[u8]) {
         if let Some(user) = self.map.get(user_id.as_ref()).map(|v| v.clone()) {
             for (_, sender) in &user {
                 if let Err(e) = sender.send(Message::binary(data)).await {
@@ -510,6 +520,54 @@ impl WebSocketUsers {
         }
     }
 
+    /// Lets the member know their access to the organization was revoked or restored.
+    pub async fn send_org_status_update(&self, member: &Membership, device: &Device, conn: &DbConn) {
+        // Skip any processing if both WebSockets and Push are not active
+        if *NOTIFICATIONS_DISABLED {
+            return;
+        }
+        let enabled = member.status != MembershipStatus::Revoked as i32;
+        let data = create_update(
+            vec![("OrganizationId".into(), member.org_uuid.to_string().into()), ("Enabled".into(), enabled.into())],
+            UpdateType::SyncOrganizationStatusChanged,
+            Some(device.uuid.clone()),
+        );
+
+        if CONFIG.enable_websocket() {
+            self.send_update(&member.user_uuid, &data).await;
+        }
+
+        if CONFIG.push_enabled() {
+            push_org_status_update(member, enabled, device, conn).await;
+        }
+    }
+
+    /// Lets the member know which collection management limits now apply to them.
+    pub async fn send_org_collection_settings_update(&self, member: &Membership, device: &Device, conn: &DbConn) {
+        // Skip any processing if both WebSockets and Push are not active
+        if *NOTIFICATIONS_DISABLED {
+            return;
+        }
+        let data = create_update(
+            vec![
+                ("OrganizationId".into(), member.org_uuid.to_string().into()),
+                ("LimitCollectionCreation".into(), member.limit_collection_creation().into()),
+                ("LimitCollectionDeletion".into(), true.into()),
+                ("LimitItemDeletion".into(), false.into()),
+            ],
+            UpdateType::SyncOrganizationCollectionSettingChanged,
+            Some(device.uuid.clone()),
+        );
+
+        if CONFIG.enable_websocket() {
+            self.send_update(&member.user_uuid, &data).await;
+        }
+
+        if CONFIG.push_enabled() {
+            push_org_collection_settings_update(member, device, conn).await;
+        }
+    }
+
     pub async fn send_auth_request(&self, user_id: &UserId, auth_request_uuid: &str, device: &Device, conn: &DbConn) {
         // Skip any processing if both WebSockets and Push are not active
         if *NOTIFICATIONS_DISABLED {
@@ -553,6 +611,55 @@ impl WebSocketUsers {
             push_auth_response(user_id, auth_request_id, device, conn).await;
         }
     }
+
+    /// Sends a notification center update, either a new notification (`UpdateType::Notification`) to all `user_ids`,
+    /// or a changed read or deleted status (`UpdateType::NotificationStatus`) to the other devices of the user.
+    pub async fn send_notification(
+        &self,
+        ut: UpdateType,
+        notification: &Notification,
+        status: Option<&NotificationStatus>,
+        user_ids: &[UserId],
+        acting_device: Option<&Device>,
+        conn: &DbConn,
+    ) {
+        // Skip any processing if both WebSockets and Push are not active
+        if *NOTIFICATIONS_DISABLED {
+            return;
+        }
+        let data = create_update(
+            vec![
+                ("Id".into(), notification.uuid.to_string().into()),
+                ("Priority".into(), notification.priority.into()),
+                ("Global".into(), notification.global.into()),
+                ("ClientType".into(), notification.client_type.into()),
+                ("UserId".into(), convert_option(notification.user_uuid.as_deref())),
+                ("OrganizationId".into(), convert_option(notification.organization_uuid.as_deref())),
+                ("InstallationId".into(), Value::Nil),
+                ("TaskId".into(), Value::Nil),
+                ("Title".into(), convert_option(notification.title.as_deref())),
+                ("Body".into(), convert_option(notification.body.as_deref())),
+                ("CreationDate".into(), serialize_date(notification.creation_date)),
+                ("RevisionDate".into(), serialize_date(notification.revision_date)),
+                ("ReadDate".into(), convert_option(status.and_then(|s| s.read_date).map(serialize_date))),
+                ("DeletedDate".into(), convert_option(status.and_then(|s| s.deleted_date).map(serialize_date))),
+            ],
+            ut,
+            acting_device.map(|d| d.uuid.clone()),
+        );
+
+        if CONFIG.enable_websocket() {
+            for uuid in user_ids {
+                self.send_update(uuid, &data).await;
+            }
+        }
+
+        if CONFIG.push_enabled() {
+            for uuid in user_ids {
+                push_notification(ut, notification, status, uuid, acting_device, conn).await;
+            }
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -587,6 +694,12 @@ impl AnonymousWebSocketSubscriptions {
     }
 
     async fn send_update(&self, token: &str, data: &[u8]) {
+        self.send_local_update(token, data).await;
+        notification_bus::publish(notification_bus::Target::Anonymous, token, data);
+    }
+
+    /// Sends the update only to the clients connected to this instance.
+    pub(super) async fn send_local_update(&self, token: &str, data: &[u8]) {
         // Clone the senders so the map isn't kept locked while sending.
         let senders = self.map.get(token).map(|v| v.clone()).unwrap_or_default();
         for (_, sender) in senders {
@@ -692,12 +805,11 @@ pub enum UpdateType {
     AuthRequest = 15,
     AuthRequestResponse = 16,
 
-    // SyncOrganizations = 17, // Not supported
-    // SyncOrganizationStatusChanged = 18, // Not supported
-    // SyncOrganizationCollectionSettingChanged = 19, // Not supported
-
-    // Notification = 20, // Not supported
-    // NotificationStatus = 21, // Not supported
+    SyncOrganizations = 17,
+    SyncOrganizationStatusChanged = 18,
+    SyncOrganizationCollectionSettingChanged = 19,
+    Notification = 20,
+    NotificationStatus = 21,
 
     // RefreshSecurityTasks = 22, // Not supported
     None = 100,
@@ -0,0 +1,99 @@
+// Sends push notifications to iOS devices through the Apple Push Notification service,
+// using token-based authentication with a .p8 key.
+// See https://developer.apple.com/documentation/usernotifications/sending-notification-requests-to-apns
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{Method, RequestBuilder, header::AUTHORIZATION};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::{CONFIG, api::EmptyResult, error::Error, http_client::make_http_request};
+
+/// APNs rejects tokens older than an hour, and also tokens which are renewed more than once every 20 minutes.
+const TOKEN_LIFETIME: Duration = Duration::from_mins(50);
+
+#[derive(Serialize)]
+struct ProviderClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+/// The provider authentication token, signed with the APNs key and cached for `TOKEN_LIFETIME`.
+async fn get_provider_token() -> Result<String, Error> {
+    static PROVIDER_TOKEN: LazyLock<RwLock<Option<(String, Instant)>>> = LazyLock::new(|| RwLock::new(None));
+
+    if let Some((token, valid_until)) = &*PROVIDER_TOKEN.read().await
+        && *valid_until > Instant::now()
+    {
+        return Ok(token.clone());
+    }
+
+    let (Some(key_file), Some(key_id), Some(team_id)) =
+        (CONFIG.push_apns_key_file(), CONFIG.push_apns_key_id(), CONFIG.push_apns_team_id())
+    else {
+        err!("APNs is not configured, `PUSH_APNS_KEY_FILE`, `PUSH_APNS_KEY_ID` and `PUSH_APNS_TEAM_ID` are required")
+    };
+
+    let key = EncodingKey::from_ec_pem(&tokio::fs::read(&key_file).await?)?;
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(key_id);
+    let claims = ProviderClaims {
+        iss: &team_id,
+        iat: chrono::Utc::now().timestamp(),
+    };
+    let token = jsonwebtoken::encode(&header, &claims, &key)?;
+
+    *PROVIDER_TOKEN.write().await = Some((token.clone(), Instant::now() + TOKEN_LIFETIME));
+    Ok(token)
+}
+
+/// The APNs payload for a notification, delivered to the app in the background.
+pub fn payload(notification_data: &Value) -> Value {
+    json!({
+        "aps": {
+            "content-available": 1
+        },
+        "data": {
+            "type": notification_data["type"],
+            "payload": notification_data["payload"].to_string(),
+        }
+    })
+}
+
+pub async fn send(push_token: &str, notification_data: &Value) -> EmptyResult {
+    let Some(topic) = CONFIG.push_apns_topic() else {
+        err!("APNs is not configured, `PUSH_APNS_TOPIC` is not set")
+    };
+    let provider_token = get_provider_token().await?;
+
+    let host = if CONFIG.push_apns_sandbox() {
+        "https://api.sandbox.push.apple.com"
+    } else {
+        "https://api.push.apple.com"
+    };
+    let request = make_http_request(Method::POST, &format!("{host}/3/device/{push_token}"))?;
+    deliver(request, &provider_token, &topic, notification_data).await
+}
+
+pub async fn deliver(
+    request: RequestBuilder,
+    provider_token: &str,
+    topic: &str,
+    notification_data: &Value,
+) -> EmptyResult {
+    request
+        .header(AUTHORIZATION, format!("bearer {provider_token}"))
+        .header("apns-topic", topic)
+        .header("apns-push-type", "background")
+        .header("apns-priority", "5")
+        .json(&payload(notification_data))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,143 @@
+// Sends push notifications to Android devices through the Firebase Cloud Messaging HTTP v1 API.
+// See https://firebase.google.com/docs/cloud-messaging/send/v1-api
+
+use std::{
+    sync::{Arc, LazyLock},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{
+    Method, RequestBuilder,
+    header::{AUTHORIZATION, CONTENT_TYPE},
+};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::{CONFIG, api::EmptyResult, error::Error, http_client::make_http_request};
+
+const FCM_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+
+/// The fields we need from the JSON key of a Google service account.
+#[derive(Deserialize)]
+struct ServiceAccount {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Serialize)]
+struct ServiceAccountClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// The path of the loaded service account, along with its content.
+type LoadedServiceAccount = (String, Arc<ServiceAccount>);
+
+/// The service account is read once and kept in memory, it's only read again when `PUSH_FCM_SERVICE_ACCOUNT` changes.
+async fn load_service_account() -> Result<Arc<ServiceAccount>, Error> {
+    static SERVICE_ACCOUNT: LazyLock<RwLock<Option<LoadedServiceAccount>>> = LazyLock::new(|| RwLock::new(None));
+
+    let Some(path) = CONFIG.push_fcm_service_account() else {
+        err!("FCM is not configured, `PUSH_FCM_SERVICE_ACCOUNT` is not set")
+    };
+    if let Some((loaded_path, account)) = &*SERVICE_ACCOUNT.read().await
+        && *loaded_path == path
+    {
+        return Ok(Arc::clone(account));
+    }
+
+    let json = tokio::fs::read_to_string(&path).await?;
+    let account: Arc<ServiceAccount> = Arc::new(serde_json::from_str(&json)?);
+    *SERVICE_ACCOUNT.write().await = Some((path, Arc::clone(&account)));
+    Ok(account)
+}
+
+/// The JWT signed with the service account key, which is exchanged for an access token.
+fn assertion(account: &ServiceAccount) -> Result<String, Error> {
+    let now = chrono::Utc::now().timestamp();
+    let claims = ServiceAccountClaims {
+        iss: &account.client_email,
+        scope: FCM_SCOPE,
+        aud: &account.token_uri,
+        iat: now,
+        exp: now + 3600,
+    };
+    let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())?;
+    Ok(jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)?)
+}
+
+async fn request_access_token(request: RequestBuilder, assertion: &str) -> Result<AccessToken, Error> {
+    let params = [("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", assertion)];
+    Ok(request.form(&params).send().await?.error_for_status()?.json().await?)
+}
+
+/// Exchanges a JWT signed with the service account key for an OAuth2 access token, which is cached until it expires.
+async fn get_access_token(account: &ServiceAccount) -> Result<String, Error> {
+    static ACCESS_TOKEN: LazyLock<RwLock<Option<(String, Instant)>>> = LazyLock::new(|| RwLock::new(None));
+
+    if let Some((token, valid_until)) = &*ACCESS_TOKEN.read().await
+        && *valid_until > Instant::now()
+    {
+        return Ok(token.clone());
+    }
+
+    let request = make_http_request(Method::POST, &account.token_uri)?;
+    let token = request_access_token(request, &assertion(account)?).await?;
+
+    // Refresh the token a bit before it actually expires
+    let valid_until = Instant::now() + Duration::from_secs(token.expires_in.saturating_sub(60));
+    *ACCESS_TOKEN.write().await = Some((token.access_token.clone(), valid_until));
+    Ok(token.access_token)
+}
+
+/// The FCM message for a notification, all data values have to be strings.
+pub fn message(push_token: &str, notification_data: &Value) -> Value {
+    json!({
+        "message": {
+            "token": push_token,
+            "android": {
+                "priority": "high"
+            },
+            "data": {
+                "type": notification_data["type"].to_string(),
+                "payload": notification_data["payload"].to_string(),
+            }
+        }
+    })
+}
+
+pub async fn send(push_token: &str, notification_data: &Value) -> EmptyResult {
+    let account = load_service_account().await?;
+    let access_token = get_access_token(&account).await?;
+
+    let url = format!("https://fcm.googleapis.com/v1/projects/{}/messages:send", account.project_id);
+    deliver(make_http_request(Method::POST, &url)?, &access_token, push_token, notification_data).await
+}
+
+pub async fn deliver(
+    request: RequestBuilder,
+    access_token: &str,
+    push_token: &str,
+    notification_data: &Value,
+) -> EmptyResult {
+    request
+        .header(CONTENT_TYPE, "application/json")
+        .header(AUTHORIZATION, format!("Bearer {access_token}"))
+        .json(&message(push_token, notification_data))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
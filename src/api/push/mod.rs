@@ -0,0 +1,707 @@
+mod apns;
+mod fcm;
+mod unifiedpush;
+
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use reqwest::{
+    Method,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+};
+use serde_json::Value;
+use tokio::sync::RwLock;
+
+use crate::{
+    CONFIG,
+    api::{ApiResult, EmptyResult, UpdateType},
+    db::{
+        DbConn,
+        models::{
+            AuthRequestId, Cipher, Device, DeviceType, Folder, Membership, Notification, NotificationStatus, PushId,
+            Send, User, UserId,
+        },
+    },
+    http_client::make_http_request,
+    util::{format_date, get_uuid},
+};
+
+/// Whether notifications go through the Bitwarden push relay, instead of directly to FCM, APNs or UnifiedPush.
+fn uses_push_relay() -> bool {
+    CONFIG.push_backend() == "relay"
+}
+
+#[derive(Deserialize)]
+struct AuthPushToken {
+    access_token: String,
+    expires_in: i32,
+}
+
+#[derive(Debug)]
+struct LocalAuthPushToken {
+    access_token: String,
+    valid_until: Instant,
+}
+
+async fn get_auth_api_token() -> ApiResult<String> {
+    static API_TOKEN: LazyLock<RwLock<LocalAuthPushToken>> = LazyLock::new(|| {
+        RwLock::new(LocalAuthPushToken {
+            access_token: String::new(),
+            valid_until: Instant::now(),
+        })
+    });
+    let api_token = API_TOKEN.read().await;
+
+    if api_token.valid_until.saturating_duration_since(Instant::now()).as_secs() > 0 {
+        debug!("Auth Push token still valid, no need for a new one");
+        return Ok(api_token.access_token.clone());
+    }
+    drop(api_token); // Drop the read lock now
+
+    let installation_id = CONFIG.push_installation_id();
+    let client_id = format!("installation.{installation_id}");
+    let client_secret = CONFIG.push_installation_key();
+
+    let params = [
+        ("grant_type", "client_credentials"),
+        ("scope", "api.push"),
+        ("client_id", &client_id),
+        ("client_secret", &client_secret),
+    ];
+
+    let res = match make_http_request(Method::POST, &format!("{}/connect/token", CONFIG.push_identity_uri()))?
+        .form(&params)
+        .send()
+        .await
+    {
+        Ok(r) => r,
+        Err(e) => err!(format!("Error getting push token from bitwarden server: {e}")),
+    };
+
+    let json_pushtoken = match res.json::<AuthPushToken>().await {
+        Ok(r) => r,
+        Err(e) => err!(format!("Unexpected push token received from bitwarden server: {e}")),
+    };
+
+    let mut api_token = API_TOKEN.write().await;
+    // Token valid for half the specified time
+    let half_expires_in = u64::from((json_pushtoken.expires_in / 2).max(0).cast_unsigned());
+    api_token.valid_until = Instant::now().checked_add(Duration::from_secs(half_expires_in)).unwrap();
+
+    api_token.access_token = json_pushtoken.access_token;
+
+    debug!("Token still valid for {}", api_token.valid_until.saturating_duration_since(Instant::now()).as_secs());
+    Ok(api_token.access_token.clone())
+}
+
+pub async fn register_push_device(device: &mut Device, conn: &DbConn) -> EmptyResult {
+    if !CONFIG.push_enabled() || !device.is_push_device() {
+        return Ok(());
+    }
+
+    if device.push_token.is_none() {
+        warn!("Skipping the registration of the device {:?} because the push_token field is empty.", device.uuid);
+        warn!("To get rid of this message you need to logout, clear the app data and login again on the device.");
+        return Ok(());
+    }
+
+    debug!("Registering Device {:?}", device.push_uuid);
+
+    // Generate a random push_uuid so if it doesn't already have one
+    if device.push_uuid.is_none() {
+        device.push_uuid = Some(PushId(get_uuid()));
+    }
+
+    // Without the relay, notifications are sent straight to the push token stored with the device
+    if !uses_push_relay() {
+        return device.save(true, conn).await;
+    }
+
+    //Needed to register a device for push to bitwarden :
+    let data = json!({
+        "deviceId": device.push_uuid, // Unique UUID per user/device
+        "pushToken": device.push_token,
+        "userId": device.user_uuid,
+        "type": device.atype,
+        "identifier": device.uuid,    // Unique UUID of the device/app, determined by the device/app it self currently registering
+        // "organizationIds:" [] // TODO: This is not yet implemented by Vaultwarden!
+        "installationId": CONFIG.push_installation_id(),
+    });
+
+    let auth_api_token = get_auth_api_token().await?;
+    let auth_header = format!("Bearer {auth_api_token}");
+
+    if let Err(e) = make_http_request(Method::POST, &(CONFIG.push_relay_uri() + "/push/register"))?
+        .header(CONTENT_TYPE, "application/json")
+        .header(ACCEPT, "application/json")
+        .header(AUTHORIZATION, auth_header)
+        .json(&data)
+        .send()
+        .await?
+        .error_for_status()
+    {
+        err!(format!("An error occurred while proceeding registration of a device: {e}"));
+    }
+
+    if let Err(e) = device.save(true, conn).await {
+        err!(format!("An error occurred while trying to save the (registered) device push uuid: {e}"));
+    }
+
+    Ok(())
+}
+
+/// Rejects push tokens which can't be used without the relay, before they are stored with the device.
+/// UnifiedPush tokens have to be Web Push subscriptions, with an endpoint which is reachable from the internet.
+pub fn validate_push_token(push_token: &str) -> EmptyResult {
+    if !CONFIG.push_enabled() || uses_push_relay() {
+        return Ok(());
+    }
+    if unifiedpush::is_subscription(push_token) || push_token.starts_with("https://") {
+        return unifiedpush::validate(push_token);
+    }
+    Ok(())
+}
+
+pub async fn unregister_push_device(push_id: Option<&PushId>) -> EmptyResult {
+    if !CONFIG.push_enabled() || !uses_push_relay() || push_id.is_none() {
+        return Ok(());
+    }
+    let auth_api_token = get_auth_api_token().await?;
+
+    let auth_header = format!("Bearer {auth_api_token}");
+
+    match make_http_request(
+        Method::POST,
+        &format!("{}/push/delete/{}", CONFIG.push_relay_uri(), push_id.as_ref().unwrap()),
+    )?
+    .header(AUTHORIZATION, auth_header)
+    .send()
+    .await
+    {
+        Ok(r) => r,
+        Err(e) => err!(format!("An error occurred during device unregistration: {e}")),
+    };
+    Ok(())
+}
+
+pub async fn push_cipher_update(ut: UpdateType, cipher: &Cipher, device: &Device, conn: &DbConn) {
+    // We shouldn't send a push notification on cipher update if the cipher belongs to an organization, this isn't implemented in the upstream server too.
+    if cipher.organization_uuid.is_some() {
+        return;
+    }
+    let Some(user_id) = &cipher.user_uuid else {
+        debug!("Cipher has no uuid");
+        return;
+    };
+
+    if Device::check_user_has_push_device(user_id, conn).await {
+        send_push(
+            json!({
+                "userId": user_id,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": ut as i32,
+                "payload": {
+                    "id": cipher.uuid,
+                    "userId": cipher.user_uuid,
+                    "organizationId": null,
+                    "collectionIds": null,
+                    "revisionDate": format_date(&cipher.updated_at)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_logout(user: &User, acting_device: Option<&Device>, conn: &DbConn) {
+    if Device::check_user_has_push_device(&user.uuid, conn).await {
+        send_push(
+            json!({
+                "userId": user.uuid,
+                "organizationId": (),
+                "deviceId": acting_device.and_then(|d| d.push_uuid.as_ref()),
+                "identifier": acting_device.map(|d| &d.uuid),
+                "type": UpdateType::LogOut as i32,
+                "payload": {
+                    "userId": user.uuid,
+                    "date": format_date(&user.updated_at)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_user_update(ut: UpdateType, user: &User, push_uuid: Option<&PushId>, conn: &DbConn) {
+    if Device::check_user_has_push_device(&user.uuid, conn).await {
+        send_push(
+            json!({
+                "userId": user.uuid,
+                "organizationId": null,
+                "deviceId": push_uuid,
+                "identifier": null,
+                "type": ut as i32,
+                "payload": {
+                    "userId": user.uuid,
+                    "date": format_date(&user.updated_at)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_folder_update(ut: UpdateType, folder: &Folder, device: &Device, conn: &DbConn) {
+    if Device::check_user_has_push_device(&folder.user_uuid, conn).await {
+        send_push(
+            json!({
+                "userId": folder.user_uuid,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": ut as i32,
+                "payload": {
+                    "id": folder.uuid,
+                    "userId": folder.user_uuid,
+                    "revisionDate": format_date(&folder.updated_at)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_send_update(ut: UpdateType, send: &Send, device: &Device, conn: &DbConn) {
+    if let Some(s) = &send.user_uuid
+        && Device::check_user_has_push_device(s, conn).await
+    {
+        send_push(
+            json!({
+                "userId": send.user_uuid,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": ut as i32,
+                "payload": {
+                    "id": send.uuid,
+                    "userId": send.user_uuid,
+                    "revisionDate": format_date(&send.revision_date)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_org_status_update(member: &Membership, enabled: bool, device: &Device, conn: &DbConn) {
+    if Device::check_user_has_push_device(&member.user_uuid, conn).await {
+        send_push(
+            json!({
+                "userId": member.user_uuid,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": UpdateType::SyncOrganizationStatusChanged as i32,
+                "payload": {
+                    "organizationId": member.org_uuid,
+                    "enabled": enabled
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_org_collection_settings_update(member: &Membership, device: &Device, conn: &DbConn) {
+    if Device::check_user_has_push_device(&member.user_uuid, conn).await {
+        send_push(
+            json!({
+                "userId": member.user_uuid,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": UpdateType::SyncOrganizationCollectionSettingChanged as i32,
+                "payload": {
+                    "organizationId": member.org_uuid,
+                    "limitCollectionCreation": member.limit_collection_creation(),
+                    "limitCollectionDeletion": true,
+                    "limitItemDeletion": false
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_notification(
+    ut: UpdateType,
+    notification: &Notification,
+    status: Option<&NotificationStatus>,
+    user_id: &UserId,
+    acting_device: Option<&Device>,
+    conn: &DbConn,
+) {
+    if Device::check_user_has_push_device(user_id, conn).await {
+        send_push(
+            json!({
+                "userId": user_id,
+                "organizationId": null,
+                "deviceId": acting_device.and_then(|d| d.push_uuid.as_ref()),
+                "identifier": acting_device.map(|d| &d.uuid),
+                "type": ut as i32,
+                "payload": {
+                    "id": notification.uuid,
+                    "priority": notification.priority,
+                    "global": notification.global,
+                    "clientType": notification.client_type,
+                    "userId": notification.user_uuid,
+                    "organizationId": notification.organization_uuid,
+                    "installationId": null,
+                    "taskId": null,
+                    "title": notification.title,
+                    "body": notification.body,
+                    "creationDate": format_date(&notification.creation_date),
+                    "revisionDate": format_date(&notification.revision_date),
+                    "readDate": status.and_then(|s| s.read_date.as_ref()).map(format_date),
+                    "deletedDate": status.and_then(|s| s.deleted_date.as_ref()).map(format_date)
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+/// Sends the notification through the push relay, or directly to every push device of the user except the acting one.
+async fn send_push(notification_data: Value, conn: &DbConn) {
+    if !CONFIG.push_enabled() {
+        return;
+    }
+
+    if uses_push_relay() {
+        tokio::task::spawn(send_to_push_relay(notification_data));
+        return;
+    }
+
+    let Some(user_id) = notification_data["userId"].as_str().map(UserId::from) else {
+        return;
+    };
+    let acting_device = notification_data["identifier"].as_str();
+    let devices: Vec<Device> = Device::find_push_devices_by_user(&user_id, conn)
+        .await
+        .into_iter()
+        .filter(|device| acting_device != Some(device.uuid.to_string().as_str()))
+        .collect();
+
+    tokio::task::spawn(send_to_devices(devices, notification_data));
+}
+
+async fn send_to_devices(devices: Vec<Device>, notification_data: Value) {
+    for device in devices {
+        let Some(push_token) = &device.push_token else {
+            continue;
+        };
+
+        let result = if unifiedpush::is_subscription(push_token) {
+            unifiedpush::send(push_token, &notification_data).await
+        } else {
+            match DeviceType::from_i32(device.atype) {
+                DeviceType::Android => fcm::send(push_token, &notification_data).await,
+                DeviceType::Ios => apns::send(push_token, &notification_data).await,
+                _ => continue,
+            }
+        };
+
+        if let Err(e) = result {
+            error!("An error occurred while sending a push notification to device {}: {e}", device.uuid);
+        }
+    }
+}
+
+async fn send_to_push_relay(notification_data: Value) {
+    let auth_api_token = match get_auth_api_token().await {
+        Ok(s) => s,
+        Err(e) => {
+            debug!("Could not get the auth push token: {e}");
+            return;
+        }
+    };
+
+    let auth_header = format!("Bearer {auth_api_token}");
+
+    let req = match make_http_request(Method::POST, &(CONFIG.push_relay_uri() + "/push/send")) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("An error occurred while sending a send update to the push relay: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = req
+        .header(ACCEPT, "application/json")
+        .header(CONTENT_TYPE, "application/json")
+        .header(AUTHORIZATION, &auth_header)
+        .json(&notification_data)
+        .send()
+        .await
+    {
+        error!("An error occurred while sending a send update to the push relay: {e}");
+    }
+}
+
+pub async fn push_auth_request(user_id: &UserId, auth_request_id: &str, device: &Device, conn: &DbConn) {
+    if Device::check_user_has_push_device(user_id, conn).await {
+        send_push(
+            json!({
+                "userId": user_id,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": UpdateType::AuthRequest as i32,
+                "payload": {
+                    "userId": user_id,
+                    "id": auth_request_id,
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+pub async fn push_auth_response(user_id: &UserId, auth_request_id: &AuthRequestId, device: &Device, conn: &DbConn) {
+    if Device::check_user_has_push_device(user_id, conn).await {
+        send_push(
+            json!({
+                "userId": user_id,
+                "organizationId": null,
+                "deviceId": device.push_uuid, // Should be the records unique uuid of the acting device (unique uuid per user/device)
+                "identifier": device.uuid, // Should be the acting device id (aka uuid per device/app)
+                "type": UpdateType::AuthRequestResponse as i32,
+                "payload": {
+                    "userId": user_id,
+                    "id": auth_request_id,
+                },
+                "clientType": null,
+                "installationId": null
+            }),
+            conn,
+        )
+        .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::make_untrusted_http_request;
+
+    fn notification() -> Value {
+        json!({
+            "userId": "00000000-0000-0000-0000-000000000001",
+            "identifier": "00000000-0000-0000-0000-000000000002",
+            "type": UpdateType::SyncCipherUpdate as i32,
+            "payload": {
+                "id": "00000000-0000-0000-0000-000000000003",
+                "revisionDate": "2026-01-01T00:00:00.000000Z"
+            },
+        })
+    }
+
+    #[test]
+    fn fcm_message_contains_string_data() {
+        let message = fcm::message("device-token", &notification());
+        assert_eq!(message["message"]["token"], "device-token");
+        assert_eq!(message["message"]["data"]["type"], "0");
+
+        let payload: Value = serde_json::from_str(message["message"]["data"]["payload"].as_str().unwrap()).unwrap();
+        assert_eq!(payload["id"], "00000000-0000-0000-0000-000000000003");
+    }
+
+    #[test]
+    fn apns_payload_is_background_notification() {
+        let payload = apns::payload(&notification());
+        assert_eq!(payload["aps"]["content-available"], 1);
+        assert_eq!(payload["data"]["type"], 0);
+        assert!(payload["data"]["payload"].is_string());
+    }
+
+    #[test]
+    fn unifiedpush_tokens_are_web_push_subscriptions() {
+        let (push_token, _) = unifiedpush::tests::subscription("https://ntfy.sh/upAbCdEf?up=1");
+        assert!(unifiedpush::is_subscription(&push_token));
+        assert!(!unifiedpush::is_subscription("https://ntfy.sh/upAbCdEf?up=1"));
+        assert!(!unifiedpush::is_subscription("fcm-token:APA91bH"));
+
+        let message = unifiedpush::message(&notification());
+        assert_eq!(message["type"], 0);
+        assert_eq!(message["payload"]["id"], "00000000-0000-0000-0000-000000000003");
+    }
+
+    #[test]
+    fn unifiedpush_rejects_private_endpoints() {
+        install_crypto_provider();
+        for endpoint in
+            ["https://127.0.0.1/up", "https://10.0.0.1/up", "https://[::1]/up", "https://169.254.169.254/up"]
+        {
+            let (push_token, _) = unifiedpush::tests::subscription(endpoint);
+            assert!(unifiedpush::validate(&push_token).is_err(), "{endpoint}");
+            assert!(make_untrusted_http_request(Method::POST, endpoint).is_err(), "{endpoint}");
+        }
+        let (push_token, _) = unifiedpush::tests::subscription("https://1.1.1.1/up");
+        assert!(unifiedpush::validate(&push_token).is_ok());
+    }
+
+    /// The HTTP clients need the crypto provider which is installed at startup.
+    fn install_crypto_provider() {
+        // This fails when another test already installed it
+        rustls::crypto::ring::default_provider().install_default().ok();
+    }
+
+    /// A request received by the mock push service.
+    struct ReceivedRequest {
+        request_line: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl ReceivedRequest {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Starts a push service on a local port which answers a single request with `status`, and returns its URL.
+    async fn mock_push_service(status: u16) -> (String, tokio::task::JoinHandle<ReceivedRequest>) {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        install_crypto_provider();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+
+            let mut request_line = String::new();
+            stream.read_line(&mut request_line).await.unwrap();
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(':').unwrap();
+                headers.push((name.to_owned(), value.trim().to_owned()));
+            }
+            let mut request = ReceivedRequest {
+                request_line: request_line.trim_end().to_owned(),
+                headers,
+                body: Vec::new(),
+            };
+
+            let length = request.header("content-length").map_or(0, |l| l.parse().unwrap());
+            request.body = vec![0; length];
+            stream.read_exact(&mut request.body).await.unwrap();
+
+            let response = format!("HTTP/1.1 {status} Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+            stream.get_mut().write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    fn mock_request(url: &str) -> reqwest::RequestBuilder {
+        reqwest::Client::new().post(url)
+    }
+
+    #[tokio::test]
+    async fn fcm_delivers_the_message() {
+        let (url, service) = mock_push_service(200).await;
+        fcm::deliver(
+            mock_request(&format!("{url}/v1/projects/p/messages:send")),
+            "access",
+            "device-token",
+            &notification(),
+        )
+        .await
+        .unwrap();
+
+        let request = service.await.unwrap();
+        assert_eq!(request.request_line, "POST /v1/projects/p/messages:send HTTP/1.1");
+        assert_eq!(request.header("authorization"), Some("Bearer access"));
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, fcm::message("device-token", &notification()));
+    }
+
+    #[tokio::test]
+    async fn apns_delivers_the_payload() {
+        let (url, service) = mock_push_service(200).await;
+        apns::deliver(mock_request(&format!("{url}/3/device/abc")), "provider", "com.example", &notification())
+            .await
+            .unwrap();
+
+        let request = service.await.unwrap();
+        assert_eq!(request.request_line, "POST /3/device/abc HTTP/1.1");
+        assert_eq!(request.header("authorization"), Some("bearer provider"));
+        assert_eq!(request.header("apns-topic"), Some("com.example"));
+        assert_eq!(request.header("apns-push-type"), Some("background"));
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body, apns::payload(&notification()));
+    }
+
+    #[tokio::test]
+    async fn unifiedpush_delivers_an_encrypted_message() {
+        let (url, service) = mock_push_service(201).await;
+        let (push_token, client_key) = unifiedpush::tests::subscription("https://push.example.com/up");
+        let subscription = unifiedpush::parse_subscription(&push_token).unwrap();
+        unifiedpush::deliver(mock_request(&format!("{url}/up")), &subscription, &notification()).await.unwrap();
+
+        let request = service.await.unwrap();
+        assert_eq!(request.request_line, "POST /up HTTP/1.1");
+        assert_eq!(request.header("content-encoding"), Some("aes128gcm"));
+        assert!(request.header("ttl").is_some());
+        let message = unifiedpush::tests::decrypt(&request.body, &subscription, client_key);
+        let message: Value = serde_json::from_slice(&message).unwrap();
+        assert_eq!(message, unifiedpush::message(&notification()));
+    }
+
+    #[tokio::test]
+    async fn delivery_fails_when_the_push_service_refuses() {
+        let (url, service) = mock_push_service(410).await;
+        let (push_token, _) = unifiedpush::tests::subscription("https://push.example.com/up");
+        let subscription = unifiedpush::parse_subscription(&push_token).unwrap();
+        assert!(unifiedpush::deliver(mock_request(&url), &subscription, &notification()).await.is_err());
+        service.await.unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+// Sends push notifications to a UnifiedPush distributor, like ntfy, or any other Web Push endpoint.
+// Clients using UnifiedPush register their Web Push subscription as the push token, which is the JSON returned by
+// `PushSubscription.toJSON()`: the endpoint URL they received from their distributor, and the keys the messages have
+// to be encrypted with.
+// See https://unifiedpush.org/developers/spec/ and https://www.rfc-editor.org/rfc/rfc8291
+
+use data_encoding::BASE64URL_NOPAD;
+use reqwest::{
+    Method, RequestBuilder,
+    header::{CONTENT_ENCODING, CONTENT_TYPE},
+};
+use ring::{
+    aead, agreement,
+    error::Unspecified,
+    hkdf,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde_json::Value;
+
+use crate::{api::EmptyResult, error::Error, http_client::make_untrusted_http_request};
+
+/// Record size of the encrypted message. Push services accept messages of up to 4096 bytes.
+const RECORD_SIZE: u32 = 4096;
+
+/// Length of the header of an `aes128gcm` encrypted message: salt, record size, key id length and the key id.
+const HEADER_LENGTH: usize = 16 + 4 + 1 + 65;
+
+#[derive(Deserialize)]
+struct SubscriptionJson {
+    endpoint: String,
+    keys: SubscriptionKeysJson,
+}
+
+#[derive(Deserialize)]
+struct SubscriptionKeysJson {
+    p256dh: String,
+    auth: String,
+}
+
+/// A Web Push subscription with its decoded keys.
+pub struct Subscription {
+    pub endpoint: String,
+    /// Uncompressed P-256 public key of the client
+    pub p256dh: Vec<u8>,
+    pub auth: [u8; 16],
+}
+
+/// UnifiedPush tokens are Web Push subscriptions, unlike the opaque tokens of FCM and APNs.
+pub fn is_subscription(push_token: &str) -> bool {
+    push_token.trim_start().starts_with('{')
+}
+
+fn decode_key(key: &str) -> Option<Vec<u8>> {
+    BASE64URL_NOPAD.decode(key.trim_end_matches('=').as_bytes()).ok()
+}
+
+pub fn parse_subscription(push_token: &str) -> Result<Subscription, Error> {
+    let Ok(subscription) = serde_json::from_str::<SubscriptionJson>(push_token) else {
+        err!("Invalid Web Push subscription")
+    };
+
+    if !subscription.endpoint.starts_with("https://") {
+        err!("Web Push endpoints have to use https")
+    }
+    let Some(p256dh) = decode_key(&subscription.keys.p256dh).filter(|key| key.len() == 65 && key[0] == 0x04) else {
+        err!("Invalid Web Push subscription key")
+    };
+    let Some(auth) = decode_key(&subscription.keys.auth).and_then(|auth| <[u8; 16]>::try_from(auth).ok()) else {
+        err!("Invalid Web Push authentication secret")
+    };
+
+    Ok(Subscription {
+        endpoint: subscription.endpoint,
+        p256dh,
+        auth,
+    })
+}
+
+pub fn message(notification_data: &Value) -> Value {
+    json!({
+        "type": notification_data["type"],
+        "payload": notification_data["payload"],
+    })
+}
+
+/// `ring::hkdf` needs the output length as a `KeyType`.
+struct OutputLength(usize);
+
+impl hkdf::KeyType for OutputLength {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn hkdf(salt: &[u8], ikm: &[u8], info: &[&[u8]], output: &mut [u8]) -> Result<(), Unspecified> {
+    let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, salt).extract(ikm);
+    prk.expand(info, OutputLength(output.len()))?.fill(output)
+}
+
+/// Derives the content encryption key and nonce of a message from the shared ECDH secret, see RFC 8291 section 3.4.
+fn derive_keys(
+    ecdh_secret: &[u8],
+    subscription: &Subscription,
+    server_public_key: &[u8],
+    salt: &[u8; 16],
+) -> Result<([u8; 16], [u8; 12]), Unspecified> {
+    let mut ikm = [0; 32];
+    hkdf(&subscription.auth, ecdh_secret, &[b"WebPush: info\0", &subscription.p256dh, server_public_key], &mut ikm)?;
+
+    let mut cek = [0; 16];
+    let mut nonce = [0; 12];
+    hkdf(salt, &ikm, &[b"Content-Encoding: aes128gcm\0"], &mut cek)?;
+    hkdf(salt, &ikm, &[b"Content-Encoding: nonce\0"], &mut nonce)?;
+    Ok((cek, nonce))
+}
+
+/// Encrypts a message for the subscription with the `aes128gcm` content encoding, as a single record.
+pub fn encrypt(plaintext: &[u8], subscription: &Subscription) -> Result<Vec<u8>, Error> {
+    // The record holds the plaintext, the padding delimiter and the authentication tag
+    if plaintext.len() + 1 + aead::AES_128_GCM.tag_len() > RECORD_SIZE as usize - HEADER_LENGTH {
+        err!("Push message too large")
+    }
+    seal(plaintext, subscription)
+        .map_err(|_| Error::new("Unable to encrypt the push message", "Web Push encryption failed"))
+}
+
+fn seal(plaintext: &[u8], subscription: &Subscription) -> Result<Vec<u8>, Unspecified> {
+    let rng = SystemRandom::new();
+    let server_key = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng)?;
+    let mut salt = [0; 16];
+    rng.fill(&mut salt)?;
+
+    let server_public_key = server_key.compute_public_key()?;
+    let client_public_key = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, &subscription.p256dh);
+    let ecdh_secret = agreement::agree_ephemeral(server_key, &client_public_key, <[u8]>::to_vec)?;
+    let (cek, nonce) = derive_keys(&ecdh_secret, subscription, server_public_key.as_ref(), &salt)?;
+
+    let mut record = Vec::with_capacity(plaintext.len() + 1 + aead::AES_128_GCM.tag_len());
+    record.extend_from_slice(plaintext);
+    record.push(0x02); // Delimiter of the last record, without padding
+    let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &cek)?);
+    key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut record)?;
+
+    let mut body = Vec::with_capacity(HEADER_LENGTH + record.len());
+    body.extend_from_slice(&salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(65); // Length of the key id, which is the public key of the server
+    body.extend_from_slice(server_public_key.as_ref());
+    body.extend_from_slice(&record);
+    Ok(body)
+}
+
+/// Checks a subscription when it's registered, before anything is sent to its endpoint.
+pub fn validate(push_token: &str) -> EmptyResult {
+    let subscription = parse_subscription(push_token)?;
+    make_untrusted_http_request(Method::POST, &subscription.endpoint).map(drop)
+}
+
+pub async fn send(push_token: &str, notification_data: &Value) -> EmptyResult {
+    let subscription = parse_subscription(push_token)?;
+    deliver(make_untrusted_http_request(Method::POST, &subscription.endpoint)?, &subscription, notification_data).await
+}
+
+pub async fn deliver(request: RequestBuilder, subscription: &Subscription, notification_data: &Value) -> EmptyResult {
+    let body = encrypt(message(notification_data).to_string().as_bytes(), subscription)?;
+    request
+        .header(CONTENT_TYPE, "application/octet-stream")
+        .header(CONTENT_ENCODING, "aes128gcm")
+        .header("TTL", "86400")
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Decrypts a message the way the client does, with the private key of its subscription.
+    pub fn decrypt(body: &[u8], subscription: &Subscription, client_key: agreement::EphemeralPrivateKey) -> Vec<u8> {
+        let salt: [u8; 16] = body[..16].try_into().unwrap();
+        assert_eq!(u32::from_be_bytes(body[16..20].try_into().unwrap()), RECORD_SIZE);
+        assert_eq!(body[20], 65);
+        let server_public_key = &body[21..HEADER_LENGTH];
+
+        let server_key = agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, server_public_key);
+        let ecdh_secret = agreement::agree_ephemeral(client_key, &server_key, <[u8]>::to_vec).unwrap();
+        let (cek, nonce) = derive_keys(&ecdh_secret, subscription, server_public_key, &salt).unwrap();
+
+        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, &cek).unwrap());
+        let mut record = body[HEADER_LENGTH..].to_vec();
+        let plaintext = key
+            .open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut record)
+            .unwrap()
+            .to_vec();
+        assert_eq!(plaintext.last(), Some(&0x02));
+        plaintext[..plaintext.len() - 1].to_vec()
+    }
+
+    /// Creates a subscription to `endpoint`, along with the private key of the client.
+    pub fn subscription(endpoint: &str) -> (String, agreement::EphemeralPrivateKey) {
+        let rng = SystemRandom::new();
+        let client_key = agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng).unwrap();
+        let public_key = client_key.compute_public_key().unwrap();
+        let push_token = json!({
+            "endpoint": endpoint,
+            "keys": {
+                "p256dh": BASE64URL_NOPAD.encode(public_key.as_ref()),
+                "auth": BASE64URL_NOPAD.encode(&[7; 16]),
+            }
+        });
+        (push_token.to_string(), client_key)
+    }
+
+    #[test]
+    fn encrypted_messages_can_be_decrypted_by_the_client() {
+        let (push_token, client_key) = subscription("https://push.example.com/up");
+        let subscription = parse_subscription(&push_token).unwrap();
+
+        let body = encrypt(b"hello", &subscription).unwrap();
+        assert_eq!(body.len(), HEADER_LENGTH + 5 + 1 + 16);
+        assert_eq!(decrypt(&body, &subscription, client_key), b"hello");
+    }
+
+    #[test]
+    fn rejects_messages_larger_than_a_record() {
+        let (push_token, _) = subscription("https://push.example.com/up");
+        let subscription = parse_subscription(&push_token).unwrap();
+
+        assert!(encrypt(&[0; 3993], &subscription).is_ok());
+        assert!(encrypt(&[0; 3994], &subscription).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_subscriptions() {
+        let (push_token, _) = subscription("http://push.example.com/up");
+        assert!(parse_subscription(&push_token).is_err());
+        assert!(parse_subscription("https://push.example.com/up").is_err());
+        assert!(
+            parse_subscription(r#"{"endpoint":"https://push.example.com/up","keys":{"p256dh":"AAAA","auth":"AAAA"}}"#)
+                .is_err()
+        );
+    }
+}
@@ -4,7 +4,7 @@ use std::{
 };
 
 use rocket::{
-    Catcher, Route,
+    Catcher, Either, Route,
     fs::NamedFile,
     http::ContentType,
     response::{Redirect, content::RawCss as Css, content::RawHtml as Html},
@@ -16,10 +16,11 @@ use crate::{
     CONFIG,
     api::{ApiResult, EmptyResult, core::now},
     auth::decode_file_download,
+    config::PathType,
     crypto::sha256_hex,
     db::{
         DbConn,
-        models::{AttachmentId, CipherId},
+        models::{Attachment, AttachmentId, CipherId},
     },
     error::Error,
     util::{Cached, EtagCached},
@@ -227,8 +228,14 @@ async fn web_files(p: PathBuf) -> Cached<Option<NamedFile>> {
     Cached::long(NamedFile::open(Path::new(&CONFIG.web_vault_folder()).join(p)).await.ok(), true)
 }
 
+/// Serves attachments from the local filesystem, or redirects to a short-lived presigned URL for object storage.
 #[get("/attachments/<cipher_id>/<file_id>?<token>")]
-async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String) -> Option<NamedFile> {
+async fn attachments(
+    cipher_id: CipherId,
+    file_id: AttachmentId,
+    token: String,
+    conn: DbConn,
+) -> Option<Either<NamedFile, Redirect>> {
     let Ok(claims) = decode_file_download(&token) else {
         return None;
     };
@@ -236,7 +243,36 @@ async fn attachments(cipher_id: CipherId, file_id: AttachmentId, token: String)
         return None;
     }
 
-    NamedFile::open(Path::new(&CONFIG.attachments_folder()).join(cipher_id.as_ref()).join(file_id.as_ref())).await.ok()
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments).ok()?;
+    if !crate::storage::is_fs_operator(&operator) {
+        let url = crate::storage::presign_read(&operator, &format!("{cipher_id}/{file_id}")).await.ok()?;
+        return Some(Either::Right(Redirect::temporary(url)));
+    }
+
+    if CONFIG.attachment_verify_on_download()
+        && let Some(attachment) = Attachment::find_by_id(&file_id, &conn).await
+        && let Some(expected) = &attachment.checksum
+    {
+        match attachment.compute_checksum().await {
+            Ok(checksum) if checksum == *expected => {}
+            Ok(_) => {
+                error!(
+                    "Attachment file '{}' doesn't match its checksum, refusing to serve it",
+                    attachment.get_file_path()
+                );
+                return None;
+            }
+            Err(e) => {
+                error!("Failed to verify attachment file '{}': {e:?}", attachment.get_file_path());
+                return None;
+            }
+        }
+    }
+
+    NamedFile::open(Path::new(&CONFIG.attachments_folder()).join(cipher_id.as_ref()).join(file_id.as_ref()))
+        .await
+        .ok()
+        .map(Either::Left)
 }
 
 // We use DbConn here to let the alive healthcheck also verify the database connection.
@@ -293,6 +329,8 @@ pub fn static_files(filename: &str) -> Result<(ContentType, &'static [u8]), Erro
         "admin_organizations.js" => {
             Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_organizations.js")))
         }
+        "admin_mail_queue.js" => Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_mail_queue.js"))),
+        "admin_icons.js" => Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_icons.js"))),
         "admin_diagnostics.js" => {
             Ok((ContentType::JavaScript, include_bytes!("../static/scripts/admin_diagnostics.js")))
         }
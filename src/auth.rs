@@ -592,6 +592,8 @@ impl<'r> FromRequest<'r> for Host {
 pub struct ClientHeaders {
     pub device_type: i32,
     pub ip: ClientIp,
+    /// The preferred language from the `Accept-Language` header, unless the client doesn't send the one of the user
+    pub language: Option<String>,
 }
 
 #[rocket::async_trait]
@@ -608,10 +610,16 @@ impl<'r> FromRequest<'r> for ClientHeaders {
             .get_one("device-type")
             .and_then(|d| d.parse().ok())
             .unwrap_or(DeviceType::UnknownBrowser as i32);
+        // The CLI runs with the locale of the system it's on, like a server, which isn't a language the user chose
+        let language = match request.headers().get_one("bitwarden-client-name") {
+            Some("cli") => None,
+            _ => request.headers().get_one("accept-language").and_then(crate::util::parse_accept_language),
+        };
 
         Outcome::Success(ClientHeaders {
             device_type,
             ip,
+            language,
         })
     }
 }
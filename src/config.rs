@@ -441,6 +441,7 @@ macro_rules! make_config {
                     "domain_path",
                     "domain",
                     "helo_name",
+                    "mail_http_url",
                     "org_creation_users",
                     "signups_domains_whitelist",
                     "_smtp_img_src",
@@ -507,6 +508,8 @@ make_config! {
         database_url:           String, false,  auto,   |c| format!("sqlite://{}", storage::join_path(&c.data_folder, "db.sqlite3"));
         /// Icon cache folder
         icon_cache_folder:      String, false,  auto,   |c| storage::join_path(&c.data_folder, "icon_cache");
+        /// Custom icons folder |> Icons uploaded on the admin page, which are kept apart from the icon cache
+        custom_icons_folder:    String, false,  auto,   |c| storage::join_path(&c.data_folder, "custom_icons");
         /// Attachments folder
         attachments_folder:     String, false,  auto,   |c| storage::join_path(&c.data_folder, "attachments");
         /// Sends folder
@@ -523,6 +526,8 @@ make_config! {
     ws {
         /// Enable websocket notifications
         enable_websocket:       bool,   false,  def,    true;
+        /// Backplane for sharing notifications between instances |> Either `postgresql` or a `redis://` URL, leave empty when running a single instance
+        websocket_backplane:    Pass,   false,  def,    String::new();
     },
     push {
         /// Enable push notifications
@@ -535,6 +540,20 @@ make_config! {
         push_installation_id:   Pass,   false,  def,    String::new();
         /// Installation key |> The installation key from https://bitwarden.com/host
         push_installation_key:  Pass,   false,  def,    String::new();
+        /// Push backend |> `relay` to use the Bitwarden push relay, or `direct` to send notifications to FCM, APNs or UnifiedPush with your own credentials. `direct` only works with clients built with those credentials
+        push_backend:           String, false,  def,    "relay".to_owned();
+        /// FCM service account |> Path to the JSON key of a Google service account allowed to send Firebase Cloud Messages, used for Android devices by the `direct` backend
+        push_fcm_service_account: String, false, option;
+        /// APNs key file |> Path to the .p8 APNs authentication key, used for iOS devices by the `direct` backend
+        push_apns_key_file:     String, false,  option;
+        /// APNs key id |> The id of the APNs authentication key
+        push_apns_key_id:       String, false,  option;
+        /// APNs team id |> The Apple developer team id the key belongs to
+        push_apns_team_id:      String, false,  option;
+        /// APNs topic |> The bundle id of the iOS app
+        push_apns_topic:        String, false,  option;
+        /// Use the APNs sandbox |> Send to the development environment of APNs, for apps signed with a development certificate
+        push_apns_sandbox:      bool,   false,  def,    false;
     },
     jobs {
         /// Job scheduler poll interval |> How often the job scheduler thread checks for jobs to run.
@@ -567,6 +586,23 @@ make_config! {
         /// Purge incomplete SSO auth. |> Cron schedule of the job that cleans leftover auth in db due to incomplete SSO login.
         /// Defaults to daily. Set blank to disable this job.
         purge_incomplete_sso_auth: String, false,  def,   "0 20 0 * * *".to_owned();
+        /// Storage garbage collection schedule |> Cron schedule of the job that cross-references the attachments and sends folders with the database
+        /// and reports files without a database record, as well as records without a file. Abandoned chunked uploads are removed as well. Defaults to daily. Set blank to disable this job.
+        storage_gc_schedule:    String, false,  def,    "0 40 3 * * *".to_owned();
+        /// Delete orphaned files |> Whether the storage garbage collection job deletes files without a database record instead of only reporting them.
+        storage_gc_delete_orphans: bool, false, def,    false;
+        /// Attachment integrity scan schedule |> Cron schedule of the job that verifies the checksum of every stored attachment.
+        /// Attachments uploaded before checksums were recorded get their checksum stored on the first scan. Defaults to weekly. Set blank to disable this job.
+        attachment_integrity_schedule: String, false, def, "0 10 4 * * Sun".to_owned();
+        /// Pending uploads schedule |> Cron schedule of the job that completes the attachments and Send files uploaded through presigned URLs,
+        /// and deletes the ones whose content wasn't uploaded within a day. Defaults to every minute. Set blank to disable this job.
+        pending_upload_schedule: String, false, def,   "45 * * * * *".to_owned();
+        /// Mail queue schedule |> Cron schedule of the job that retries sending the mails in the outbound mail queue.
+        /// Only used when the mail queue is enabled. Defaults to every minute. Set blank to disable this job.
+        mail_queue_schedule:    String, false,  def,    "15 * * * * *".to_owned();
+        /// Icon refresh schedule |> Cron schedule of the job that fetches the icons of the domains listed on the admin page ahead of time,
+        /// and refreshes them before they expire. Only used with the internal icon service. Defaults to daily. Set blank to disable this job.
+        icon_refresh_schedule:  String, false,  def,    "0 30 2 * * *".to_owned();
     },
 
     /// General settings
@@ -594,14 +630,30 @@ make_config! {
         user_attachment_limit:  i64,    true,   option;
         /// Per-organization attachment storage limit (KB) |> Max kilobytes of attachment storage allowed per org. When this limit is reached, org members will not be allowed to upload further attachments for ciphers owned by that org.
         org_attachment_limit:   i64,    true,   option;
+        /// Direct uploads to object storage |> Let clients upload attachments and Send files directly to the object storage through presigned URLs, instead of streaming them through Vaultwarden.
+        /// Requires a CORS policy on the bucket which allows PUT requests from the web vault domain. Has no effect when files are stored on the local filesystem.
+        storage_direct_upload:  bool,   true,   def,    false;
+        /// Verify attachments on download |> Verify the checksum of an attachment before serving it from local storage. Corrupted files are refused instead of being sent to the client.
+        /// This reads every file twice, so it is disabled by default and the periodic integrity scan is relied upon instead.
+        attachment_verify_on_download: bool, true, def, false;
         /// Per-user send storage limit (KB) |> Max kilobytes of sends storage allowed per user. When this limit is reached, the user will not be allowed to upload further sends.
         user_send_limit:   i64,    true,   option;
 
         /// Trash auto-delete days |> Number of days to wait before auto-deleting a trashed item.
-        /// If unset, trashed items are not auto-deleted. This setting applies globally, so make
-        /// sure to inform all users of any changes to this setting.
+        /// If unset, trashed items are not auto-deleted, with 0 they're deleted at the next purge. This setting applies globally,
+        /// so make sure to inform all users of any changes to this setting. Organization admins can override it for their items,
+        /// where 0 has the same meaning.
         trash_auto_delete_days: i64,    true,   option;
 
+        /// Cipher revisions limit |> Number of previous versions kept per item, which can be listed and restored.
+        /// Permanently deleted items and folders are kept as well, so admins can restore a vault to an earlier point in time.
+        /// Disabled by default (0), when enabled deleted items stay on the server until the retention has passed.
+        cipher_revisions_limit: u32,    true,   def,    0;
+
+        /// Cipher revisions retention days |> Number of days after which previous item versions and deleted items are removed
+        /// by the trash purge job, which is also how far back a vault can be restored. Has to be at least 1.
+        cipher_revisions_retention_days: i64, true, def,  90;
+
         /// Incomplete 2FA time limit |> Number of minutes to wait before a 2FA-enabled login is
         /// considered incomplete, resulting in an email notification. An incomplete 2FA login is one
         /// where the correct master password was provided but the required 2FA step was not completed,
@@ -700,6 +752,11 @@ make_config! {
         icon_cache_negttl:      u64,    true,   def,    259_200;
         /// Icon download timeout |> Number of seconds when to stop attempting to download an icon.
         icon_download_timeout:  u64,    true,   def,    10;
+        /// Icon refresh concurrency |> Number of icons fetched at the same time when refreshing the listed domains.
+        icon_refresh_concurrency: usize, true,  def,    4;
+        /// Icon size |> Width and height in pixels the downloaded icons are resized to. They are stored as PNG, and served as WebP to the clients which accept it.
+        /// SVG icons are rasterized. Set to 0 to store the icons as they were downloaded.
+        icon_size:              u32,    true,   def,    64;
 
         /// [Deprecated] Icon blacklist Regex |> Use `http_request_block_regex` instead
         icon_blacklist_regex:   String, false,   option;
@@ -765,6 +822,13 @@ make_config! {
         /// Database connection init |> SQL statements to run when creating a new database connection, mainly useful for connection-scoped pragmas. If empty, a database-specific default is used.
         database_conn_init:     String, false,  def,    String::new();
 
+        /// Sync cache lifetime |> Number of seconds a full sync response is kept in memory and reused as long as the vault of the user doesn't change, set to 0 to disable.
+        /// At most 240, as the attachment download links in the response expire after 5 minutes.
+        sync_cache_ttl:         u64,    false,  def,    60;
+
+        /// Sync cache size |> The maximum size in MB of all sync responses kept in memory
+        sync_cache_size_mb:     u64,    false,  def,    64;
+
         /// Bypass admin page security (Know the risks!) |> Disables the Admin Token for the admin page so you may use your own auth in-front
         disable_admin_token:    bool,   false,  def,    false;
 
@@ -778,6 +842,8 @@ make_config! {
         login_ratelimit_seconds:       u64, false, def, 60;
         /// Max burst size for login requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `login_ratelimit_seconds`. Note that this applies to both the login and the 2FA, so it's recommended to allow a burst size of at least 2
         login_ratelimit_max_burst:     u32, false, def, 10;
+        /// Rate limit logins per account |> Also apply the login rate limit to every account, regardless of the IP address the requests come from
+        login_ratelimit_per_account:   bool, false, def, false;
 
         /// Seconds between unauthenticated requests |> Number of seconds, on average, between requests from the same IP address to any of the rate limited unauthenticated endpoints
         unauthenticated_ratelimit_seconds:   u64, false, def, 60;
@@ -789,6 +855,9 @@ make_config! {
         /// Max burst size for admin login requests |> Allow a burst of requests of up to this size, while maintaining the average indicated by `admin_ratelimit_seconds`
         admin_ratelimit_max_burst:     u32, false, def, 3;
 
+        /// Rate limit backend |> Where the rate limit state is kept: empty to keep it in memory, `database` or a `redis://` URL to share it between instances and keep it across restarts
+        ratelimit_backend:             Pass, false, def, String::new();
+
         /// Admin session lifetime |> Set the lifetime of admin sessions to this value (in minutes).
         admin_session_lifetime:        i64, true,  def, 20;
 
@@ -883,6 +952,17 @@ make_config! {
         use_sendmail:                  bool,   true,   def,     false;
         /// Sendmail Command |> Which sendmail command to use. The one found in the $PATH is used if not specified.
         sendmail_command:              String, false,  option;
+        /// HTTP mail API URL |> Send mail by posting it to this URL of an HTTP mail API, instead of using SMTP or sendmail
+        mail_http_url:                 String, true,   option;
+        /// HTTP mail API format |> ("generic", "sendgrid") The format of the request body sent to the HTTP mail API
+        mail_http_format:              String, true,   def,     "generic".to_owned();
+        /// HTTP mail API key |> Sent as a Bearer token in the Authorization header
+        mail_http_api_key:             Pass,   true,   option;
+        /// Queue outgoing mail |> Store outgoing mail in the database and retry sending it in the background when the mail server fails,
+        /// instead of failing the request which sends the mail
+        mail_queue_enabled:            bool,   true,   def,     false;
+        /// Mail queue max attempts |> Number of times sending a queued mail is tried before giving up on it
+        mail_queue_max_attempts:       u32,    true,   def,     10;
         /// Host
         smtp_host:                     String, true,   option;
         /// DEPRECATED smtp_ssl |> DEPRECATED - Please use SMTP_SECURITY
@@ -922,7 +1002,7 @@ make_config! {
     /// Email 2FA Settings
     email_2fa: _enable_email_2fa {
         /// Enabled |> Disabling will prevent users from setting up new email 2FA and using existing email 2FA configured
-        _enable_email_2fa:      bool,   true,   auto,    |c| c._enable_smtp && (c.smtp_host.is_some() || c.use_sendmail);
+        _enable_email_2fa:      bool,   true,   auto,    |c| c._enable_smtp && (c.smtp_host.is_some() || c.use_sendmail || c.mail_http_url.is_some());
         /// Email token size |> Number of digits in an email 2FA token (min: 6, max: 255). Note that the Bitwarden clients are hardcoded to mention 6 digit codes regardless of this setting.
         email_token_size:       u8,     true,   def,      6;
         /// Token expiration time |> Maximum time in seconds a token is valid. The time the user has to open email client and copy token.
@@ -970,10 +1050,44 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         }
     }
 
+    match cfg.websocket_backplane.as_str() {
+        "" => {}
+        "postgresql" => {
+            #[cfg(postgresql)]
+            let is_postgresql =
+                matches!(crate::db::DbConnType::from_url(&cfg.database_url), Ok(crate::db::DbConnType::Postgresql));
+            #[cfg(not(postgresql))]
+            let is_postgresql = false;
+            if !is_postgresql {
+                err!("`WEBSOCKET_BACKPLANE=postgresql` requires a PostgreSQL database")
+            }
+        }
+        url if url.starts_with("redis://") || url.starts_with("rediss://") => {
+            #[cfg(not(redis))]
+            err!("Using Redis as `WEBSOCKET_BACKPLANE` requires Vaultwarden to be built with the `redis` feature")
+        }
+        _ => err!("`WEBSOCKET_BACKPLANE` must be empty, `postgresql` or a `redis://` URL"),
+    }
+
+    match cfg.ratelimit_backend.as_str() {
+        "" | "database" => {}
+        url if url.starts_with("redis://") || url.starts_with("rediss://") => {
+            #[cfg(not(redis))]
+            err!("Using Redis as `RATELIMIT_BACKEND` requires Vaultwarden to be built with the `redis` feature")
+        }
+        _ => err!("`RATELIMIT_BACKEND` must be empty, `database` or a `redis://` URL"),
+    }
+
     if cfg.password_iterations < 100_000 {
         err!("PASSWORD_ITERATIONS should be at least 100000 or higher. The default is 600000!");
     }
 
+    if cfg.sync_cache_ttl > 240 {
+        err!(
+            "`SYNC_CACHE_TTL` can be at most 240 seconds, as the attachment download links in the sync expire after 5 minutes"
+        );
+    }
+
     let limit = 256;
     if cfg.database_max_conns < 1 || cfg.database_max_conns > limit {
         err!(format!("`DATABASE_MAX_CONNS` contains an invalid value. Ensure it is between 1 and {limit}.",));
@@ -1029,7 +1143,8 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         println!("[WARNING] To enable the admin page without a token, use `DISABLE_ADMIN_TOKEN`.");
     }
 
-    if cfg.push_enabled && (cfg.push_installation_id == String::new() || cfg.push_installation_key == String::new()) {
+    let push_relay = cfg.push_backend == "relay";
+    if cfg.push_enabled && push_relay && (cfg.push_installation_id.is_empty() || cfg.push_installation_key.is_empty()) {
         err!(
             "Misconfigured Push Notification service\n\
             ########################################################################################\n\
@@ -1040,7 +1155,7 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         )
     }
 
-    if cfg.push_enabled {
+    if cfg.push_enabled && push_relay {
         let push_relay_uri = cfg.push_relay_uri.to_lowercase();
         if !push_relay_uri.starts_with("https://") {
             err!("`PUSH_RELAY_URI` must start with 'https://'.")
@@ -1060,6 +1175,19 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         }
     }
 
+    match cfg.push_backend.as_str() {
+        "relay" => {}
+        "direct" => {
+            let apns = [&cfg.push_apns_key_file, &cfg.push_apns_key_id, &cfg.push_apns_team_id, &cfg.push_apns_topic];
+            if apns.iter().any(|o| o.is_some()) && !apns.iter().all(|o| o.is_some()) {
+                err!(
+                    "`PUSH_APNS_KEY_FILE`, `PUSH_APNS_KEY_ID`, `PUSH_APNS_TEAM_ID` and `PUSH_APNS_TOPIC` must all be set to use APNs"
+                )
+            }
+        }
+        _ => err!("`PUSH_BACKEND` must be either `relay` or `direct`"),
+    }
+
     let invalid_flags = parse_experimental_client_feature_flags(
         &cfg.experimental_client_feature_flags,
         &FeatureFlagFilter::InvalidOnly,
@@ -1167,6 +1295,22 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
                     }
                 }
             }
+        } else if let Some(mail_http_url) = &cfg.mail_http_url {
+            if cfg.smtp_host.is_some() {
+                err!("Only one of `SMTP_HOST` and `MAIL_HTTP_URL` can be set")
+            }
+            if !mail_http_url.starts_with("https://") && !mail_http_url.starts_with("http://") {
+                err!("`MAIL_HTTP_URL` must be a valid URL and start with 'https://' or 'http://'")
+            }
+            if cfg.smtp_from.is_empty() {
+                err!("`SMTP_FROM` needs to be set for email support with `MAIL_HTTP_URL`")
+            }
+            match cfg.mail_http_format.as_str() {
+                "generic" | "sendgrid" => (),
+                _ => err!(
+                    "`MAIL_HTTP_FORMAT` is invalid. It needs to be one of the following options: generic or sendgrid"
+                ),
+            }
         } else {
             if cfg.smtp_host.is_some() == cfg.smtp_from.is_empty() {
                 err!("Both `SMTP_HOST` and `SMTP_FROM` need to be set for email support without `USE_SENDMAIL`")
@@ -1179,7 +1323,9 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
             }
         }
 
-        if (cfg.smtp_host.is_some() || cfg.use_sendmail) && !is_valid_email(&cfg.smtp_from) {
+        if (cfg.smtp_host.is_some() || cfg.use_sendmail || cfg.mail_http_url.is_some())
+            && !is_valid_email(&cfg.smtp_from)
+        {
             err!(format!("SMTP_FROM '{}' is not a valid email address", cfg.smtp_from))
         }
 
@@ -1188,7 +1334,7 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         }
     }
 
-    if cfg._enable_email_2fa && !(cfg.smtp_host.is_some() || cfg.use_sendmail) {
+    if cfg._enable_email_2fa && !(cfg.smtp_host.is_some() || cfg.use_sendmail || cfg.mail_http_url.is_some()) {
         err!("To enable email 2FA, a mail transport must be configured")
     }
 
@@ -1267,6 +1413,39 @@ fn validate_config(cfg: &ConfigItems, on_update: bool) -> Result<(), Error> {
         err!("`AUTH_REQUEST_PURGE_SCHEDULE` is not a valid cron expression")
     }
 
+    if !cfg.storage_gc_schedule.is_empty() && cfg.storage_gc_schedule.parse::<Schedule>().is_err() {
+        err!("`STORAGE_GC_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.attachment_integrity_schedule.is_empty() && cfg.attachment_integrity_schedule.parse::<Schedule>().is_err() {
+        err!("`ATTACHMENT_INTEGRITY_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.pending_upload_schedule.is_empty() && cfg.pending_upload_schedule.parse::<Schedule>().is_err() {
+        err!("`PENDING_UPLOAD_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.mail_queue_schedule.is_empty() && cfg.mail_queue_schedule.parse::<Schedule>().is_err() {
+        err!("`MAIL_QUEUE_SCHEDULE` is not a valid cron expression")
+    }
+
+    if !cfg.icon_refresh_schedule.is_empty() && cfg.icon_refresh_schedule.parse::<Schedule>().is_err() {
+        err!("`ICON_REFRESH_SCHEDULE` is not a valid cron expression")
+    }
+
+    if cfg.icon_refresh_concurrency < 1 {
+        err!("`ICON_REFRESH_CONCURRENCY` must be at least 1")
+    }
+
+    if cfg.icon_size > 512 {
+        err!("`ICON_SIZE` can't be more than 512")
+    }
+
+    // Deleted items aren't bound by the revisions limit, without a retention they would be kept forever
+    if cfg.cipher_revisions_retention_days < 1 {
+        err!("`CIPHER_REVISIONS_RETENTION_DAYS` has to be at least 1")
+    }
+
     if !cfg.disable_admin_token {
         match cfg.admin_token.as_ref() {
             Some(t) if t.starts_with("$argon2") => {
@@ -1405,6 +1584,7 @@ fn smtp_convert_deprecated_ssl_options(smtp_ssl: Option<bool>, smtp_explicit_tls
 pub enum PathType {
     Data,
     IconCache,
+    CustomIcons,
     Attachments,
     Sends,
     RsaKey,
@@ -1505,6 +1685,7 @@ impl Config {
 
         // Invalidate CSS Cache because several config items might have impact on the rendered CSS
         crate::api::invalidate_css_cache();
+        crate::api::clear_sync_cache();
 
         Ok(())
     }
@@ -1590,6 +1771,7 @@ impl Config {
 
         // Invalidate CSS Cache because several config items might have impact on the rendered CSS
         crate::api::invalidate_css_cache();
+        crate::api::clear_sync_cache();
 
         Ok(())
     }
@@ -1599,7 +1781,7 @@ impl Config {
     }
     pub fn mail_enabled(&self) -> bool {
         let inner = &self.inner.read().unwrap().config;
-        inner._enable_smtp && (inner.smtp_host.is_some() || inner.use_sendmail)
+        inner._enable_smtp && (inner.smtp_host.is_some() || inner.use_sendmail || inner.mail_http_url.is_some())
     }
 
     pub async fn get_duo_akey(&self) -> String {
@@ -1634,6 +1816,7 @@ impl Config {
         let path = match path_type {
             PathType::Data => self.data_folder(),
             PathType::IconCache => self.icon_cache_folder(),
+            PathType::CustomIcons => self.custom_icons_folder(),
             PathType::Attachments => self.attachments_folder(),
             PathType::Sends => self.sends_folder(),
             PathType::RsaKey => storage::parent(&self.private_rsa_key())
@@ -1654,6 +1837,33 @@ impl Config {
         }
     }
 
+    /// Renders the template of the language, like `email/de/welcome` for `email/welcome`, from the `templates_folder`.
+    /// Falls back to the primary language (`de` for `de-AT`) and then to the default English template.
+    pub fn render_localized_template<T: serde::ser::Serialize>(
+        &self,
+        name: &str,
+        language: Option<&str>,
+        data: &T,
+    ) -> Result<String, Error> {
+        let render = |hb: &Handlebars<'static>| {
+            let localized = language.zip(name.rsplit_once('/')).and_then(|(language, (folder, file))| {
+                let primary = language.split('-').next().unwrap_or(language);
+                [language, primary]
+                    .into_iter()
+                    .map(|l| format!("{folder}/{l}/{file}"))
+                    .find(|candidate| hb.has_template(candidate))
+            });
+            hb.render(localized.as_deref().unwrap_or(name), data).map_err(Into::into)
+        };
+
+        if self.reload_templates() {
+            warn!("RELOADING TEMPLATES");
+            render(&load_templates(CONFIG.templates_folder()))
+        } else {
+            render(&self.inner.read().unwrap().templates)
+        }
+    }
+
     pub fn render_fallback_template<T: serde::ser::Serialize>(&self, name: &str, data: &T) -> Result<String, Error> {
         let hb = &self.inner.read().unwrap().templates;
         hb.render(&format!("fallback_{name}"), data).map_err(Into::into)
@@ -1767,6 +1977,8 @@ where
     reg!("admin/settings");
     reg!("admin/users");
     reg!("admin/organizations");
+    reg!("admin/mail_queue");
+    reg!("admin/icons");
     reg!("admin/diagnostics");
 
     reg!("404");
@@ -252,6 +252,30 @@ impl DbPool {
     }
 }
 
+#[cfg(all(test, sqlite))]
+impl DbPool {
+    /// A pool for a new SQLite database with all the migrations applied, for the tests of the database models.
+    /// The tests using it need the multi-threaded runtime, since queries run in `block_in_place`.
+    pub fn for_tests(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vw-db-test-{name}.sqlite3"));
+        std::fs::remove_file(&path).ok();
+        let db_url = path.to_str().unwrap().to_owned();
+        sqlite_migrations::run_migrations(&db_url).unwrap();
+
+        let pool = Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(DbConnOptions {
+                init_stmts: DbConnType::Sqlite.default_init_stmts(),
+            }))
+            .build(DbConnManager::new(&db_url))
+            .unwrap();
+        DbPool {
+            pool: Some(pool),
+            semaphore: Arc::new(Semaphore::new(1)),
+        }
+    }
+}
+
 impl DbConnType {
     pub fn from_url(url: &str) -> Result<Self, Error> {
         // Mysql
@@ -332,6 +356,31 @@ impl DbConn {
         // Run blocking can't be used due to the 'static limitation, use block_in_place instead
         tokio::task::block_in_place(move || f(conn))
     }
+
+    /// Runs `f` in a single transaction, which is committed when it succeeds and rolled back when it fails.
+    /// All the queries of `f` have to use this connection. Transactions started within `f` become savepoints,
+    /// and a connection which is dropped while the transaction is still open gets discarded by the pool.
+    pub async fn transaction<F, R>(&self, f: F) -> Result<R, Error>
+    where
+        F: AsyncFnOnce() -> Result<R, Error>,
+    {
+        use diesel::connection::TransactionManager;
+        type Manager = <DbConnInner as Connection>::TransactionManager;
+
+        self.run(Manager::begin_transaction).await.map_res("Error starting transaction")?;
+        match f().await {
+            Ok(result) => {
+                self.run(Manager::commit_transaction).await.map_res("Error committing transaction")?;
+                Ok(result)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = self.run(Manager::rollback_transaction).await {
+                    error!("Error rolling back transaction: {rollback_error:?}");
+                }
+                Err(e)
+            }
+        }
+    }
 }
 
 #[macro_export]
@@ -534,3 +583,32 @@ mod postgresql_migrations {
         Ok(())
     }
 }
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+    use crate::db::models::{Cipher, Folder, User};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transactions_are_rolled_back_when_they_fail() {
+        let pool = DbPool::for_tests("transactions");
+        let conn = &pool.get().await.unwrap();
+        let mut user = User::new("transactions@example.com", None);
+        user.save(conn).await.unwrap();
+
+        let mut cipher = Cipher::new(2, "name".to_owned());
+        let failed: Result<(), Error> = conn
+            .transaction(async || {
+                cipher.save(conn).await?;
+                Folder::new(user.uuid.clone(), "folder".to_owned()).save(conn).await?;
+                err!("Failed")
+            })
+            .await;
+        assert!(failed.is_err());
+        assert!(Cipher::find_by_uuid(&cipher.uuid, conn).await.is_none());
+        assert!(Folder::find_by_user(&user.uuid, conn).await.is_empty());
+
+        conn.transaction(async || cipher.save(conn).await).await.unwrap();
+        assert!(Cipher::find_by_uuid(&cipher.uuid, conn).await.is_some());
+    }
+}
@@ -1,5 +1,3 @@
-use std::time::Duration;
-
 use bigdecimal::{BigDecimal, ToPrimitive};
 use derive_more::{AsRef, Deref, Display};
 use diesel::prelude::*;
@@ -30,6 +28,7 @@ pub struct Attachment {
     pub file_name: String, // encrypted
     pub file_size: i64,
     pub akey: Option<String>,
+    pub checksum: Option<String>, // SHA-256 of the stored (encrypted) file, hex encoded
 }
 
 /// Local methods
@@ -47,6 +46,7 @@ impl Attachment {
             file_name,
             file_size,
             akey,
+            checksum: None,
         }
     }
 
@@ -54,27 +54,28 @@ impl Attachment {
         format!("{}/{}", self.cipher_uuid, self.id)
     }
 
-    pub async fn get_url(&self, host: &str) -> Result<String, crate::Error> {
+    /// Computes the SHA-256 of the stored file, to be compared against `checksum`.
+    pub async fn compute_checksum(&self) -> Result<String, crate::Error> {
         let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments)?;
+        crate::storage::sha256_hex(&operator, &self.get_file_path()).await
+    }
 
-        if crate::storage::is_fs_operator(&operator) {
-            let token = encode_jwt(&generate_file_download_claims(self.cipher_uuid.clone(), self.id.clone()));
-            Ok(format!("{host}/attachments/{}/{}?token={token}", self.cipher_uuid, self.id))
-        } else {
-            Ok(operator.presign_read(&self.get_file_path(), Duration::from_mins(5)).await?.uri().to_string())
-        }
+    /// Downloads always go through Vaultwarden, which redirects to a presigned URL for object storage.
+    pub fn get_url(&self, host: &str) -> String {
+        let token = encode_jwt(&generate_file_download_claims(self.cipher_uuid.clone(), self.id.clone()));
+        format!("{host}/attachments/{}/{}?token={token}", self.cipher_uuid, self.id)
     }
 
-    pub async fn to_json(&self, host: &str) -> Result<Value, crate::Error> {
-        Ok(json!({
+    pub fn to_json(&self, host: &str) -> Value {
+        json!({
             "id": self.id,
-            "url": self.get_url(host).await?,
+            "url": self.get_url(host),
             "fileName": self.file_name,
             "size": self.file_size.to_string(),
             "sizeName": crate::util::get_display_size(self.file_size),
             "key": self.akey,
             "object": "attachment"
-        }))
+        })
     }
 }
 
@@ -148,6 +149,10 @@ impl Attachment {
             .await
     }
 
+    pub async fn find_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| attachments::table.load::<Self>(conn).expect("Error loading attachments")).await
+    }
+
     pub async fn find_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             attachments::table
@@ -24,8 +24,8 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Archive, Attachment, CollectionCipher, CollectionId, Favorite, FolderCipher, FolderId, Group, Membership,
-    MembershipStatus, MembershipType, OrganizationId, User, UserId,
+    Archive, Attachment, CollectionCipher, CollectionId, DeletedItem, Favorite, FolderCipher, FolderId, Group,
+    Membership, MembershipStatus, MembershipType, Organization, OrganizationId, Tombstone, TombstoneType, User, UserId,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -159,20 +159,12 @@ impl Cipher {
             if let Some(attachments) = cipher_sync_data.cipher_attachments.get(&self.uuid)
                 && !attachments.is_empty()
             {
-                let mut attachments_json_vec = vec![];
-                for attachment in attachments {
-                    attachments_json_vec.push(attachment.to_json(host).await?);
-                }
-                attachments_json = Value::Array(attachments_json_vec);
+                attachments_json = attachments.iter().map(|attachment| attachment.to_json(host)).collect();
             }
         } else {
             let attachments = Attachment::find_by_cipher(&self.uuid, conn).await;
             if !attachments.is_empty() {
-                let mut attachments_json_vec = vec![];
-                for attachment in attachments {
-                    attachments_json_vec.push(attachment.to_json(host).await?);
-                }
-                attachments_json = Value::Array(attachments_json_vec);
+                attachments_json = attachments.iter().map(|attachment| attachment.to_json(host)).collect();
             }
         }
 
@@ -439,25 +431,18 @@ impl Cipher {
         self.update_users_revision(conn).await;
         self.updated_at = Utc::now().naive_utc();
 
+        // An upsert instead of replace_into(), which would delete the record and cascade to its revisions first
         db_run! { conn:
-            sqlite, mysql {
-                match diesel::replace_into(ciphers::table)
+            mysql {
+                diesel::insert_into(ciphers::table)
                     .values(&*self)
+                    .on_conflict(diesel::dsl::DuplicatedKeys)
+                    .do_update()
+                    .set(&*self)
                     .execute(conn)
-                {
-                    Ok(_) => Ok(()),
-                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
-                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
-                        diesel::update(ciphers::table)
-                            .filter(ciphers::uuid.eq(&self.uuid))
-                            .set(&*self)
-                            .execute(conn)
-                            .map_res("Error saving cipher")
-                    }
-                    Err(e) => Err(e.into()),
-                }.map_res("Error saving cipher")
+                    .map_res("Error saving cipher")
             }
-            postgresql {
+            postgresql, sqlite {
                 diesel::insert_into(ciphers::table)
                     .values(&*self)
                     .on_conflict(ciphers::uuid)
@@ -469,14 +454,24 @@ impl Cipher {
         }
     }
 
+    /// Deletes the cipher and keeps it in the item history, so it can be brought back by restoring the vault.
     pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
-        self.update_users_revision(conn).await;
+        if CONFIG.cipher_revisions_limit() > 0 {
+            DeletedItem::save_cipher(self, conn).await?;
+        }
+        self.delete_without_history(conn).await
+    }
+
+    /// Deletes the cipher without keeping it, for when its owner is deleted as well.
+    async fn delete_without_history(&self, conn: &DbConn) -> EmptyResult {
+        let user_uuids = self.update_users_revision(conn).await;
+        Tombstone::create(&user_uuids, TombstoneType::Cipher, &self.uuid, conn).await;
 
         FolderCipher::delete_all_by_cipher(&self.uuid, conn).await?;
         CollectionCipher::delete_all_by_cipher(&self.uuid, conn).await?;
         Attachment::delete_all_by_cipher(&self.uuid, conn).await?;
         Favorite::delete_all_by_cipher(&self.uuid, conn).await?;
-
+        // The revisions of the cipher are removed by the database, through the ON DELETE CASCADE of their foreign key
         conn.run(move |conn| {
             diesel::delete(ciphers::table.filter(ciphers::uuid.eq(&self.uuid)))
                 .execute(conn)
@@ -485,6 +480,7 @@ impl Cipher {
         .await
     }
 
+    /// Deletes the ciphers of the organization, which are kept in the item history.
     pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
         // TODO: Optimize this by executing a DELETE directly on the database, instead of first fetching.
         for cipher in Self::find_by_org(org_uuid, conn).await {
@@ -493,26 +489,63 @@ impl Cipher {
         Ok(())
     }
 
+    /// Deletes the ciphers of an organization which gets deleted, so they aren't kept in the item history.
+    pub async fn delete_all_by_deleted_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        for cipher in Self::find_by_org(org_uuid, conn).await {
+            cipher.delete_without_history(conn).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the ciphers of a user who gets deleted, so they aren't kept in the item history.
     pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
         for cipher in Self::find_owned_by_user(user_uuid, conn).await {
-            cipher.delete(conn).await?;
+            cipher.delete_without_history(conn).await?;
         }
         Ok(())
     }
 
     /// Purge all ciphers that are old enough to be auto-deleted.
-    pub async fn purge_trash(conn: &DbConn) {
-        if let Some(auto_delete_days) = CONFIG.trash_auto_delete_days() {
-            let now = Utc::now().naive_utc();
-            let dt = now - TimeDelta::try_days(auto_delete_days).unwrap();
-            for cipher in Self::find_deleted_before(&dt, conn).await {
-                cipher.delete(conn).await.ok();
+    /// Deletes the trashed ciphers which are old enough and returns how many were deleted.
+    /// Organizations can override the global retention for their items.
+    pub async fn purge_trash(conn: &DbConn) -> usize {
+        let global_days = CONFIG.trash_auto_delete_days();
+        let org_days = Organization::find_trash_retention_days(conn).await;
+
+        // Like the global retention, an override of 0 days deletes the trashed items at the next run.
+        // An unset override falls back to the global retention.
+        let retention_days = |org_uuid: &OrganizationId| match org_days.get(org_uuid) {
+            Some(days) => Some(i64::from(*days)),
+            None => global_days,
+        };
+
+        // Only ciphers which were trashed before the shortest retention in use can be old enough
+        let shortest = global_days.into_iter().chain(org_days.values().map(|d| i64::from(*d))).min();
+        let Some(shortest) = shortest else {
+            return 0;
+        };
+
+        let now = Utc::now().naive_utc();
+        let mut deleted = 0;
+        for cipher in Self::find_deleted_before(&(now - TimeDelta::try_days(shortest).unwrap()), conn).await {
+            let days = match &cipher.organization_uuid {
+                Some(org_uuid) => retention_days(org_uuid),
+                None => global_days,
+            };
+            let Some(days) = days else {
+                continue;
+            };
+            if cipher.deleted_at.is_some_and(|dt| dt < now - TimeDelta::try_days(days).unwrap())
+                && cipher.delete(conn).await.is_ok()
+            {
+                deleted += 1;
             }
         }
+        deleted
     }
 
     pub async fn move_to_folder(
-        &self,
+        &mut self,
         folder_uuid: Option<FolderId>,
         user_uuid: &UserId,
         conn: &DbConn,
@@ -521,16 +554,16 @@ impl Cipher {
 
         match (self.get_folder_uuid(user_uuid, conn).await, folder_uuid) {
             // No changes
-            (None, None) => Ok(()),
-            (Some(ref old_folder), Some(ref new_folder)) if old_folder == new_folder => Ok(()),
+            (None, None) => return Ok(()),
+            (Some(ref old_folder), Some(ref new_folder)) if old_folder == new_folder => return Ok(()),
 
             // Add to folder
-            (None, Some(new_folder)) => FolderCipher::new(new_folder, self.uuid.clone()).save(conn).await,
+            (None, Some(new_folder)) => FolderCipher::new(new_folder, self.uuid.clone()).save(conn).await?,
 
             // Remove from folder
             (Some(old_folder), None) => {
                 if let Some(old_folder) = FolderCipher::find_by_folder_and_cipher(&old_folder, &self.uuid, conn).await {
-                    old_folder.delete(conn).await
+                    old_folder.delete(conn).await?;
                 } else {
                     err!("Couldn't move from previous folder")
                 }
@@ -541,9 +574,23 @@ impl Cipher {
                 if let Some(old_folder) = FolderCipher::find_by_folder_and_cipher(&old_folder, &self.uuid, conn).await {
                     old_folder.delete(conn).await?;
                 }
-                FolderCipher::new(new_folder, self.uuid.clone()).save(conn).await
+                FolderCipher::new(new_folder, self.uuid.clone()).save(conn).await?;
             }
         }
+        self.touch(conn).await
+    }
+
+    /// Bumps the revision date for changes which aren't stored in the cipher itself,
+    /// like its folder or collections, so they are part of a delta sync.
+    pub async fn touch(&mut self, conn: &DbConn) -> EmptyResult {
+        self.updated_at = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            diesel::update(ciphers::table.filter(ciphers::uuid.eq(&self.uuid)))
+                .set(ciphers::updated_at.eq(self.updated_at))
+                .execute(conn)
+                .map_res("Error updating cipher revision date")
+        })
+        .await
     }
 
     /// Returns whether this cipher is directly owned by the user.
@@ -738,10 +785,14 @@ impl Cipher {
     }
 
     // Sets whether this cipher is a favorite of the specified user.
-    pub async fn set_favorite(&self, favorite: Option<bool>, user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+    pub async fn set_favorite(&mut self, favorite: Option<bool>, user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
         match favorite {
             None => Ok(()), // No change requested.
-            Some(status) => Favorite::set_favorite(status, &self.uuid, user_uuid, conn).await,
+            Some(status) if status == self.is_favorite(user_uuid, conn).await => Ok(()),
+            Some(status) => {
+                Favorite::set_favorite(status, &self.uuid, user_uuid, conn).await?;
+                self.touch(conn).await
+            }
         }
     }
 
@@ -749,12 +800,19 @@ impl Cipher {
         Archive::get_archived_at(&self.uuid, user_uuid, conn).await
     }
 
-    pub async fn set_archived_at(&self, archived_at: NaiveDateTime, user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
-        Archive::save(user_uuid, &self.uuid, archived_at, conn).await
+    pub async fn set_archived_at(
+        &mut self,
+        archived_at: NaiveDateTime,
+        user_uuid: &UserId,
+        conn: &DbConn,
+    ) -> EmptyResult {
+        Archive::save(user_uuid, &self.uuid, archived_at, conn).await?;
+        self.touch(conn).await
     }
 
-    pub async fn unarchive(&self, user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
-        Archive::delete_by_cipher(user_uuid, &self.uuid, conn).await
+    pub async fn unarchive(&mut self, user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        Archive::delete_by_cipher(user_uuid, &self.uuid, conn).await?;
+        self.touch(conn).await
     }
 
     pub async fn get_folder_uuid(&self, user_uuid: &UserId, conn: &DbConn) -> Option<FolderId> {
@@ -1181,3 +1239,59 @@ impl Cipher {
     UuidFromParam,
 )]
 pub struct CipherId(String);
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+    use crate::db::DbPool;
+
+    async fn trashed_cipher(org: Option<&Organization>, trashed_hours_ago: i64, conn: &DbConn) -> Cipher {
+        let mut cipher = Cipher::new(2, "name".to_owned());
+        cipher.organization_uuid = org.map(|org| org.uuid.clone());
+        cipher.deleted_at = Some(Utc::now().naive_utc() - TimeDelta::hours(trashed_hours_ago));
+        cipher.save(conn).await.unwrap();
+        cipher
+    }
+
+    async fn org(trash_retention_days: Option<i32>, conn: &DbConn) -> Organization {
+        let mut org = Organization::new(String::from("org"), "org@example.com", None, None);
+        org.trash_retention_days = trash_retention_days;
+        org.save(conn).await.unwrap();
+        org
+    }
+
+    async fn exists(cipher: &Cipher, conn: &DbConn) -> bool {
+        Cipher::find_by_uuid(&cipher.uuid, conn).await.is_some()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn purge_trash_uses_the_retention_of_the_organization() {
+        // Without `TRASH_AUTO_DELETE_DAYS` only organizations which override it have trashed items deleted
+        assert!(CONFIG.trash_auto_delete_days().is_none());
+        let pool = DbPool::for_tests("purge-trash");
+        let conn = &pool.get().await.unwrap();
+
+        let one_day = org(Some(1), conn).await;
+        let expired = trashed_cipher(Some(&one_day), 36, conn).await;
+        let recent = trashed_cipher(Some(&one_day), 12, conn).await;
+        let mut untrashed = trashed_cipher(Some(&one_day), 36, conn).await;
+        untrashed.deleted_at = None;
+        untrashed.save(conn).await.unwrap();
+
+        // 0 days deletes the trashed items at the next purge, like the global setting
+        let immediately = org(Some(0), conn).await;
+        let just_trashed = trashed_cipher(Some(&immediately), 0, conn).await;
+
+        let default = org(None, conn).await;
+        let default_cipher = trashed_cipher(Some(&default), 24 * 1000, conn).await;
+        let personal = trashed_cipher(None, 24 * 1000, conn).await;
+
+        assert_eq!(Cipher::purge_trash(conn).await, 2);
+        assert!(!exists(&expired, conn).await);
+        assert!(exists(&recent, conn).await);
+        assert!(exists(&untrashed, conn).await);
+        assert!(!exists(&just_trashed, conn).await);
+        assert!(exists(&default_cipher, conn).await);
+        assert!(exists(&personal, conn).await);
+    }
+}
@@ -0,0 +1,351 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    api::EmptyResult,
+    db::{DbConn, schema::cipher_revisions},
+    error::MapResult,
+    util::format_date,
+};
+use macros::UuidFromParam;
+
+use super::{Attachment, AttachmentId, Cipher, CipherId};
+
+/// A previous, still encrypted, version of a cipher, kept so it can be restored later.
+#[derive(Identifiable, Queryable, Insertable, Serialize, Deserialize)]
+#[diesel(table_name = cipher_revisions)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct CipherRevision {
+    pub uuid: CipherRevisionId,
+    pub cipher_uuid: CipherId,
+    /// The revision date of the cipher when this version was current
+    pub revision_date: NaiveDateTime,
+    /// When this version got replaced
+    pub created_at: NaiveDateTime,
+    pub atype: i32,
+    pub name: String,
+    pub notes: Option<String>,
+    pub fields: Option<String>,
+    pub data: String,
+    pub key: Option<String>,
+    pub password_history: Option<String>,
+    pub reprompt: Option<i32>,
+    /// JSON list of the attachment ids with their file name and key at that time
+    pub attachments: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentRevision {
+    pub id: AttachmentId,
+    pub file_name: String,
+    pub key: Option<String>,
+}
+
+/// The version of a cipher at a point in time, see `CipherRevision::version_at`.
+pub enum VersionAt<'a> {
+    /// The current version of the cipher was already in use back then
+    Current,
+    Version(&'a CipherRevision),
+    /// The version in use back then was removed, the history of the cipher is incomplete
+    Unknown,
+}
+
+/// Local methods
+impl CipherRevision {
+    pub fn from_cipher(cipher: &Cipher, attachments: &[Attachment]) -> Self {
+        let attachments: Vec<AttachmentRevision> = attachments
+            .iter()
+            .map(|a| AttachmentRevision {
+                id: a.id.clone(),
+                file_name: a.file_name.clone(),
+                key: a.akey.clone(),
+            })
+            .collect();
+
+        Self {
+            uuid: CipherRevisionId(crate::util::get_uuid()),
+            cipher_uuid: cipher.uuid.clone(),
+            revision_date: cipher.updated_at,
+            created_at: Utc::now().naive_utc(),
+            atype: cipher.atype,
+            name: cipher.name.clone(),
+            notes: cipher.notes.clone(),
+            fields: cipher.fields.clone(),
+            data: cipher.data.clone(),
+            key: cipher.key.clone(),
+            password_history: cipher.password_history.clone(),
+            reprompt: cipher.reprompt,
+            attachments: (!attachments.is_empty()).then(|| serde_json::to_string(&attachments).unwrap_or_default()),
+        }
+    }
+
+    /// Whether the cipher content differs from this version, ignoring changes which only touch the metadata.
+    pub fn differs_from(&self, cipher: &Cipher, attachments: &[Attachment]) -> bool {
+        let other = Self::from_cipher(cipher, attachments);
+        self.atype != other.atype
+            || self.name != other.name
+            || self.notes != other.notes
+            || self.fields != other.fields
+            || self.data != other.data
+            || self.key != other.key
+            || self.password_history != other.password_history
+            || self.reprompt != other.reprompt
+            || self.attachments != other.attachments
+    }
+
+    /// Picks the version which was current at the given time out of the versions of a cipher, newest first.
+    /// Once older versions were removed because of the limit, which `complete` tells, the oldest kept version
+    /// only shows the state at that time when it was already in use back then.
+    pub fn version_at<'a>(versions: &'a [Self], dt: &NaiveDateTime, complete: bool) -> VersionAt<'a> {
+        let Some(index) = versions.iter().rposition(|v| v.created_at > *dt) else {
+            return VersionAt::Current;
+        };
+        let version = &versions[index];
+        if !complete && index == versions.len() - 1 && version.revision_date > *dt {
+            VersionAt::Unknown
+        } else {
+            VersionAt::Version(version)
+        }
+    }
+
+    /// Whether all the versions of a cipher with this many kept revisions are there, none were removed by the limit.
+    pub fn history_is_complete(revisions: usize) -> bool {
+        revisions < CONFIG.cipher_revisions_limit() as usize
+    }
+
+    pub fn get_attachments(&self) -> Vec<AttachmentRevision> {
+        self.attachments.as_deref().and_then(|a| serde_json::from_str(a).ok()).unwrap_or_default()
+    }
+
+    /// Copies this version back into the cipher, only the attachments which still exist are restored.
+    pub fn apply_to(&self, cipher: &mut Cipher, attachments: &mut [Attachment]) {
+        cipher.atype = self.atype;
+        cipher.name.clone_from(&self.name);
+        cipher.notes.clone_from(&self.notes);
+        cipher.fields.clone_from(&self.fields);
+        cipher.data.clone_from(&self.data);
+        cipher.key.clone_from(&self.key);
+        cipher.password_history.clone_from(&self.password_history);
+        cipher.reprompt = self.reprompt;
+
+        for revision in self.get_attachments() {
+            if let Some(attachment) = attachments.iter_mut().find(|a| a.id == revision.id) {
+                attachment.file_name = revision.file_name;
+                attachment.akey = revision.key;
+            }
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        fn parse(s: Option<&str>) -> Value {
+            s.and_then(|s| serde_json::from_str(s).ok()).unwrap_or(Value::Null)
+        }
+
+        json!({
+            "id": self.uuid,
+            "cipherId": self.cipher_uuid,
+            "type": self.atype,
+            "name": self.name,
+            "notes": self.notes,
+            "fields": parse(self.fields.as_deref()),
+            "data": parse(Some(&self.data)),
+            "key": self.key,
+            "passwordHistory": parse(self.password_history.as_deref()),
+            "reprompt": self.reprompt.unwrap_or(0),
+            "attachments": self.get_attachments(),
+            "revisionDate": format_date(&self.revision_date),
+            "creationDate": format_date(&self.created_at),
+            "object": "cipherRevision",
+        })
+    }
+}
+
+/// Database methods
+impl CipherRevision {
+    /// Stores this version and removes the oldest versions of the cipher above the configured limit.
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        let limit = CONFIG.cipher_revisions_limit() as usize;
+        if limit == 0 {
+            return Ok(());
+        }
+
+        conn.run(move |conn| {
+            diesel::insert_into(cipher_revisions::table)
+                .values(self)
+                .execute(conn)
+                .map(|_| ())
+                .map_res("Error saving cipher revision")
+        })
+        .await?;
+
+        let expired: Vec<CipherRevisionId> =
+            Self::find_by_cipher(&self.cipher_uuid, conn).await.into_iter().skip(limit).map(|r| r.uuid).collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        conn.run(move |conn| {
+            diesel::delete(cipher_revisions::table.filter(cipher_revisions::uuid.eq_any(expired)))
+                .execute(conn)
+                .map_res("Error deleting cipher revisions")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(cipher_revisions::table.filter(cipher_revisions::cipher_uuid.eq(cipher_uuid)))
+                .execute(conn)
+                .map_res("Error deleting cipher revisions")
+        })
+        .await
+    }
+
+    /// Removes the versions which are older than the configured retention.
+    pub async fn purge(conn: &DbConn) -> usize {
+        let Some(dt) = TimeDelta::try_days(CONFIG.cipher_revisions_retention_days())
+            .and_then(|d| Utc::now().naive_utc().checked_sub_signed(d))
+        else {
+            return 0;
+        };
+        conn.run(move |conn| {
+            diesel::delete(cipher_revisions::table.filter(cipher_revisions::created_at.lt(dt)))
+                .execute(conn)
+                .unwrap_or_else(|e| {
+                    error!("Error purging cipher revisions: {e:?}");
+                    0
+                })
+        })
+        .await
+    }
+
+    /// Returns the versions of the cipher, newest first.
+    pub async fn find_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            cipher_revisions::table
+                .filter(cipher_revisions::cipher_uuid.eq(cipher_uuid))
+                .order(cipher_revisions::created_at.desc())
+                .load::<Self>(conn)
+                .expect("Error loading cipher revisions")
+        })
+        .await
+    }
+
+    pub async fn find_by_uuid_and_cipher(
+        uuid: &CipherRevisionId,
+        cipher_uuid: &CipherId,
+        conn: &DbConn,
+    ) -> Option<Self> {
+        conn.run(move |conn| {
+            cipher_revisions::table
+                .filter(cipher_revisions::uuid.eq(uuid))
+                .filter(cipher_revisions::cipher_uuid.eq(cipher_uuid))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct CipherRevisionId(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_at(versions: &[CipherRevision], dt: NaiveDateTime, complete: bool) -> String {
+        match CipherRevision::version_at(versions, &dt, complete) {
+            VersionAt::Current => "current".to_owned(),
+            VersionAt::Version(version) => version.name.clone(),
+            VersionAt::Unknown => "unknown".to_owned(),
+        }
+    }
+
+    #[test]
+    fn version_at_picks_the_version_current_back_then() {
+        let cipher = Cipher::new(2, "name".to_owned());
+        let start = Utc::now().naive_utc();
+        // Newest first, replaced one, two and three days after the start
+        let versions: Vec<CipherRevision> = (1..=3)
+            .rev()
+            .map(|days| CipherRevision {
+                revision_date: start,
+                created_at: start + TimeDelta::days(days),
+                name: format!("replaced after {days} days"),
+                ..CipherRevision::from_cipher(&cipher, &[])
+            })
+            .collect();
+
+        let at = |hours| name_at(&versions, start + TimeDelta::hours(hours), true);
+        assert_eq!(at(0), "replaced after 1 days");
+        assert_eq!(at(36), "replaced after 2 days");
+        assert_eq!(at(60), "replaced after 3 days");
+        assert_eq!(at(96), "current");
+    }
+
+    #[test]
+    fn version_at_detects_removed_versions() {
+        let cipher = Cipher::new(2, "name".to_owned());
+        let start = Utc::now().naive_utc();
+        // The oldest kept version only became current a day after the start, the one before was removed
+        let versions: Vec<CipherRevision> = (2..=3)
+            .rev()
+            .map(|days| CipherRevision {
+                revision_date: start + TimeDelta::days(days - 1),
+                created_at: start + TimeDelta::days(days),
+                name: format!("replaced after {days} days"),
+                ..CipherRevision::from_cipher(&cipher, &[])
+            })
+            .collect();
+
+        assert_eq!(name_at(&versions, start + TimeDelta::hours(12), false), "unknown");
+        assert_eq!(name_at(&versions, start + TimeDelta::hours(36), false), "replaced after 2 days");
+        assert_eq!(name_at(&versions, start + TimeDelta::hours(60), false), "replaced after 3 days");
+        // Without removed versions the oldest one was in use since the cipher was created
+        assert_eq!(name_at(&versions, start + TimeDelta::hours(12), true), "replaced after 2 days");
+    }
+
+    #[cfg(sqlite)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn revisions_survive_updates_and_go_with_their_cipher() {
+        let pool = crate::db::DbPool::for_tests("cipher-revisions-cascade");
+        let conn = &pool.get().await.unwrap();
+
+        let mut cipher = Cipher::new(2, "name".to_owned());
+        cipher.save(conn).await.unwrap();
+        // Inserted directly, since `save` doesn't keep any versions while the item history is disabled by default
+        let revision = CipherRevision::from_cipher(&cipher, &[]);
+        conn.run(move |conn| diesel::insert_into(cipher_revisions::table).values(&revision).execute(conn))
+            .await
+            .unwrap();
+
+        // Saving the cipher again updates it in place, instead of replacing it along with its revisions
+        cipher.name = "renamed".to_owned();
+        cipher.save(conn).await.unwrap();
+        assert_eq!(CipherRevision::find_by_cipher(&cipher.uuid, conn).await.len(), 1);
+
+        cipher.delete(conn).await.unwrap();
+        assert!(CipherRevision::find_by_cipher(&cipher.uuid, conn).await.is_empty());
+    }
+}
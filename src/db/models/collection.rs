@@ -17,8 +17,8 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    CipherId, CollectionGroup, GroupUser, Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId,
-    User, UserId,
+    Cipher, CipherId, CollectionGroup, GroupUser, Membership, MembershipId, MembershipStatus, MembershipType,
+    OrganizationId, Tombstone, TombstoneType, User, UserId,
 };
 
 // See (v2026.7.0): https://github.com/bitwarden/server/blob/5d4461aa42cadbacfef8fe2166c5453a5c52773a/src/Core/AdminConsole/Entities/Collection.cs
@@ -198,7 +198,8 @@ impl Collection {
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        self.update_users_revision(conn).await;
+        let user_uuids = self.update_users_revision(conn).await;
+        Tombstone::create(&user_uuids, TombstoneType::Collection, &self.uuid, conn).await;
         CollectionCipher::delete_all_by_collection(&self.uuid, conn).await?;
         CollectionUser::delete_all_by_collection(&self.uuid, conn).await?;
         CollectionGroup::delete_all_by_collection(&self.uuid, &self.org_uuid, conn).await?;
@@ -218,10 +219,13 @@ impl Collection {
         Ok(())
     }
 
-    pub async fn update_users_revision(&self, conn: &DbConn) {
-        for member in &Membership::find_by_collection_and_org(&self.uuid, &self.org_uuid, conn).await {
+    pub async fn update_users_revision(&self, conn: &DbConn) -> Vec<UserId> {
+        let mut user_uuids = Vec::new();
+        for member in Membership::find_by_collection_and_org(&self.uuid, &self.org_uuid, conn).await {
             User::update_uuid_revision(&member.user_uuid, conn).await;
+            user_uuids.push(member.user_uuid);
         }
+        user_uuids
     }
 
     pub async fn find_by_uuid(uuid: &CollectionId, conn: &DbConn) -> Option<Self> {
@@ -728,6 +732,14 @@ impl CollectionUser {
     ) -> EmptyResult {
         User::update_uuid_revision(user_uuid, conn).await;
 
+        // The ciphers of a new collection, or ones with other permissions, aren't part of a delta sync
+        let unchanged = Self::find_by_collection_and_user(collection_uuid, user_uuid, conn)
+            .await
+            .is_some_and(|c| c.read_only == read_only && c.hide_passwords == hide_passwords && c.manage == manage);
+        if !unchanged {
+            Tombstone::create(std::slice::from_ref(user_uuid), TombstoneType::Access, collection_uuid, conn).await;
+        }
+
         db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(users_collections::table)
@@ -783,6 +795,8 @@ impl CollectionUser {
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(&self.user_uuid, conn).await;
+        Tombstone::create(std::slice::from_ref(&self.user_uuid), TombstoneType::Access, &self.collection_uuid, conn)
+            .await;
 
         conn.run(move |conn| {
             diesel::delete(
@@ -862,9 +876,12 @@ impl CollectionUser {
     }
 
     pub async fn delete_all_by_collection(collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
-        for collection in &CollectionUser::find_by_collection(collection_uuid, conn).await {
+        let mut user_uuids = Vec::new();
+        for collection in CollectionUser::find_by_collection(collection_uuid, conn).await {
             User::update_uuid_revision(&collection.user_uuid, conn).await;
+            user_uuids.push(collection.user_uuid);
         }
+        Tombstone::create(&user_uuids, TombstoneType::Access, collection_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(users_collections::table.filter(users_collections::collection_uuid.eq(collection_uuid)))
@@ -880,6 +897,10 @@ impl CollectionUser {
         conn: &DbConn,
     ) -> EmptyResult {
         let collectionusers = Self::find_by_organization_and_user_uuid(org_uuid, user_uuid, conn).await;
+        if !collectionusers.is_empty() {
+            User::update_uuid_revision(user_uuid, conn).await;
+            Tombstone::create(std::slice::from_ref(user_uuid), TombstoneType::Access, org_uuid, conn).await;
+        }
 
         conn.run(move |conn| {
             for user in collectionusers {
@@ -936,16 +957,51 @@ impl CollectionCipher {
     }
 
     pub async fn delete(cipher_uuid: &CipherId, collection_uuid: &CollectionId, conn: &DbConn) -> EmptyResult {
-        Self::update_users_revision(collection_uuid, conn).await;
+        let mut user_uuids = Vec::new();
+        if let Some(collection) = Collection::find_by_uuid(collection_uuid, conn).await {
+            user_uuids = collection.update_users_revision(conn).await;
+            if CONFIG.org_groups_enabled() {
+                for user_uuid in
+                    CollectionGroup::find_user_uuids_by_collection(collection_uuid, &collection.org_uuid, conn).await
+                {
+                    User::update_uuid_revision(&user_uuid, conn).await;
+                    user_uuids.push(user_uuid);
+                }
+            }
+        }
+
+        let _: () = conn
+            .run(move |conn| {
+                diesel::delete(
+                    ciphers_collections::table
+                        .filter(ciphers_collections::cipher_uuid.eq(cipher_uuid))
+                        .filter(ciphers_collections::collection_uuid.eq(collection_uuid)),
+                )
+                .execute(conn)
+                .map_res("Error deleting cipher from collection")
+            })
+            .await?;
 
+        // The users who could only see the cipher through this collection have to remove it on their next delta sync
+        if let Some(cipher) = Cipher::find_by_uuid(cipher_uuid, conn).await {
+            let mut lost_access = Vec::new();
+            for user_uuid in user_uuids {
+                if !cipher.is_accessible_to_user(&user_uuid, conn).await {
+                    lost_access.push(user_uuid);
+                }
+            }
+            Tombstone::create(&lost_access, TombstoneType::Cipher, cipher_uuid, conn).await;
+        }
+        Ok(())
+    }
+
+    pub async fn find_collection_uuids_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<CollectionId> {
         conn.run(move |conn| {
-            diesel::delete(
-                ciphers_collections::table
-                    .filter(ciphers_collections::cipher_uuid.eq(cipher_uuid))
-                    .filter(ciphers_collections::collection_uuid.eq(collection_uuid)),
-            )
-            .execute(conn)
-            .map_res("Error deleting cipher from collection")
+            ciphers_collections::table
+                .filter(ciphers_collections::cipher_uuid.eq(cipher_uuid))
+                .select(ciphers_collections::collection_uuid)
+                .load::<CollectionId>(conn)
+                .unwrap_or_default()
         })
         .await
     }
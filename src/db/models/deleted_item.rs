@@ -0,0 +1,213 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use crate::{
+    CONFIG,
+    api::EmptyResult,
+    db::{DbConn, schema::deleted_items},
+    error::MapResult,
+    util::get_uuid,
+};
+
+use super::{
+    Cipher, CipherId, CipherRevision, CollectionCipher, CollectionId, Favorite, Folder, FolderCipher, FolderId,
+    OrganizationId, TombstoneType, UserId,
+};
+
+/// A permanently deleted cipher or folder, kept for the item history so a vault can be restored to an earlier point in time.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = deleted_items)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct DeletedItem {
+    pub uuid: String,
+    pub object_type: i32, // TombstoneType
+    pub object_uuid: String,
+    pub user_uuid: Option<UserId>,
+    pub organization_uuid: Option<OrganizationId>,
+    /// When the object itself was created
+    pub created_at: NaiveDateTime,
+    pub deleted_at: NaiveDateTime,
+    pub data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedCipher {
+    /// All known versions, the one at the time of deletion first
+    pub versions: Vec<CipherRevision>,
+    /// When the cipher was moved to the trash, if it was
+    pub deleted_at: Option<NaiveDateTime>,
+    pub collections: Vec<CollectionId>,
+    pub folders: Vec<FolderId>,
+    pub favorites: Vec<UserId>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeletedFolder {
+    pub name: String,
+    pub ciphers: Vec<CipherId>,
+}
+
+/// Local methods
+impl DeletedItem {
+    fn new(
+        object_type: TombstoneType,
+        object_uuid: String,
+        user_uuid: Option<UserId>,
+        organization_uuid: Option<OrganizationId>,
+        created_at: NaiveDateTime,
+        data: String,
+    ) -> Self {
+        Self {
+            uuid: get_uuid(),
+            object_type: object_type as i32,
+            object_uuid,
+            user_uuid,
+            organization_uuid,
+            created_at,
+            deleted_at: Utc::now().naive_utc(),
+            data,
+        }
+    }
+
+    pub fn get_cipher(&self) -> Option<DeletedCipher> {
+        if self.object_type != TombstoneType::Cipher as i32 {
+            return None;
+        }
+        serde_json::from_str(&self.data).inspect_err(|e| warn!("Error parsing deleted cipher {}: {e}", self.uuid)).ok()
+    }
+
+    pub fn get_folder(&self) -> Option<DeletedFolder> {
+        if self.object_type != TombstoneType::Folder as i32 {
+            return None;
+        }
+        serde_json::from_str(&self.data).inspect_err(|e| warn!("Error parsing deleted folder {}: {e}", self.uuid)).ok()
+    }
+}
+
+/// Database methods
+impl DeletedItem {
+    /// Keeps the cipher with its history and relations, called before the cipher gets deleted.
+    pub async fn save_cipher(cipher: &Cipher, conn: &DbConn) -> EmptyResult {
+        let mut versions = vec![CipherRevision::from_cipher(cipher, &[])];
+        versions.extend(CipherRevision::find_by_cipher(&cipher.uuid, conn).await);
+
+        let deleted = DeletedCipher {
+            versions,
+            deleted_at: cipher.deleted_at,
+            collections: CollectionCipher::find_collection_uuids_by_cipher(&cipher.uuid, conn).await,
+            folders: FolderCipher::find_folder_uuids_by_cipher(&cipher.uuid, conn).await,
+            favorites: Favorite::find_user_uuids_by_cipher(&cipher.uuid, conn).await,
+        };
+        let item = Self::new(
+            TombstoneType::Cipher,
+            cipher.uuid.to_string(),
+            cipher.user_uuid.clone(),
+            cipher.organization_uuid.clone(),
+            cipher.created_at,
+            serde_json::to_string(&deleted)?,
+        );
+        item.save(conn).await
+    }
+
+    /// Keeps the folder with the ciphers it contained, called before the folder gets deleted.
+    pub async fn save_folder(folder: &Folder, conn: &DbConn) -> EmptyResult {
+        let deleted = DeletedFolder {
+            name: folder.name.clone(),
+            ciphers: FolderCipher::find_by_folder(&folder.uuid, conn)
+                .await
+                .into_iter()
+                .map(|fc| fc.cipher_uuid)
+                .collect(),
+        };
+        let item = Self::new(
+            TombstoneType::Folder,
+            folder.uuid.to_string(),
+            Some(folder.user_uuid.clone()),
+            None,
+            folder.created_at,
+            serde_json::to_string(&deleted)?,
+        );
+        item.save(conn).await
+    }
+
+    async fn save(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::insert_into(deleted_items::table)
+                .values(self)
+                .execute(conn)
+                .map(|_| ())
+                .map_res("Error saving deleted item")
+        })
+        .await
+    }
+
+    pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(deleted_items::table.filter(deleted_items::uuid.eq(&self.uuid)))
+                .execute(conn)
+                .map_res("Error deleting deleted item")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(deleted_items::table.filter(deleted_items::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting deleted items")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(deleted_items::table.filter(deleted_items::organization_uuid.eq(org_uuid)))
+                .execute(conn)
+                .map_res("Error deleting deleted items")
+        })
+        .await
+    }
+
+    /// Removes the items which are older than the configured retention.
+    pub async fn purge(conn: &DbConn) -> usize {
+        let Some(dt) = TimeDelta::try_days(CONFIG.cipher_revisions_retention_days())
+            .and_then(|d| Utc::now().naive_utc().checked_sub_signed(d))
+        else {
+            return 0;
+        };
+        conn.run(move |conn| {
+            diesel::delete(deleted_items::table.filter(deleted_items::deleted_at.lt(dt))).execute(conn).unwrap_or_else(
+                |e| {
+                    error!("Error purging deleted items: {e:?}");
+                    0
+                },
+            )
+        })
+        .await
+    }
+
+    /// Finds the personal items of the user which were deleted after the given time.
+    pub async fn find_by_user_deleted_after(user_uuid: &UserId, dt: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            deleted_items::table
+                .filter(deleted_items::user_uuid.eq(user_uuid))
+                .filter(deleted_items::organization_uuid.is_null())
+                .filter(deleted_items::deleted_at.gt(dt))
+                .load::<Self>(conn)
+                .expect("Error loading deleted items")
+        })
+        .await
+    }
+
+    pub async fn find_by_org_deleted_after(org_uuid: &OrganizationId, dt: &NaiveDateTime, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            deleted_items::table
+                .filter(deleted_items::organization_uuid.eq(org_uuid))
+                .filter(deleted_items::deleted_at.gt(dt))
+                .load::<Self>(conn)
+                .expect("Error loading deleted items")
+        })
+        .await
+    }
+}
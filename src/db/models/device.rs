@@ -103,6 +103,24 @@ impl Device {
     pub fn is_mobile(&self) -> bool {
         matches!(DeviceType::from_i32(self.atype), DeviceType::Android | DeviceType::Ios)
     }
+
+    /// The `ClientType` of the device, used to target notifications to a kind of client.
+    // https://github.com/bitwarden/server/blob/main/src/Core/Enums/ClientType.cs
+    pub fn client_type(&self) -> i32 {
+        match DeviceType::from_i32(self.atype) {
+            DeviceType::Android | DeviceType::AndroidAmazon | DeviceType::Ios => 4,
+            DeviceType::ChromeExtension
+            | DeviceType::FirefoxExtension
+            | DeviceType::OperaExtension
+            | DeviceType::EdgeExtension
+            | DeviceType::VivaldiExtension
+            | DeviceType::SafariExtension => 2,
+            DeviceType::WindowsDesktop | DeviceType::MacOsDesktop | DeviceType::LinuxDesktop | DeviceType::Uwp => 3,
+            DeviceType::WindowsCLI | DeviceType::MacOsCLI | DeviceType::LinuxCLI => 5,
+            DeviceType::Sdk | DeviceType::Server => 0,
+            _ => 1,
+        }
+    }
 }
 
 pub struct DeviceWithAuthRequest {
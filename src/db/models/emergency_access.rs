@@ -5,7 +5,10 @@ use serde_json::Value;
 
 use crate::{
     api::EmptyResult,
-    db::{DbConn, schema::emergency_access},
+    db::{
+        DbConn,
+        schema::{emergency_access, emergency_access_quorum_members, emergency_access_quorums},
+    },
     error::MapResult,
 };
 use macros::UuidFromParam;
@@ -145,25 +148,18 @@ impl EmergencyAccess {
         User::update_uuid_revision(&self.grantor_uuid, conn).await;
         self.updated_at = Utc::now().naive_utc();
 
+        // An upsert instead of replace_into(), which would delete the record and its quorum membership first
         db_run! { conn:
-            sqlite, mysql {
-                match diesel::replace_into(emergency_access::table)
+            mysql {
+                diesel::insert_into(emergency_access::table)
                     .values(&*self)
+                    .on_conflict(diesel::dsl::DuplicatedKeys)
+                    .do_update()
+                    .set(&*self)
                     .execute(conn)
-                {
-                    Ok(_) => Ok(()),
-                    // Record already exists and causes a Foreign Key Violation because replace_into() wants to delete the record first.
-                    Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::ForeignKeyViolation, _)) => {
-                        diesel::update(emergency_access::table)
-                            .filter(emergency_access::uuid.eq(&self.uuid))
-                            .set(&*self)
-                            .execute(conn)
-                            .map_res("Error updating emergency access")
-                    }
-                    Err(e) => Err(e.into()),
-                }.map_res("Error saving emergency access")
+                    .map_res("Error saving emergency access")
             }
-            postgresql {
+            postgresql, sqlite {
                 diesel::insert_into(emergency_access::table)
                     .values(&*self)
                     .on_conflict(emergency_access::uuid)
@@ -219,6 +215,7 @@ impl EmergencyAccess {
     }
 
     pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        EmergencyAccessQuorum::delete_by_grantor(user_uuid, conn).await?;
         for ea in Self::find_all_by_grantor_uuid(user_uuid, conn).await {
             ea.delete(conn).await?;
         }
@@ -387,6 +384,104 @@ impl EmergencyAccess {
     }
 }
 
+/// The number of grantees of a grantor which together can recover the account before the wait time has passed.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = emergency_access_quorums)]
+#[diesel(primary_key(grantor_uuid))]
+pub struct EmergencyAccessQuorum {
+    pub grantor_uuid: UserId,
+    pub quorum: i32,
+}
+
+/// An emergency access whose recoveries count towards the quorum of its grantor.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = emergency_access_quorum_members)]
+#[diesel(primary_key(emergency_access_uuid))]
+pub struct EmergencyAccessQuorumMember {
+    pub emergency_access_uuid: EmergencyAccessId,
+    pub grantor_uuid: UserId,
+}
+
+impl EmergencyAccessQuorum {
+    /// The quorum of the grantor, `1` when every grantee recovers on their own.
+    pub async fn find_by_grantor(grantor_uuid: &UserId, conn: &DbConn) -> i32 {
+        conn.run(move |conn| {
+            emergency_access_quorums::table
+                .filter(emergency_access_quorums::grantor_uuid.eq(grantor_uuid))
+                .select(emergency_access_quorums::quorum)
+                .first::<i32>(conn)
+                .ok()
+        })
+        .await
+        .unwrap_or(1)
+    }
+
+    /// The confirmed emergency accesses of the grantor which are part of the quorum.
+    pub async fn find_members(grantor_uuid: &UserId, conn: &DbConn) -> Vec<EmergencyAccess> {
+        conn.run(move |conn| {
+            emergency_access::table
+                .inner_join(emergency_access_quorum_members::table)
+                .filter(emergency_access_quorum_members::grantor_uuid.eq(grantor_uuid))
+                .filter(emergency_access::grantor_uuid.eq(grantor_uuid))
+                .filter(emergency_access::status.ge(EmergencyAccessStatus::Confirmed as i32))
+                .select(emergency_access::all_columns)
+                .load::<EmergencyAccess>(conn)
+                .expect("Error loading emergency access quorum members")
+        })
+        .await
+    }
+
+    /// Replaces the quorum of the grantor and its members at once, a quorum of `1` removes it.
+    pub async fn set(
+        grantor_uuid: &UserId,
+        quorum: i32,
+        members: Vec<EmergencyAccessId>,
+        conn: &DbConn,
+    ) -> EmptyResult {
+        conn.run(move |conn| {
+            conn.transaction::<_, diesel::result::Error, _>(|conn| {
+                // The members are removed along with the quorum
+                diesel::delete(
+                    emergency_access_quorums::table.filter(emergency_access_quorums::grantor_uuid.eq(grantor_uuid)),
+                )
+                .execute(conn)?;
+                if quorum <= 1 {
+                    return Ok(());
+                }
+
+                diesel::insert_into(emergency_access_quorums::table)
+                    .values(Self {
+                        grantor_uuid: grantor_uuid.clone(),
+                        quorum,
+                    })
+                    .execute(conn)?;
+                for emergency_access_uuid in members {
+                    diesel::insert_into(emergency_access_quorum_members::table)
+                        .values(EmergencyAccessQuorumMember {
+                            emergency_access_uuid,
+                            grantor_uuid: grantor_uuid.clone(),
+                        })
+                        .execute(conn)?;
+                }
+                Ok(())
+            })
+            .map_res("Error saving emergency access quorum")
+        })
+        .await
+    }
+
+    pub async fn delete_by_grantor(grantor_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(
+                emergency_access_quorums::table.filter(emergency_access_quorums::grantor_uuid.eq(grantor_uuid)),
+            )
+            .execute(conn)
+            .map_res("Error deleting emergency access quorum")
+        })
+        .await
+    }
+}
+
 // endregion
 
 #[derive(
@@ -9,7 +9,7 @@ use crate::{
         DbConn,
         schema::{event, users_organizations},
     },
-    error::MapResult,
+    error::{Error, MapResult},
 };
 
 use super::{CipherId, CollectionId, GroupId, MembershipId, OrgPolicyId, OrganizationId, UserId};
@@ -60,6 +60,14 @@ pub enum EventType {
     UserRequestedDeviceApproval = 1010,
     // UserTdeOffboardingPasswordSet = 1011, // Not supported
 
+    // Emergency access, Vaultwarden specific and logged for the grantor
+    UserEmergencyAccessInitiated = 1090,
+    UserEmergencyAccessApproved = 1091,
+    UserEmergencyAccessRejected = 1092,
+    UserEmergencyAccessViewed = 1093,
+    UserEmergencyAccessTakeover = 1094,
+    UserEmergencyAccessPasswordReset = 1095,
+
     // Cipher
     CipherCreated = 1100,
     CipherUpdated = 1101,
@@ -79,6 +87,8 @@ pub enum EventType {
     CipherSoftDeleted = 1115,
     CipherRestored = 1116,
     CipherClientToggledCardNumberVisible = 1117,
+    // Vaultwarden specific, a previous version of the cipher was restored
+    CipherRevisionRestored = 1190,
 
     // Collection
     CollectionCreated = 1300,
@@ -319,6 +329,26 @@ impl Event {
         .await
     }
 
+    /// Finds the events of the user which are not tied to an organization.
+    pub async fn find_by_user_uuid(
+        user_uuid: &UserId,
+        start: &NaiveDateTime,
+        end: &NaiveDateTime,
+        conn: &DbConn,
+    ) -> Vec<Self> {
+        conn.run(move |conn| {
+            event::table
+                .filter(event::user_uuid.eq(user_uuid))
+                .filter(event::org_uuid.is_null())
+                .filter(event::event_date.between(start, end))
+                .order_by(event::event_date.desc())
+                .limit(Self::PAGE_SIZE)
+                .load::<Self>(conn)
+                .expect("Error filtering events")
+        })
+        .await
+    }
+
     pub async fn find_by_cipher_uuid(
         cipher_uuid: &CipherId,
         start: &NaiveDateTime,
@@ -337,7 +367,7 @@ impl Event {
         .await
     }
 
-    pub async fn clean_events(conn: &DbConn) -> EmptyResult {
+    pub async fn clean_events(conn: &DbConn) -> Result<usize, Error> {
         if let Some(days_to_retain) = CONFIG.events_days_retain() {
             let dt = Utc::now().naive_utc() - TimeDelta::try_days(days_to_retain).unwrap();
             conn.run(move |conn| {
@@ -347,7 +377,7 @@ impl Event {
             })
             .await
         } else {
-            Ok(())
+            Ok(0)
         }
     }
 }
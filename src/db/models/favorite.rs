@@ -87,6 +87,18 @@ impl Favorite {
         .await
     }
 
+    // Returns the users which flagged the specified cipher as favorite.
+    pub async fn find_user_uuids_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<UserId> {
+        conn.run(move |conn| {
+            favorites::table
+                .filter(favorites::cipher_uuid.eq(cipher_uuid))
+                .select(favorites::user_uuid)
+                .load::<UserId>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
     /// Return a vec with (cipher_uuid) this will only contain favorite flagged ciphers
     /// This is used during a full sync so we only need one query for all favorite cipher matches.
     pub async fn get_all_cipher_uuid_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<CipherId> {
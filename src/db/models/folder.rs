@@ -4,6 +4,7 @@ use diesel::prelude::*;
 use serde_json::Value;
 
 use crate::{
+    CONFIG,
     api::EmptyResult,
     db::{
         DbConn,
@@ -13,7 +14,7 @@ use crate::{
 };
 use macros::UuidFromParam;
 
-use super::{CipherId, User, UserId};
+use super::{CipherId, DeletedItem, Tombstone, TombstoneType, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = folders)]
@@ -106,8 +107,18 @@ impl Folder {
         }
     }
 
+    /// Deletes the folder and keeps it in the item history, so it can be brought back by restoring the vault.
     pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
+        if CONFIG.cipher_revisions_limit() > 0 {
+            DeletedItem::save_folder(self, conn).await?;
+        }
+        self.delete_without_history(conn).await
+    }
+
+    /// Deletes the folder without keeping it, for when its owner is deleted as well.
+    async fn delete_without_history(&self, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(&self.user_uuid, conn).await;
+        Tombstone::create(std::slice::from_ref(&self.user_uuid), TombstoneType::Folder, &self.uuid, conn).await;
         FolderCipher::delete_all_by_folder(&self.uuid, conn).await?;
 
         conn.run(move |conn| {
@@ -118,13 +129,18 @@ impl Folder {
         .await
     }
 
+    /// Deletes the folders of a user who gets deleted, so they aren't kept in the item history.
     pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
         for folder in Self::find_by_user(user_uuid, conn).await {
-            folder.delete(conn).await?;
+            folder.delete_without_history(conn).await?;
         }
         Ok(())
     }
 
+    pub async fn find_by_uuid(uuid: &FolderId, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| folders::table.filter(folders::uuid.eq(uuid)).first::<Self>(conn).ok()).await
+    }
+
     pub async fn find_by_uuid_and_user(uuid: &FolderId, user_uuid: &UserId, conn: &DbConn) -> Option<Self> {
         conn.run(move |conn| {
             folders::table
@@ -213,6 +229,17 @@ impl FolderCipher {
         .await
     }
 
+    pub async fn find_folder_uuids_by_cipher(cipher_uuid: &CipherId, conn: &DbConn) -> Vec<FolderId> {
+        conn.run(move |conn| {
+            folders_ciphers::table
+                .filter(folders_ciphers::cipher_uuid.eq(cipher_uuid))
+                .select(folders_ciphers::folder_uuid)
+                .load::<FolderId>(conn)
+                .unwrap_or_default()
+        })
+        .await
+    }
+
     pub async fn find_by_folder(folder_uuid: &FolderId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             folders_ciphers::table
@@ -13,7 +13,7 @@ use crate::{
 };
 use macros::UuidFromParam;
 
-use super::{CollectionId, Membership, MembershipId, OrganizationId, User, UserId};
+use super::{CollectionId, Membership, MembershipId, OrganizationId, Tombstone, TombstoneType, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = groups)]
@@ -165,6 +165,14 @@ impl Group {
     pub async fn save(&mut self, conn: &DbConn) -> EmptyResult {
         self.revision_date = Utc::now().naive_utc();
 
+        // Members of a group which got or lost access to all collections need a full sync
+        if Self::find_by_uuid_and_org(&self.uuid, &self.organizations_uuid, conn)
+            .await
+            .is_some_and(|group| group.access_all != self.access_all)
+        {
+            GroupUser::update_users_access(&self.uuid, &self.organizations_uuid, conn).await;
+        }
+
         db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(groups::table)
@@ -321,10 +329,7 @@ impl Group {
 
 impl CollectionGroup {
     pub async fn save(&mut self, org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
-        let group_users = GroupUser::find_by_group(&self.groups_uuid, org_uuid, conn).await;
-        for group_user in group_users {
-            group_user.update_user_revision(conn).await;
-        }
+        GroupUser::update_users_access(&self.groups_uuid, org_uuid, conn).await;
 
         db_run! { conn:
             sqlite, mysql {
@@ -397,6 +402,31 @@ impl CollectionGroup {
         .await
     }
 
+    /// The users with access to the collection through one of its groups.
+    pub async fn find_user_uuids_by_collection(
+        collection_uuid: &CollectionId,
+        org_uuid: &OrganizationId,
+        conn: &DbConn,
+    ) -> Vec<UserId> {
+        conn.run(move |conn| {
+            collections_groups::table
+                .inner_join(groups::table.on(groups::uuid.eq(collections_groups::groups_uuid)))
+                .inner_join(groups_users::table.on(groups_users::groups_uuid.eq(groups::uuid)))
+                .inner_join(
+                    users_organizations::table.on(users_organizations::uuid
+                        .eq(groups_users::users_organizations_uuid)
+                        .and(users_organizations::org_uuid.eq(groups::organizations_uuid))),
+                )
+                .filter(collections_groups::collections_uuid.eq(collection_uuid))
+                .filter(groups::organizations_uuid.eq(org_uuid))
+                .select(users_organizations::user_uuid)
+                .distinct()
+                .load::<UserId>(conn)
+                .expect("Error loading collection group users")
+        })
+        .await
+    }
+
     pub async fn find_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             collections_groups::table
@@ -440,10 +470,7 @@ impl CollectionGroup {
     }
 
     pub async fn delete(&self, org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
-        let group_users = GroupUser::find_by_group(&self.groups_uuid, org_uuid, conn).await;
-        for group_user in group_users {
-            group_user.update_user_revision(conn).await;
-        }
+        GroupUser::update_users_access(&self.groups_uuid, org_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(collections_groups::table)
@@ -456,10 +483,7 @@ impl CollectionGroup {
     }
 
     pub async fn delete_all_by_group(group_uuid: &GroupId, org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
-        let group_users = GroupUser::find_by_group(group_uuid, org_uuid, conn).await;
-        for group_user in group_users {
-            group_user.update_user_revision(conn).await;
-        }
+        GroupUser::update_users_access(group_uuid, org_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(collections_groups::table)
@@ -477,10 +501,7 @@ impl CollectionGroup {
     ) -> EmptyResult {
         let collection_assigned_to_groups = CollectionGroup::find_by_collection(collection_uuid, conn).await;
         for collection_assigned_to_group in collection_assigned_to_groups {
-            let group_users = GroupUser::find_by_group(&collection_assigned_to_group.groups_uuid, org_uuid, conn).await;
-            for group_user in group_users {
-                group_user.update_user_revision(conn).await;
-            }
+            GroupUser::update_users_access(&collection_assigned_to_group.groups_uuid, org_uuid, conn).await;
         }
 
         conn.run(move |conn| {
@@ -495,7 +516,7 @@ impl CollectionGroup {
 
 impl GroupUser {
     pub async fn save(&mut self, conn: &DbConn) -> EmptyResult {
-        self.update_user_revision(conn).await;
+        Self::update_member_access(&self.users_organizations_uuid, &self.groups_uuid, conn).await;
 
         db_run! { conn:
             sqlite, mysql {
@@ -558,6 +579,29 @@ impl GroupUser {
         .await
     }
 
+    /// The users which are members of the group.
+    pub async fn find_user_uuids_by_group(
+        group_uuid: &GroupId,
+        org_uuid: &OrganizationId,
+        conn: &DbConn,
+    ) -> Vec<UserId> {
+        conn.run(move |conn| {
+            groups_users::table
+                .inner_join(groups::table.on(groups::uuid.eq(groups_users::groups_uuid)))
+                .inner_join(
+                    users_organizations::table.on(users_organizations::uuid
+                        .eq(groups_users::users_organizations_uuid)
+                        .and(users_organizations::org_uuid.eq(groups::organizations_uuid))),
+                )
+                .filter(groups_users::groups_uuid.eq(group_uuid))
+                .filter(groups::organizations_uuid.eq(org_uuid))
+                .select(users_organizations::user_uuid)
+                .load::<UserId>(conn)
+                .expect("Error loading group users")
+        })
+        .await
+    }
+
     pub async fn find_by_member(member_uuid: &MembershipId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             groups_users::table
@@ -611,22 +655,33 @@ impl GroupUser {
         .await
     }
 
-    pub async fn update_user_revision(&self, conn: &DbConn) {
-        match Membership::find_by_uuid(&self.users_organizations_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
+    /// Updates the revision of the member after their access changed, and makes their next sync a full one.
+    async fn update_member_access(member_uuid: &MembershipId, group_uuid: &GroupId, conn: &DbConn) {
+        match Membership::find_by_uuid(member_uuid, conn).await {
+            Some(member) => {
+                User::update_uuid_revision(&member.user_uuid, conn).await;
+                Tombstone::create(std::slice::from_ref(&member.user_uuid), TombstoneType::Access, group_uuid, conn)
+                    .await;
+            }
             None => warn!("Member could not be found!"),
         }
     }
 
+    /// Updates the revision of the members of the group after their access changed, and makes their next sync a full one.
+    pub async fn update_users_access(group_uuid: &GroupId, org_uuid: &OrganizationId, conn: &DbConn) {
+        let user_uuids = Self::find_user_uuids_by_group(group_uuid, org_uuid, conn).await;
+        for user_uuid in &user_uuids {
+            User::update_uuid_revision(user_uuid, conn).await;
+        }
+        Tombstone::create(&user_uuids, TombstoneType::Access, group_uuid, conn).await;
+    }
+
     pub async fn delete_by_group_and_member(
         group_uuid: &GroupId,
         member_uuid: &MembershipId,
         conn: &DbConn,
     ) -> EmptyResult {
-        match Membership::find_by_uuid(member_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
-            None => warn!("Member could not be found!"),
-        }
+        Self::update_member_access(member_uuid, group_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(groups_users::table)
@@ -639,10 +694,7 @@ impl GroupUser {
     }
 
     pub async fn delete_all_by_group(group_uuid: &GroupId, org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
-        let group_users = GroupUser::find_by_group(group_uuid, org_uuid, conn).await;
-        for group_user in group_users {
-            group_user.update_user_revision(conn).await;
-        }
+        GroupUser::update_users_access(group_uuid, org_uuid, conn).await;
 
         conn.run(move |conn| {
             diesel::delete(groups_users::table)
@@ -654,9 +706,9 @@ impl GroupUser {
     }
 
     pub async fn delete_all_by_member(member_uuid: &MembershipId, conn: &DbConn) -> EmptyResult {
-        match Membership::find_by_uuid(member_uuid, conn).await {
-            Some(member) => User::update_uuid_revision(&member.user_uuid, conn).await,
-            None => warn!("Member could not be found!"),
+        let groups = Self::find_by_member(member_uuid, conn).await;
+        if let Some(group_user) = groups.first() {
+            Self::update_member_access(member_uuid, &group_user.groups_uuid, conn).await;
         }
 
         conn.run(move |conn| {
@@ -686,3 +738,77 @@ impl GroupUser {
     UuidFromParam,
 )]
 pub struct GroupId(String);
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::db::{
+        DbPool,
+        models::{Collection, CollectionUser, Organization, User},
+    };
+
+    async fn member(org: &Organization, email: &str, conn: &DbConn) -> Membership {
+        let mut user = User::new(email, None);
+        user.save(conn).await.unwrap();
+        let member = Membership::new(user.uuid, org.uuid.clone(), None);
+        member.save(conn).await.unwrap();
+        member
+    }
+
+    async fn group(org: &Organization, members: &[&Membership], conn: &DbConn) -> Group {
+        let mut group = Group::new(org.uuid.clone(), String::from("group"), false, None);
+        group.save(conn).await.unwrap();
+        for member in members {
+            GroupUser::new(group.uuid.clone(), member.uuid.clone()).save(conn).await.unwrap();
+        }
+        group
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn finds_the_users_of_groups_and_collections() {
+        let pool = DbPool::for_tests("group-users");
+        let conn = &pool.get().await.unwrap();
+
+        let org = Organization::new(String::from("org"), "org@example.com", None, None);
+        org.save(conn).await.unwrap();
+        let other_org = Organization::new(String::from("other"), "other@example.com", None, None);
+        other_org.save(conn).await.unwrap();
+
+        let alice = member(&org, "alice@example.com", conn).await;
+        let bob = member(&org, "bob@example.com", conn).await;
+        let carol = member(&org, "carol@example.com", conn).await;
+        let dave = member(&other_org, "dave@example.com", conn).await;
+
+        let erin = member(&org, "erin@example.com", conn).await;
+
+        let first = group(&org, &[&alice, &bob], conn).await;
+        let second = group(&org, &[&bob, &carol], conn).await;
+        group(&org, &[&erin], conn).await;
+
+        let user_ids: HashSet<UserId> =
+            GroupUser::find_user_uuids_by_group(&first.uuid, &org.uuid, conn).await.into_iter().collect();
+        assert_eq!(user_ids, HashSet::from([alice.user_uuid.clone(), bob.user_uuid.clone()]));
+        // Groups are only found within their own organization
+        assert!(GroupUser::find_user_uuids_by_group(&first.uuid, &other_org.uuid, conn).await.is_empty());
+
+        let collection = Collection::new(org.uuid.clone(), String::from("collection"), None);
+        collection.save(conn).await.unwrap();
+        for group in [&first, &second] {
+            CollectionGroup::new(collection.uuid.clone(), group.uuid.clone(), false, false, false)
+                .save(&org.uuid, conn)
+                .await
+                .unwrap();
+        }
+        CollectionUser::save(&dave.user_uuid, &collection.uuid, false, false, false, conn).await.unwrap();
+
+        // Bob is a member of both groups but only listed once, and the direct access of Dave isn't included
+        let user_ids = CollectionGroup::find_user_uuids_by_collection(&collection.uuid, &org.uuid, conn).await;
+        assert_eq!(user_ids.len(), 3);
+        assert_eq!(
+            user_ids.into_iter().collect::<HashSet<_>>(),
+            HashSet::from([alice.user_uuid, bob.user_uuid, carol.user_uuid])
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::icon_domains},
+    error::MapResult,
+    util::format_naive_datetime_local,
+};
+
+/// A domain whose icon is fetched ahead of time and kept fresh by the icon refresh job.
+/// Admins provide these, as the URIs stored in the vaults are encrypted.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = icon_domains)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(domain))]
+pub struct IconDomain {
+    pub domain: String,
+    pub created_at: NaiveDateTime,
+    /// The last time the icon was fetched, successfully or not
+    pub refreshed_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+}
+
+/// Local methods
+impl IconDomain {
+    pub fn new(domain: String) -> Self {
+        Self {
+            domain,
+            created_at: Utc::now().naive_utc(),
+            refreshed_at: None,
+            last_error: None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "domain": self.domain,
+            "created_at": format_naive_datetime_local(&self.created_at, "%Y-%m-%d %H:%M:%S %Z"),
+            "refreshed_at": self.refreshed_at.map(|dt| format_naive_datetime_local(&dt, "%Y-%m-%d %H:%M:%S %Z")),
+            "last_error": self.last_error,
+        })
+    }
+}
+
+/// Database methods
+impl IconDomain {
+    /// Adds the domains which aren't in the list yet.
+    pub async fn add_all(domains: Vec<Self>, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                for domain in domains {
+                    diesel::insert_or_ignore_into(icon_domains::table)
+                        .values(&domain)
+                        .execute(conn)
+                        .map(|_| ())
+                        .map_res("Error adding icon domain")?;
+                }
+                Ok(())
+            }
+            postgresql {
+                diesel::insert_into(icon_domains::table)
+                    .values(&domains)
+                    .on_conflict_do_nothing()
+                    .execute(conn)
+                    .map_res("Error adding icon domains")
+            }
+        }
+    }
+
+    pub async fn record_refresh(domain: &str, error: Option<String>, conn: &DbConn) -> EmptyResult {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            diesel::update(icon_domains::table.filter(icon_domains::domain.eq(domain)))
+                .set((icon_domains::refreshed_at.eq(now), icon_domains::last_error.eq(error)))
+                .execute(conn)
+                .map_res("Error updating icon domain")
+        })
+        .await
+    }
+
+    pub async fn delete(domain: &str, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(icon_domains::table.filter(icon_domains::domain.eq(domain)))
+                .execute(conn)
+                .map_res("Error deleting icon domain")
+        })
+        .await
+    }
+
+    pub async fn get_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            icon_domains::table
+                .order(icon_domains::domain.asc())
+                .load::<Self>(conn)
+                .expect("Error loading icon domains")
+        })
+        .await
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::job_runs},
+    error::MapResult,
+    util::{INSTANCE_ID, format_naive_datetime_local, get_uuid},
+};
+
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = job_runs)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct JobRun {
+    pub uuid: String,
+    pub job_name: String,
+    /// The time the job was scheduled for, shared by all instances running the same schedule
+    pub scheduled_at: NaiveDateTime,
+    /// The instance which ran the job
+    pub instance: String,
+    pub started_at: NaiveDateTime,
+    pub finished_at: Option<NaiveDateTime>,
+    /// `success`, or the error the job failed with
+    pub result: Option<String>,
+    pub rows_affected: Option<i64>,
+    /// JSON report of the run, for jobs whose results are shown on the admin panel
+    pub details: Option<String>,
+}
+
+/// Local methods
+impl JobRun {
+    pub fn new(job_name: &str, scheduled_at: NaiveDateTime) -> Self {
+        Self {
+            uuid: get_uuid(),
+            job_name: job_name.to_owned(),
+            scheduled_at,
+            instance: INSTANCE_ID.clone(),
+            started_at: Utc::now().naive_utc(),
+            finished_at: None,
+            result: None,
+            rows_affected: None,
+            details: None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "job_name": self.job_name,
+            "instance": self.instance,
+            "started_at": format_naive_datetime_local(&self.started_at, "%Y-%m-%d %H:%M:%S %Z"),
+            "duration": self.finished_at.and_then(|finished_at| {
+                (finished_at - self.started_at).to_std().ok().map(|duration| format!("{duration:.1?}"))
+            }),
+            "result": self.result,
+            "rows_affected": self.rows_affected,
+            "failed": self.result.as_ref().is_some_and(|r| r != "success"),
+        })
+    }
+}
+
+/// Database methods
+impl JobRun {
+    /// Records the start of the job run, unless another instance already started the same run.
+    /// Returns whether this instance should run the job.
+    pub async fn claim(&self, conn: &DbConn) -> bool {
+        let result = conn
+            .run(move |conn| {
+                // Inserting fails on the unique (job_name, scheduled_at) key if another instance was faster
+                diesel::insert_into(job_runs::table).values(self).execute(conn)
+            })
+            .await;
+
+        match result {
+            Ok(_) => true,
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => false,
+            Err(e) => {
+                error!("Failed to record the start of job '{}': {e:?}", self.job_name);
+                false
+            }
+        }
+    }
+
+    pub async fn finish(
+        &mut self,
+        result: String,
+        rows_affected: Option<i64>,
+        details: Option<String>,
+        conn: &DbConn,
+    ) -> EmptyResult {
+        self.finished_at = Some(Utc::now().naive_utc());
+        self.result = Some(result);
+        self.rows_affected = rows_affected;
+        self.details = details;
+
+        conn.run(move |conn| {
+            diesel::update(job_runs::table.filter(job_runs::uuid.eq(&self.uuid)))
+                .set(&*self)
+                .execute(conn)
+                .map_res("Error saving job run")
+        })
+        .await
+    }
+
+    /// Finds an unfinished run of the job which started after `since`.
+    pub async fn find_running(job_name: &str, since: NaiveDateTime, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            job_runs::table
+                .filter(job_runs::job_name.eq(job_name))
+                .filter(job_runs::finished_at.is_null())
+                .filter(job_runs::started_at.gt(since))
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    /// Finds the most recent run of the job which finished with a report, on any instance.
+    pub async fn find_last_report(job_name: &str, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            job_runs::table
+                .filter(job_runs::job_name.eq(job_name))
+                .filter(job_runs::details.is_not_null())
+                .order(job_runs::started_at.desc())
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_recent(limit: i64, conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            job_runs::table
+                .order(job_runs::started_at.desc())
+                .limit(limit)
+                .load::<Self>(conn)
+                .expect("Error loading job runs")
+        })
+        .await
+    }
+
+    /// Removes the old runs, except for the most recent report of each job.
+    pub async fn delete_started_before(dt: NaiveDateTime, conn: &DbConn) -> EmptyResult {
+        let mut reports: Vec<(String, String)> = conn
+            .run(move |conn| {
+                job_runs::table
+                    .filter(job_runs::details.is_not_null())
+                    .order(job_runs::started_at.desc())
+                    .select((job_runs::uuid, job_runs::job_name))
+                    .load(conn)
+                    .unwrap_or_default()
+            })
+            .await;
+        let mut seen_jobs = HashSet::new();
+        reports.retain(|(_, job_name)| seen_jobs.insert(job_name.clone()));
+        let last_reports: Vec<String> = reports.into_iter().map(|(uuid, _)| uuid).collect();
+
+        conn.run(move |conn| {
+            diesel::delete(
+                job_runs::table.filter(job_runs::started_at.lt(dt)).filter(job_runs::uuid.ne_all(last_reports)),
+            )
+            .execute(conn)
+            .map_res("Error deleting old job runs")
+        })
+        .await
+    }
+}
@@ -0,0 +1,332 @@
+use chrono::{NaiveDateTime, SubsecRound, TimeDelta, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::mail_queue},
+    error::{Error, MapResult},
+    util::{format_naive_datetime_local, get_uuid},
+};
+use macros::UuidFromParam;
+
+/// An outgoing mail which is sent in the background, see `MAIL_QUEUE_ENABLED`.
+/// Mails are removed from the queue once they have been sent, and purged when they failed or expired.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = mail_queue)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct QueuedMail {
+    pub uuid: MailId,
+    pub recipient: String,
+    pub subject: String,
+    pub body_html: String,
+    pub body_text: String,
+    /// The number of failed attempts to send the mail
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// The mail is not sent before this time, either to back off after a failure,
+    /// or because an instance is currently sending it
+    pub next_attempt_at: NaiveDateTime,
+    /// When the token in the mail, like an invitation or a login code, expires and the mail isn't worth sending anymore
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// Local methods
+impl QueuedMail {
+    /// How long an instance which started sending a mail has before other instances try again.
+    pub const SEND_LEASE: TimeDelta = TimeDelta::minutes(5);
+
+    /// The longest delay between two attempts to send a mail.
+    const MAX_BACKOFF: TimeDelta = TimeDelta::hours(6);
+
+    /// How long mails which failed too often are kept, so the admin can look into them and retry them.
+    pub const FAILED_RETENTION: TimeDelta = TimeDelta::days(7);
+
+    /// Creates a mail which is leased to the current instance, so it can try to send it right away.
+    pub fn new(
+        recipient: &str,
+        subject: &str,
+        body_html: String,
+        body_text: String,
+        expires_at: Option<NaiveDateTime>,
+    ) -> Self {
+        let now = Utc::now().naive_utc().trunc_subsecs(0);
+
+        Self {
+            uuid: MailId(get_uuid()),
+            recipient: recipient.to_owned(),
+            subject: subject.to_owned(),
+            body_html,
+            body_text,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            next_attempt_at: now + Self::SEND_LEASE,
+            expires_at,
+        }
+    }
+
+    /// Calculates the delay before the next attempt, which doubles with every failed attempt.
+    pub fn backoff(attempts: i32) -> TimeDelta {
+        TimeDelta::minutes(1i64 << attempts.clamp(0, 20)).min(Self::MAX_BACKOFF)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= Utc::now().naive_utc())
+    }
+
+    pub fn to_json(&self, max_attempts: i32) -> Value {
+        json!({
+            "id": self.uuid,
+            "recipient": self.recipient,
+            "subject": self.subject,
+            "attempts": self.attempts,
+            "last_error": self.last_error,
+            "created_at": format_naive_datetime_local(&self.created_at, "%Y-%m-%d %H:%M:%S %Z"),
+            "next_attempt_at": format_naive_datetime_local(&self.next_attempt_at, "%Y-%m-%d %H:%M:%S %Z"),
+            "expires_at": self.expires_at.map(|dt| format_naive_datetime_local(&dt, "%Y-%m-%d %H:%M:%S %Z")),
+            "failed": self.attempts >= max_attempts,
+        })
+    }
+}
+
+/// Database methods
+impl QueuedMail {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::insert_into(mail_queue::table).values(self).execute(conn).map_res("Error queueing mail")
+        })
+        .await
+    }
+
+    pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(mail_queue::table.filter(mail_queue::uuid.eq(self.uuid)))
+                .execute(conn)
+                .map_res("Error deleting queued mail")
+        })
+        .await
+    }
+
+    /// Takes the mail for the current instance to send, unless another instance was faster.
+    pub async fn claim(&mut self, conn: &DbConn) -> bool {
+        let until = Utc::now().naive_utc().trunc_subsecs(0) + Self::SEND_LEASE;
+        let claimed = conn
+            .run(|conn| {
+                diesel::update(
+                    mail_queue::table
+                        .filter(mail_queue::uuid.eq(&self.uuid))
+                        .filter(mail_queue::next_attempt_at.eq(self.next_attempt_at)),
+                )
+                .set(mail_queue::next_attempt_at.eq(until))
+                .execute(conn)
+            })
+            .await;
+
+        if matches!(claimed, Ok(1)) {
+            self.next_attempt_at = until;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records a failed attempt, and backs off exponentially before the next one.
+    pub async fn record_failure(&mut self, error: String, conn: &DbConn) -> EmptyResult {
+        self.attempts = self.attempts.saturating_add(1);
+        self.next_attempt_at = Utc::now().naive_utc().trunc_subsecs(0) + Self::backoff(self.attempts);
+        self.last_error = Some(error);
+
+        conn.run(move |conn| {
+            diesel::update(mail_queue::table.filter(mail_queue::uuid.eq(&self.uuid)))
+                .set(&*self)
+                .execute(conn)
+                .map_res("Error updating queued mail")
+        })
+        .await
+    }
+
+    /// Makes a mail which failed, or is waiting for its next attempt, be sent again right away.
+    pub async fn retry_now(&mut self, conn: &DbConn) -> EmptyResult {
+        self.attempts = 0;
+        self.next_attempt_at = Utc::now().naive_utc().trunc_subsecs(0);
+
+        conn.run(move |conn| {
+            diesel::update(mail_queue::table.filter(mail_queue::uuid.eq(&self.uuid)))
+                .set(&*self)
+                .execute(conn)
+                .map_res("Error updating queued mail")
+        })
+        .await
+    }
+
+    /// Finds the mails which are due to be sent and haven't failed too often yet.
+    pub async fn find_due(max_attempts: i32, conn: &DbConn) -> Vec<Self> {
+        let now = Utc::now().naive_utc();
+        conn.run(move |conn| {
+            mail_queue::table
+                .filter(mail_queue::next_attempt_at.le(now))
+                .filter(mail_queue::attempts.lt(max_attempts))
+                .filter(mail_queue::expires_at.is_null().or(mail_queue::expires_at.gt(now)))
+                .order(mail_queue::created_at.asc())
+                .load::<Self>(conn)
+                .expect("Error loading queued mails")
+        })
+        .await
+    }
+
+    /// Removes the mails whose token expired, and the ones which failed too often and weren't retried for a while.
+    /// Returns how many were removed.
+    pub async fn purge(max_attempts: i32, conn: &DbConn) -> Result<usize, Error> {
+        let now = Utc::now().naive_utc();
+        let failed_before = now - Self::FAILED_RETENTION;
+        conn.run(move |conn| {
+            diesel::delete(
+                mail_queue::table.filter(
+                    mail_queue::expires_at
+                        .le(now)
+                        .or(mail_queue::attempts.ge(max_attempts).and(mail_queue::next_attempt_at.le(failed_before))),
+                ),
+            )
+            .execute(conn)
+            .map_res("Error purging queued mails")
+        })
+        .await
+    }
+
+    pub async fn find_by_uuid(uuid: &MailId, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| mail_queue::table.filter(mail_queue::uuid.eq(uuid)).first::<Self>(conn).ok()).await
+    }
+
+    pub async fn get_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            mail_queue::table
+                .order(mail_queue::created_at.desc())
+                .load::<Self>(conn)
+                .expect("Error loading queued mails")
+        })
+        .await
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct MailId(String);
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+    use crate::db::DbPool;
+
+    fn mail(recipient: &str, expires_at: Option<NaiveDateTime>) -> QueuedMail {
+        QueuedMail::new(recipient, "subject", String::from("<p>body</p>"), String::from("body"), expires_at)
+    }
+
+    fn recipients(mails: Vec<QueuedMail>) -> Vec<String> {
+        let mut recipients: Vec<String> = mails.into_iter().map(|mail| mail.recipient).collect();
+        recipients.sort_unstable();
+        recipients
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_maximum() {
+        assert_eq!(QueuedMail::backoff(0), TimeDelta::minutes(1));
+        assert_eq!(QueuedMail::backoff(1), TimeDelta::minutes(2));
+        assert_eq!(QueuedMail::backoff(5), TimeDelta::minutes(32));
+        assert_eq!(QueuedMail::backoff(9), TimeDelta::hours(6));
+        assert_eq!(QueuedMail::backoff(i32::MAX), TimeDelta::hours(6));
+        assert_eq!(QueuedMail::backoff(-1), TimeDelta::minutes(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_claimed_once_and_retried_after_the_backoff() {
+        let pool = DbPool::for_tests("mail-queue-claim");
+        let conn = &pool.get().await.unwrap();
+
+        // New mails are leased to the instance which queued them
+        let mut queued = mail("new@example.com", None);
+        queued.save(conn).await.unwrap();
+        assert!(QueuedMail::find_due(3, conn).await.is_empty());
+
+        queued.retry_now(conn).await.unwrap();
+        let mut first = QueuedMail::find_by_uuid(&queued.uuid, conn).await.unwrap();
+        let mut second = QueuedMail::find_by_uuid(&queued.uuid, conn).await.unwrap();
+        assert_eq!(recipients(QueuedMail::find_due(3, conn).await), ["new@example.com"]);
+        assert!(first.claim(conn).await);
+        assert!(!second.claim(conn).await);
+        assert!(QueuedMail::find_due(3, conn).await.is_empty());
+
+        first.record_failure(String::from("timeout"), conn).await.unwrap();
+        let failed = QueuedMail::find_by_uuid(&queued.uuid, conn).await.unwrap();
+        assert_eq!(failed.attempts, 1);
+        assert_eq!(failed.last_error.as_deref(), Some("timeout"));
+        assert!(failed.next_attempt_at > Utc::now().naive_utc() + TimeDelta::seconds(90));
+        assert!(QueuedMail::find_due(3, conn).await.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn skips_expired_mails_and_those_which_failed_too_often() {
+        let pool = DbPool::for_tests("mail-queue-due");
+        let conn = &pool.get().await.unwrap();
+        let now = Utc::now().naive_utc();
+
+        for (recipient, expires_at, attempts) in [
+            ("due@example.com", None, 0),
+            ("valid@example.com", Some(now + TimeDelta::hours(1)), 2),
+            ("expired@example.com", Some(now - TimeDelta::minutes(1)), 0),
+            ("failed@example.com", None, 3),
+        ] {
+            let mut queued = mail(recipient, expires_at);
+            queued.attempts = attempts;
+            queued.next_attempt_at = now - TimeDelta::minutes(1);
+            queued.save(conn).await.unwrap();
+        }
+
+        assert_eq!(recipients(QueuedMail::find_due(3, conn).await), ["due@example.com", "valid@example.com"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn purges_expired_mails_and_old_failures() {
+        let pool = DbPool::for_tests("mail-queue-purge");
+        let conn = &pool.get().await.unwrap();
+        let now = Utc::now().naive_utc().trunc_subsecs(0);
+        let long_ago = now - QueuedMail::FAILED_RETENTION - TimeDelta::hours(1);
+
+        for (recipient, expires_at, attempts, next_attempt_at) in [
+            ("pending@example.com", None, 1, now),
+            ("valid@example.com", Some(now + TimeDelta::hours(1)), 0, now),
+            ("expired@example.com", Some(now - TimeDelta::minutes(1)), 0, now),
+            ("recently-failed@example.com", None, 3, now - TimeDelta::hours(1)),
+            ("failed@example.com", None, 3, long_ago),
+            ("backing-off@example.com", None, 2, long_ago),
+        ] {
+            let mut queued = mail(recipient, expires_at);
+            queued.attempts = attempts;
+            queued.next_attempt_at = next_attempt_at;
+            queued.save(conn).await.unwrap();
+        }
+
+        assert_eq!(QueuedMail::purge(3, conn).await.unwrap(), 2);
+        assert_eq!(
+            recipients(QueuedMail::get_all(conn).await),
+            ["backing-off@example.com", "pending@example.com", "recently-failed@example.com", "valid@example.com"]
+        );
+    }
+}
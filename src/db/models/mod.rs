@@ -2,17 +2,26 @@ mod archive;
 mod attachment;
 mod auth_request;
 mod cipher;
+mod cipher_revision;
 mod collection;
+mod deleted_item;
 mod device;
 mod emergency_access;
 mod event;
 mod favorite;
 mod folder;
 mod group;
+mod icon_domain;
+mod job_run;
+mod mail_queue;
+mod notification;
 mod org_policy;
 mod organization;
+mod pending_upload;
+mod ratelimit;
 mod send;
 mod sso_auth;
+mod tombstone;
 mod two_factor;
 mod two_factor_duo_context;
 mod two_factor_incomplete;
@@ -22,21 +31,32 @@ pub use self::archive::Archive;
 pub use self::attachment::{Attachment, AttachmentId};
 pub use self::auth_request::{AuthRequest, AuthRequestId};
 pub use self::cipher::{Cipher, CipherId, RepromptType};
+pub use self::cipher_revision::{CipherRevision, CipherRevisionId, VersionAt};
 pub use self::collection::{Collection, CollectionCipher, CollectionId, CollectionUser};
+pub use self::deleted_item::DeletedItem;
 pub use self::device::{Device, DeviceId, DeviceType, DeviceWithAuthRequest, PushId};
-pub use self::emergency_access::{EmergencyAccess, EmergencyAccessId, EmergencyAccessStatus, EmergencyAccessType};
+pub use self::emergency_access::{
+    EmergencyAccess, EmergencyAccessId, EmergencyAccessQuorum, EmergencyAccessStatus, EmergencyAccessType,
+};
 pub use self::event::{Event, EventType};
 pub use self::favorite::Favorite;
 pub use self::folder::{Folder, FolderCipher, FolderId};
 pub use self::group::{CollectionGroup, Group, GroupId, GroupUser};
+pub use self::icon_domain::IconDomain;
+pub use self::job_run::JobRun;
+pub use self::mail_queue::{MailId, QueuedMail};
+pub use self::notification::{Notification, NotificationId, NotificationPriority, NotificationStatus};
 pub use self::org_policy::{OrgPolicy, OrgPolicyId, OrgPolicyType};
 pub use self::organization::{
     Membership, MembershipId, MembershipStatus, MembershipType, OrgApiKeyId, Organization, OrganizationApiKey,
     OrganizationId,
 };
+pub use self::pending_upload::{PendingUpload, PendingUploadType};
+pub use self::ratelimit::RateLimit;
 pub use self::send::{Send, SendFileId, SendId, SendType};
 pub use self::sso_auth::{OIDCAuthenticatedUser, OIDCCodeResponseError, SsoAuth};
+pub use self::tombstone::{Tombstone, TombstoneType};
 pub use self::two_factor::{TwoFactor, TwoFactorType};
 pub use self::two_factor_duo_context::TwoFactorDuoContext;
 pub use self::two_factor_incomplete::TwoFactorIncomplete;
-pub use self::user::{Invitation, SsoUser, User, UserId, UserKdfType, UserStampException};
+pub use self::user::{Invitation, SsoUser, User, UserId, UserKdfType, UserLanguage, UserStampException};
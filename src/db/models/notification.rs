@@ -0,0 +1,258 @@
+use chrono::{NaiveDateTime, Utc};
+use derive_more::{AsRef, Deref, Display, From};
+use diesel::prelude::*;
+use serde_json::Value;
+
+use crate::{
+    api::EmptyResult,
+    db::{
+        DbConn,
+        schema::{notification_statuses, notifications, users_organizations},
+    },
+    error::MapResult,
+    util::{format_date, get_uuid},
+};
+use macros::UuidFromParam;
+
+use super::{MembershipStatus, OrganizationId, UserId};
+
+/// A message shown in the notification center of the clients.
+/// It is targeted at either all users (`global`), a single user, or all confirmed members of an organization.
+// https://github.com/bitwarden/server/blob/main/src/Core/NotificationCenter/Entities/Notification.cs
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = notifications)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(uuid))]
+pub struct Notification {
+    pub uuid: NotificationId,
+    pub priority: i32,
+    pub global: bool,
+    /// The `ClientType` the notification is shown on, `0` for all clients
+    pub client_type: i32,
+    pub user_uuid: Option<UserId>,
+    pub organization_uuid: Option<OrganizationId>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub creation_date: NaiveDateTime,
+    pub revision_date: NaiveDateTime,
+}
+
+/// Whether a user has read or deleted a notification.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = notification_statuses)]
+#[diesel(treat_none_as_null = true)]
+#[diesel(primary_key(notification_uuid, user_uuid))]
+pub struct NotificationStatus {
+    pub notification_uuid: NotificationId,
+    pub user_uuid: UserId,
+    pub read_date: Option<NaiveDateTime>,
+    pub deleted_date: Option<NaiveDateTime>,
+}
+
+// https://github.com/bitwarden/server/blob/main/src/Core/NotificationCenter/Enums/Priority.cs
+#[derive(Copy, Clone, PartialEq, Eq, num_derive::FromPrimitive)]
+pub enum NotificationPriority {
+    Informational = 0,
+    Low = 1,
+    Medium = 2,
+    High = 3,
+    Critical = 4,
+}
+
+/// Local methods
+impl Notification {
+    pub fn new(
+        priority: NotificationPriority,
+        client_type: i32,
+        user_uuid: Option<UserId>,
+        organization_uuid: Option<OrganizationId>,
+        title: Option<String>,
+        body: Option<String>,
+    ) -> Self {
+        let now = Utc::now().naive_utc();
+
+        Self {
+            uuid: NotificationId(get_uuid()),
+            priority: priority as i32,
+            global: user_uuid.is_none() && organization_uuid.is_none(),
+            client_type,
+            user_uuid,
+            organization_uuid,
+            title,
+            body,
+            creation_date: now,
+            revision_date: now,
+        }
+    }
+
+    pub fn to_json(&self, status: Option<&NotificationStatus>) -> Value {
+        json!({
+            "id": self.uuid,
+            "priority": self.priority,
+            "title": self.title,
+            "body": self.body,
+            "date": format_date(&self.revision_date),
+            "taskId": null,
+            "readDate": status.and_then(|s| s.read_date.as_ref()).map(format_date),
+            "deletedDate": status.and_then(|s| s.deleted_date.as_ref()).map(format_date),
+            "object": "notification",
+        })
+    }
+}
+
+/// Database methods
+impl Notification {
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::insert_into(notifications::table).values(self).execute(conn).map_res("Error saving notification")
+        })
+        .await
+    }
+
+    /// Finds the notifications shown to the user on the given kind of client, most important and newest first,
+    /// together with the read and deleted status of the user.
+    pub async fn find_by_user(
+        user_uuid: &UserId,
+        client_type: i32,
+        conn: &DbConn,
+    ) -> Vec<(Self, Option<NotificationStatus>)> {
+        conn.run(move |conn| {
+            notifications::table
+                .left_join(
+                    notification_statuses::table.on(notification_statuses::notification_uuid
+                        .eq(notifications::uuid)
+                        .and(notification_statuses::user_uuid.eq(user_uuid))),
+                )
+                .filter(notifications::client_type.eq(0).or(notifications::client_type.eq(client_type)))
+                .filter(
+                    notifications::global.eq(true).nullable().or(notifications::user_uuid.eq(user_uuid)).or(
+                        notifications::organization_uuid.eq_any(
+                            users_organizations::table
+                                .filter(users_organizations::user_uuid.eq(user_uuid))
+                                .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
+                                .select(users_organizations::org_uuid.nullable()),
+                        ),
+                    ),
+                )
+                .order((notifications::priority.desc(), notifications::creation_date.desc()))
+                .select((notifications::all_columns, notification_statuses::all_columns.nullable()))
+                .load::<(Self, Option<NotificationStatus>)>(conn)
+                .expect("Error loading notifications")
+        })
+        .await
+    }
+
+    /// Finds a notification which is shown to the user, on any kind of client.
+    pub async fn find_by_uuid_and_user(uuid: &NotificationId, user_uuid: &UserId, conn: &DbConn) -> Option<Self> {
+        conn.run(move |conn| {
+            notifications::table
+                .filter(notifications::uuid.eq(uuid))
+                .filter(
+                    notifications::global.eq(true).nullable().or(notifications::user_uuid.eq(user_uuid)).or(
+                        notifications::organization_uuid.eq_any(
+                            users_organizations::table
+                                .filter(users_organizations::user_uuid.eq(user_uuid))
+                                .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
+                                .select(users_organizations::org_uuid.nullable()),
+                        ),
+                    ),
+                )
+                .first::<Self>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        // The statuses of the notifications targeted at the user can only belong to the user as well
+        NotificationStatus::delete_all_by_user(user_uuid, conn).await?;
+        conn.run(move |conn| {
+            diesel::delete(notifications::table.filter(notifications::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting notifications")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_organization(org_uuid: &OrganizationId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            let org_notifications = notifications::table.filter(notifications::organization_uuid.eq(org_uuid));
+            diesel::delete(notification_statuses::table.filter(
+                notification_statuses::notification_uuid.eq_any(org_notifications.select(notifications::uuid)),
+            ))
+            .execute(conn)?;
+
+            diesel::delete(org_notifications).execute(conn).map_res("Error deleting notifications")
+        })
+        .await
+    }
+}
+
+/// Database methods
+impl NotificationStatus {
+    pub async fn find_or_new(notification_uuid: &NotificationId, user_uuid: &UserId, conn: &DbConn) -> Self {
+        let status = conn
+            .run(move |conn| {
+                notification_statuses::table
+                    .filter(notification_statuses::notification_uuid.eq(notification_uuid))
+                    .filter(notification_statuses::user_uuid.eq(user_uuid))
+                    .first::<Self>(conn)
+                    .ok()
+            })
+            .await;
+
+        status.unwrap_or_else(|| Self {
+            notification_uuid: notification_uuid.clone(),
+            user_uuid: user_uuid.clone(),
+            read_date: None,
+            deleted_date: None,
+        })
+    }
+
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(notification_statuses::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving notification status")
+            }
+            postgresql {
+                diesel::insert_into(notification_statuses::table)
+                    .values(self)
+                    .on_conflict((notification_statuses::notification_uuid, notification_statuses::user_uuid))
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving notification status")
+            }
+        }
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(notification_statuses::table.filter(notification_statuses::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting notification statuses")
+        })
+        .await
+    }
+}
+
+#[derive(
+    Clone,
+    Debug,
+    AsRef,
+    Deref,
+    DieselNewType,
+    Display,
+    From,
+    FromForm,
+    Hash,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    UuidFromParam,
+)]
+pub struct NotificationId(String);
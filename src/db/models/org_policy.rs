@@ -13,7 +13,7 @@ use crate::{
     error::MapResult,
 };
 
-use super::{Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, UserId};
+use super::{Membership, MembershipId, MembershipStatus, MembershipType, OrganizationId, TwoFactor, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = org_policies)]
@@ -110,6 +110,8 @@ impl OrgPolicy {
 /// Database methods
 impl OrgPolicy {
     pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        self.update_members_revision(conn).await;
+
         db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(org_policies::table)
@@ -152,6 +154,8 @@ impl OrgPolicy {
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
+        self.update_members_revision(conn).await;
+
         conn.run(move |conn| {
             diesel::delete(org_policies::table.filter(org_policies::uuid.eq(self.uuid)))
                 .execute(conn)
@@ -160,6 +164,13 @@ impl OrgPolicy {
         .await
     }
 
+    /// The policies are part of the sync of the members, so it needs to be updated.
+    async fn update_members_revision(&self, conn: &DbConn) {
+        for member in Membership::find_by_org(&self.org_uuid, conn).await {
+            User::update_uuid_revision(&member.user_uuid, conn).await;
+        }
+    }
+
     pub async fn find_by_org(org_uuid: &OrganizationId, conn: &DbConn) -> Vec<Self> {
         conn.run(move |conn| {
             org_policies::table
@@ -24,8 +24,8 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Cipher, CipherId, Collection, CollectionGroup, CollectionId, CollectionUser, Group, GroupId, GroupUser, OrgPolicy,
-    OrgPolicyType, TwoFactor, User, UserId,
+    Cipher, CipherId, Collection, CollectionGroup, CollectionId, CollectionUser, DeletedItem, Group, GroupId,
+    GroupUser, Notification, OrgPolicy, OrgPolicyType, Tombstone, TombstoneType, TwoFactor, User, UserId,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -38,6 +38,10 @@ pub struct Organization {
     pub billing_email: String,
     pub private_key: Option<String>,
     pub public_key: Option<String>,
+    /// JSON list of equivalent domain groups, which are added to those of every member
+    pub equivalent_domains: String,
+    /// Days before trashed items are auto-deleted, overriding `TRASH_AUTO_DELETE_DAYS` (0 deletes them at the next purge)
+    pub trash_retention_days: Option<i32>,
 }
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
@@ -193,6 +197,8 @@ impl Organization {
             billing_email,
             private_key,
             public_key,
+            equivalent_domains: "[]".to_owned(),
+            trash_retention_days: None,
         }
     }
     // https://github.com/bitwarden/server/blob/9ebe16587175b1c0e9208f84397bb75d0d595510/src/Api/AdminConsole/Models/Response/Organizations/OrganizationResponseModel.cs
@@ -251,6 +257,30 @@ impl Organization {
             "object": "organization",
         })
     }
+
+    pub fn get_equivalent_domains(&self) -> Vec<Vec<String>> {
+        serde_json::from_str(&self.equivalent_domains).unwrap_or_default()
+    }
+
+    /// Stores the equivalent domain groups, lowercased and without duplicates.
+    /// Groups with fewer than two domains don't make anything equivalent and are dropped.
+    pub fn set_equivalent_domains(&mut self, groups: Vec<Vec<String>>) {
+        let groups: Vec<Vec<String>> = groups
+            .into_iter()
+            .map(|group| {
+                let mut domains: Vec<String> = Vec::with_capacity(group.len());
+                for domain in group {
+                    let domain = domain.trim().to_lowercase();
+                    if !domain.is_empty() && !domains.contains(&domain) {
+                        domains.push(domain);
+                    }
+                }
+                domains
+            })
+            .filter(|domains| domains.len() >= 2)
+            .collect();
+        self.equivalent_domains = serde_json::to_string(&groups).unwrap_or_else(|_| "[]".to_owned());
+    }
 }
 
 // Used to either subtract or add to the current status
@@ -322,6 +352,11 @@ impl Membership {
             _ => self.atype,
         }
     }
+
+    /// Limit collection creation to managers with access_all permission to prevent issues
+    pub fn limit_collection_creation(&self) -> bool {
+        self.atype < MembershipType::Manager || !self.access_all
+    }
 }
 
 impl OrganizationApiKey {
@@ -384,12 +419,14 @@ impl Organization {
     }
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
-        Cipher::delete_all_by_organization(&self.uuid, conn).await?;
+        Cipher::delete_all_by_deleted_organization(&self.uuid, conn).await?;
+        DeletedItem::delete_all_by_organization(&self.uuid, conn).await?;
         Collection::delete_all_by_organization(&self.uuid, conn).await?;
         Membership::delete_all_by_organization(&self.uuid, conn).await?;
         OrgPolicy::delete_all_by_organization(&self.uuid, conn).await?;
         Group::delete_all_by_organization(&self.uuid, conn).await?;
         OrganizationApiKey::delete_all_by_organization(&self.uuid, conn).await?;
+        Notification::delete_all_by_organization(&self.uuid, conn).await?;
 
         conn.run(move |conn| {
             diesel::delete(organizations::table.filter(organizations::uuid.eq(self.uuid)))
@@ -411,6 +448,21 @@ impl Organization {
         conn.run(move |conn| organizations::table.load::<Self>(conn).expect("Error loading organizations")).await
     }
 
+    /// Returns the organizations which override the global trash retention, with their retention in days.
+    pub async fn find_trash_retention_days(conn: &DbConn) -> HashMap<OrganizationId, i32> {
+        conn.run(move |conn| {
+            organizations::table
+                .filter(organizations::trash_retention_days.is_not_null())
+                .select((organizations::uuid, organizations::trash_retention_days))
+                .load::<(OrganizationId, Option<i32>)>(conn)
+                .expect("Error loading organizations")
+        })
+        .await
+        .into_iter()
+        .filter_map(|(uuid, days)| Some((uuid, days?)))
+        .collect()
+    }
+
     pub async fn find_main_org_user_email(user_email: &str, conn: &DbConn) -> Option<Self> {
         let lower_mail = user_email.to_lowercase();
 
@@ -428,6 +480,26 @@ impl Organization {
         .await
     }
 
+    /// The equivalent domain groups of all organizations the user is a confirmed member of.
+    pub async fn find_equivalent_domains_by_user(user_uuid: &UserId, conn: &DbConn) -> Vec<Vec<String>> {
+        let org_domains: Vec<String> = conn
+            .run(move |conn| {
+                organizations::table
+                    .inner_join(users_organizations::table.on(users_organizations::org_uuid.eq(organizations::uuid)))
+                    .filter(users_organizations::user_uuid.eq(user_uuid))
+                    .filter(users_organizations::status.eq(MembershipStatus::Confirmed as i32))
+                    .select(organizations::equivalent_domains)
+                    .load::<String>(conn)
+                    .expect("Error loading organization equivalent domains")
+            })
+            .await;
+
+        org_domains
+            .iter()
+            .flat_map(|domains| serde_json::from_str::<Vec<Vec<String>>>(domains).unwrap_or_default())
+            .collect()
+    }
+
     pub async fn find_org_user_email(user_email: &str, conn: &DbConn) -> Vec<Self> {
         let lower_mail = user_email.to_lowercase();
 
@@ -522,8 +594,7 @@ impl Membership {
             "familySponsorshipValidUntil": null,
             "familySponsorshipToDelete": null,
             "accessSecretsManager": false,
-            // limit collection creation to managers with access_all permission to prevent issues
-            "limitCollectionCreation": self.atype < MembershipType::Manager || !self.access_all,
+            "limitCollectionCreation": self.limit_collection_creation(),
             "limitCollectionDeletion": true,
             "limitItemDeletion": false,
             "allowAdminAccessToAllCollectionItems": true,
@@ -752,6 +823,14 @@ impl Membership {
     pub async fn save(&self, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(&self.user_uuid, conn).await;
 
+        // The ciphers of an organization the user got or lost access to aren't part of a delta sync
+        let unchanged = Self::find_by_uuid(&self.uuid, conn).await.is_some_and(|member| {
+            member.status == self.status && member.atype == self.atype && member.access_all == self.access_all
+        });
+        if !unchanged {
+            Tombstone::create(std::slice::from_ref(&self.user_uuid), TombstoneType::Access, &self.uuid, conn).await;
+        }
+
         db_run! { conn:
             sqlite, mysql {
                 match diesel::replace_into(users_organizations::table)
@@ -784,6 +863,9 @@ impl Membership {
 
     pub async fn delete(self, conn: &DbConn) -> EmptyResult {
         User::update_uuid_revision(&self.user_uuid, conn).await;
+        Tombstone::create(std::slice::from_ref(&self.user_uuid), TombstoneType::Organization, &self.org_uuid, conn)
+            .await;
+        Tombstone::create(std::slice::from_ref(&self.user_uuid), TombstoneType::Access, &self.uuid, conn).await;
 
         CollectionUser::delete_all_by_user_and_org(&self.user_uuid, &self.org_uuid, conn).await?;
         GroupUser::delete_all_by_member(&self.uuid, conn).await?;
@@ -1285,4 +1367,14 @@ mod tests {
         assert!(MembershipType::Manager > MembershipType::User);
         assert!(MembershipType::Manager == MembershipType::from_str("4").unwrap());
     }
+
+    #[test]
+    fn equivalent_domains_are_normalized() {
+        let mut org = Organization::new(String::from("Org"), "org@example.com", None, None);
+        org.set_equivalent_domains(vec![
+            vec![String::from(" Example.com"), String::from("example.net"), String::from("EXAMPLE.COM")],
+            vec![String::from("single.org"), String::new()],
+        ]);
+        assert_eq!(org.get_equivalent_domains(), vec![vec![String::from("example.com"), String::from("example.net")]]);
+    }
 }
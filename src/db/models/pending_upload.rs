@@ -0,0 +1,106 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use super::{Device, DeviceId, UserId};
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::pending_uploads},
+    error::MapResult,
+};
+
+/// An attachment or Send file created through the v2 API, whose content hasn't been received yet.
+/// Files uploaded through a presigned URL never pass through the server, so these are checked by the pending
+/// uploads job, which also removes the records whose content never arrived.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = pending_uploads)]
+#[diesel(primary_key(uuid))]
+pub struct PendingUpload {
+    /// The id of the attachment, or the uuid of the Send
+    pub uuid: String,
+    pub atype: i32,
+    pub user_uuid: UserId,
+    /// The device and address which created the record, used for the notifications and events once it completes
+    pub device_uuid: DeviceId,
+    pub device_type: i32,
+    pub ip_address: String,
+    /// Whether the client was told to upload the content through a presigned URL
+    pub presigned: bool,
+    pub expires_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PendingUploadType {
+    Attachment = 0,
+    Send = 1,
+}
+
+/// Local methods
+impl PendingUpload {
+    /// How long the client has to upload the content, counted from the creation or the last renewal of the upload URL.
+    pub const EXPIRY: TimeDelta = TimeDelta::hours(24);
+
+    pub fn new(uuid: String, atype: PendingUploadType, device: &Device, ip_address: String, presigned: bool) -> Self {
+        Self {
+            uuid,
+            atype: atype as i32,
+            user_uuid: device.user_uuid.clone(),
+            device_uuid: device.uuid.clone(),
+            device_type: device.atype,
+            ip_address,
+            presigned,
+            expires_at: Utc::now().naive_utc() + Self::EXPIRY,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now().naive_utc()
+    }
+}
+
+/// Database methods
+impl PendingUpload {
+    /// Creates the record, or restarts its expiry when the upload URL was renewed.
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            sqlite, mysql {
+                diesel::replace_into(pending_uploads::table)
+                    .values(self)
+                    .execute(conn)
+                    .map_res("Error saving pending upload")
+            }
+            postgresql {
+                diesel::insert_into(pending_uploads::table)
+                    .values(self)
+                    .on_conflict(pending_uploads::uuid)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving pending upload")
+            }
+        }
+    }
+
+    /// Removes the record once the content has been received, or when the upload was given up.
+    pub async fn delete(uuid: &str, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(pending_uploads::table.filter(pending_uploads::uuid.eq(uuid)))
+                .execute(conn)
+                .map_res("Error deleting pending upload")
+        })
+        .await
+    }
+
+    pub async fn find_all(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| pending_uploads::table.load::<Self>(conn).expect("Error loading pending uploads")).await
+    }
+
+    pub async fn find_all_uuids(conn: &DbConn) -> Vec<String> {
+        conn.run(move |conn| {
+            pending_uploads::table
+                .select(pending_uploads::uuid)
+                .load::<String>(conn)
+                .expect("Error loading pending uploads")
+        })
+        .await
+    }
+}
@@ -0,0 +1,127 @@
+use diesel::prelude::*;
+
+use crate::{
+    db::{DbConn, DbConnInner, schema::ratelimits},
+    error::Error,
+};
+
+/// Rate limiter state shared between instances, using the same GCRA algorithm as the in-memory limiter.
+/// Every key stores its theoretical arrival time (TAT) in milliseconds since the epoch:
+/// a request is allowed as long as the TAT isn't more than `tolerance` ahead of the current time,
+/// and every allowed request moves the TAT forward by `period`.
+pub struct RateLimit;
+
+impl RateLimit {
+    /// Returns whether a request for `key` is allowed, and records it if it is.
+    /// Every statement checks and updates the state atomically, so concurrent requests can't exceed the limit.
+    pub async fn check(key: &str, now: i64, period: i64, tolerance: i64, conn: &DbConn) -> Result<bool, Error> {
+        conn.run(move |conn| Self::check_blocking(key, now, period, tolerance, conn)).await
+    }
+
+    fn check_blocking(key: &str, now: i64, period: i64, tolerance: i64, conn: &mut DbConnInner) -> Result<bool, Error> {
+        let next = now.saturating_add(period);
+        let limit = now.saturating_add(tolerance);
+
+        // The TAT is in the past, start over from the current time
+        let updated =
+            diesel::update(ratelimits::table.filter(ratelimits::limit_key.eq(key)).filter(ratelimits::tat.lt(now)))
+                .set(ratelimits::tat.eq(next))
+                .execute(conn)?;
+        if updated > 0 {
+            return Ok(true);
+        }
+
+        // Still within the allowed burst
+        let updated = diesel::update(
+            ratelimits::table
+                .filter(ratelimits::limit_key.eq(key))
+                .filter(ratelimits::tat.ge(now))
+                .filter(ratelimits::tat.le(limit)),
+        )
+        .set(ratelimits::tat.eq(ratelimits::tat + period))
+        .execute(conn)?;
+        if updated > 0 {
+            return Ok(true);
+        }
+
+        // Either the first request for this key, or the key exists and is over the limit
+        match diesel::insert_into(ratelimits::table)
+            .values((ratelimits::limit_key.eq(key), ratelimits::tat.eq(next)))
+            .execute(conn)
+        {
+            Ok(_) => {
+                // Keys whose TAT has passed are in the same state as keys which don't exist
+                diesel::delete(ratelimits::table.filter(ratelimits::tat.lt(now))).execute(conn)?;
+                Ok(true)
+            }
+            Err(diesel::result::Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _)) => {
+                Ok(false)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use diesel::{Connection, connection::SimpleConnection, sqlite::SqliteConnection};
+
+    use super::*;
+
+    const PERIOD: i64 = 60_000;
+    const TOLERANCE: i64 = 2 * PERIOD; // A burst of 3 requests
+
+    fn test_conn() -> DbConnInner {
+        let mut conn = SqliteConnection::establish(":memory:").unwrap();
+        conn.batch_execute(include_str!("../../../migrations/sqlite/2026-06-10-120000_add_ratelimits/up.sql")).unwrap();
+        DbConnInner::Sqlite(conn)
+    }
+
+    fn check(key: &str, now: i64, conn: &mut DbConnInner) -> bool {
+        RateLimit::check_blocking(key, now, PERIOD, TOLERANCE, conn).unwrap()
+    }
+
+    #[test]
+    fn allows_the_burst_then_limits() {
+        let conn = &mut test_conn();
+        let now = 1_000_000;
+
+        assert!(check("login:a", now, conn));
+        assert!(check("login:a", now, conn));
+        assert!(check("login:a", now, conn));
+        assert!(!check("login:a", now, conn));
+
+        // Other keys have their own limit
+        assert!(check("login:b", now, conn));
+
+        // One request is allowed again per period
+        assert!(!check("login:a", now + PERIOD - 1, conn));
+        assert!(check("login:a", now + PERIOD, conn));
+        assert!(!check("login:a", now + PERIOD, conn));
+    }
+
+    #[test]
+    fn starts_over_once_the_limit_has_passed() {
+        let conn = &mut test_conn();
+        let now = 1_000_000;
+        for _ in 0..3 {
+            assert!(check("login:a", now, conn));
+        }
+
+        let later = now + 10 * PERIOD;
+        for _ in 0..3 {
+            assert!(check("login:a", later, conn));
+        }
+        assert!(!check("login:a", later, conn));
+    }
+
+    #[test]
+    fn removes_passed_keys() {
+        let conn = &mut test_conn();
+        assert!(check("login:a", 1_000_000, conn));
+        assert!(check("login:b", 1_000_000 + 10 * PERIOD, conn));
+
+        let keys: Vec<String> = ratelimits::table.select(ratelimits::limit_key).load(conn).unwrap();
+        assert_eq!(keys, vec!["login:b"]);
+    }
+}
@@ -17,7 +17,7 @@ use crate::{
     util::{LowerCase, NumberOrString, format_date},
 };
 
-use super::{OrganizationId, User, UserId};
+use super::{OrganizationId, Tombstone, TombstoneType, User, UserId};
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset)]
 #[diesel(table_name = sends)]
@@ -173,6 +173,16 @@ impl Send {
         })
     }
 
+    /// The id of the stored file of a file Send, as saved in its data.
+    pub fn file_id(&self) -> Option<String> {
+        if self.atype != SendType::File as i32 {
+            return None;
+        }
+
+        let data = serde_json::from_str::<LowerCase<Value>>(&self.data).map(|d| d.data).ok()?;
+        data.get("id").and_then(Value::as_str).map(str::to_owned)
+    }
+
     pub async fn to_json_access(&self, conn: &DbConn) -> Value {
         let mut data = serde_json::from_str::<LowerCase<Value>>(&self.data).map(|d| d.data).unwrap_or_default();
 
@@ -279,7 +289,8 @@ impl Send {
     }
 
     pub async fn delete(&self, conn: &DbConn) -> EmptyResult {
-        self.update_users_revision(conn).await;
+        let user_uuids = self.update_users_revision(conn).await;
+        Tombstone::create(&user_uuids, TombstoneType::Send, &self.uuid, conn).await;
 
         if self.atype == SendType::File as i32 {
             let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
@@ -293,10 +304,15 @@ impl Send {
     }
 
     /// Purge all sends that are past their deletion date.
-    pub async fn purge(conn: &DbConn) {
+    /// Deletes the sends which are past their deletion date and returns how many were deleted.
+    pub async fn purge(conn: &DbConn) -> usize {
+        let mut deleted = 0;
         for send in Self::find_by_past_deletion_date(conn).await {
-            send.delete(conn).await.ok();
+            if send.delete(conn).await.is_ok() {
+                deleted += 1;
+            }
         }
+        deleted
     }
 
     pub async fn update_users_revision(&self, conn: &DbConn) -> Vec<UserId> {
@@ -376,6 +392,13 @@ impl Send {
         .await
     }
 
+    pub async fn find_all_files(conn: &DbConn) -> Vec<Self> {
+        conn.run(move |conn| {
+            sends::table.filter(sends::atype.eq(SendType::File as i32)).load::<Self>(conn).expect("Error loading sends")
+        })
+        .await
+    }
+
     pub async fn find_by_past_deletion_date(conn: &DbConn) -> Vec<Self> {
         let now = Utc::now().naive_utc();
         conn.run(move |conn| {
@@ -0,0 +1,274 @@
+use chrono::{NaiveDateTime, TimeDelta, Utc};
+use diesel::prelude::*;
+
+use super::UserId;
+use crate::{
+    api::EmptyResult,
+    db::{DbConn, schema::tombstones},
+    error::MapResult,
+    util::get_uuid,
+};
+
+/// Remembers that an object was deleted, so a delta sync can tell the user's clients to remove it.
+#[derive(Identifiable, Queryable, Insertable)]
+#[diesel(table_name = tombstones)]
+#[diesel(primary_key(uuid))]
+pub struct Tombstone {
+    pub uuid: String,
+    pub user_uuid: UserId,
+    pub object_type: i32,
+    pub object_uuid: String,
+    pub deleted_at: NaiveDateTime,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum TombstoneType {
+    Cipher = 0,
+    Folder = 1,
+    Send = 2,
+    Collection = 3,
+    /// The user is no longer a member of the organization
+    Organization = 4,
+    /// The access of the user to an organization changed, like a collection or group they were removed from, or
+    /// changed permissions. The affected ciphers aren't tracked, so the next sync of the user has to be a full one.
+    /// The object is the membership, collection or group whose access changed.
+    Access = 5,
+}
+
+/// Local methods
+impl Tombstone {
+    /// How long tombstones are kept. Clients which didn't sync for longer need to do a full sync.
+    pub const RETENTION: TimeDelta = TimeDelta::days(30);
+}
+
+/// Database methods
+impl Tombstone {
+    pub async fn create(user_uuids: &[UserId], object_type: TombstoneType, object_uuid: &str, conn: &DbConn) {
+        if user_uuids.is_empty() {
+            return;
+        }
+
+        let now = Utc::now().naive_utc();
+        let tombstones: Vec<Self> = user_uuids
+            .iter()
+            .map(|user_uuid| Self {
+                uuid: get_uuid(),
+                user_uuid: user_uuid.clone(),
+                object_type: object_type as i32,
+                object_uuid: object_uuid.to_owned(),
+                deleted_at: now,
+            })
+            .collect();
+
+        let res = conn
+            .run(move |conn| {
+                for tombstone in &tombstones {
+                    diesel::insert_into(tombstones::table).values(tombstone).execute(conn)?;
+                }
+                Ok::<(), diesel::result::Error>(())
+            })
+            .await;
+        if let Err(e) = res {
+            warn!("Failed to record the deletion of {object_uuid}: {e:#?}");
+        }
+    }
+
+    /// Finds the objects of the type which were deleted for the user since the given time.
+    pub async fn find_object_uuids_since(
+        user_uuid: &UserId,
+        object_type: TombstoneType,
+        since: &NaiveDateTime,
+        conn: &DbConn,
+    ) -> Vec<String> {
+        conn.run(move |conn| {
+            tombstones::table
+                .filter(tombstones::user_uuid.eq(user_uuid))
+                .filter(tombstones::object_type.eq(object_type as i32))
+                .filter(tombstones::deleted_at.ge(since))
+                .select(tombstones::object_uuid)
+                .distinct()
+                .load::<String>(conn)
+                .expect("Error loading tombstones")
+        })
+        .await
+    }
+
+    /// Whether the access of the user changed since the given time, see `TombstoneType::Access`.
+    pub async fn access_changed_since(user_uuid: &UserId, since: &NaiveDateTime, conn: &DbConn) -> bool {
+        conn.run(move |conn| {
+            tombstones::table
+                .filter(tombstones::user_uuid.eq(user_uuid))
+                .filter(tombstones::object_type.eq(TombstoneType::Access as i32))
+                .filter(tombstones::deleted_at.ge(since))
+                .count()
+                .first::<i64>(conn)
+                .unwrap_or(0)
+                != 0
+        })
+        .await
+    }
+
+    /// Forgets the deletion of an object which got restored.
+    pub async fn delete_by_object(object_uuid: &str, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(tombstones::table.filter(tombstones::object_uuid.eq(object_uuid)))
+                .execute(conn)
+                .map_res("Error deleting tombstones")
+        })
+        .await
+    }
+
+    pub async fn delete_all_by_user(user_uuid: &UserId, conn: &DbConn) -> EmptyResult {
+        conn.run(move |conn| {
+            diesel::delete(tombstones::table.filter(tombstones::user_uuid.eq(user_uuid)))
+                .execute(conn)
+                .map_res("Error deleting tombstones")
+        })
+        .await
+    }
+
+    /// Deletes the tombstones which are older than the retention and returns how many were deleted.
+    pub async fn purge(conn: &DbConn) -> usize {
+        let dt = Utc::now().naive_utc() - Self::RETENTION;
+        conn.run(move |conn| {
+            diesel::delete(tombstones::table.filter(tombstones::deleted_at.lt(dt))).execute(conn).unwrap_or_default()
+        })
+        .await
+    }
+}
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+    use crate::db::{
+        DbPool,
+        models::{
+            Cipher, Collection, CollectionCipher, CollectionGroup, CollectionUser, Group, GroupUser, Membership,
+            MembershipStatus, Organization, User,
+        },
+    };
+
+    async fn member(org: &Organization, email: &str, conn: &DbConn) -> Membership {
+        let mut user = User::new(email, None);
+        user.save(conn).await.unwrap();
+        let mut member = Membership::new(user.uuid, org.uuid.clone(), None);
+        member.status = MembershipStatus::Confirmed as i32;
+        member.save(conn).await.unwrap();
+        member
+    }
+
+    async fn access_changed(member: &Membership, since: &NaiveDateTime, conn: &DbConn) -> bool {
+        Tombstone::access_changed_since(&member.user_uuid, since, conn).await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn changed_collection_access_needs_a_full_sync() {
+        let pool = DbPool::for_tests("tombstones-collections");
+        let conn = &pool.get().await.unwrap();
+
+        let org = Organization::new(String::from("org"), "org@example.com", None, None);
+        org.save(conn).await.unwrap();
+        let alice = member(&org, "alice@example.com", conn).await;
+        let bob = member(&org, "bob@example.com", conn).await;
+        let collection = Collection::new(org.uuid.clone(), String::from("collection"), None);
+        collection.save(conn).await.unwrap();
+
+        let since = Utc::now().naive_utc();
+        CollectionUser::save(&alice.user_uuid, &collection.uuid, false, false, false, conn).await.unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+        assert!(!access_changed(&bob, &since, conn).await);
+
+        // Saving the same permissions again doesn't change anything
+        let since = Utc::now().naive_utc();
+        CollectionUser::save(&alice.user_uuid, &collection.uuid, false, false, false, conn).await.unwrap();
+        assert!(!access_changed(&alice, &since, conn).await);
+
+        let revision = User::find_by_uuid(&alice.user_uuid, conn).await.unwrap().updated_at;
+        CollectionUser::save(&alice.user_uuid, &collection.uuid, true, true, false, conn).await.unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+        assert!(User::find_by_uuid(&alice.user_uuid, conn).await.unwrap().updated_at > revision);
+
+        let since = Utc::now().naive_utc();
+        CollectionUser::find_by_collection_and_user(&collection.uuid, &alice.user_uuid, conn)
+            .await
+            .unwrap()
+            .delete(conn)
+            .await
+            .unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn changed_group_and_membership_access_needs_a_full_sync() {
+        let pool = DbPool::for_tests("tombstones-groups");
+        let conn = &pool.get().await.unwrap();
+
+        let org = Organization::new(String::from("org"), "org@example.com", None, None);
+        org.save(conn).await.unwrap();
+        let alice = member(&org, "alice@example.com", conn).await;
+        let bob = member(&org, "bob@example.com", conn).await;
+        let collection = Collection::new(org.uuid.clone(), String::from("collection"), None);
+        collection.save(conn).await.unwrap();
+        let mut group = Group::new(org.uuid.clone(), String::from("group"), false, None);
+        group.save(conn).await.unwrap();
+        GroupUser::new(group.uuid.clone(), alice.uuid.clone()).save(conn).await.unwrap();
+
+        let since = Utc::now().naive_utc();
+        let mut collection_group =
+            CollectionGroup::new(collection.uuid.clone(), group.uuid.clone(), false, false, false);
+        collection_group.save(&org.uuid, conn).await.unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+        assert!(!access_changed(&bob, &since, conn).await);
+
+        let since = Utc::now().naive_utc();
+        GroupUser::delete_by_group_and_member(&group.uuid, &alice.uuid, conn).await.unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+
+        // Revoking and removing a member both change their access
+        let since = Utc::now().naive_utc();
+        let mut revoked = Membership::find_by_uuid(&bob.uuid, conn).await.unwrap();
+        revoked.revoke();
+        revoked.save(conn).await.unwrap();
+        assert!(access_changed(&bob, &since, conn).await);
+
+        let since = Utc::now().naive_utc();
+        Membership::find_by_uuid(&alice.uuid, conn).await.unwrap().delete(conn).await.unwrap();
+        assert!(access_changed(&alice, &since, conn).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn removing_a_cipher_from_a_collection_deletes_it_for_the_users_who_lost_access() {
+        let pool = DbPool::for_tests("tombstones-ciphers");
+        let conn = &pool.get().await.unwrap();
+
+        let org = Organization::new(String::from("org"), "org@example.com", None, None);
+        org.save(conn).await.unwrap();
+        let alice = member(&org, "alice@example.com", conn).await;
+        let bob = member(&org, "bob@example.com", conn).await;
+        let first = Collection::new(org.uuid.clone(), String::from("first"), None);
+        first.save(conn).await.unwrap();
+        let second = Collection::new(org.uuid.clone(), String::from("second"), None);
+        second.save(conn).await.unwrap();
+        CollectionUser::save(&alice.user_uuid, &first.uuid, false, false, false, conn).await.unwrap();
+        CollectionUser::save(&bob.user_uuid, &first.uuid, false, false, false, conn).await.unwrap();
+        CollectionUser::save(&bob.user_uuid, &second.uuid, false, false, false, conn).await.unwrap();
+
+        let mut cipher = Cipher::new(1, String::from("cipher"));
+        cipher.organization_uuid = Some(org.uuid.clone());
+        cipher.save(conn).await.unwrap();
+        CollectionCipher::save(&cipher.uuid, &first.uuid, conn).await.unwrap();
+        CollectionCipher::save(&cipher.uuid, &second.uuid, conn).await.unwrap();
+
+        let since = Utc::now().naive_utc();
+        CollectionCipher::delete(&cipher.uuid, &first.uuid, conn).await.unwrap();
+
+        assert_eq!(
+            Tombstone::find_object_uuids_since(&alice.user_uuid, TombstoneType::Cipher, &since, conn).await,
+            [cipher.uuid.to_string()]
+        );
+        // Bob still sees the cipher through the second collection
+        assert!(
+            Tombstone::find_object_uuids_since(&bob.user_uuid, TombstoneType::Cipher, &since, conn).await.is_empty()
+        );
+    }
+}
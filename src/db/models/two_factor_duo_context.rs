@@ -68,9 +68,13 @@ impl TwoFactorDuoContext {
         .await
     }
 
-    pub async fn purge_expired_duo_contexts(conn: &DbConn) {
+    pub async fn purge_expired_duo_contexts(conn: &DbConn) -> usize {
+        let mut deleted = 0;
         for context in Self::find_expired(conn).await {
-            context.delete(conn).await.ok();
+            if context.delete(conn).await.is_ok() {
+                deleted += 1;
+            }
         }
+        deleted
     }
 }
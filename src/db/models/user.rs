@@ -10,7 +10,7 @@ use crate::{
     db::{
         DbConn,
         models::DeviceId,
-        schema::{invitations, sso_users, twofactor_incomplete, users},
+        schema::{devices, invitations, sso_users, twofactor, twofactor_incomplete, user_languages, users},
     },
     error::MapResult,
     sso::OIDCIdentifier,
@@ -19,7 +19,8 @@ use crate::{
 use macros::UuidFromParam;
 
 use super::{
-    Cipher, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, TwoFactor, TwoFactorIncomplete,
+    Cipher, DeletedItem, Device, EmergencyAccess, Favorite, Folder, Membership, MembershipType, Notification,
+    Tombstone, TwoFactor, TwoFactorIncomplete,
 };
 
 #[derive(Identifiable, Queryable, Insertable, AsChangeset, Selectable)]
@@ -86,6 +87,15 @@ pub struct SsoUser {
     pub identifier: OIDCIdentifier,
 }
 
+/// The preferred language of a user, used for the emails sent to them.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = user_languages)]
+#[diesel(primary_key(user_uuid))]
+pub struct UserLanguage {
+    pub user_uuid: UserId,
+    pub language: String,
+}
+
 pub enum UserKdfType {
     Pbkdf2 = 0,
     Argon2id = 1,
@@ -260,6 +270,7 @@ impl User {
         }
 
         let twofactor_enabled = !TwoFactor::find_by_user(&self.uuid, conn).await.is_empty();
+        let culture = UserLanguage::find_by_user(&self.uuid, conn).await.unwrap_or_else(|| String::from("en-US"));
 
         // TODO: Might want to save the status field in the DB
         let status = if self.password_hash.is_empty() {
@@ -293,7 +304,7 @@ impl User {
             "emailVerified": !CONFIG.mail_enabled() || self.verified_at.is_some(),
             "premium": true,
             "premiumFromOrganization": false,
-            "culture": "en-US",
+            "culture": culture,
             "twoFactorEnabled": twofactor_enabled,
             "key": self.akey,
             "privateKey": self.private_key,
@@ -315,6 +326,7 @@ impl User {
         }
 
         self.updated_at = Utc::now().naive_utc();
+        crate::api::invalidate_sync_cache(&self.uuid);
 
         db_run! { conn:
             mysql {
@@ -354,10 +366,13 @@ impl User {
         Cipher::delete_all_by_user(&self.uuid, conn).await?;
         Favorite::delete_all_by_user(&self.uuid, conn).await?;
         Folder::delete_all_by_user(&self.uuid, conn).await?;
+        DeletedItem::delete_all_by_user(&self.uuid, conn).await?;
         Device::delete_all_by_user(&self.uuid, conn).await?;
         TwoFactor::delete_all_by_user(&self.uuid, conn).await?;
         TwoFactorIncomplete::delete_all_by_user(&self.uuid, conn).await?;
+        Notification::delete_all_by_user(&self.uuid, conn).await?;
         Invitation::take(&self.email, conn).await; // Delete invitation if any
+        Tombstone::delete_all_by_user(&self.uuid, conn).await?;
 
         conn.run(move |conn| {
             diesel::delete(users::table.filter(users::uuid.eq(self.uuid))).execute(conn).map_res("Error deleting user")
@@ -388,6 +403,7 @@ impl User {
     }
 
     async fn update_revision_impl(uuid: &UserId, date: &NaiveDateTime, conn: &DbConn) -> EmptyResult {
+        crate::api::invalidate_sync_cache(uuid);
         conn.run(move |conn| {
             retry(
                 || {
@@ -441,6 +457,26 @@ impl User {
         .await
     }
 
+    /// Loads all the users with their SSO link, when one of their devices was last active and whether they have 2FA.
+    pub async fn get_all_with_activity(conn: &DbConn) -> Vec<(Self, Option<SsoUser>, Option<NaiveDateTime>, bool)> {
+        conn.run(move |conn| {
+            let last_active = devices::table
+                .filter(devices::user_uuid.eq(users::uuid))
+                .select(diesel::dsl::max(devices::updated_at))
+                .single_value();
+            // Types of 1000 and above are implementation details, like the challenges of a login
+            let two_factor = diesel::dsl::exists(
+                twofactor::table.filter(twofactor::user_uuid.eq(users::uuid)).filter(twofactor::atype.lt(1000)),
+            );
+            users::table
+                .left_join(sso_users::table)
+                .select((Self::as_select(), Option::<SsoUser>::as_select(), last_active, two_factor))
+                .load(conn)
+                .expect("Error loading users")
+        })
+        .await
+    }
+
     pub async fn last_active(&self, conn: &DbConn) -> Option<NaiveDateTime> {
         match Device::find_latest_active_by_user(&self.uuid, conn).await {
             Some(device) => Some(device.updated_at),
@@ -578,3 +614,122 @@ impl SsoUser {
         .await
     }
 }
+
+impl UserLanguage {
+    pub fn new(user_uuid: UserId, language: String) -> Self {
+        Self {
+            user_uuid,
+            language,
+        }
+    }
+
+    /// Stores the preferred language, without changing the revision of the user.
+    pub async fn save(&self, conn: &DbConn) -> EmptyResult {
+        db_run! { conn:
+            mysql {
+                diesel::insert_into(user_languages::table)
+                    .values(self)
+                    .on_conflict(diesel::dsl::DuplicatedKeys)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving user language")
+            }
+            postgresql, sqlite {
+                diesel::insert_into(user_languages::table)
+                    .values(self)
+                    .on_conflict(user_languages::user_uuid)
+                    .do_update()
+                    .set(self)
+                    .execute(conn)
+                    .map_res("Error saving user language")
+            }
+        }
+    }
+
+    pub async fn find_by_user(user_uuid: &UserId, conn: &DbConn) -> Option<String> {
+        conn.run(move |conn| {
+            user_languages::table
+                .filter(user_languages::user_uuid.eq(user_uuid))
+                .select(user_languages::language)
+                .first::<String>(conn)
+                .ok()
+        })
+        .await
+    }
+
+    pub async fn find_by_mail(mail: &str, conn: &DbConn) -> Option<String> {
+        let lower_mail = mail.to_lowercase();
+        conn.run(move |conn| {
+            user_languages::table
+                .inner_join(users::table)
+                .filter(users::email.eq(lower_mail))
+                .select(user_languages::language)
+                .first::<String>(conn)
+                .ok()
+        })
+        .await
+    }
+}
+
+#[cfg(all(test, sqlite))]
+mod tests {
+    use super::*;
+    use crate::db::{
+        DbPool,
+        models::{DeviceId, TwoFactorType},
+    };
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stores_the_language_of_users_apart() {
+        let pool = DbPool::for_tests("user-languages");
+        let conn = &pool.get().await.unwrap();
+
+        let mut user = User::new("Lang@example.com", None);
+        user.save(conn).await.unwrap();
+        assert_eq!(UserLanguage::find_by_mail("lang@example.com", conn).await, None);
+
+        UserLanguage::new(user.uuid.clone(), String::from("de")).save(conn).await.unwrap();
+        UserLanguage::new(user.uuid.clone(), String::from("pt-BR")).save(conn).await.unwrap();
+        assert_eq!(UserLanguage::find_by_mail("LANG@example.com", conn).await.as_deref(), Some("pt-BR"));
+
+        // Saving the user again keeps the language
+        user.save(conn).await.unwrap();
+        assert_eq!(UserLanguage::find_by_user(&user.uuid, conn).await.as_deref(), Some("pt-BR"));
+
+        let user_uuid = user.uuid.clone();
+        user.delete(conn).await.unwrap();
+        assert_eq!(UserLanguage::find_by_user(&user_uuid, conn).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn loads_the_activity_of_all_users_at_once() {
+        let pool = DbPool::for_tests("user-activity");
+        let conn = &pool.get().await.unwrap();
+
+        let mut idle = User::new("idle@example.com", None);
+        idle.save(conn).await.unwrap();
+        // Challenges don't count as 2FA
+        TwoFactor::new(idle.uuid.clone(), TwoFactorType::EmailVerificationChallenge, String::new())
+            .save(conn)
+            .await
+            .unwrap();
+
+        let mut active = User::new("active@example.com", None);
+        active.save(conn).await.unwrap();
+        for name in ["laptop", "phone"] {
+            let mut device = Device::new(DeviceId::from(get_uuid()), active.uuid.clone(), name.to_owned(), 8);
+            device.save(true, conn).await.unwrap();
+        }
+        TwoFactor::new(active.uuid.clone(), TwoFactorType::Authenticator, String::new()).save(conn).await.unwrap();
+
+        let users = User::get_all_with_activity(conn).await;
+        assert_eq!(users.len(), 2);
+        for (user, sso_user, last_active, two_factor) in users {
+            assert!(sso_user.is_none());
+            assert_eq!(last_active, user.last_active(conn).await);
+            assert_eq!(last_active.is_some(), user.uuid == active.uuid);
+            assert_eq!(two_factor, user.uuid == active.uuid);
+        }
+    }
+}
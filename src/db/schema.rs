@@ -5,6 +5,7 @@ table! {
         file_name -> Text,
         file_size -> BigInt,
         akey -> Nullable<Text>,
+        checksum -> Nullable<Text>,
     }
 }
 
@@ -27,6 +28,24 @@ table! {
     }
 }
 
+table! {
+    cipher_revisions (uuid) {
+        uuid -> Text,
+        cipher_uuid -> Text,
+        revision_date -> Timestamp,
+        created_at -> Timestamp,
+        atype -> Integer,
+        name -> Text,
+        notes -> Nullable<Text>,
+        fields -> Nullable<Text>,
+        data -> Text,
+        key -> Nullable<Text>,
+        password_history -> Nullable<Text>,
+        reprompt -> Nullable<Integer>,
+        attachments -> Nullable<Text>,
+    }
+}
+
 table! {
     ciphers_collections (cipher_uuid, collection_uuid) {
         cipher_uuid -> Text,
@@ -43,6 +62,19 @@ table! {
     }
 }
 
+table! {
+    deleted_items (uuid) {
+        uuid -> Text,
+        object_type -> Integer,
+        object_uuid -> Text,
+        user_uuid -> Nullable<Text>,
+        organization_uuid -> Nullable<Text>,
+        created_at -> Timestamp,
+        deleted_at -> Timestamp,
+        data -> Text,
+    }
+}
+
 table! {
     devices (uuid, user_uuid) {
         uuid -> Text,
@@ -126,6 +158,8 @@ table! {
         billing_email -> Text,
         private_key -> Nullable<Text>,
         public_key -> Nullable<Text>,
+        equivalent_domains -> Text,
+        trash_retention_days -> Nullable<Integer>,
     }
 }
 
@@ -270,6 +304,13 @@ table! {
     }
 }
 
+table! {
+    user_languages (user_uuid) {
+        user_uuid -> Text,
+        language -> Text,
+    }
+}
+
 table! {
     sso_users (user_uuid) {
         user_uuid -> Text,
@@ -351,8 +392,115 @@ table! {
     }
 }
 
+table! {
+    job_runs (uuid) {
+        uuid -> Text,
+        job_name -> Text,
+        scheduled_at -> Timestamp,
+        instance -> Text,
+        started_at -> Timestamp,
+        finished_at -> Nullable<Timestamp>,
+        result -> Nullable<Text>,
+        rows_affected -> Nullable<BigInt>,
+        details -> Nullable<Text>,
+    }
+}
+
+table! {
+    ratelimits (limit_key) {
+        limit_key -> Text,
+        tat -> BigInt,
+    }
+}
+
+table! {
+    emergency_access_quorum_members (emergency_access_uuid) {
+        emergency_access_uuid -> Text,
+        grantor_uuid -> Text,
+    }
+}
+
+table! {
+    notifications (uuid) {
+        uuid -> Text,
+        priority -> Integer,
+        global -> Bool,
+        client_type -> Integer,
+        user_uuid -> Nullable<Text>,
+        organization_uuid -> Nullable<Text>,
+        title -> Nullable<Text>,
+        body -> Nullable<Text>,
+        creation_date -> Timestamp,
+        revision_date -> Timestamp,
+    }
+}
+
+table! {
+    notification_statuses (notification_uuid, user_uuid) {
+        notification_uuid -> Text,
+        user_uuid -> Text,
+        read_date -> Nullable<Timestamp>,
+        deleted_date -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    mail_queue (uuid) {
+        uuid -> Text,
+        recipient -> Text,
+        subject -> Text,
+        body_html -> Text,
+        body_text -> Text,
+        attempts -> Integer,
+        last_error -> Nullable<Text>,
+        created_at -> Timestamp,
+        next_attempt_at -> Timestamp,
+        expires_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    emergency_access_quorums (grantor_uuid) {
+        grantor_uuid -> Text,
+        quorum -> Integer,
+    }
+}
+
+table! {
+    icon_domains (domain) {
+        domain -> Text,
+        created_at -> Timestamp,
+        refreshed_at -> Nullable<Timestamp>,
+        last_error -> Nullable<Text>,
+    }
+}
+
+table! {
+    tombstones (uuid) {
+        uuid -> Text,
+        user_uuid -> Text,
+        object_type -> Integer,
+        object_uuid -> Text,
+        deleted_at -> Timestamp,
+    }
+}
+
+table! {
+    pending_uploads (uuid) {
+        uuid -> Text,
+        atype -> Integer,
+        user_uuid -> Text,
+        device_uuid -> Text,
+        device_type -> Integer,
+        ip_address -> Text,
+        presigned -> Bool,
+        expires_at -> Timestamp,
+    }
+}
+
 joinable!(archives -> users (user_uuid));
 joinable!(archives -> ciphers (cipher_uuid));
+joinable!(cipher_revisions -> ciphers (cipher_uuid));
 joinable!(attachments -> ciphers (cipher_uuid));
 joinable!(ciphers -> organizations (organization_uuid));
 joinable!(ciphers -> users (user_uuid));
@@ -382,10 +530,14 @@ joinable!(collections_groups -> groups (groups_uuid));
 joinable!(event -> users_organizations (uuid));
 joinable!(auth_requests -> users (user_uuid));
 joinable!(sso_users -> users (user_uuid));
+joinable!(user_languages -> users (user_uuid));
+joinable!(notification_statuses -> notifications (notification_uuid));
+joinable!(emergency_access_quorum_members -> emergency_access (emergency_access_uuid));
 
 allow_tables_to_appear_in_same_query!(
     archives,
     attachments,
+    cipher_revisions,
     ciphers,
     ciphers_collections,
     collections,
@@ -398,6 +550,7 @@ allow_tables_to_appear_in_same_query!(
     sends,
     sso_users,
     twofactor,
+    user_languages,
     users,
     users_collections,
     users_organizations,
@@ -406,6 +559,13 @@ allow_tables_to_appear_in_same_query!(
     groups,
     groups_users,
     collections_groups,
+    deleted_items,
     event,
     auth_requests,
+    notifications,
+    notification_statuses,
+    emergency_access_quorums,
+    emergency_access_quorum_members,
+    mail_queue,
+    pending_uploads,
 );
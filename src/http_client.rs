@@ -32,6 +32,47 @@ pub fn make_http_request(method: reqwest::Method, url: &str) -> Result<reqwest::
     Ok(INSTANCE.request(method, url))
 }
 
+/// Like `make_http_request()`, for URLs provided by users instead of the admin.
+/// Addresses which aren't globally reachable are always refused, even when `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS` is
+/// disabled, and redirects are not followed.
+pub fn make_untrusted_http_request(
+    method: reqwest::Method,
+    url: &str,
+) -> Result<reqwest::RequestBuilder, crate::Error> {
+    static INSTANCE: LazyLock<Client> = LazyLock::new(|| {
+        get_reqwest_client_builder(true)
+            .dns_resolver(CustomDns::global_only())
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build client")
+    });
+
+    let Ok(url) = url::Url::parse(url) else {
+        err!("Invalid URL");
+    };
+    let Some(host) = url.host() else {
+        err!("Invalid host");
+    };
+
+    should_block_host(&host)?;
+    let ip = match host {
+        Host::Ipv4(ip) => Some(IpAddr::V4(ip)),
+        Host::Ipv6(ip) => Some(IpAddr::V6(ip)),
+        Host::Domain(_) => None,
+    };
+    if let Some(ip) = ip
+        && !is_global(ip)
+    {
+        return Err(CustomHttpClientError::NonGlobalIp {
+            domain: None,
+            ip,
+        }
+        .into());
+    }
+
+    Ok(INSTANCE.request(method, url))
+}
+
 pub fn get_reqwest_client_builder(enforce_block: bool) -> ClientBuilder {
     let mut headers = header::HeaderMap::new();
     headers.insert(header::USER_AGENT, header::HeaderValue::from_static("Vaultwarden"));
@@ -233,6 +274,8 @@ impl std::error::Error for CustomHttpClientError {}
 
 pub struct CustomDns {
     enforce_block: bool,
+    /// Refuse addresses which aren't globally reachable, regardless of `HTTP_REQUEST_BLOCK_NON_GLOBAL_IPS`
+    global_only: bool,
     resolver: Arc<CustomDnsResolver>,
 }
 
@@ -249,9 +292,17 @@ impl CustomDns {
 
         CustomDns {
             enforce_block,
+            global_only: false,
             resolver: Arc::clone(&*INSTANCE),
         }
     }
+
+    fn global_only() -> Self {
+        CustomDns {
+            global_only: true,
+            ..Self::instance(true)
+        }
+    }
 }
 
 impl CustomDnsResolver {
@@ -271,7 +322,12 @@ impl CustomDnsResolver {
     }
 
     // Note that we get an iterator of addresses, but we only grab the first one for convenience
-    async fn resolve_domain(&self, name: &str, enforce_block: bool) -> Result<Vec<SocketAddr>, BoxError> {
+    async fn resolve_domain(
+        &self,
+        name: &str,
+        enforce_block: bool,
+        global_only: bool,
+    ) -> Result<Vec<SocketAddr>, BoxError> {
         pre_resolve(name, enforce_block)?;
 
         let results: Vec<SocketAddr> = match self {
@@ -285,6 +341,14 @@ impl CustomDnsResolver {
             }
         }
 
+        if global_only && let Some(addr) = results.iter().find(|addr| !is_global(addr.ip())) {
+            return Err(CustomHttpClientError::NonGlobalIp {
+                domain: Some(name.to_owned()),
+                ip: addr.ip(),
+            }
+            .into());
+        }
+
         Ok(results)
     }
 }
@@ -317,10 +381,11 @@ fn post_resolve(name: &str, ip: IpAddr) -> Result<(), CustomHttpClientError> {
 impl Resolve for CustomDns {
     fn resolve(&self, name: Name) -> Resolving {
         let enforce_block = self.enforce_block;
+        let global_only = self.global_only;
         let this = Arc::clone(&self.resolver);
         Box::pin(async move {
             let name = name.as_str();
-            let results = this.resolve_domain(name, enforce_block).await?;
+            let results = this.resolve_domain(name, enforce_block, global_only).await?;
             if results.is_empty() {
                 warn!("Unable to resolve {name} to any valid IP address");
             }
@@ -0,0 +1,133 @@
+// Runs the scheduled jobs, making sure every scheduled run of a job is only executed once,
+// even when multiple instances share the same database and run the same schedules.
+//
+// All instances compute the same `scheduled_at` time for a run from the job schedule.
+// The first instance to insert the run into the `job_runs` table gets to execute it, the unique
+// (job_name, scheduled_at) key makes the others skip it. The table also keeps the run history,
+// which is shown on the admin diagnostics page.
+
+use std::{future::Future, time::Duration};
+
+use chrono::{NaiveDateTime, SubsecRound, TimeDelta, Utc};
+use job_scheduler_ng::Schedule;
+
+use crate::{
+    CONFIG,
+    db::{DbPool, models::JobRun},
+    error::Error,
+};
+
+/// An unfinished run which started longer ago than this is considered to have crashed,
+/// and doesn't prevent the job from running again.
+const JOB_LEASE: TimeDelta = TimeDelta::hours(1);
+
+/// How long the job history is kept.
+const JOB_RUNS_RETAIN: TimeDelta = TimeDelta::days(30);
+
+/// What a finished job reports, stored with its run.
+#[derive(Default)]
+pub struct JobSummary {
+    pub rows_affected: Option<i64>,
+    /// JSON report, for jobs whose results are shown on the admin panel
+    pub details: Option<String>,
+}
+
+/// Result of a job, optionally with the number of rows it affected.
+pub trait JobOutcome {
+    fn into_result(self) -> Result<JobSummary, String>;
+}
+
+impl JobOutcome for () {
+    fn into_result(self) -> Result<JobSummary, String> {
+        Ok(JobSummary::default())
+    }
+}
+
+impl JobOutcome for usize {
+    fn into_result(self) -> Result<JobSummary, String> {
+        Ok(JobSummary {
+            rows_affected: i64::try_from(self).ok(),
+            details: None,
+        })
+    }
+}
+
+impl<T: JobOutcome> JobOutcome for Result<T, Error> {
+    fn into_result(self) -> Result<JobSummary, String> {
+        self.map_err(|e| e.to_string()).and_then(JobOutcome::into_result)
+    }
+}
+
+/// Runs `job`, unless another instance already runs it for the current tick of `schedule`.
+pub async fn run<F, Fut>(name: &'static str, schedule: String, pool: DbPool, job: F)
+where
+    F: FnOnce(DbPool) -> Fut,
+    Fut: Future<Output: JobOutcome>,
+{
+    let Ok(conn) = pool.get().await else {
+        error!("Failed to get DB connection while starting job '{name}'");
+        return;
+    };
+
+    // Another key than the one the other instances claimed wouldn't keep the job from running twice
+    let Some(scheduled_at) = scheduled_at(&schedule) else {
+        warn!("Skipping job '{name}', it started too long after its schedule fired");
+        return;
+    };
+    let mut run = JobRun::new(name, scheduled_at);
+    if let Some(running) = JobRun::find_running(name, run.started_at - JOB_LEASE, &conn).await {
+        debug!("Skipping job '{name}', it is still running on instance '{}'", running.instance);
+        return;
+    }
+    if !run.claim(&conn).await {
+        debug!("Skipping job '{name}', it already ran on another instance");
+        return;
+    }
+    // Don't hold on to the connection while the job runs
+    drop(conn);
+
+    let (result, summary) = match job(pool.clone()).await.into_result() {
+        Ok(summary) => (String::from("success"), summary),
+        Err(e) => {
+            error!("Job '{name}' failed: {e}");
+            (e, JobSummary::default())
+        }
+    };
+
+    if let Ok(conn) = pool.get().await {
+        if let Err(e) = run.finish(result, summary.rows_affected, summary.details, &conn).await {
+            error!("Failed to record the result of job '{name}': {e:?}");
+        }
+        JobRun::delete_started_before(run.started_at - JOB_RUNS_RETAIN, &conn).await.ok();
+    } else {
+        error!("Failed to get DB connection while finishing job '{name}'");
+    }
+}
+
+/// The most recent time `schedule` fired, which is the same on all instances.
+/// The scheduler runs a job within one poll interval after it fired, so only that window needs to be searched.
+/// Returns `None` when it didn't fire within the window, because the job was delayed too long.
+fn scheduled_at(schedule: &str) -> Option<NaiveDateTime> {
+    let now = Utc::now().trunc_subsecs(0);
+    let poll_interval = Duration::from_millis(CONFIG.job_poll_interval_ms());
+    let window = TimeDelta::minutes(1) + TimeDelta::from_std(poll_interval * 2).unwrap_or(TimeDelta::days(1));
+
+    schedule
+        .parse::<Schedule>()
+        .ok()
+        .and_then(|schedule| schedule.after(&(now - window)).take_while(|fired| *fired <= now).last())
+        .map(|fired| fired.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scheduled_at_is_only_found_within_the_window() {
+        // Fires every second, so it just fired
+        assert!(scheduled_at("* * * * * *").is_some());
+        // Last fired long ago, like for a run which was delayed too long
+        assert!(scheduled_at("0 0 0 1 1 * 2000").is_none());
+    }
+}
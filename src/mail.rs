@@ -1,6 +1,6 @@
-use std::{env::consts::EXE_SUFFIX, str::FromStr};
+use std::{env::consts::EXE_SUFFIX, str::FromStr, sync::OnceLock};
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use lettre::{
     Address, AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
     message::{Attachment, Body, Mailbox, Message, MultiPart, SinglePart},
@@ -9,19 +9,34 @@ use lettre::{
     transport::smtp::extension::ClientId,
 };
 use percent_encoding::{NON_ALPHANUMERIC, percent_encode};
+use reqwest::Method;
 
 use crate::{
     CONFIG,
     api::EmptyResult,
     auth::{
-        encode_jwt, generate_delete_claims, generate_emergency_access_invite_claims, generate_invite_claims,
-        generate_verify_email_claims,
+        decode_register_verify, encode_jwt, generate_delete_claims, generate_emergency_access_invite_claims,
+        generate_invite_claims, generate_verify_email_claims,
+    },
+    db::{
+        DbConn, DbPool,
+        models::{
+            Device, DeviceType, EmergencyAccessId, MailId, MembershipId, OrganizationId, QueuedMail, User, UserId,
+            UserLanguage,
+        },
     },
-    db::models::{Device, DeviceType, EmergencyAccessId, MembershipId, OrganizationId, User, UserId},
     error::Error,
+    http_client::make_http_request,
     util::upcase_first,
 };
 
+static DB_POOL: OnceLock<DbPool> = OnceLock::new();
+
+/// Makes the database available for the outbound mail queue, see `MAIL_QUEUE_ENABLED`.
+pub fn init(pool: DbPool) {
+    DB_POOL.set(pool).ok();
+}
+
 fn sendmail_transport() -> AsyncSendmailTransport<Tokio1Executor> {
     if let Some(command) = CONFIG.sendmail_command() {
         AsyncSendmailTransport::new_with_command(command)
@@ -118,16 +133,28 @@ fn sanitize_data(data: &mut serde_json::Value) {
     }
 }
 
-fn get_text(template_name: &'static str, data: serde_json::Value) -> Result<(String, String, String), Error> {
+/// Renders the mail in the preferred language of the recipient, if there is a template for it.
+async fn get_text(
+    template_name: &'static str,
+    data: serde_json::Value,
+    address: &str,
+    conn: &DbConn,
+) -> Result<(String, String, String), Error> {
     let mut data = data;
     sanitize_data(&mut data);
-    let (subject_html, body_html) = get_template(&format!("{template_name}.html"), &data)?;
-    let (_subject_text, body_text) = get_template(template_name, &data)?;
+    let language = UserLanguage::find_by_mail(address, conn).await;
+    let language = language.as_deref();
+    let (subject_html, body_html) = get_template(&format!("{template_name}.html"), language, &data)?;
+    let (_subject_text, body_text) = get_template(template_name, language, &data)?;
     Ok((subject_html, body_html, body_text))
 }
 
-fn get_template(template_name: &str, data: &serde_json::Value) -> Result<(String, String), Error> {
-    let text = CONFIG.render_template(template_name, data)?;
+fn get_template(
+    template_name: &str,
+    language: Option<&str>,
+    data: &serde_json::Value,
+) -> Result<(String, String), Error> {
+    let text = CONFIG.render_localized_template(template_name, language, data)?;
     let mut text_split = text.split("<!---------------->");
 
     let subject = if let Some(s) = text_split.next() {
@@ -149,7 +176,17 @@ fn get_template(template_name: &str, data: &serde_json::Value) -> Result<(String
     Ok((subject, body))
 }
 
-pub async fn send_password_hint(address: &str, hint: Option<String>) -> EmptyResult {
+/// When a mail holding a JWT becomes useless, from the expiration time of its claims.
+fn token_expiry(exp: i64) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(exp, 0).map(|dt| dt.naive_utc())
+}
+
+/// When a mail holding a token from `send_token` or `send_protected_action_token` becomes useless.
+fn email_token_expiry() -> Option<NaiveDateTime> {
+    Some(Utc::now().naive_utc() + TimeDelta::seconds(CONFIG.email_expiration_time().cast_signed()))
+}
+
+pub async fn send_password_hint(address: &str, hint: Option<String>, conn: &DbConn) -> EmptyResult {
     let template_name = if hint.is_some() {
         "email/pw_hint_some"
     } else {
@@ -163,12 +200,15 @@ pub async fn send_password_hint(address: &str, hint: Option<String>) -> EmptyRes
             "img_src": CONFIG._smtp_img_src(),
             "hint": hint,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_delete_account(address: &str, user_id: &UserId) -> EmptyResult {
+pub async fn send_delete_account(address: &str, user_id: &UserId, conn: &DbConn) -> EmptyResult {
     let claims = generate_delete_claims(user_id.to_string());
     let delete_token = encode_jwt(&claims);
 
@@ -181,12 +221,15 @@ pub async fn send_delete_account(address: &str, user_id: &UserId) -> EmptyResult
             "email": percent_encode(address.as_bytes(), NON_ALPHANUMERIC).to_string(),
             "token": delete_token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, token_expiry(claims.exp), conn).await
 }
 
-pub async fn send_verify_email(address: &str, user_id: &UserId) -> EmptyResult {
+pub async fn send_verify_email(address: &str, user_id: &UserId, conn: &DbConn) -> EmptyResult {
     let claims = generate_verify_email_claims(user_id);
     let verify_email_token = encode_jwt(&claims);
 
@@ -199,12 +242,15 @@ pub async fn send_verify_email(address: &str, user_id: &UserId) -> EmptyResult {
             "email": percent_encode(address.as_bytes(), NON_ALPHANUMERIC).to_string(),
             "token": verify_email_token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, token_expiry(claims.exp), conn).await
 }
 
-pub async fn send_register_verify_email(email: &str, token: &str) -> EmptyResult {
+pub async fn send_register_verify_email(email: &str, token: &str, conn: &DbConn) -> EmptyResult {
     let mut query = url::Url::parse("https://query.builder").unwrap();
     query.query_pairs_mut().append_pair("email", email).append_pair("token", token);
     let Some(query_string) = query.query() else {
@@ -219,24 +265,31 @@ pub async fn send_register_verify_email(email: &str, token: &str) -> EmptyResult
             "img_src": CONFIG._smtp_img_src(),
             "email": email,
         }),
-    )?;
+        email,
+        conn,
+    )
+    .await?;
 
-    send_email(email, &subject, body_html, body_text).await
+    let expires_at = decode_register_verify(token).ok().and_then(|claims| token_expiry(claims.exp));
+    send_expiring_email(email, &subject, body_html, body_text, expires_at, conn).await
 }
 
-pub async fn send_welcome(address: &str) -> EmptyResult {
+pub async fn send_welcome(address: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/welcome",
         json!({
             "url": CONFIG.domain(),
             "img_src": CONFIG._smtp_img_src(),
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_welcome_must_verify(address: &str, user_id: &UserId) -> EmptyResult {
+pub async fn send_welcome_must_verify(address: &str, user_id: &UserId, conn: &DbConn) -> EmptyResult {
     let claims = generate_verify_email_claims(user_id);
     let verify_email_token = encode_jwt(&claims);
 
@@ -248,12 +301,15 @@ pub async fn send_welcome_must_verify(address: &str, user_id: &UserId) -> EmptyR
             "user_id": user_id,
             "token": verify_email_token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, token_expiry(claims.exp), conn).await
 }
 
-pub async fn send_2fa_removed_from_org(address: &str, org_name: &str) -> EmptyResult {
+pub async fn send_2fa_removed_from_org(address: &str, org_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/send_2fa_removed_from_org",
         json!({
@@ -261,12 +317,15 @@ pub async fn send_2fa_removed_from_org(address: &str, org_name: &str) -> EmptyRe
             "img_src": CONFIG._smtp_img_src(),
             "org_name": org_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_single_org_removed_from_org(address: &str, org_name: &str) -> EmptyResult {
+pub async fn send_single_org_removed_from_org(address: &str, org_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/send_single_org_removed_from_org",
         json!({
@@ -274,9 +333,12 @@ pub async fn send_single_org_removed_from_org(address: &str, org_name: &str) ->
             "img_src": CONFIG._smtp_img_src(),
             "org_name": org_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
 pub async fn send_invite(
@@ -285,6 +347,7 @@ pub async fn send_invite(
     member_id: MembershipId,
     org_name: &str,
     invited_by_email: Option<String>,
+    conn: &DbConn,
 ) -> EmptyResult {
     let claims = generate_invite_claims(
         user.uuid.clone(),
@@ -333,9 +396,12 @@ pub async fn send_invite(
             "img_src": CONFIG._smtp_img_src(),
             "org_name": org_name,
         }),
-    )?;
+        &user.email,
+        conn,
+    )
+    .await?;
 
-    send_email(&user.email, &subject, body_html, body_text).await
+    send_expiring_email(&user.email, &subject, body_html, body_text, token_expiry(claims.exp), conn).await
 }
 
 pub async fn send_emergency_access_invite(
@@ -344,6 +410,7 @@ pub async fn send_emergency_access_invite(
     emer_id: EmergencyAccessId,
     grantor_name: &str,
     grantor_email: &str,
+    conn: &DbConn,
 ) -> EmptyResult {
     let claims = generate_emergency_access_invite_claims(
         user_id,
@@ -376,12 +443,15 @@ pub async fn send_emergency_access_invite(
             "img_src": CONFIG._smtp_img_src(),
             "grantor_name": grantor_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, token_expiry(claims.exp), conn).await
 }
 
-pub async fn send_emergency_access_invite_accepted(address: &str, grantee_email: &str) -> EmptyResult {
+pub async fn send_emergency_access_invite_accepted(address: &str, grantee_email: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_invite_accepted",
         json!({
@@ -389,12 +459,15 @@ pub async fn send_emergency_access_invite_accepted(address: &str, grantee_email:
             "img_src": CONFIG._smtp_img_src(),
             "grantee_email": grantee_email,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_emergency_access_invite_confirmed(address: &str, grantor_name: &str) -> EmptyResult {
+pub async fn send_emergency_access_invite_confirmed(address: &str, grantor_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_invite_confirmed",
         json!({
@@ -402,12 +475,15 @@ pub async fn send_emergency_access_invite_confirmed(address: &str, grantor_name:
             "img_src": CONFIG._smtp_img_src(),
             "grantor_name": grantor_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_emergency_access_recovery_approved(address: &str, grantor_name: &str) -> EmptyResult {
+pub async fn send_emergency_access_recovery_approved(address: &str, grantor_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_recovery_approved",
         json!({
@@ -415,9 +491,12 @@ pub async fn send_emergency_access_recovery_approved(address: &str, grantor_name
             "img_src": CONFIG._smtp_img_src(),
             "grantor_name": grantor_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
 pub async fn send_emergency_access_recovery_initiated(
@@ -425,6 +504,7 @@ pub async fn send_emergency_access_recovery_initiated(
     grantee_name: &str,
     atype: &str,
     wait_time_days: &i32,
+    conn: &DbConn,
 ) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_recovery_initiated",
@@ -435,9 +515,12 @@ pub async fn send_emergency_access_recovery_initiated(
             "atype": atype,
             "wait_time_days": wait_time_days,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
 pub async fn send_emergency_access_recovery_reminder(
@@ -445,6 +528,7 @@ pub async fn send_emergency_access_recovery_reminder(
     grantee_name: &str,
     atype: &str,
     days_left: &str,
+    conn: &DbConn,
 ) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_recovery_reminder",
@@ -455,12 +539,15 @@ pub async fn send_emergency_access_recovery_reminder(
             "atype": atype,
             "days_left": days_left,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_emergency_access_recovery_rejected(address: &str, grantor_name: &str) -> EmptyResult {
+pub async fn send_emergency_access_recovery_rejected(address: &str, grantor_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_recovery_rejected",
         json!({
@@ -468,12 +555,20 @@ pub async fn send_emergency_access_recovery_rejected(address: &str, grantor_name
             "img_src": CONFIG._smtp_img_src(),
             "grantor_name": grantor_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_emergency_access_recovery_timed_out(address: &str, grantee_name: &str, atype: &str) -> EmptyResult {
+pub async fn send_emergency_access_recovery_timed_out(
+    address: &str,
+    grantee_name: &str,
+    atype: &str,
+    conn: &DbConn,
+) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/emergency_access_recovery_timed_out",
         json!({
@@ -482,12 +577,15 @@ pub async fn send_emergency_access_recovery_timed_out(address: &str, grantee_nam
             "grantee_name": grantee_name,
             "atype": atype,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_invite_accepted(new_user_email: &str, address: &str, org_name: &str) -> EmptyResult {
+pub async fn send_invite_accepted(new_user_email: &str, address: &str, org_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/invite_accepted",
         json!({
@@ -496,12 +594,15 @@ pub async fn send_invite_accepted(new_user_email: &str, address: &str, org_name:
             "email": new_user_email,
             "org_name": org_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_invite_confirmed(address: &str, org_name: &str) -> EmptyResult {
+pub async fn send_invite_confirmed(address: &str, org_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/invite_confirmed",
         json!({
@@ -509,12 +610,21 @@ pub async fn send_invite_confirmed(address: &str, org_name: &str) -> EmptyResult
             "img_src": CONFIG._smtp_img_src(),
             "org_name": org_name,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_new_device_logged_in(address: &str, ip: &str, dt: &NaiveDateTime, device: &Device) -> EmptyResult {
+pub async fn send_new_device_logged_in(
+    address: &str,
+    ip: &str,
+    dt: &NaiveDateTime,
+    device: &Device,
+    conn: &DbConn,
+) -> EmptyResult {
     let fmt = "%A, %B %_d, %Y at %r %Z";
     let (subject, body_html, body_text) = get_text(
         "email/new_device_logged_in",
@@ -526,9 +636,12 @@ pub async fn send_new_device_logged_in(address: &str, ip: &str, dt: &NaiveDateTi
             "device_type": DeviceType::from_i32(device.atype).to_string(),
             "datetime": crate::util::format_naive_datetime_local(dt, fmt),
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
 pub async fn send_incomplete_2fa_login(
@@ -537,6 +650,7 @@ pub async fn send_incomplete_2fa_login(
     dt: &NaiveDateTime,
     device_name: &str,
     device_type: &str,
+    conn: &DbConn,
 ) -> EmptyResult {
     let fmt = "%A, %B %_d, %Y at %r %Z";
     let (subject, body_html, body_text) = get_text(
@@ -550,12 +664,15 @@ pub async fn send_incomplete_2fa_login(
             "datetime": crate::util::format_naive_datetime_local(dt, fmt),
             "time_limit": CONFIG.incomplete_2fa_time_limit(),
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_token(address: &str, token: &str) -> EmptyResult {
+pub async fn send_token(address: &str, token: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/twofactor_email",
         json!({
@@ -563,12 +680,15 @@ pub async fn send_token(address: &str, token: &str) -> EmptyResult {
             "img_src": CONFIG._smtp_img_src(),
             "token": token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, email_token_expiry(), conn).await
 }
 
-pub async fn send_change_email(address: &str, token: &str) -> EmptyResult {
+pub async fn send_change_email(address: &str, token: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/change_email",
         json!({
@@ -576,12 +696,15 @@ pub async fn send_change_email(address: &str, token: &str) -> EmptyResult {
             "img_src": CONFIG._smtp_img_src(),
             "token": token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_change_email_existing(address: &str, acting_address: &str) -> EmptyResult {
+pub async fn send_change_email_existing(address: &str, acting_address: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/change_email_existing",
         json!({
@@ -590,12 +713,15 @@ pub async fn send_change_email_existing(address: &str, acting_address: &str) ->
             "existing_address": address,
             "acting_address": acting_address,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_change_email_invited(address: &str, acting_address: &str) -> EmptyResult {
+pub async fn send_change_email_invited(address: &str, acting_address: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/change_email_invited",
         json!({
@@ -604,36 +730,46 @@ pub async fn send_change_email_invited(address: &str, acting_address: &str) -> E
             "existing_address": address,
             "acting_address": acting_address,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_sso_change_email(address: &str) -> EmptyResult {
+pub async fn send_sso_change_email(address: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/sso_change_email",
         json!({
             "url": format!("{}/#/settings/account", CONFIG.domain()),
             "img_src": CONFIG._smtp_img_src(),
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_test(address: &str) -> EmptyResult {
+pub async fn send_test(address: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/smtp_test",
         json!({
             "url": CONFIG.domain(),
             "img_src": CONFIG._smtp_img_src(),
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    // Never queued, so the admin sees whether the mail settings work
+    deliver_email(address, &subject, &body_html, &body_text).await
 }
 
-pub async fn send_admin_reset_password(address: &str, user_name: &str, org_name: &str) -> EmptyResult {
+pub async fn send_admin_reset_password(address: &str, user_name: &str, org_name: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/admin_reset_password",
         json!({
@@ -642,11 +778,14 @@ pub async fn send_admin_reset_password(address: &str, user_name: &str, org_name:
             "user_name": user_name,
             "org_name": org_name,
         }),
-    )?;
-    send_email(address, &subject, body_html, body_text).await
+        address,
+        conn,
+    )
+    .await?;
+    send_email(address, &subject, body_html, body_text, conn).await
 }
 
-pub async fn send_protected_action_token(address: &str, token: &str) -> EmptyResult {
+pub async fn send_protected_action_token(address: &str, token: &str, conn: &DbConn) -> EmptyResult {
     let (subject, body_html, body_text) = get_text(
         "email/protected_action",
         json!({
@@ -654,9 +793,12 @@ pub async fn send_protected_action_token(address: &str, token: &str) -> EmptyRes
             "img_src": CONFIG._smtp_img_src(),
             "token": token,
         }),
-    )?;
+        address,
+        conn,
+    )
+    .await?;
 
-    send_email(address, &subject, body_html, body_text).await
+    send_expiring_email(address, &subject, body_html, body_text, email_token_expiry(), conn).await
 }
 
 async fn send_with_selected_transport(email: Message) -> EmptyResult {
@@ -709,7 +851,77 @@ async fn send_with_selected_transport(email: Message) -> EmptyResult {
     }
 }
 
-async fn send_email(address: &str, subject: &str, body_html: String, body_text: String) -> EmptyResult {
+/// Sends the mail through the HTTP mail API configured with `MAIL_HTTP_URL`.
+async fn send_with_http_api(address: &str, subject: &str, body_html: &str, body_text: &str) -> EmptyResult {
+    let Some(url) = CONFIG.mail_http_url() else {
+        err!("No HTTP mail API configured")
+    };
+    let from = CONFIG.smtp_from();
+    let from_name = CONFIG.smtp_from_name();
+
+    let payload = if CONFIG.mail_http_format() == "sendgrid" {
+        let attachments: Vec<serde_json::Value> = if CONFIG.smtp_embed_images() {
+            ["logo-gray.png", "mail-github.png"]
+                .into_iter()
+                .map(|name| {
+                    let (_, content) = crate::api::static_files(name)?;
+                    Ok(json!({
+                        "content": data_encoding::BASE64.encode(content),
+                        "filename": name,
+                        "type": "image/png",
+                        "disposition": "inline",
+                        "content_id": name,
+                    }))
+                })
+                .collect::<Result<_, Error>>()?
+        } else {
+            Vec::new()
+        };
+
+        json!({
+            "personalizations": [{ "to": [{ "email": address }] }],
+            "from": { "email": from, "name": from_name },
+            "subject": subject,
+            "content": [
+                { "type": "text/plain", "value": body_text },
+                { "type": "text/html", "value": body_html },
+            ],
+            "attachments": attachments,
+        })
+    } else {
+        let email = build_email(address, subject, body_html.to_owned(), body_text.to_owned())?;
+        json!({
+            "from": from,
+            "fromName": from_name,
+            "to": address,
+            "subject": subject,
+            "html": body_html,
+            "text": body_text,
+            "raw": data_encoding::BASE64.encode(&email.formatted()),
+        })
+    };
+
+    let mut request = make_http_request(Method::POST, &url)?.json(&payload);
+    if let Some(api_key) = CONFIG.mail_http_api_key() {
+        request = request.bearer_auth(api_key);
+    }
+
+    match request.send().await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            debug!("HTTP mail API error response: {body}");
+            err!(format!("HTTP mail API error: {status}"))
+        }
+        Err(e) => {
+            debug!("HTTP mail API error: {e:#?}");
+            err!(format!("HTTP mail API error: {e}"))
+        }
+    }
+}
+
+fn build_email(address: &str, subject: &str, body_html: String, body_text: String) -> Result<Message, Error> {
     let smtp_from = Address::from_str(&CONFIG.smtp_from())?;
 
     let body = if CONFIG.smtp_embed_images() {
@@ -738,5 +950,137 @@ async fn send_email(address: &str, subject: &str, body_html: String, body_text:
         .subject(subject)
         .multipart(body)?;
 
-    send_with_selected_transport(email).await
+    Ok(email)
+}
+
+/// Sends the mail right away with the configured transport.
+async fn deliver_email(address: &str, subject: &str, body_html: &str, body_text: &str) -> EmptyResult {
+    if CONFIG.mail_http_url().is_some() {
+        send_with_http_api(address, subject, body_html, body_text).await
+    } else {
+        let email = build_email(address, subject, body_html.to_owned(), body_text.to_owned())?;
+        send_with_selected_transport(email).await
+    }
+}
+
+async fn send_email(address: &str, subject: &str, body_html: String, body_text: String, conn: &DbConn) -> EmptyResult {
+    send_expiring_email(address, subject, body_html, body_text, None, conn).await
+}
+
+/// Sends a mail which is useless after `expires_at`, like one holding a token.
+/// When it's still queued by then, it's dropped instead of being sent.
+async fn send_expiring_email(
+    address: &str,
+    subject: &str,
+    body_html: String,
+    body_text: String,
+    expires_at: Option<NaiveDateTime>,
+    conn: &DbConn,
+) -> EmptyResult {
+    if CONFIG.mail_queue_enabled()
+        && let Some(pool) = DB_POOL.get()
+    {
+        // Invalid addresses won't get better by retrying
+        Address::from_str(address)?;
+
+        let mail = QueuedMail::new(address, subject, body_html, body_text, expires_at);
+        mail.save(conn).await?;
+
+        // Try to send it right away, but without making the request wait for the mail server.
+        // The connection is only needed to update the queue afterwards.
+        let pool = pool.clone();
+        tokio::spawn(async move {
+            let result = deliver_queued_email(&mail).await;
+            match pool.get().await {
+                Ok(conn) => {
+                    finish_queued_email(mail, result, &conn).await;
+                }
+                Err(e) => error!("Failed to get DB connection while sending queued mail: {e:?}"),
+            }
+        });
+        return Ok(());
+    }
+
+    deliver_email(address, subject, &body_html, &body_text).await
+}
+
+async fn deliver_queued_email(mail: &QueuedMail) -> EmptyResult {
+    deliver_email(&mail.recipient, &mail.subject, &mail.body_html, &mail.body_text).await
+}
+
+/// Removes a mail from the queue once it's sent, or records the failed attempt.
+/// Returns whether it was sent.
+async fn finish_queued_email(mut mail: QueuedMail, result: EmptyResult, conn: &DbConn) -> bool {
+    match result {
+        Ok(()) => {
+            if let Err(e) = mail.delete(conn).await {
+                error!("Failed to remove sent mail from the queue: {e:?}");
+            }
+            true
+        }
+        Err(e) => {
+            warn!("Failed to send queued mail (attempt {}): {}", mail.attempts + 1, e.message());
+            if let Err(e) = mail.record_failure(e.message().to_owned(), conn).await {
+                error!("Failed to update queued mail: {e:?}");
+            }
+            false
+        }
+    }
+}
+
+/// Sends a mail from the queue, which has to be claimed by the current instance.
+/// Mails which expired meanwhile are removed instead. Returns whether it was sent.
+async fn send_queued_email(mail: QueuedMail, conn: &DbConn) -> bool {
+    if mail.is_expired() {
+        debug!("Dropping expired mail {} from the queue", mail.uuid);
+        if let Err(e) = mail.delete(conn).await {
+            error!("Failed to remove expired mail from the queue: {e:?}");
+        }
+        return false;
+    }
+
+    let result = deliver_queued_email(&mail).await;
+    finish_queued_email(mail, result, conn).await
+}
+
+/// Sends the queued mails which are due, returning how many were sent.
+pub async fn mail_queue_job(pool: DbPool) -> Result<usize, Error> {
+    debug!("Start mail_queue_job");
+    if !CONFIG.mail_enabled() {
+        return Ok(0);
+    }
+
+    let conn = pool.get().await?;
+    let max_attempts = i32::try_from(CONFIG.mail_queue_max_attempts()).unwrap_or(i32::MAX);
+
+    match QueuedMail::purge(max_attempts, &conn).await {
+        Ok(0) => (),
+        Ok(purged) => debug!("Removed {purged} expired or failed mails from the queue"),
+        Err(e) => error!("Failed to purge the mail queue: {e:?}"),
+    }
+
+    let mut sent = 0;
+    for mut mail in QueuedMail::find_due(max_attempts, &conn).await {
+        if mail.claim(&conn).await && send_queued_email(mail, &conn).await {
+            sent += 1;
+        }
+    }
+    Ok(sent)
+}
+
+/// Sends a queued mail right away, on request of the admin.
+pub async fn retry_queued_email(uuid: &MailId, conn: &DbConn) -> EmptyResult {
+    let Some(mut mail) = QueuedMail::find_by_uuid(uuid, conn).await else {
+        err!("Queued mail not found")
+    };
+    if mail.is_expired() {
+        mail.delete(conn).await?;
+        err!("The mail expired and was removed from the queue")
+    }
+    mail.retry_now(conn).await?;
+
+    if mail.claim(conn).await && !send_queued_email(mail, conn).await {
+        err!("Sending the mail failed again")
+    }
+    Ok(())
 }
@@ -56,6 +56,7 @@ mod crypto;
 #[macro_use]
 mod db;
 mod http_client;
+mod jobs;
 mod mail;
 mod ratelimit;
 mod sso;
@@ -73,7 +74,7 @@ pub use util::is_running_in_container;
 #[rocket::main]
 async fn main() -> Result<(), Error> {
     install_rustls_crypto_provider();
-    parse_args();
+    parse_args().await;
     launch_info();
 
     let level = init_logging()?;
@@ -89,6 +90,9 @@ async fn main() -> Result<(), Error> {
 
     let pool = create_db_pool().await;
     schedule_jobs(pool.clone());
+    api::start_notification_bus();
+    ratelimit::init(pool.clone());
+    mail::init(pool.clone());
     db::models::TwoFactor::migrate_u2f_to_webauthn(&pool.get().await.unwrap()).await.unwrap();
     db::models::TwoFactor::migrate_credential_to_passkey(&pool.get().await.unwrap()).await.unwrap();
 
@@ -110,6 +114,9 @@ COMMAND:
     hash [--preset {bitwarden|owasp}]  Generate an Argon2id PHC ADMIN_TOKEN
     backup                             Create a backup of the SQLite database
                                        You can also send the USR1 signal to trigger a backup
+    storage-gc [--delete]              Report attachment and send files without a database
+                                       record, and records without a file. With `--delete`,
+                                       files without a record are deleted as well
 
 PRESETS:                  m=         t=          p=
     bitwarden (default) 64MiB, 3 Iterations, 4 Threads
@@ -119,7 +126,7 @@ PRESETS:                  m=         t=          p=
 
 pub const VERSION: Option<&str> = option_env!("VW_VERSION");
 
-fn parse_args() {
+async fn parse_args() {
     let mut pargs = pico_args::Arguments::from_env();
     let version = VERSION.unwrap_or("(Version info from Git not present)");
 
@@ -197,6 +204,23 @@ fn parse_args() {
                     exit(1);
                 }
             }
+        } else if command == "storage-gc" {
+            let delete_orphans = pargs.contains("--delete");
+            let pool = create_db_pool().await;
+            let conn = pool.get().await.unwrap_or_else(|e| {
+                println!("Unable to get a database connection. {e:?}");
+                exit(1);
+            });
+            match storage::gc::collect(&conn, delete_orphans).await {
+                Ok(report) => {
+                    println!("{report}");
+                    exit(0);
+                }
+                Err(e) => {
+                    println!("Storage garbage collection failed. {e:?}");
+                    exit(1);
+                }
+            }
         }
         exit(0);
     }
@@ -454,17 +478,17 @@ fn create_dir(path: &str, description: &str) {
 async fn check_data_folder() {
     let data_folder = &CONFIG.data_folder();
 
-    if data_folder.starts_with("s3://") {
+    if storage::is_remote_path(data_folder) {
         if let Err(e) = CONFIG
             .opendal_operator_for_path_type(&PathType::Data)
             .unwrap_or_else(|e| {
-                error!("Failed to create S3 operator for data folder '{data_folder}': {e:?}");
+                error!("Failed to create storage operator for data folder '{data_folder}': {e:?}");
                 exit(1);
             })
             .check()
             .await
         {
-            error!("Could not access S3 data folder '{data_folder}': {e:?}");
+            error!("Could not access remote data folder '{data_folder}': {e:?}");
             exit(1);
         }
 
@@ -679,14 +703,24 @@ fn schedule_jobs(pool: db::DbPool) {
             // Purge sends that are past their deletion date.
             if !CONFIG.send_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.send_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::purge_sends(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "send_purge",
+                        CONFIG.send_purge_schedule(),
+                        pool.clone(),
+                        api::purge_sends,
+                    ));
                 }));
             }
 
             // Purge trashed items that are old enough to be auto-deleted.
             if !CONFIG.trash_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.trash_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::purge_trashed_ciphers(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "trash_purge",
+                        CONFIG.trash_purge_schedule(),
+                        pool.clone(),
+                        api::purge_trashed_ciphers,
+                    ));
                 }));
             }
 
@@ -694,7 +728,12 @@ fn schedule_jobs(pool: db::DbPool) {
             // indicates that a user's master password has been compromised.
             if !CONFIG.incomplete_2fa_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.incomplete_2fa_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::send_incomplete_2fa_notifications(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "incomplete_2fa",
+                        CONFIG.incomplete_2fa_schedule(),
+                        pool.clone(),
+                        api::send_incomplete_2fa_notifications,
+                    ));
                 }));
             }
 
@@ -703,7 +742,12 @@ fn schedule_jobs(pool: db::DbPool) {
             // sending reminders for requests that are about to be granted anyway.
             if !CONFIG.emergency_request_timeout_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.emergency_request_timeout_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::emergency_request_timeout_job(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "emergency_request_timeout",
+                        CONFIG.emergency_request_timeout_schedule(),
+                        pool.clone(),
+                        api::emergency_request_timeout_job,
+                    ));
                 }));
             }
 
@@ -711,20 +755,35 @@ fn schedule_jobs(pool: db::DbPool) {
             // emergency access requests.
             if !CONFIG.emergency_notification_reminder_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.emergency_notification_reminder_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::emergency_notification_reminder_job(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "emergency_notification_reminder",
+                        CONFIG.emergency_notification_reminder_schedule(),
+                        pool.clone(),
+                        api::emergency_notification_reminder_job,
+                    ));
                 }));
             }
 
             if !CONFIG.auth_request_purge_schedule().is_empty() {
                 sched.add(Job::new(CONFIG.auth_request_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(purge_auth_requests(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "auth_request_purge",
+                        CONFIG.auth_request_purge_schedule(),
+                        pool.clone(),
+                        purge_auth_requests,
+                    ));
                 }));
             }
 
             // Clean unused, expired Duo authentication contexts.
             if !CONFIG.duo_context_purge_schedule().is_empty() && CONFIG._enable_duo() && !CONFIG.duo_use_iframe() {
                 sched.add(Job::new(CONFIG.duo_context_purge_schedule().parse().unwrap(), || {
-                    runtime.spawn(purge_duo_contexts(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "duo_context_purge",
+                        CONFIG.duo_context_purge_schedule(),
+                        pool.clone(),
+                        purge_duo_contexts,
+                    ));
                 }));
             }
 
@@ -734,14 +793,84 @@ fn schedule_jobs(pool: db::DbPool) {
                 && CONFIG.events_days_retain().is_some()
             {
                 sched.add(Job::new(CONFIG.event_cleanup_schedule().parse().unwrap(), || {
-                    runtime.spawn(api::event_cleanup_job(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "event_cleanup",
+                        CONFIG.event_cleanup_schedule(),
+                        pool.clone(),
+                        api::event_cleanup_job,
+                    ));
                 }));
             }
 
             // Purge sso auth from incomplete flow (default to daily at 00h20).
             if !CONFIG.purge_incomplete_sso_auth().is_empty() {
                 sched.add(Job::new(CONFIG.purge_incomplete_sso_auth().parse().unwrap(), || {
-                    runtime.spawn(db::models::SsoAuth::delete_expired(pool.clone()));
+                    runtime.spawn(jobs::run(
+                        "purge_incomplete_sso_auth",
+                        CONFIG.purge_incomplete_sso_auth(),
+                        pool.clone(),
+                        db::models::SsoAuth::delete_expired,
+                    ));
+                }));
+            }
+
+            // Report (and optionally delete) orphaned attachment and send files.
+            if !CONFIG.storage_gc_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.storage_gc_schedule().parse().unwrap(), || {
+                    runtime.spawn(jobs::run(
+                        "storage_gc",
+                        CONFIG.storage_gc_schedule(),
+                        pool.clone(),
+                        storage::gc::storage_gc_job,
+                    ));
+                }));
+            }
+
+            // Verify the checksums of all stored attachments.
+            if !CONFIG.attachment_integrity_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.attachment_integrity_schedule().parse().unwrap(), || {
+                    runtime.spawn(jobs::run(
+                        storage::integrity::JOB_NAME,
+                        CONFIG.attachment_integrity_schedule(),
+                        pool.clone(),
+                        storage::integrity::attachment_integrity_job,
+                    ));
+                }));
+            }
+
+            // Complete or expire the attachments and Send files which haven't been uploaded yet.
+            if !CONFIG.pending_upload_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.pending_upload_schedule().parse().unwrap(), || {
+                    runtime.spawn(jobs::run(
+                        "pending_uploads",
+                        CONFIG.pending_upload_schedule(),
+                        pool.clone(),
+                        api::pending_uploads_job,
+                    ));
+                }));
+            }
+
+            // Retry sending the mails in the outbound mail queue.
+            if !CONFIG.mail_queue_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.mail_queue_schedule().parse().unwrap(), || {
+                    runtime.spawn(jobs::run(
+                        "mail_queue",
+                        CONFIG.mail_queue_schedule(),
+                        pool.clone(),
+                        mail::mail_queue_job,
+                    ));
+                }));
+            }
+
+            // Fetch the icons of the listed domains ahead of time.
+            if !CONFIG.icon_refresh_schedule().is_empty() {
+                sched.add(Job::new(CONFIG.icon_refresh_schedule().parse().unwrap(), || {
+                    runtime.spawn(jobs::run(
+                        "icon_refresh",
+                        CONFIG.icon_refresh_schedule(),
+                        pool.clone(),
+                        api::icon_refresh_job,
+                    ));
                 }));
             }
 
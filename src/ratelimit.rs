@@ -1,8 +1,19 @@
-use std::{net::IpAddr, num::NonZeroU32, sync::LazyLock, time::Duration};
+use std::{
+    fmt::Display,
+    hash::Hash,
+    net::IpAddr,
+    num::NonZeroU32,
+    sync::{LazyLock, OnceLock},
+    time::Duration,
+};
 
+use chrono::Utc;
 use governor::{Quota, RateLimiter, clock::DefaultClock, state::keyed::DashMapStateStore};
 
-use crate::{CONFIG, Error};
+use crate::{
+    CONFIG, Error,
+    db::{DbPool, models::RateLimit},
+};
 
 type Limiter<T = IpAddr> = RateLimiter<T, DashMapStateStore<T>, DefaultClock>;
 
@@ -12,6 +23,12 @@ static LIMITER_LOGIN: LazyLock<Limiter> = LazyLock::new(|| {
     RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero login ratelimit seconds").allow_burst(burst))
 });
 
+static LIMITER_LOGIN_ACCOUNT: LazyLock<Limiter<String>> = LazyLock::new(|| {
+    let seconds = Duration::from_secs(CONFIG.login_ratelimit_seconds());
+    let burst = NonZeroU32::new(CONFIG.login_ratelimit_max_burst()).expect("Non-zero login ratelimit burst");
+    RateLimiter::keyed(Quota::with_period(seconds).expect("Non-zero login ratelimit seconds").allow_burst(burst))
+});
+
 static LIMITER_ADMIN: LazyLock<Limiter> = LazyLock::new(|| {
     let seconds = Duration::from_secs(CONFIG.admin_ratelimit_seconds());
     let burst = NonZeroU32::new(CONFIG.admin_ratelimit_max_burst()).expect("Non-zero admin ratelimit burst");
@@ -27,29 +44,199 @@ static LIMITER_UNAUTHENTICATED: LazyLock<Limiter> = LazyLock::new(|| {
     )
 });
 
-pub fn check_limit_unauthenticated(ip: &IpAddr) -> Result<(), Error> {
-    match LIMITER_UNAUTHENTICATED.check_key(ip) {
-        Ok(()) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many requests", 429);
-        }
+/// Where the rate limiter state is kept, see `RATELIMIT_BACKEND`.
+enum Backend {
+    /// In-memory state, which is per instance and lost on restart
+    Local,
+    Database(DbPool),
+    #[cfg(redis)]
+    Redis(Box<redis::RedisLimiter>),
+}
+
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+/// Sets up the rate limiter state shared between instances, if configured.
+pub fn init(pool: DbPool) {
+    let backend = match CONFIG.ratelimit_backend().as_str() {
+        "database" => Backend::Database(pool),
+        #[cfg(redis)]
+        url if url.starts_with("redis://") || url.starts_with("rediss://") => match redis::RedisLimiter::new(url) {
+            Ok(limiter) => Backend::Redis(Box::new(limiter)),
+            Err(e) => {
+                error!("Invalid RATELIMIT_BACKEND Redis URL, falling back to in-memory rate limits: {e:?}");
+                Backend::Local
+            }
+        },
+        // Other values are rejected while validating the config
+        _ => Backend::Local,
+    };
+    BACKEND.set(backend).ok();
+}
+
+/// Returns the GCRA period and tolerance in milliseconds matching `Quota::with_period(seconds).allow_burst(burst)`.
+fn gcra_parameters(seconds: u64, burst: u32) -> (i64, i64) {
+    let period = i64::try_from(seconds.saturating_mul(1000)).unwrap_or(i64::MAX);
+    let tolerance = period.saturating_mul(i64::from(burst.saturating_sub(1)));
+    (period, tolerance)
+}
+
+/// Checks the limit of `key` in the configured backend, falling back to the in-memory `limiter` if it is unavailable.
+async fn check_key<K>(limiter: &Limiter<K>, name: &str, key: &K, seconds: u64, burst: u32) -> bool
+where
+    K: Clone + Display + Eq + Hash,
+{
+    let (period, tolerance) = gcra_parameters(seconds, burst);
+    let now = Utc::now().timestamp_millis();
+    let shared_key = format!("{name}:{key}");
+
+    let result = match BACKEND.get().unwrap_or(&Backend::Local) {
+        Backend::Local => return limiter.check_key(key).is_ok(),
+        Backend::Database(pool) => match pool.get().await {
+            Ok(conn) => RateLimit::check(&shared_key, now, period, tolerance, &conn).await,
+            Err(e) => Err(e),
+        },
+        #[cfg(redis)]
+        Backend::Redis(redis) => redis.check(&shared_key, now, period, tolerance).await,
+    };
+
+    result.unwrap_or_else(|e| {
+        error!("Failed to check the shared rate limit, using the in-memory rate limit: {e:?}");
+        limiter.check_key(key).is_ok()
+    })
+}
+
+pub async fn check_limit_unauthenticated(ip: &IpAddr) -> Result<(), Error> {
+    let seconds = CONFIG.unauthenticated_ratelimit_seconds();
+    let burst = CONFIG.unauthenticated_ratelimit_max_burst();
+    if !check_key(&LIMITER_UNAUTHENTICATED, "unauthenticated", ip, seconds, burst).await {
+        err_code!("Too many requests", 429);
     }
+    Ok(())
 }
 
-pub fn check_limit_login(ip: &IpAddr) -> Result<(), Error> {
-    match LIMITER_LOGIN.check_key(ip) {
-        Ok(()) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many login requests", 429);
-        }
+pub async fn check_limit_login(ip: &IpAddr) -> Result<(), Error> {
+    let seconds = CONFIG.login_ratelimit_seconds();
+    let burst = CONFIG.login_ratelimit_max_burst();
+    if !check_key(&LIMITER_LOGIN, "login", ip, seconds, burst).await {
+        err_code!("Too many login requests", 429);
     }
+    Ok(())
 }
 
-pub fn check_limit_admin(ip: &IpAddr) -> Result<(), Error> {
-    match LIMITER_ADMIN.check_key(ip) {
-        Ok(()) => Ok(()),
-        Err(_e) => {
-            err_code!("Too many admin requests", 429);
+/// Limits the login attempts for a single account, regardless of the IP address they come from.
+/// Only active when `LOGIN_RATELIMIT_PER_ACCOUNT` is enabled.
+pub async fn check_limit_login_account(email: &str) -> Result<(), Error> {
+    if !CONFIG.login_ratelimit_per_account() {
+        return Ok(());
+    }
+    let seconds = CONFIG.login_ratelimit_seconds();
+    let burst = CONFIG.login_ratelimit_max_burst();
+    if !check_key(&LIMITER_LOGIN_ACCOUNT, "login_account", &email.trim().to_lowercase(), seconds, burst).await {
+        err_code!("Too many login requests", 429);
+    }
+    Ok(())
+}
+
+pub async fn check_limit_admin(ip: &IpAddr) -> Result<(), Error> {
+    let seconds = CONFIG.admin_ratelimit_seconds();
+    let burst = CONFIG.admin_ratelimit_max_burst();
+    if !check_key(&LIMITER_ADMIN, "admin", ip, seconds, burst).await {
+        err_code!("Too many admin requests", 429);
+    }
+    Ok(())
+}
+
+#[cfg(redis)]
+mod redis {
+    use ::redis::{Client, RedisResult, Script, aio::ConnectionManager};
+    use tokio::sync::OnceCell;
+
+    use crate::Error;
+
+    /// Same algorithm as `RateLimit::check`, run as a script so it is atomic.
+    const CHECK_SCRIPT: &str = r"
+        local now = tonumber(ARGV[1])
+        local period = tonumber(ARGV[2])
+        local tolerance = tonumber(ARGV[3])
+        local tat = math.max(tonumber(redis.call('GET', KEYS[1]) or now), now)
+        if tat - now > tolerance then
+            return 0
+        end
+        redis.call('SET', KEYS[1], tat + period, 'PX', tat + period - now)
+        return 1
+    ";
+
+    pub struct RedisLimiter {
+        client: Client,
+        conn: OnceCell<ConnectionManager>,
+        script: Script,
+    }
+
+    impl RedisLimiter {
+        pub fn new(url: &str) -> RedisResult<Self> {
+            Ok(Self {
+                client: Client::open(url)?,
+                conn: OnceCell::new(),
+                script: Script::new(CHECK_SCRIPT),
+            })
+        }
+
+        pub async fn check(&self, key: &str, now: i64, period: i64, tolerance: i64) -> Result<bool, Error> {
+            // The connection manager reconnects by itself once it has been created
+            let result: RedisResult<i64> = async {
+                let conn = self.conn.get_or_try_init(|| ConnectionManager::new(self.client.clone())).await?;
+                self.script
+                    .key(format!("vaultwarden:ratelimit:{key}"))
+                    .arg(now)
+                    .arg(period)
+                    .arg(tolerance)
+                    .invoke_async(&mut conn.clone())
+                    .await
+            }
+            .await;
+
+            match result {
+                Ok(allowed) => Ok(allowed == 1),
+                Err(e) => Err(Error::new("Error checking rate limit", e.to_string())),
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcra_parameters_match_the_quota() {
+        // 3 requests at once, then one more every minute
+        assert_eq!(gcra_parameters(60, 3), (60_000, 120_000));
+        // Without a burst, requests have to be a full period apart
+        assert_eq!(gcra_parameters(10, 1), (10_000, 0));
+        assert_eq!(gcra_parameters(u64::MAX, u32::MAX), (i64::MAX, i64::MAX));
+    }
+
+    #[test]
+    fn gcra_parameters_match_the_in_memory_limiter() {
+        let limiter: Limiter<u32> = RateLimiter::keyed(
+            Quota::with_period(Duration::from_mins(1)).unwrap().allow_burst(NonZeroU32::new(3).unwrap()),
+        );
+        let allowed = (0..5).filter(|_| limiter.check_key(&1).is_ok()).count();
+
+        // The shared backends allow a request as long as the TAT isn't more than `tolerance` ahead
+        let (period, tolerance) = gcra_parameters(60, 3);
+        let mut tat = 0;
+        let shared_allowed = (0..5)
+            .filter(|_| {
+                let allow = tat <= tolerance;
+                if allow {
+                    tat += period;
+                }
+                allow
+            })
+            .count();
+
+        assert_eq!(allowed, 3);
+        assert_eq!(shared_allowed, allowed);
+    }
+}
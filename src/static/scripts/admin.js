@@ -1,5 +1,5 @@
 "use strict";
-/* exported BASE_URL, _post, _delete */
+/* exported BASE_URL, _post, _delete, promptRestorePoint */
 
 function getBaseUrl() {
     // If the base URL is `https://vaultwarden.example.com/base/path/admin/`,
@@ -64,6 +64,20 @@ function _fetch(method, url, successMsg, errMsg, body, reload_page = true) {
     });
 }
 
+// Asks for a point in time in the local timezone of the browser and returns it as an ISO 8601 UTC string
+function promptRestorePoint(name) {
+    const input = prompt(`WARNING: Restoring the vault of "${name}" reverts all its items to the given point in time.\nItems created afterwards are moved to the trash.\n\nEnter the point in time to restore to (YYYY-MM-DD HH:MM, local time):`);
+    if (!input) {
+        return null;
+    }
+    const restorePoint = new Date(input.trim().replace(" ", "T"));
+    if (isNaN(restorePoint.getTime())) {
+        alert("Invalid date, please use the YYYY-MM-DD HH:MM format");
+        return null;
+    }
+    return restorePoint.toISOString();
+}
+
 function _post(url, successMsg, errMsg, body, reload_page = true) {
     return _fetch("POST", url, successMsg, errMsg, body, reload_page);
 }
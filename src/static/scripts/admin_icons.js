@@ -0,0 +1,125 @@
+"use strict";
+/* global _post:readable, BASE_URL:readable, reload:readable */
+
+function getDomain(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const domain = event.target.dataset.vwDomain;
+    if (!domain) {
+        alert("Required parameters not found!");
+        return null;
+    }
+    return encodeURIComponent(domain);
+}
+
+function addIconDomains(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const domains = document.getElementById("icon-domains").value.trim();
+    if (!domains) {
+        alert("No domains entered!");
+        return false;
+    }
+    _post(`${BASE_URL}/admin/icons/domains`,
+        "Domains added correctly",
+        "Error adding domains",
+        JSON.stringify({ "domains": domains })
+    );
+}
+
+function deleteIconDomain(event) {
+    const domain = getDomain(event);
+    if (domain && confirm(`Are you sure you want to remove ${event.target.dataset.vwDomain}? Its cached icon is kept until it expires.`)) {
+        _post(`${BASE_URL}/admin/icons/domains/${domain}/delete`,
+            "Domain removed correctly",
+            "Error removing domain"
+        );
+    }
+}
+
+function retryIcon(event) {
+    const domain = getDomain(event);
+    if (domain) {
+        _post(`${BASE_URL}/admin/icons/${domain}/retry`,
+            "Icon fetched correctly",
+            "Error fetching icon"
+        );
+    }
+}
+
+function purgeIcon(event) {
+    const domain = getDomain(event);
+    if (domain) {
+        _post(`${BASE_URL}/admin/icons/${domain}/purge`,
+            "Negative cache entry purged correctly",
+            "Error purging negative cache entry"
+        );
+    }
+}
+
+function saveCustomIcon(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const domain = document.getElementById("custom-icon-domain").value.trim();
+    const file = document.getElementById("custom-icon-file").files[0];
+    if (!domain || !file) {
+        alert("Domain or icon missing!");
+        return false;
+    }
+    const reader = new FileReader();
+    reader.onload = () => {
+        // Strip the `data:<type>;base64,` prefix
+        const icon = reader.result.substring(reader.result.indexOf(",") + 1);
+        _post(`${BASE_URL}/admin/icons/custom`,
+            "Icon uploaded correctly",
+            "Error uploading icon",
+            JSON.stringify({ "domain": domain, "icon": icon })
+        );
+    };
+    reader.readAsDataURL(file);
+}
+
+function deleteCustomIcon(event) {
+    const domain = getDomain(event);
+    if (domain && confirm(`Are you sure you want to delete the custom icon of ${event.target.dataset.vwDomain}?`)) {
+        _post(`${BASE_URL}/admin/icons/custom/${domain}/delete`,
+            "Icon deleted correctly",
+            "Error deleting icon"
+        );
+    }
+}
+
+function refreshIcons(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    event.target.disabled = true;
+    _post(`${BASE_URL}/admin/icons/refresh`,
+        "Icons refreshed, failures are shown in the last error column",
+        "Error refreshing icons"
+    );
+}
+
+// onLoad events
+document.addEventListener("DOMContentLoaded", (/*event*/) => {
+    document.querySelectorAll("button[vw-retry-icon]").forEach(btn => {
+        btn.addEventListener("click", retryIcon);
+    });
+    document.querySelectorAll("button[vw-delete-icon-domain]").forEach(btn => {
+        btn.addEventListener("click", deleteIconDomain);
+    });
+    document.querySelectorAll("button[vw-purge-icon]").forEach(btn => {
+        btn.addEventListener("click", purgeIcon);
+    });
+    document.querySelectorAll("button[vw-delete-custom-icon]").forEach(btn => {
+        btn.addEventListener("click", deleteCustomIcon);
+    });
+
+    document.getElementById("add-icon-domains-form").addEventListener("submit", addIconDomains);
+    document.getElementById("custom-icon-form").addEventListener("submit", saveCustomIcon);
+    document.getElementById("refreshIcons").addEventListener("click", refreshIcons);
+
+    const btnReload = document.getElementById("reload");
+    if (btnReload) {
+        btnReload.addEventListener("click", reload);
+    }
+});
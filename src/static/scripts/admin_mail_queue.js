@@ -0,0 +1,48 @@
+"use strict";
+/* global _post:readable, BASE_URL:readable, reload:readable */
+
+function retryMail(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const mail_uuid = event.target.dataset.vwMailUuid;
+    if (!mail_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+    _post(`${BASE_URL}/admin/mail-queue/${mail_uuid}/retry`,
+        "Mail sent correctly",
+        "Error sending mail"
+    );
+}
+
+function deleteMail(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const mail_uuid = event.target.dataset.vwMailUuid;
+    const mail_recipient = event.target.dataset.vwMailRecipient;
+    if (!mail_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+    if (confirm(`Are you sure you want to delete the queued mail to ${mail_recipient}? It will not be sent.`)) {
+        _post(`${BASE_URL}/admin/mail-queue/${mail_uuid}/delete`,
+            "Mail deleted correctly",
+            "Error deleting mail"
+        );
+    }
+}
+
+// onLoad events
+document.addEventListener("DOMContentLoaded", (/*event*/) => {
+    document.querySelectorAll("button[vw-retry-mail]").forEach(btn => {
+        btn.addEventListener("click", retryMail);
+    });
+    document.querySelectorAll("button[vw-delete-mail]").forEach(btn => {
+        btn.addEventListener("click", deleteMail);
+    });
+
+    const btnReload = document.getElementById("reload");
+    if (btnReload) {
+        btnReload.addEventListener("click", reload);
+    }
+});
@@ -1,5 +1,5 @@
 "use strict";
-/* global jQuery, _post:readable, BASE_URL:readable, reload:readable, jdenticon:readable */
+/* global jQuery, _post:readable, BASE_URL:readable, reload:readable, jdenticon:readable, promptRestorePoint:readable */
 
 function deleteOrganization(event) {
     event.preventDefault();
@@ -29,10 +29,32 @@ function deleteOrganization(event) {
     }
 }
 
+function restoreOrganizationVault(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const org_uuid = event.target.dataset.vwOrgUuid;
+    const org_name = event.target.dataset.vwOrgName;
+    if (!org_uuid) {
+        alert("Required parameters not found!");
+        return false;
+    }
+    const timestamp = promptRestorePoint(org_name);
+    if (timestamp) {
+        _post(`${BASE_URL}/admin/organizations/${org_uuid}/restore-vault`,
+            "Vault restored successfully",
+            "Error restoring vault",
+            JSON.stringify({ "timestamp": timestamp })
+        );
+    }
+}
+
 function initActions() {
     document.querySelectorAll("button[vw-delete-organization]").forEach(btn => {
         btn.addEventListener("click", deleteOrganization);
     });
+    document.querySelectorAll("button[vw-restore-organization-vault]").forEach(btn => {
+        btn.addEventListener("click", restoreOrganizationVault);
+    });
 
     if (jdenticon) {
         jdenticon();
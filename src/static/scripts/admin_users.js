@@ -1,5 +1,5 @@
 "use strict";
-/* global jQuery, _post:readable, _delete:readable, BASE_URL:readable, reload:readable, jdenticon:readable */
+/* global jQuery, _post:readable, _delete:readable, BASE_URL:readable, reload:readable, jdenticon:readable, promptRestorePoint:readable */
 
 function deleteUser(event) {
     event.preventDefault();
@@ -99,6 +99,25 @@ function disableUser(event) {
     }
 }
 
+function restoreUserVault(event) {
+    event.preventDefault();
+    event.stopPropagation();
+    const id = event.target.parentNode.dataset.vwUserUuid;
+    const email = event.target.parentNode.dataset.vwUserEmail;
+    if (!id || !email) {
+        alert("Required parameters not found!");
+        return false;
+    }
+    const timestamp = promptRestorePoint(email);
+    if (timestamp) {
+        _post(`${BASE_URL}/admin/users/${id}/restore-vault`,
+            "Vault restored successfully",
+            "Error restoring vault",
+            JSON.stringify({ "timestamp": timestamp })
+        );
+    }
+}
+
 function enableUser(event) {
     event.preventDefault();
     event.stopPropagation();
@@ -159,6 +178,104 @@ function resendUserInvite (event) {
     }
 }
 
+let bulkPreviewUsers = null;
+
+function bulkUserFilter() {
+    const lastActiveBefore = document.getElementById("bulkLastActiveBefore").value;
+    const ssoOnly = document.getElementById("bulkSsoOnly");
+    return {
+        // The date is taken as midnight in the local timezone of the browser
+        lastActiveBefore: lastActiveBefore ? new Date(`${lastActiveBefore}T00:00`).toISOString() : null,
+        noTwoFactor: document.getElementById("bulkNoTwoFactor").checked,
+        unverifiedEmail: document.getElementById("bulkUnverifiedEmail").checked,
+        neverLoggedIn: document.getElementById("bulkNeverLoggedIn").checked,
+        ssoOnly: ssoOnly ? ssoOnly.checked : false,
+    };
+}
+
+async function bulkFetch(url, body) {
+    const resp = await fetch(url, {
+        method: "POST",
+        body: JSON.stringify(body),
+        mode: "same-origin",
+        credentials: "same-origin",
+        headers: { "Content-Type": "application/json" }
+    });
+    const respJson = await resp.json().catch(() => null);
+    if (!resp.ok) {
+        const apiMsg = respJson && respJson.errorModel ? respJson.errorModel.message : "Unknown error";
+        throw new Error(`${resp.status} - ${resp.statusText}\n\n${apiMsg}`);
+    }
+    return respJson;
+}
+
+function resetBulkPreview() {
+    bulkPreviewUsers = null;
+    document.getElementById("bulkPreview").classList.add("d-none");
+    document.getElementById("bulkApply").classList.add("d-none");
+}
+
+async function previewBulkUsers(event) {
+    event.preventDefault();
+    resetBulkPreview();
+    let preview;
+    try {
+        preview = await bulkFetch(`${BASE_URL}/admin/users/bulk/preview`, bulkUserFilter());
+    } catch (e) {
+        alert(`Error previewing users\n${e.message}`);
+        return;
+    }
+
+    const list = document.getElementById("bulkPreviewUsers");
+    list.replaceChildren(...preview.users.map(u => {
+        const item = document.createElement("li");
+        item.textContent = `${u.email} (${u.name}), last active: ${u.lastActive || "Never"}`;
+        return item;
+    }));
+    document.getElementById("bulkPreviewTotal").textContent = `${preview.total} matching user(s)`;
+    document.getElementById("bulkPreview").classList.remove("d-none");
+    if (preview.total > 0) {
+        bulkPreviewUsers = preview.users.map(u => u.id);
+        document.getElementById("bulkApply").classList.remove("d-none");
+    }
+}
+
+async function applyBulkAction(event) {
+    event.preventDefault();
+    if (!bulkPreviewUsers) {
+        return;
+    }
+    const actionSelect = document.getElementById("bulkAction");
+    const actionName = actionSelect.options[actionSelect.selectedIndex].text;
+    if (actionSelect.value === "delete") {
+        const input = prompt(`To delete ${bulkPreviewUsers.length} user(s), please type "delete" below`);
+        if (input !== "delete") {
+            return;
+        }
+    } else if (!confirm(`Apply "${actionName}" to ${bulkPreviewUsers.length} user(s)?`)) {
+        return;
+    }
+
+    let summary;
+    try {
+        summary = await bulkFetch(`${BASE_URL}/admin/users/bulk`, {
+            ...bulkUserFilter(),
+            action: actionSelect.value,
+            userIds: bulkPreviewUsers,
+        });
+    } catch (e) {
+        alert(`Error applying "${actionName}"\n${e.message}`);
+        return;
+    }
+
+    let report = `${actionName}: ${summary.succeeded} of ${summary.total} user(s) succeeded`;
+    if (summary.failed.length > 0) {
+        report += `\n\nFailed:\n${summary.failed.map(f => `${f.email}: ${f.error}`).join("\n")}`;
+    }
+    alert(report);
+    reload();
+}
+
 const ORG_TYPES = {
     "0": {
         "name": "Owner",
@@ -276,6 +393,9 @@ function initUserTable() {
     document.querySelectorAll("button[vw-enable-user]").forEach(btn => {
         btn.addEventListener("click", enableUser);
     });
+    document.querySelectorAll("button[vw-restore-user-vault]").forEach(btn => {
+        btn.addEventListener("click", restoreUserVault);
+    });
     document.querySelectorAll("button[vw-resend-user-invite]").forEach(btn => {
         btn.addEventListener("click", resendUserInvite);
     });
@@ -329,4 +449,16 @@ document.addEventListener("DOMContentLoaded", (/*event*/) => {
     if (btnInviteUserForm) {
         btnInviteUserForm.addEventListener("submit", inviteUser);
     }
+    const bulkUsersForm = document.getElementById("bulkUsersForm");
+    if (bulkUsersForm) {
+        bulkUsersForm.addEventListener("submit", previewBulkUsers);
+        // A changed filter needs a new preview before applying the action
+        bulkUsersForm.querySelectorAll("input").forEach(input => {
+            input.addEventListener("change", resetBulkPreview);
+        });
+    }
+    const btnBulkApply = document.getElementById("bulkApply");
+    if (btnBulkApply) {
+        btnBulkApply.addEventListener("click", applyBulkAction);
+    }
 });
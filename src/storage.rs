@@ -1,9 +1,29 @@
-use std::sync::LazyLock;
+use std::{sync::LazyLock, time::Duration};
+
+pub(crate) mod gc;
+pub(crate) mod integrity;
+
+// Presigned URLs are only handed out right before the client uses them, so they can be short-lived.
+const PRESIGNED_READ_EXPIRY: Duration = Duration::from_mins(1);
+const PRESIGNED_WRITE_EXPIRY: Duration = Duration::from_mins(10);
+
+// Bitwarden clients upload files up to this size with a single `PUT` request.
+// Larger files are uploaded in blocks, using an API which is specific to Azure Blob Storage.
+const MAX_DIRECT_UPLOAD_SIZE: i64 = 256 * 1024 * 1024;
+
+// URI schemes of the supported storage services besides the local filesystem, e.g. `s3://bucket/path`.
+const REMOTE_SCHEMES: [&str; 4] = ["s3", "azblob", "gcs", "webdav"];
+
+/// Whether `path` points to a storage service instead of the local filesystem.
+/// This doesn't depend on the enabled features, so a path of a disabled service is reported instead of used as a local path.
+pub(crate) fn is_remote_path(path: &str) -> bool {
+    path.split_once("://").is_some_and(|(scheme, _)| REMOTE_SCHEMES.contains(&scheme))
+}
 
 pub(crate) fn join_path(base: &str, child: &str) -> String {
-    #[cfg(s3)]
-    if s3::is_uri(base) {
-        return s3::join_path(base, child);
+    #[cfg(remote_storage)]
+    if is_remote_path(base) {
+        return uri::join_path(base, child);
     }
 
     let base = base.trim_end_matches('/');
@@ -20,27 +40,27 @@ pub(crate) fn join_path(base: &str, child: &str) -> String {
 pub(crate) fn with_extension(path: &str, extension: &str) -> String {
     let extension = extension.trim_start_matches('.');
 
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::with_extension(path, extension);
+    #[cfg(remote_storage)]
+    if is_remote_path(path) {
+        return uri::with_extension(path, extension);
     }
 
     format!("{path}.{extension}")
 }
 
 pub(crate) fn parent(path: &str) -> Option<String> {
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::parent(path);
+    #[cfg(remote_storage)]
+    if is_remote_path(path) {
+        return uri::parent(path);
     }
 
     std::path::Path::new(path).parent()?.to_str().map(str::to_owned)
 }
 
 pub(crate) fn file_name(path: &str) -> Option<String> {
-    #[cfg(s3)]
-    if s3::is_uri(path) {
-        return s3::file_name(path);
+    #[cfg(remote_storage)]
+    if is_remote_path(path) {
+        return uri::file_name(path);
     }
 
     std::path::Path::new(path).file_name()?.to_str().map(str::to_owned)
@@ -50,6 +70,50 @@ pub(crate) fn is_fs_operator(operator: &opendal::Operator) -> bool {
     operator.info().scheme() == opendal::services::FS_SCHEME
 }
 
+/// Returns a presigned URL to download `path` directly from the storage service.
+/// Not supported by the local filesystem.
+pub(crate) async fn presign_read(operator: &opendal::Operator, path: &str) -> Result<String, crate::Error> {
+    Ok(operator.presign_read(path, PRESIGNED_READ_EXPIRY).await?.uri().to_string())
+}
+
+/// Returns a presigned URL to upload `path` directly to the storage service with a `PUT` request.
+/// Not supported by the local filesystem.
+async fn presign_write(operator: &opendal::Operator, path: &str) -> Result<String, crate::Error> {
+    Ok(operator.presign_write(path, PRESIGNED_WRITE_EXPIRY).await?.uri().to_string())
+}
+
+/// Returns a presigned upload URL if a file of `size` bytes should be uploaded directly to the storage service,
+/// or `None` if it has to be uploaded through Vaultwarden.
+pub(crate) async fn direct_upload_url(
+    path_type: &crate::config::PathType,
+    path: &str,
+    size: i64,
+) -> Result<Option<String>, crate::Error> {
+    if !crate::CONFIG.storage_direct_upload() || size > MAX_DIRECT_UPLOAD_SIZE {
+        return Ok(None);
+    }
+
+    let operator = crate::CONFIG.opendal_operator_for_path_type(path_type)?;
+    if is_fs_operator(&operator) {
+        return Ok(None);
+    }
+
+    presign_write(&operator, path).await.map(Some)
+}
+
+/// Computes the hex encoded SHA-256 of a stored file, streaming it instead of loading it into memory at once.
+pub(crate) async fn sha256_hex(operator: &opendal::Operator, path: &str) -> Result<String, crate::Error> {
+    use futures::TryStreamExt as _;
+
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut stream = operator.reader(path).await?.into_bytes_stream(..).await?;
+    while let Some(chunk) = stream.try_next().await? {
+        context.update(&chunk);
+    }
+
+    Ok(data_encoding::HEXLOWER.encode(context.finish().as_ref()))
+}
+
 pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::Error> {
     // Cache of previously built operators by path
     static OPERATORS_BY_PATH: LazyLock<dashmap::DashMap<String, opendal::Operator>> =
@@ -65,6 +129,32 @@ pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::
 
         #[cfg(s3)]
         s3::operator_for_path(path)?
+    } else if path.starts_with("azblob://") {
+        #[cfg(not(azblob))]
+        return Err(opendal::Error::new(
+            opendal::ErrorKind::ConfigInvalid,
+            "Azure Blob Storage support is not enabled",
+        )
+        .into());
+
+        #[cfg(azblob)]
+        azblob::operator_for_path(path)?
+    } else if path.starts_with("gcs://") {
+        #[cfg(not(gcs))]
+        return Err(opendal::Error::new(
+            opendal::ErrorKind::ConfigInvalid,
+            "Google Cloud Storage support is not enabled",
+        )
+        .into());
+
+        #[cfg(gcs)]
+        gcs::operator_for_path(path)?
+    } else if path.starts_with("webdav://") {
+        #[cfg(not(webdav))]
+        return Err(opendal::Error::new(opendal::ErrorKind::ConfigInvalid, "WebDAV support is not enabled").into());
+
+        #[cfg(webdav)]
+        webdav::operator_for_path(path)?
     } else {
         let builder = opendal::services::Fs::default().root(path);
         opendal::Operator::new(builder)?
@@ -75,16 +165,11 @@ pub(crate) fn operator_for_path(path: &str) -> Result<opendal::Operator, crate::
     Ok(operator)
 }
 
-#[cfg(s3)]
-mod s3 {
+// Path handling for storage service URIs, which keeps the query string with the service options at the end.
+#[cfg(remote_storage)]
+mod uri {
     use reqwest::Url;
 
-    use crate::error::Error;
-
-    pub(super) fn is_uri(path: &str) -> bool {
-        path.starts_with("s3://")
-    }
-
     pub(super) fn join_path(base: &str, child: &str) -> String {
         if let Ok(mut url) = Url::parse(base) {
             let mut segments = path_segments(&url);
@@ -150,6 +235,11 @@ mod s3 {
             url.set_path(&format!("/{}", segments.join("/")));
         }
     }
+}
+
+#[cfg(s3)]
+mod s3 {
+    use crate::error::Error;
 
     pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
         use crate::http_client::aws::AwsReqwestConnector;
@@ -254,6 +344,76 @@ mod s3 {
     }
 }
 
+#[cfg(azblob)]
+mod azblob {
+    use opendal::Configurator;
+
+    use crate::error::Error;
+
+    pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
+        let uri = opendal::OperatorUri::new(path, std::iter::empty::<(String, String)>())?;
+        let mut config = opendal::services::AzblobConfig::from_uri(&uri)?;
+
+        // Fall back to the environment variables used by the Azure tooling when the URI has no credentials.
+        // Without any, OpenDAL tries the other Azure credential sources, like a managed identity.
+        if config.account_name.is_none() {
+            config.account_name = std::env::var("AZURE_STORAGE_ACCOUNT_NAME").ok();
+        }
+        if config.account_key.is_none() && config.sas_token.is_none() {
+            config.account_key = std::env::var("AZURE_STORAGE_ACCOUNT_KEY").ok();
+            config.sas_token = std::env::var("AZURE_STORAGE_SAS_TOKEN").ok();
+        }
+
+        if config.endpoint.is_none()
+            && let Some(account_name) = &config.account_name
+        {
+            config.endpoint = Some(format!("https://{account_name}.blob.core.windows.net"));
+        }
+
+        Ok(opendal::Operator::new(config.into_builder())?)
+    }
+}
+
+#[cfg(gcs)]
+mod gcs {
+    use opendal::Configurator;
+
+    use crate::error::Error;
+
+    pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
+        // Without credentials in the URI, OpenDAL uses `GOOGLE_APPLICATION_CREDENTIALS` or the VM metadata service.
+        let uri = opendal::OperatorUri::new(path, std::iter::empty::<(String, String)>())?;
+        let config = opendal::services::GcsConfig::from_uri(&uri)?;
+
+        Ok(opendal::Operator::new(config.into_builder())?)
+    }
+}
+
+#[cfg(webdav)]
+mod webdav {
+    use opendal::Configurator;
+
+    use crate::error::Error;
+
+    pub(super) fn operator_for_path(path: &str) -> Result<opendal::Operator, Error> {
+        let uri = opendal::OperatorUri::new(path, std::iter::empty::<(String, String)>())?;
+        let mut config = opendal::services::WebdavConfig::from_uri(&uri)?;
+
+        // OpenDAL always derives an `https://` endpoint from the host, allow overriding it, e.g. for a local server.
+        if let Some(endpoint) = uri.options().get("endpoint") {
+            config.endpoint = Some(endpoint.clone());
+        }
+
+        if config.username.is_none() && config.token.is_none() {
+            config.username = std::env::var("WEBDAV_USERNAME").ok();
+            config.password = std::env::var("WEBDAV_PASSWORD").ok();
+            config.token = std::env::var("WEBDAV_TOKEN").ok();
+        }
+
+        Ok(opendal::Operator::new(config.into_builder())?)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,8 +427,8 @@ mod tests {
     }
 }
 
-#[cfg(all(test, s3))]
-mod s3_tests {
+#[cfg(all(test, remote_storage))]
+mod uri_tests {
     use super::*;
 
     #[test]
@@ -294,4 +454,15 @@ mod s3_tests {
         assert_eq!(parent(path).as_deref(), Some("s3://bucket/base?region=us-west-2"));
         assert_eq!(file_name(path).as_deref(), Some("config.json"));
     }
+
+    #[test]
+    fn keeps_webdav_host_when_walking_up() {
+        let path = "webdav://dav.example.com/vaultwarden/attachments";
+
+        assert_eq!(parent(path).as_deref(), Some("webdav://dav.example.com/vaultwarden"));
+        assert_eq!(
+            join_path("webdav://dav.example.com/vaultwarden", "sends"),
+            "webdav://dav.example.com/vaultwarden/sends"
+        );
+    }
 }
@@ -0,0 +1,273 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    CONFIG,
+    config::PathType,
+    db::{
+        DbConn, DbPool,
+        models::{Attachment, PendingUpload, Send},
+    },
+    error::Error,
+};
+
+/// Files modified more recently than this are never considered orphaned.
+/// Uploads are written to storage before or right after their database record is saved,
+/// so a very young file without a record is most likely still part of an ongoing request.
+const ORPHAN_GRACE_PERIOD: Duration = Duration::from_hours(1);
+
+/// Result of cross-referencing the attachments and sends storage with the database.
+/// All paths are relative to their respective storage folder.
+#[derive(Default)]
+pub struct GcReport {
+    /// Files in the attachments folder without an `attachments` record
+    pub orphaned_attachment_files: Vec<String>,
+    /// Files in the sends folder without a file Send record
+    pub orphaned_send_files: Vec<String>,
+    /// `attachments` records whose file doesn't exist, and which aren't waiting for their upload
+    pub dangling_attachments: Vec<String>,
+    /// File Send records whose file doesn't exist, and which aren't waiting for their upload
+    pub dangling_sends: Vec<String>,
+    /// Number of orphaned files which were deleted
+    pub deleted_files: usize,
+}
+
+impl GcReport {
+    pub fn orphan_count(&self) -> usize {
+        self.orphaned_attachment_files.len() + self.orphaned_send_files.len()
+    }
+
+    pub fn dangling_count(&self) -> usize {
+        self.dangling_attachments.len() + self.dangling_sends.len()
+    }
+
+    fn log(&self) {
+        if self.orphan_count() == 0 && self.dangling_count() == 0 {
+            info!("Storage garbage collection found no orphaned files or missing files");
+            return;
+        }
+
+        for path in &self.orphaned_attachment_files {
+            warn!("Orphaned attachment file: '{path}'");
+        }
+        for path in &self.orphaned_send_files {
+            warn!("Orphaned send file: '{path}'");
+        }
+        for path in &self.dangling_attachments {
+            warn!("Attachment record without file: '{path}'");
+        }
+        for path in &self.dangling_sends {
+            warn!("Send record without file: '{path}'");
+        }
+        warn!(
+            "Storage garbage collection found {} orphaned file(s) ({} deleted) and {} record(s) without a file",
+            self.orphan_count(),
+            self.deleted_files,
+            self.dangling_count()
+        );
+    }
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("Orphaned attachment files", &self.orphaned_attachment_files),
+            ("Orphaned send files", &self.orphaned_send_files),
+            ("Attachment records without file", &self.dangling_attachments),
+            ("Send records without file", &self.dangling_sends),
+        ];
+
+        for (title, paths) in sections {
+            writeln!(f, "{title}: {}", paths.len())?;
+            for path in paths {
+                writeln!(f, "  {path}")?;
+            }
+        }
+        write!(f, "Deleted files: {}", self.deleted_files)
+    }
+}
+
+pub async fn storage_gc_job(pool: DbPool) {
+    debug!("Start storage garbage collection job");
+    crate::api::purge_stale_uploads().await;
+
+    if let Ok(conn) = pool.get().await {
+        match collect(&conn, CONFIG.storage_gc_delete_orphans()).await {
+            Ok(report) => report.log(),
+            Err(e) => error!("Storage garbage collection failed: {e:?}"),
+        }
+    } else {
+        error!("Failed to get DB connection while running storage garbage collection");
+    }
+}
+
+/// Walks the attachments and sends storage, cross-references every file with the database and
+/// reports both files without a record and records without a file.
+/// When `delete_orphans` is set, files without a record are deleted as well.
+/// Records without a file are only reported, since removing them would hide the data loss from the user.
+/// Records created through the v2 API whose content is still being uploaded are skipped,
+/// the pending uploads job removes them once their upload expires.
+pub async fn collect(conn: &DbConn, delete_orphans: bool) -> Result<GcReport, Error> {
+    let mut report = GcReport::default();
+    let pending: HashSet<String> = PendingUpload::find_all_uuids(conn).await.into_iter().collect();
+
+    let attachments = Attachment::find_all(conn).await;
+    let uploading: HashSet<String> =
+        attachments.iter().filter(|a| pending.contains(&*a.id)).map(Attachment::get_file_path).collect();
+    let attachments: HashSet<String> = attachments.iter().map(Attachment::get_file_path).collect();
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Attachments)?;
+    let (orphans, dangling) = cross_reference(&operator, &attachments, &uploading).await?;
+    report.deleted_files += delete_files(&operator, &orphans, delete_orphans).await;
+    report.orphaned_attachment_files = orphans;
+    report.dangling_attachments = dangling;
+
+    let sends: Vec<(bool, String)> = Send::find_all_files(conn)
+        .await
+        .iter()
+        .filter_map(|send| {
+            send.file_id().map(|file_id| (pending.contains(&*send.uuid), format!("{}/{file_id}", send.uuid)))
+        })
+        .collect();
+    let uploading: HashSet<String> =
+        sends.iter().filter(|(pending, _)| *pending).map(|(_, path)| path.clone()).collect();
+    let sends: HashSet<String> = sends.into_iter().map(|(_, path)| path).collect();
+    let operator = CONFIG.opendal_operator_for_path_type(&PathType::Sends)?;
+    let (orphans, dangling) = cross_reference(&operator, &sends, &uploading).await?;
+    report.deleted_files += delete_files(&operator, &orphans, delete_orphans).await;
+    report.orphaned_send_files = orphans;
+    report.dangling_sends = dangling;
+
+    Ok(report)
+}
+
+/// Returns the stored files which are not `expected` and the `expected` files which are not stored,
+/// leaving out the ones which are still `uploading`.
+async fn cross_reference(
+    operator: &opendal::Operator,
+    expected: &HashSet<String>,
+    uploading: &HashSet<String>,
+) -> Result<(Vec<String>, Vec<String>), Error> {
+    let entries = match operator.list_with("/").recursive(true).await {
+        Ok(entries) => entries,
+        // Nothing was ever stored, so there is nothing to list
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut stored = HashSet::with_capacity(entries.len());
+    let mut orphans = Vec::new();
+    for entry in entries {
+        if !entry.metadata().mode().is_file() {
+            continue;
+        }
+
+        let path = entry.path().trim_start_matches('/').to_owned();
+        if !expected.contains(&path) && is_past_grace_period(operator, &entry).await {
+            orphans.push(path.clone());
+        }
+        stored.insert(path);
+    }
+
+    let mut dangling: Vec<String> =
+        expected.difference(&stored).filter(|path| !uploading.contains(*path)).cloned().collect();
+    orphans.sort();
+    dangling.sort();
+
+    Ok((orphans, dangling))
+}
+
+async fn is_past_grace_period(operator: &opendal::Operator, entry: &opendal::Entry) -> bool {
+    let last_modified = match entry.metadata().last_modified() {
+        Some(last_modified) => Some(last_modified),
+        // Not all services return the modification time while listing
+        None => operator.stat(entry.path()).await.ok().and_then(|meta| meta.last_modified()),
+    };
+
+    match last_modified {
+        Some(last_modified) => {
+            SystemTime::now().duration_since(last_modified.into()).is_ok_and(|age| age >= ORPHAN_GRACE_PERIOD)
+        }
+        None => false,
+    }
+}
+
+async fn delete_files(operator: &opendal::Operator, paths: &[String], delete: bool) -> usize {
+    if !delete {
+        return 0;
+    }
+
+    let mut deleted = 0;
+    for path in paths {
+        match operator.delete(path).await {
+            Ok(()) => deleted += 1,
+            Err(e) => error!("Failed to delete orphaned file '{path}': {e:?}"),
+        }
+    }
+    deleted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_operator(name: &str) -> (opendal::Operator, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("vw-gc-test-{name}"));
+        std::fs::remove_dir_all(&dir).ok();
+        let operator = opendal::Operator::new(opendal::services::Fs::default().root(dir.to_str().unwrap())).unwrap();
+        (operator, dir)
+    }
+
+    fn store(dir: &std::path::Path, path: &str, age: Duration) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = std::fs::File::create(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn paths(paths: &[&str]) -> HashSet<String> {
+        paths.iter().map(|path| (*path).to_owned()).collect()
+    }
+
+    #[tokio::test]
+    async fn cross_reference_reports_orphans_and_dangling_records() {
+        let (operator, dir) = test_operator("report");
+        let old = ORPHAN_GRACE_PERIOD * 2;
+        store(&dir, "cipher/stored", old);
+        store(&dir, "cipher/orphan", old);
+        store(&dir, "cipher/recent", Duration::ZERO);
+
+        let expected = paths(&["cipher/stored", "cipher/missing"]);
+        let (orphans, dangling) = cross_reference(&operator, &expected, &HashSet::new()).await.unwrap();
+        assert_eq!(orphans, vec!["cipher/orphan"]);
+        assert_eq!(dangling, vec!["cipher/missing"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn cross_reference_skips_uploads_in_flight() {
+        let (operator, dir) = test_operator("uploading");
+        store(&dir, "cipher/stored", Duration::ZERO);
+
+        let expected = paths(&["cipher/stored", "cipher/uploading", "cipher/missing"]);
+        let (orphans, dangling) = cross_reference(&operator, &expected, &paths(&["cipher/uploading"])).await.unwrap();
+        assert!(orphans.is_empty());
+        assert_eq!(dangling, vec!["cipher/missing"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn cross_reference_handles_empty_storage() {
+        let (operator, dir) = test_operator("empty");
+
+        let (orphans, dangling) = cross_reference(&operator, &paths(&["send/file"]), &HashSet::new()).await.unwrap();
+        assert!(orphans.is_empty());
+        assert_eq!(dangling, vec!["send/file"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,130 @@
+use serde_json::Value;
+
+use crate::{
+    CONFIG,
+    config::PathType,
+    db::{
+        DbConn, DbPool,
+        models::{Attachment, JobRun},
+    },
+    error::Error,
+    jobs::{JobOutcome, JobSummary},
+    storage::sha256_hex,
+    util::format_naive_datetime_local,
+};
+
+/// Name of the scheduled job, its last report is shown on the admin diagnostics page.
+pub const JOB_NAME: &str = "attachment_integrity";
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Number of attachments whose checksum matched
+    pub verified: usize,
+    /// Number of attachments which didn't have a checksum yet and got one stored
+    pub backfilled: usize,
+    /// Attachments whose file doesn't exist
+    pub missing: Vec<String>,
+    /// Attachments whose file doesn't match the stored checksum
+    pub corrupted: Vec<String>,
+}
+
+impl JobOutcome for IntegrityReport {
+    fn into_result(self) -> Result<JobSummary, String> {
+        Ok(JobSummary {
+            rows_affected: i64::try_from(self.missing.len() + self.corrupted.len()).ok(),
+            details: Some(serde_json::to_string(&self).map_err(|e| e.to_string())?),
+        })
+    }
+}
+
+pub async fn attachment_integrity_job(pool: DbPool) -> Result<IntegrityReport, Error> {
+    debug!("Start attachment integrity scan job");
+    if let Ok(conn) = pool.get().await {
+        let report = scan(&conn).await;
+        if report.missing.is_empty() && report.corrupted.is_empty() {
+            info!(
+                "Attachment integrity scan verified {} attachment(s) and stored {} new checksum(s)",
+                report.verified, report.backfilled
+            );
+        } else {
+            warn!(
+                "Attachment integrity scan found {} missing and {} corrupted attachment(s)",
+                report.missing.len(),
+                report.corrupted.len()
+            );
+        }
+        Ok(report)
+    } else {
+        err!("Failed to get DB connection while running attachment integrity scan")
+    }
+}
+
+/// Verifies the stored file of every attachment against its recorded checksum.
+/// Attachments without a checksum, which were uploaded before checksums were recorded, get one stored.
+pub async fn scan(conn: &DbConn) -> IntegrityReport {
+    let mut report = IntegrityReport::default();
+
+    let operator = match CONFIG.opendal_operator_for_path_type(&PathType::Attachments) {
+        Ok(operator) => operator,
+        Err(e) => {
+            error!("Failed to get attachments storage: {e:?}");
+            return report;
+        }
+    };
+
+    for mut attachment in Attachment::find_all(conn).await {
+        let file_path = attachment.get_file_path();
+        if !operator.exists(&file_path).await.unwrap_or(true) {
+            error!("Attachment file '{file_path}' is missing");
+            report.missing.push(file_path);
+            continue;
+        }
+
+        let checksum = match sha256_hex(&operator, &file_path).await {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                error!("Failed to read attachment file '{file_path}': {e:?}");
+                continue;
+            }
+        };
+
+        match &attachment.checksum {
+            Some(expected) if *expected == checksum => report.verified += 1,
+            Some(_) => {
+                error!("Attachment file '{file_path}' doesn't match its checksum");
+                report.corrupted.push(file_path);
+            }
+            None => {
+                attachment.checksum = Some(checksum);
+                if let Err(e) = attachment.save(conn).await {
+                    error!("Failed to store checksum of attachment '{file_path}': {e:?}");
+                } else {
+                    report.backfilled += 1;
+                }
+            }
+        }
+    }
+
+    report
+}
+
+/// Summary of the last integrity scan for the admin diagnostics page, or `Value::Null` if no scan ran yet.
+/// The report is taken from the job history, so it is available on every instance, not only the one which ran the scan.
+pub async fn last_report_json(conn: &DbConn) -> Value {
+    let Some(run) = JobRun::find_last_report(JOB_NAME, conn).await else {
+        return Value::Null;
+    };
+    let Some(report) = run.details.as_deref().and_then(|details| serde_json::from_str::<IntegrityReport>(details).ok())
+    else {
+        return Value::Null;
+    };
+
+    json!({
+        "finished_at": format_naive_datetime_local(&run.finished_at.unwrap_or(run.started_at), "%Y-%m-%d %H:%M:%S %Z"),
+        "verified": report.verified,
+        "backfilled": report.backfilled,
+        "missing": report.missing,
+        "corrupted": report.corrupted,
+        "has_errors": !report.missing.is_empty() || !report.corrupted.is_empty(),
+    })
+}
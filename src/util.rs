@@ -1,7 +1,7 @@
 //
 // Web Headers and caching
 //
-use std::{collections::HashMap, env, fmt, io::Cursor, path::Path, str::FromStr};
+use std::{collections::HashMap, env, fmt, io::Cursor, path::Path, str::FromStr, sync::LazyLock};
 
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use num_traits::ToPrimitive;
@@ -395,6 +395,12 @@ pub fn get_uuid() -> String {
     uuid::Uuid::new_v4().to_string()
 }
 
+/// Identifies this process when multiple instances share the same database, e.g. `vaultwarden-1a2b3c4d`.
+pub static INSTANCE_ID: LazyLock<String> = LazyLock::new(|| {
+    let hostname = env::var("HOSTNAME").unwrap_or_else(|_| String::from("vaultwarden"));
+    format!("{hostname}-{}", &get_uuid()[..8])
+});
+
 //
 // String util methods
 //
@@ -543,6 +549,30 @@ pub fn is_valid_email(email: &str) -> bool {
     true
 }
 
+/// Returns the preferred language of an `Accept-Language` header, like `de` or `pt-BR`.
+pub fn parse_accept_language(header: &str) -> Option<String> {
+    let mut preferred: Option<(&str, f32)> = None;
+    for entry in header.split(',') {
+        let mut parts = entry.split(';').map(str::trim);
+        let tag = parts.next().unwrap_or_default();
+        let quality =
+            parts.find_map(|p| p.strip_prefix("q=")).map_or(Some(1.0), |q| q.parse::<f32>().ok()).unwrap_or(0.0);
+
+        if is_valid_language_tag(tag) && quality > 0.0 && preferred.is_none_or(|(_, q)| quality > q) {
+            preferred = Some((tag, quality));
+        }
+    }
+    preferred.map(|(tag, _)| tag.replace('_', "-"))
+}
+
+/// Only allows simple language tags, which are also used as the name of the template folder of the language.
+pub fn is_valid_language_tag(tag: &str) -> bool {
+    let mut subtags = tag.split(['-', '_']);
+    subtags.next().is_some_and(|l| (2..=3).contains(&l.len()) && l.chars().all(|c| c.is_ascii_alphabetic()))
+        && subtags.all(|s| (1..=8).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric()))
+        && tag.len() <= 35
+}
+
 //
 // Deployment environment methods
 //
@@ -913,23 +943,53 @@ pub fn is_global(ip: std::net::IpAddr) -> bool {
 }
 
 /// Saves a Rocket temporary file to the OpenDAL Operator at the given path.
+/// Returns the hex encoded SHA-256 of the saved content.
 pub async fn save_temp_file(
     path_type: &PathType,
     path: &str,
     temp_file: rocket::fs::TempFile<'_>,
     overwrite: bool,
-) -> Result<(), crate::Error> {
-    use futures::AsyncWriteExt as _;
-    use tokio_util::compat::TokioAsyncReadCompatExt as _;
+) -> Result<String, crate::Error> {
+    save_file(path_type, path, temp_file.open().await?, overwrite).await
+}
 
+/// Saves the content of `reader` to the OpenDAL Operator at the given path.
+/// Returns the hex encoded SHA-256 of the saved content.
+pub async fn save_file(
+    path_type: &PathType,
+    path: &str,
+    reader: impl tokio::io::AsyncRead + Unpin,
+    overwrite: bool,
+) -> Result<String, crate::Error> {
     let operator = CONFIG.opendal_operator_for_path_type(path_type)?;
+    write_file(&operator, path, reader, overwrite).await
+}
+
+/// Saves the content of `reader` to `operator` at the given path.
+/// Returns the hex encoded SHA-256 of the saved content.
+pub async fn write_file(
+    operator: &opendal::Operator,
+    path: &str,
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+    overwrite: bool,
+) -> Result<String, crate::Error> {
+    use futures::AsyncWriteExt as _;
+    use tokio::io::AsyncReadExt as _;
 
-    let mut read_stream = temp_file.open().await?.compat();
     let mut writer = operator.writer_with(path).if_not_exists(!overwrite).await?.into_futures_async_write();
-    futures::io::copy(&mut read_stream, &mut writer).await?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buf[..read]);
+        writer.write_all(&buf[..read]).await?;
+    }
     writer.close().await?;
 
-    Ok(())
+    Ok(data_encoding::HEXLOWER.encode(context.finish().as_ref()))
 }
 
 /// These are some tests to check that the implementations match
@@ -985,3 +1045,17 @@ mod tests {
         });
     }
 }
+
+#[cfg(test)]
+mod language_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(parse_accept_language("de-DE,de;q=0.9,en;q=0.8").as_deref(), Some("de-DE"));
+        assert_eq!(parse_accept_language("en;q=0.5, pt_BR").as_deref(), Some("pt-BR"));
+        assert_eq!(parse_accept_language("*, fr;q=0.3").as_deref(), Some("fr"));
+        assert_eq!(parse_accept_language("../etc, x;q=0").as_deref(), None);
+        assert_eq!(parse_accept_language(""), None);
+    }
+}